@@ -0,0 +1,392 @@
+use crate::error::DatabaseError;
+use crate::moderation::Moderator;
+use crate::moderations::check_moderator;
+use crate::pg::conn;
+use crate::revision::{RevisionAction, RouteEditor};
+use crate::revisions::{check_editor, record_revision};
+use crate::route::RouteMerge;
+use crate::routes::select_route;
+use actix_web::{delete, post, put, web, HttpResponse, Responder};
+use log::error;
+/// Functions for the API endpoint /admin, for undoing soft deletes, merging duplicate routes and
+/// choosing moderators
+use sqlx::{self, postgres::PgConnection, query, Connection};
+
+/// Undo a route's soft delete and record it in the route's history. False if the route isn't
+/// deleted or was merged into another route.
+async fn undelete_route(
+    conn: &mut PgConnection,
+    id: i32,
+    editor_id: Option<i32>,
+) -> Result<bool, DatabaseError> {
+    let mut transaction = conn.begin().await?;
+    let rows_affected = query!(
+        r#"UPDATE routes SET deleted_at = NULL, version = version + 1 WHERE id = ($1) AND deleted_at IS NOT NULL AND merged_into IS NULL"#,
+        id,
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    if rows_affected == 0 {
+        return Ok(false);
+    }
+    let route = select_route(&mut transaction, id)
+        .await?
+        .ok_or(DatabaseError::RouteNotFound)?;
+    record_revision(
+        &mut transaction,
+        id,
+        editor_id,
+        RevisionAction::Restore,
+        Some(&route),
+        &route,
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(true)
+}
+
+/// Restores a soft deleted route, unless it was deleted by merging it into another route
+#[post("/routes/{id}/restore")]
+async fn restore_route(path: web::Path<i32>, editor: web::Query<RouteEditor>) -> impl Responder {
+    let id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        if let Some(response) = check_editor(&mut conn, editor.editor_id).await {
+            return response;
+        }
+        match undelete_route(&mut conn, id, editor.editor_id).await {
+            Ok(true) => HttpResponse::NoContent().finish(),
+            Ok(false) => HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("UPDATE query failed in restore_route()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in restore_route()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+#[post("/climbers/{id}/restore")]
+async fn restore_climber(path: web::Path<i32>) -> impl Responder {
+    let id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match query!(
+            r#"UPDATE climbers SET deleted_at = NULL, version = version + 1 WHERE id = ($1) AND deleted_at IS NOT NULL"#,
+            id,
+        )
+        .execute(&mut conn)
+        .await
+        {
+            Ok(query_result) if query_result.rows_affected() == 0 => HttpResponse::NotFound(),
+            Ok(_) => HttpResponse::NoContent(),
+            Err(_) => {
+                error!("UPDATE query failed in restore_climber()");
+                HttpResponse::BadGateway()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in restore_climber()");
+        HttpResponse::BadGateway()
+    }
+}
+
+/// Restores the most recently deleted review of the route by the climber
+#[post("/climbers/{climber_id}/{route_id}/restore")]
+async fn restore_review(path: web::Path<(i32, i32)>) -> impl Responder {
+    let (climber_id, route_id) = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match query!(
+            r#"UPDATE climbs SET deleted_at = NULL, version = version + 1 WHERE id = (
+                SELECT id FROM climbs WHERE climber_id = ($1) AND route_id = ($2) AND deleted_at IS NOT NULL
+                ORDER BY deleted_at DESC LIMIT 1
+            )"#,
+            climber_id,
+            route_id,
+        )
+        .execute(&mut conn)
+        .await
+        {
+            Ok(query_result) if query_result.rows_affected() == 0 => HttpResponse::NotFound(),
+            Ok(_) => HttpResponse::NoContent(),
+            Err(_) => {
+                error!("UPDATE query failed in restore_review()");
+                HttpResponse::BadGateway()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in restore_review()");
+        HttpResponse::BadGateway()
+    }
+}
+
+/// Move every climb (and so every review), photo, ticklist entry, comment and condition report on
+/// a route to the target route, then delete the route and leave a redirect to the target.
+/// Redirects to the route are pointed at the target as well. Both routes are locked first, so that
+/// two merges running at once can't redirect a pair of routes into each other. The merge is
+/// recorded in the history of both routes.
+async fn merge_routes(
+    conn: &mut PgConnection,
+    id: i32,
+    mut merge: RouteMerge,
+    editor_id: Option<i32>,
+) -> Result<Result<RouteMerge, HttpResponse>, DatabaseError> {
+    let mut transaction = conn.begin().await?;
+    let live_routes = query!(
+        "SELECT id FROM routes WHERE id IN ($1, $2) AND deleted_at IS NULL ORDER BY id FOR UPDATE",
+        id,
+        merge.target_id,
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+    if !live_routes.iter().any(|route| route.id == id) {
+        return Ok(Err(HttpResponse::NotFound().finish()));
+    }
+    if !live_routes.iter().any(|route| route.id == merge.target_id) {
+        return Ok(Err(
+            HttpResponse::BadRequest().body(DatabaseError::RouteNotFound.to_string())
+        ));
+    }
+    let route = select_route(&mut transaction, id)
+        .await?
+        .ok_or(DatabaseError::RouteNotFound)?;
+    let target_before = select_route(&mut transaction, merge.target_id)
+        .await?
+        .ok_or(DatabaseError::RouteNotFound)?;
+    // The duplicate's grade, and the pitches it may pass on, have to suit the target's discipline
+    let grade_fits = target_before
+        .discipline
+        .check_grade(&route.difficulty)
+        .and_then(|()| {
+            route
+                .pitches
+                .iter()
+                .try_for_each(|pitch| target_before.discipline.check_grade(&pitch.difficulty))
+        });
+    if grade_fits.is_err() {
+        return Ok(Err(HttpResponse::BadRequest()
+            .body(DatabaseError::MergedGradeNotValidForTarget.to_string())));
+    }
+    // The pitches and approach only move if the target has none, taking the grade and length
+    // derived from the pitches along with them
+    query!(
+        r#"UPDATE routes SET difficulty = source.difficulty, number_pitches = source.number_pitches,
+        length_meters = source.length_meters, bolts = source.bolts, version = routes.version + 1
+        FROM routes source WHERE routes.id = ($2) AND source.id = ($1)
+        AND EXISTS (SELECT 1 FROM pitches WHERE route_id = ($1))
+        AND NOT EXISTS (SELECT 1 FROM pitches WHERE route_id = ($2))"#,
+        id,
+        merge.target_id,
+    )
+    .execute(&mut transaction)
+    .await?;
+    merge.pitches = query!(
+        r#"UPDATE pitches SET route_id = ($2) WHERE route_id = ($1)
+        AND NOT EXISTS (SELECT 1 FROM pitches WHERE route_id = ($2))"#,
+        id,
+        merge.target_id,
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    merge.approach_waypoints = query!(
+        r#"UPDATE approach_waypoints SET route_id = ($2) WHERE route_id = ($1)
+        AND NOT EXISTS (SELECT 1 FROM approach_waypoints WHERE route_id = ($2))"#,
+        id,
+        merge.target_id,
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    // A climber who logged both routes keeps their climb of the target, and the duplicate is
+    // soft deleted so that the target has one climb per climber
+    merge.duplicate_climbs = query!(
+        r#"UPDATE climbs SET deleted_at = current_timestamp, version = version + 1
+        WHERE route_id = ($1) AND deleted_at IS NULL
+        AND climber_id IN (SELECT climber_id FROM climbs WHERE route_id = ($2) AND deleted_at IS NULL)"#,
+        id,
+        merge.target_id,
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    // Led pitches that the target doesn't have are dropped
+    merge.climbs = query!(
+        r#"UPDATE climbs SET route_id = ($2), version = climbs.version + 1,
+        led_pitches = ARRAY(SELECT pitch FROM unnest(climbs.led_pitches) pitch WHERE pitch <= target.number_pitches)
+        FROM routes target WHERE target.id = ($2) AND climbs.route_id = ($1) AND climbs.deleted_at IS NULL"#,
+        id,
+        merge.target_id,
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    merge.photos = query!(
+        "UPDATE photos SET route_id = ($2) WHERE route_id = ($1)",
+        id,
+        merge.target_id,
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    // A climber with both routes on their ticklist keeps their entry for the target
+    merge.ticklist_entries = query!(
+        r#"UPDATE ticklist_entries SET route_id = ($2) WHERE route_id = ($1)
+        AND climber_id NOT IN (SELECT climber_id FROM ticklist_entries WHERE route_id = ($2))"#,
+        id,
+        merge.target_id,
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    query!("DELETE FROM ticklist_entries WHERE route_id = ($1)", id)
+        .execute(&mut transaction)
+        .await?;
+    merge.comments = query!(
+        "UPDATE comments SET route_id = ($2) WHERE route_id = ($1)",
+        id,
+        merge.target_id,
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    merge.condition_reports = query!(
+        "UPDATE condition_reports SET route_id = ($2) WHERE route_id = ($1)",
+        id,
+        merge.target_id,
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    // The attempts on a problem follow it through the foreign key. A competition that has both
+    // routes as problems keeps them apart.
+    merge.competition_problems = query!(
+        r#"UPDATE competition_problems SET route_id = ($2) WHERE route_id = ($1)
+        AND competition_id NOT IN (SELECT competition_id FROM competition_problems WHERE route_id = ($2))"#,
+        id,
+        merge.target_id,
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    query!(
+        "UPDATE routes SET merged_into = ($2) WHERE merged_into = ($1)",
+        id,
+        merge.target_id,
+    )
+    .execute(&mut transaction)
+    .await?;
+    query!(
+        r#"UPDATE routes SET deleted_at = current_timestamp, merged_into = ($2), version = version + 1
+        WHERE id = ($1)"#,
+        id,
+        merge.target_id,
+    )
+    .execute(&mut transaction)
+    .await?;
+    query!(
+        "UPDATE routes SET version = version + 1 WHERE id = ($1)",
+        merge.target_id,
+    )
+    .execute(&mut transaction)
+    .await?;
+    let target_after = select_route(&mut transaction, merge.target_id)
+        .await?
+        .ok_or(DatabaseError::RouteNotFound)?;
+    record_revision(
+        &mut transaction,
+        id,
+        editor_id,
+        RevisionAction::Merge,
+        Some(&route),
+        &route,
+    )
+    .await?;
+    record_revision(
+        &mut transaction,
+        merge.target_id,
+        editor_id,
+        RevisionAction::Merge,
+        Some(&target_before),
+        &target_after,
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(Ok(merge))
+}
+
+/// Merge a duplicate route into the `target_id` route, which only a moderator (the `editor_id`)
+/// can do. Its pitches and approach waypoints are only moved if the target has none.
+#[post("/routes/{id}/merge")]
+async fn merge_route(
+    path: web::Path<i32>,
+    json: web::Json<RouteMerge>,
+    editor: web::Query<RouteEditor>,
+) -> impl Responder {
+    let id = path.into_inner();
+    if json.target_id == id {
+        return HttpResponse::BadRequest()
+            .body(DatabaseError::CannotMergeRouteIntoItself.to_string());
+    }
+    if let Ok(mut conn) = conn().await {
+        if let Some(response) = check_moderator(&mut conn, editor.editor_id).await {
+            return response;
+        }
+        match merge_routes(&mut conn, id, json.into_inner(), editor.editor_id).await {
+            Ok(Ok(merge)) => HttpResponse::Ok().json(merge),
+            Ok(Err(response)) => response,
+            Err(_) => {
+                error!("UPDATE query failed in merge_route()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in merge_route()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Set whether a climber is a moderator, which only a moderator can do
+async fn set_moderator(id: i32, is_moderator: bool, moderator_id: Option<i32>) -> HttpResponse {
+    if let Ok(mut conn) = conn().await {
+        if let Some(response) = check_moderator(&mut conn, moderator_id).await {
+            return response;
+        }
+        match query!(
+            "UPDATE climbers SET is_moderator = ($2) WHERE id = ($1) AND deleted_at IS NULL",
+            id,
+            is_moderator,
+        )
+        .execute(&mut conn)
+        .await
+        {
+            Ok(query_result) if query_result.rows_affected() == 0 => {
+                HttpResponse::NotFound().finish()
+            }
+            Ok(_) => HttpResponse::NoContent().finish(),
+            Err(_) => {
+                error!("UPDATE query failed in set_moderator()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in set_moderator()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Make a climber a moderator
+#[put("/moderators/{climber_id}")]
+async fn add_moderator(path: web::Path<i32>, moderator: web::Query<Moderator>) -> impl Responder {
+    set_moderator(path.into_inner(), true, moderator.moderator_id).await
+}
+
+/// Take away a climber's moderator role
+#[delete("/moderators/{climber_id}")]
+async fn remove_moderator(
+    path: web::Path<i32>,
+    moderator: web::Query<Moderator>,
+) -> impl Responder {
+    set_moderator(path.into_inner(), false, moderator.moderator_id).await
+}
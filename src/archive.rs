@@ -0,0 +1,34 @@
+use crate::error::DatabaseError;
+use crate::pg::conn;
+use log::{error, info};
+/// Background job that archives gym routes once their strip date has passed
+use sqlx::{self, postgres::PgConnection, query};
+use std::time::Duration;
+
+const ARCHIVE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Archive every route whose strip date is today or earlier, returning the number of routes
+/// archived. Climbs logged on them are left alone.
+pub async fn archive_stripped_routes(conn: &mut PgConnection) -> Result<u64, DatabaseError> {
+    Ok(query!(
+        "UPDATE routes SET archived_at = current_timestamp, version = version + 1 WHERE strip_date <= current_date AND archived_at IS NULL AND deleted_at IS NULL",
+    )
+    .execute(conn)
+    .await?
+    .rows_affected())
+}
+
+pub async fn archive_job() {
+    let mut interval = tokio::time::interval(ARCHIVE_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Ok(mut conn) = conn().await {
+            match archive_stripped_routes(&mut conn).await {
+                Ok(routes_archived) => info!("Archived {routes_archived} stripped routes"),
+                Err(_) => error!("UPDATE query failed in archive_job()"),
+            }
+        } else {
+            error!("Failed to connect to the database in archive_job()");
+        }
+    }
+}
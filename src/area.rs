@@ -0,0 +1,21 @@
+/// module for structs related to Area, mostly handled by the API endpoint /areas
+use serde::{Deserialize, Serialize};
+
+/// A crag, wall or region that routes are in. Areas nest, so a route's area can be inside any
+/// number of larger ones.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct Area {
+    pub id: Option<i32>,
+    pub name: String,
+    pub parent_id: Option<i32>,
+}
+
+impl Area {
+    pub fn new(id: Option<i32>, name: String, parent_id: Option<i32>) -> Self {
+        Self {
+            id,
+            name,
+            parent_id,
+        }
+    }
+}
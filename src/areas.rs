@@ -0,0 +1,84 @@
+use crate::area::Area;
+use crate::error::DatabaseError;
+use crate::pg::conn;
+use actix_web::{get, http::header, post, web, HttpResponse, Responder};
+use log::error;
+/// Functions for the API endpoint /areas
+use sqlx::{self, query};
+
+#[get("")]
+async fn get_areas() -> impl Responder {
+    if let Ok(mut conn) = conn().await {
+        if let Ok(query_result) = query!("SELECT id, name, parent_id FROM areas ORDER BY name")
+            .fetch_all(&mut conn)
+            .await
+        {
+            let areas = query_result
+                .into_iter()
+                .map(|record| Area::new(Some(record.id), record.name, record.parent_id))
+                .collect::<Vec<Area>>();
+            HttpResponse::Ok().json(areas)
+        } else {
+            error!("SELECT query failed in get_areas()");
+            HttpResponse::BadGateway().finish()
+        }
+    } else {
+        error!("Failed to connect to the database in get_areas()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+#[post("")]
+async fn add_new_area(json: web::Json<Area>) -> impl Responder {
+    if let Ok(mut conn) = conn().await {
+        match query!(
+            r#"INSERT INTO areas (name, parent_id)
+            SELECT $1, $2 WHERE $2::int4 IS NULL OR EXISTS (SELECT 1 FROM areas WHERE id = ($2))
+            RETURNING id"#,
+            json.0.name,
+            json.0.parent_id,
+        )
+        .fetch_optional(&mut conn)
+        .await
+        {
+            Ok(Some(query_result)) => {
+                let mut area = json.into_inner();
+                area.id = Some(query_result.id);
+                HttpResponse::Created()
+                    .insert_header((header::LOCATION, format!("/areas/{}", query_result.id)))
+                    .json(area)
+            }
+            Ok(None) => HttpResponse::BadRequest().body(DatabaseError::AreaNotFound.to_string()),
+            Err(_) => {
+                error!("INSERT query failed in add_new_area()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in add_new_area()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+#[get("/{id}")]
+async fn get_area_by_id(path: web::Path<i32>) -> impl Responder {
+    let id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match query!("SELECT id, name, parent_id FROM areas WHERE id = ($1)", id)
+            .fetch_optional(&mut conn)
+            .await
+        {
+            Ok(Some(record)) => {
+                HttpResponse::Ok().json(Area::new(Some(record.id), record.name, record.parent_id))
+            }
+            Ok(None) => HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in get_area_by_id()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in get_area_by_id()");
+        HttpResponse::BadGateway().finish()
+    }
+}
@@ -1,5 +1,21 @@
 use serde::{Deserialize, Serialize};
-use sqlx::types::chrono::NaiveDate;
+use sqlx::types::chrono::{NaiveDate, NaiveDateTime};
+use std::fmt::Display;
+
+use crate::error::DatabaseError;
+use crate::page::Page;
+
+/// Longest a review can be, in characters
+pub const MAX_REVIEW_LENGTH: usize = 1000;
+
+/// Check that the text of a review isn't too long
+pub fn check_review_length(review: &str) -> Result<(), DatabaseError> {
+    if review.chars().count() > MAX_REVIEW_LENGTH {
+        Err(DatabaseError::ReviewTooLong)
+    } else {
+        Ok(())
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Climb {
@@ -8,6 +24,10 @@ pub struct Climb {
     pub route_id: i32,
     #[serde(flatten)]
     pub review: Review,
+    /// Whether a moderator has hidden the review, in which case only its climber sees it. Ignored
+    /// on writes.
+    #[serde(default)]
+    pub hidden: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -39,6 +59,9 @@ pub struct Review {
     pub rating: Rating,
     pub review: String,
     pub completion_date: NaiveDate,
+    /// Numbers (starting from 1) of the pitches the climber led
+    #[serde(default)]
+    pub led_pitches: Vec<i32>,
 }
 
 impl Review {
@@ -47,6 +70,139 @@ impl Review {
             rating: rating.into(),
             review,
             completion_date,
+            led_pitches: Vec::new(),
         }
     }
 }
+
+pub fn check_led_pitches(led_pitches: &[i32], number_pitches: i32) -> Result<(), DatabaseError> {
+    if led_pitches
+        .iter()
+        .all(|pitch| (1..=number_pitches).contains(pitch))
+    {
+        Ok(())
+    } else {
+        Err(DatabaseError::LedPitchNotOnRoute)
+    }
+}
+
+/// Body of PATCH /climbers/{climber_id}/{route_id} as a JSON Merge Patch. Fields that are missing
+/// (or null) are left unchanged, since every field of a Review is required.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ReviewPatch {
+    pub rating: Option<Rating>,
+    pub review: Option<String>,
+    pub completion_date: Option<NaiveDate>,
+    pub led_pitches: Option<Vec<i32>>,
+}
+
+impl ReviewPatch {
+    pub fn check_not_empty(&self) -> Result<(), DatabaseError> {
+        if self.rating.is_none()
+            && self.review.is_none()
+            && self.completion_date.is_none()
+            && self.led_pitches.is_none()
+        {
+            Err(DatabaseError::UpdateReviewAllNone)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Order of a listing of reviews. Ties go to the newest review.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum ReviewSort {
+    #[default]
+    Newest,
+    /// Highest rated first
+    Rating,
+    /// Most marked helpful first
+    Helpful,
+    /// Best match for the search first. Listings that aren't searches are newest first.
+    Relevance,
+}
+
+impl Display for ReviewSort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let display_str = match self {
+            Self::Newest => "newest",
+            Self::Rating => "rating",
+            Self::Helpful => "helpful",
+            Self::Relevance => "relevance",
+        };
+        write!(f, "{}", display_str)
+    }
+}
+
+/// Query parameters for GET /routes/{id}/reviews
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+pub struct RouteReviewQuery {
+    pub viewer_id: Option<i32>,
+    #[serde(default)]
+    pub sort: ReviewSort,
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+impl RouteReviewQuery {
+    pub fn page(&self) -> Page {
+        Page {
+            page: self.page,
+            per_page: self.per_page,
+        }
+    }
+}
+
+/// Query parameters for GET /reviews, a full-text search of every review
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+pub struct ReviewSearchQuery {
+    /// Words to search for, in the syntax of a web search: "quoted phrases", `or` and `-excluded`
+    pub q: String,
+    pub viewer_id: Option<i32>,
+    pub sort: Option<ReviewSort>,
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+impl ReviewSearchQuery {
+    /// Best matches first unless another order is asked for
+    pub fn sort(&self) -> ReviewSort {
+        self.sort.unwrap_or(ReviewSort::Relevance)
+    }
+
+    pub fn page(&self) -> Page {
+        Page {
+            page: self.page,
+            per_page: self.per_page,
+        }
+    }
+}
+
+/// A review in a listing, along with who wrote it about which route and how many climbers found
+/// it helpful
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PublishedReview {
+    #[serde(flatten)]
+    pub climb: Climb,
+    pub username: String,
+    pub route_name: String,
+    pub created_at: NaiveDateTime,
+    pub helpful_count: i64,
+    /// Whether the viewer marked the review helpful
+    pub viewer_found_helpful: bool,
+    /// The parts of the review that matched a search, HTML-escaped, with the matching words in
+    /// <b></b>
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headline: Option<String>,
+}
+
+/// Body of GET /routes/{id}/reviews and GET /reviews
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Reviews {
+    pub reviews: Vec<PublishedReview>,
+    pub page: i64,
+    pub per_page: i64,
+    /// The next page number, if there are more reviews
+    pub next_page: Option<i64>,
+}
@@ -1,18 +1,601 @@
+use chrono::Datelike;
 use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::{NaiveDate, NaiveDateTime};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::climb::Climb;
+use crate::error::DatabaseError;
+use crate::route::{DifficultyRating, Discipline, GradeScale, Route};
+
+/// Who can see something a climber shares
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum Visibility {
+    #[default]
+    Public,
+    Followers,
+    Private,
+}
+
+impl Visibility {
+    /// Whether a viewer can see something with this visibility, given whether they are the
+    /// climber it belongs to and whether they follow that climber
+    pub fn visible_to(&self, is_self: bool, is_follower: bool) -> bool {
+        match self {
+            Self::Public => true,
+            Self::Followers => is_self || is_follower,
+            Self::Private => is_self,
+        }
+    }
+}
+
+impl Display for Visibility {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let display_str = match self {
+            Self::Public => "public",
+            Self::Followers => "followers",
+            Self::Private => "private",
+        };
+        write!(f, "{}", display_str)
+    }
+}
+
+impl FromStr for Visibility {
+    type Err = DatabaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "public" => Ok(Self::Public),
+            "followers" => Ok(Self::Followers),
+            "private" => Ok(Self::Private),
+            _ => Err(DatabaseError::FailedParseVisibility),
+        }
+    }
+}
+
+/// Optional details a climber can add to their profile. Fields hidden from whoever is looking
+/// are left out.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Profile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bio: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub home_area_id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub climbing_since: Option<NaiveDate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preferred_grade_scale: Option<GradeScale>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avatar_url: Option<String>,
+}
+
+/// Who can see each part of a climber's profile, their logbook (including their reviews and
+/// climb photos), and their activity in other climbers' feeds
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Default)]
+#[serde(default)]
+pub struct Privacy {
+    pub display_name: Visibility,
+    pub bio: Visibility,
+    pub home_area: Visibility,
+    pub climbing_since: Visibility,
+    pub preferred_grade_scale: Visibility,
+    pub avatar: Visibility,
+    pub logbook: Visibility,
+    pub activity: Visibility,
+}
 
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub struct Climber {
     pub id: Option<i32>,
     pub username: String,
+    #[serde(flatten)]
+    pub profile: Profile,
+    #[serde(default)]
+    pub privacy: Privacy,
 }
 
 impl Climber {
     pub fn new(id: Option<i32>, username: String) -> Self {
-        Self { id, username }
+        Self {
+            id,
+            username,
+            profile: Profile::default(),
+            privacy: Privacy::default(),
+        }
     }
+
+    /// Leave out the profile fields that a viewer isn't allowed to see
+    pub fn hide_from(&mut self, is_self: bool, is_follower: bool) {
+        let privacy = self.privacy;
+        let profile = &mut self.profile;
+        let visible = |visibility: Visibility| visibility.visible_to(is_self, is_follower);
+        if !visible(privacy.display_name) {
+            profile.display_name = None;
+        }
+        if !visible(privacy.bio) {
+            profile.bio = None;
+        }
+        if !visible(privacy.home_area) {
+            profile.home_area_id = None;
+        }
+        if !visible(privacy.climbing_since) {
+            profile.climbing_since = None;
+        }
+        if !visible(privacy.preferred_grade_scale) {
+            profile.preferred_grade_scale = None;
+        }
+        if !visible(privacy.avatar) {
+            profile.avatar_url = None;
+        }
+    }
+}
+
+/// Query parameters for endpoints that honor privacy settings. Without a `viewer_id`, only
+/// public fields are shown.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+pub struct Viewer {
+    pub viewer_id: Option<i32>,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub struct NumberClimbers {
     pub number_climbers: i64,
 }
+
+/// One entry of a climber's logbook: a climb along with the route that was climbed
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LogbookEntry {
+    pub climb: Climb,
+    pub route: Route,
+}
+
+/// Totals for the climbs of one discipline in a climber's logbook
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct DisciplineSummary {
+    pub discipline: Discipline,
+    pub number_climbs: i64,
+    /// The hardest grade on each scale the discipline's climbs are graded on, since grades from
+    /// different scales can't be compared
+    pub hardest: Vec<DifficultyRating>,
+    pub average_rating: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Logbook {
+    pub entries: Vec<LogbookEntry>,
+    pub disciplines: Vec<DisciplineSummary>,
+}
+
+impl Logbook {
+    pub fn new(entries: Vec<LogbookEntry>) -> Self {
+        let mut by_discipline: BTreeMap<Discipline, Vec<&LogbookEntry>> = BTreeMap::new();
+        for entry in entries.iter() {
+            by_discipline
+                .entry(entry.route.discipline)
+                .or_default()
+                .push(entry);
+        }
+        let disciplines = by_discipline
+            .into_iter()
+            .map(|(discipline, entries)| {
+                let mut hardest: BTreeMap<GradeScale, &DifficultyRating> = BTreeMap::new();
+                for entry in entries.iter() {
+                    let difficulty = &entry.route.difficulty;
+                    let scale_hardest = hardest.entry(difficulty.scale()).or_insert(difficulty);
+                    *scale_hardest = (*scale_hardest).max(difficulty);
+                }
+                let total_rating: i32 = entries
+                    .iter()
+                    .map(|entry| entry.climb.review.rating.i32())
+                    .sum();
+                DisciplineSummary {
+                    discipline,
+                    number_climbs: entries.len() as i64,
+                    hardest: hardest.into_values().cloned().collect(),
+                    average_rating: total_rating as f64 / entries.len() as f64,
+                }
+            })
+            .collect();
+        Self {
+            entries,
+            disciplines,
+        }
+    }
+}
+
+/// Number of climbs of one grade in a grade pyramid
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct GradeCount {
+    pub difficulty: DifficultyRating,
+    pub number_climbs: i64,
+}
+
+/// Climbs of one discipline by grade, hardest first
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct GradePyramid {
+    pub discipline: Discipline,
+    pub grades: Vec<GradeCount>,
+}
+
+/// A single climb picked out of a logbook, such as a climber's hardest send
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct AscentSummary {
+    pub discipline: Discipline,
+    pub difficulty: DifficultyRating,
+    pub route_id: i32,
+    pub route_name: String,
+    pub completion_date: NaiveDate,
+}
+
+impl From<&LogbookEntry> for AscentSummary {
+    fn from(entry: &LogbookEntry) -> Self {
+        Self {
+            discipline: entry.route.discipline,
+            difficulty: entry.route.difficulty.clone(),
+            route_id: entry.climb.route_id,
+            route_name: entry.route.name.clone(),
+            completion_date: entry.climb.review.completion_date,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct MonthlyAscents {
+    pub year: i32,
+    pub month: u32,
+    pub number_climbs: i64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct YearlyAscents {
+    pub year: i32,
+    pub number_climbs: i64,
+}
+
+/// Body of GET /climbers/{id}/stats. Hardest sends are one per discipline and grade scale, and ties
+/// go to the climb that came first. Everything by date is oldest first.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct ClimberStats {
+    pub number_climbs: i64,
+    pub pyramids: Vec<GradePyramid>,
+    pub hardest_sends: Vec<AscentSummary>,
+    pub ascents_per_month: Vec<MonthlyAscents>,
+    pub ascents_per_year: Vec<YearlyAscents>,
+    pub unique_areas: i64,
+    pub average_rating: Option<f64>,
+    /// The first climb of each grade in each discipline
+    pub first_ascents_of_grade: Vec<AscentSummary>,
+}
+
+impl ClimberStats {
+    pub fn new(entries: &[LogbookEntry]) -> Self {
+        let mut chronological = entries.iter().collect::<Vec<&LogbookEntry>>();
+        chronological.sort_by_key(|entry| (entry.climb.review.completion_date, entry.climb.id));
+
+        let mut by_discipline: BTreeMap<Discipline, Vec<&LogbookEntry>> = BTreeMap::new();
+        let mut firsts: BTreeMap<(Discipline, DifficultyRating), &LogbookEntry> = BTreeMap::new();
+        let mut per_month: BTreeMap<(i32, u32), i64> = BTreeMap::new();
+        let mut per_year: BTreeMap<i32, i64> = BTreeMap::new();
+        for entry in chronological.iter() {
+            let discipline = entry.route.discipline;
+            let date = entry.climb.review.completion_date;
+            by_discipline.entry(discipline).or_default().push(entry);
+            firsts
+                .entry((discipline, entry.route.difficulty.clone()))
+                .or_insert(entry);
+            *per_month.entry((date.year(), date.month())).or_default() += 1;
+            *per_year.entry(date.year()).or_default() += 1;
+        }
+
+        let mut pyramids = Vec::new();
+        let mut hardest_sends = Vec::new();
+        for (discipline, entries) in by_discipline {
+            let mut grades: BTreeMap<DifficultyRating, i64> = BTreeMap::new();
+            for entry in entries.iter() {
+                *grades.entry(entry.route.difficulty.clone()).or_default() += 1;
+            }
+            pyramids.push(GradePyramid {
+                discipline,
+                grades: grades
+                    .into_iter()
+                    .rev()
+                    .map(|(difficulty, number_climbs)| GradeCount {
+                        difficulty,
+                        number_climbs,
+                    })
+                    .collect(),
+            });
+            // The climbs are oldest first, so only a harder climb replaces the one kept
+            let mut hardest: BTreeMap<GradeScale, &LogbookEntry> = BTreeMap::new();
+            for entry in entries.iter() {
+                let kept = hardest
+                    .entry(entry.route.difficulty.scale())
+                    .or_insert(entry);
+                if entry.route.difficulty > kept.route.difficulty {
+                    *kept = entry;
+                }
+            }
+            hardest_sends.extend(hardest.into_values().map(AscentSummary::from));
+        }
+
+        let mut first_ascents_of_grade = firsts
+            .into_values()
+            .map(AscentSummary::from)
+            .collect::<Vec<AscentSummary>>();
+        first_ascents_of_grade.sort_by_key(|ascent| ascent.completion_date);
+
+        let total_rating: i32 = entries
+            .iter()
+            .map(|entry| entry.climb.review.rating.i32())
+            .sum();
+        Self {
+            number_climbs: entries.len() as i64,
+            pyramids,
+            hardest_sends,
+            ascents_per_month: per_month
+                .into_iter()
+                .map(|((year, month), number_climbs)| MonthlyAscents {
+                    year,
+                    month,
+                    number_climbs,
+                })
+                .collect(),
+            ascents_per_year: per_year
+                .into_iter()
+                .map(|(year, number_climbs)| YearlyAscents {
+                    year,
+                    number_climbs,
+                })
+                .collect(),
+            unique_areas: entries
+                .iter()
+                .filter_map(|entry| entry.route.metadata.area_id)
+                .collect::<BTreeSet<i32>>()
+                .len() as i64,
+            average_rating: (!entries.is_empty())
+                .then(|| total_rating as f64 / entries.len() as f64),
+            first_ascents_of_grade,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum FeedEventKind {
+    /// A climb logged without a written review
+    Ascent,
+    /// A climb logged with a written review
+    Review,
+    /// A new route the climber set
+    RouteSet,
+}
+
+/// Something a followed climber did. Ascents and reviews come with their climb.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FeedEvent {
+    pub kind: FeedEventKind,
+    pub climber_id: i32,
+    pub username: String,
+    pub route_id: i32,
+    pub route_name: String,
+    pub difficulty: DifficultyRating,
+    pub climb: Option<Climb>,
+    pub occurred_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Feed {
+    pub events: Vec<FeedEvent>,
+    pub page: i64,
+    pub per_page: i64,
+    /// The next page number, if there are more events
+    pub next_page: Option<i64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::climb::Review;
+    use chrono::NaiveDate;
+
+    fn test_entry(
+        discipline: Discipline,
+        difficulty: DifficultyRating,
+        rating: i32,
+    ) -> LogbookEntry {
+        LogbookEntry {
+            climb: Climb {
+                id: None,
+                climber_id: 1,
+                route_id: 1,
+                review: Review::new(
+                    rating,
+                    String::new(),
+                    NaiveDate::from_ymd_opt(2023, 4, 2).unwrap(),
+                ),
+                hidden: false,
+            },
+            route: Route::new(
+                None,
+                "funky monkey".to_string(),
+                discipline,
+                difficulty,
+                123.45,
+                52.310,
+            ),
+        }
+    }
+
+    #[test]
+    fn test_logbook_discipline_summaries() {
+        let logbook = Logbook::new(vec![
+            test_entry(Discipline::Sport, DifficultyRating::Rating510, 4),
+            test_entry(Discipline::Boulder, DifficultyRating::RatingV2, 6),
+            test_entry(Discipline::Sport, DifficultyRating::Rating512, 8),
+            test_entry(Discipline::Mixed, DifficultyRating::RatingWI5, 6),
+            test_entry(Discipline::Mixed, DifficultyRating::RatingM4, 6),
+            test_entry(Discipline::Mixed, DifficultyRating::RatingWI3, 6),
+        ]);
+        assert_eq!(
+            logbook.disciplines,
+            vec![
+                DisciplineSummary {
+                    discipline: Discipline::Sport,
+                    number_climbs: 2,
+                    hardest: vec![DifficultyRating::Rating512],
+                    average_rating: 6.0,
+                },
+                DisciplineSummary {
+                    discipline: Discipline::Boulder,
+                    number_climbs: 1,
+                    hardest: vec![DifficultyRating::RatingV2],
+                    average_rating: 6.0,
+                },
+                // Mixed climbs graded on the water ice scale aren't compared with the others
+                DisciplineSummary {
+                    discipline: Discipline::Mixed,
+                    number_climbs: 3,
+                    hardest: vec![DifficultyRating::RatingWI5, DifficultyRating::RatingM4],
+                    average_rating: 6.0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_climber_stats() {
+        let mut entries = vec![
+            test_entry(Discipline::Sport, DifficultyRating::Rating510, 4),
+            test_entry(Discipline::Sport, DifficultyRating::Rating512, 8),
+            test_entry(Discipline::Sport, DifficultyRating::Rating510, 6),
+            test_entry(Discipline::Boulder, DifficultyRating::RatingV2, 6),
+            test_entry(Discipline::Mixed, DifficultyRating::RatingWI5, 6),
+            test_entry(Discipline::Mixed, DifficultyRating::RatingM4, 6),
+        ];
+        for (entry, (date, area_id)) in entries.iter_mut().zip([
+            (NaiveDate::from_ymd_opt(2022, 12, 30), Some(1)),
+            (NaiveDate::from_ymd_opt(2023, 1, 5), Some(1)),
+            (NaiveDate::from_ymd_opt(2023, 1, 20), Some(2)),
+            (NaiveDate::from_ymd_opt(2023, 1, 2), None),
+            (NaiveDate::from_ymd_opt(2023, 2, 1), Some(2)),
+            (NaiveDate::from_ymd_opt(2023, 2, 2), Some(2)),
+        ]) {
+            entry.climb.review.completion_date = date.unwrap();
+            entry.route.metadata.area_id = area_id;
+        }
+        let stats = ClimberStats::new(&entries);
+        assert_eq!(stats.number_climbs, 6);
+        assert_eq!(
+            stats.pyramids[0],
+            GradePyramid {
+                discipline: Discipline::Sport,
+                grades: vec![
+                    GradeCount {
+                        difficulty: DifficultyRating::Rating512,
+                        number_climbs: 1,
+                    },
+                    GradeCount {
+                        difficulty: DifficultyRating::Rating510,
+                        number_climbs: 2,
+                    },
+                ],
+            }
+        );
+        assert_eq!(
+            stats
+                .hardest_sends
+                .iter()
+                .map(|ascent| &ascent.difficulty)
+                .collect::<Vec<&DifficultyRating>>(),
+            vec![
+                &DifficultyRating::Rating512,
+                &DifficultyRating::RatingV2,
+                &DifficultyRating::RatingWI5,
+                &DifficultyRating::RatingM4
+            ]
+        );
+        assert_eq!(
+            stats.ascents_per_year,
+            vec![
+                YearlyAscents {
+                    year: 2022,
+                    number_climbs: 1,
+                },
+                YearlyAscents {
+                    year: 2023,
+                    number_climbs: 5,
+                },
+            ]
+        );
+        assert_eq!(stats.ascents_per_month.len(), 3);
+        assert_eq!(stats.unique_areas, 2);
+        assert_eq!(stats.average_rating, Some(6.0));
+        // the second 5.10 isn't a first
+        assert_eq!(
+            stats
+                .first_ascents_of_grade
+                .iter()
+                .map(|ascent| ascent.completion_date.to_string())
+                .collect::<Vec<String>>(),
+            vec![
+                "2022-12-30",
+                "2023-01-02",
+                "2023-01-05",
+                "2023-02-01",
+                "2023-02-02"
+            ]
+        );
+        assert_eq!(ClimberStats::new(&[]).average_rating, None);
+    }
+
+    #[test]
+    fn test_visibility() {
+        assert!(Visibility::Public.visible_to(false, false));
+        assert!(!Visibility::Followers.visible_to(false, false));
+        assert!(Visibility::Followers.visible_to(false, true));
+        assert!(!Visibility::Private.visible_to(false, true));
+        assert!(Visibility::Private.visible_to(true, false));
+        for visibility in [
+            Visibility::Public,
+            Visibility::Followers,
+            Visibility::Private,
+        ] {
+            assert_eq!(
+                Visibility::from_str(&visibility.to_string()).unwrap(),
+                visibility
+            );
+        }
+    }
+
+    #[test]
+    fn test_hide_profile_fields() {
+        let mut climber = Climber::new(Some(1), "crimpy".to_string());
+        climber.profile = Profile {
+            display_name: Some("Crimpy McCrimpface".to_string()),
+            bio: Some("Slab hater".to_string()),
+            climbing_since: NaiveDate::from_ymd_opt(2015, 5, 1),
+            ..Default::default()
+        };
+        climber.privacy.bio = Visibility::Followers;
+        climber.privacy.climbing_since = Visibility::Private;
+
+        let mut seen_by_follower = Climber::new(Some(1), "crimpy".to_string());
+        seen_by_follower.profile = climber.profile.clone();
+        seen_by_follower.privacy = climber.privacy;
+        seen_by_follower.hide_from(false, true);
+        assert_eq!(seen_by_follower.profile.bio, climber.profile.bio);
+        assert_eq!(seen_by_follower.profile.climbing_since, None);
+
+        climber.hide_from(false, false);
+        assert_eq!(
+            climber.profile,
+            Profile {
+                display_name: Some("Crimpy McCrimpface".to_string()),
+                ..Default::default()
+            }
+        );
+    }
+}
@@ -1,31 +1,151 @@
-use crate::climb::{Climb, Review};
-use crate::climber::{Climber, NumberClimbers};
+use crate::climb::{check_led_pitches, Climb, Rating, Review, ReviewPatch};
+use crate::climber::{
+    Climber, ClimberStats, Feed, FeedEvent, FeedEventKind, Logbook, LogbookEntry, NumberClimbers,
+    Privacy, Profile, Viewer, Visibility,
+};
+use crate::error::DatabaseError;
+use crate::etag::{etag, if_match_versions, not_modified};
+use crate::page::Page;
 use crate::pg::conn;
-use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+use crate::recommendation::{
+    recommend, GradeEnvelope, OtherRating, RecommendationQuery, Recommendations, MAX_CANDIDATES,
+};
+use crate::route::{DifficultyRating, Discipline, GradeScale, Route, RouteMetadata};
+use crate::routes::select_routes;
+use actix_web::{
+    delete, get, http::header, patch, post, put, web, HttpRequest, HttpResponse, Responder,
+};
 use log::error;
-use sqlx::{self, query};
+use sqlx::{self, postgres::PgConnection, query};
+use std::str::FromStr;
+
+/// Filters for select_climbers. A `limit` of None returns every matching climber.
+#[derive(Default)]
+struct ClimberFilter {
+    id: Option<i32>,
+    /// Only climbers followed by this climber
+    followed_by: Option<i32>,
+    /// Only climbers following this climber
+    following: Option<i32>,
+    limit: Option<i64>,
+}
+
+/// Shared SELECT behind every endpoint that returns climbers, newest first, along with their
+/// versions. Profile fields the viewer isn't allowed to see are left out.
+async fn select_climbers(
+    conn: &mut PgConnection,
+    filter: &ClimberFilter,
+    viewer_id: Option<i32>,
+) -> Result<Vec<(Climber, i32)>, DatabaseError> {
+    query!(
+        r#"SELECT id, username as "username!", version, display_name, bio, home_area_id, climbing_since, preferred_grade_scale, avatar_url,
+        display_name_visibility, bio_visibility, home_area_visibility, climbing_since_visibility, preferred_grade_scale_visibility, avatar_visibility, logbook_visibility, activity_visibility,
+        EXISTS (SELECT 1 FROM follows WHERE follower_id = ($5) AND followed_id = climbers.id) as "viewer_follows!"
+        FROM climbers
+        WHERE deleted_at IS NULL
+        AND ($1::int4 IS NULL OR id = $1)
+        AND ($2::int4 IS NULL OR id IN (SELECT followed_id FROM follows WHERE follower_id = $2))
+        AND ($3::int4 IS NULL OR id IN (SELECT follower_id FROM follows WHERE followed_id = $3))
+        ORDER BY created_at DESC LIMIT ($4)"#,
+        filter.id,
+        filter.followed_by,
+        filter.following,
+        filter.limit,
+        viewer_id,
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|record| {
+        let mut climber = Climber::new(Some(record.id), record.username);
+        climber.profile = Profile {
+            display_name: record.display_name,
+            bio: record.bio,
+            home_area_id: record.home_area_id,
+            climbing_since: record.climbing_since,
+            preferred_grade_scale: record
+                .preferred_grade_scale
+                .as_deref()
+                .map(GradeScale::from_str)
+                .transpose()?,
+            avatar_url: record.avatar_url,
+        };
+        climber.privacy = Privacy {
+            display_name: Visibility::from_str(&record.display_name_visibility)?,
+            bio: Visibility::from_str(&record.bio_visibility)?,
+            home_area: Visibility::from_str(&record.home_area_visibility)?,
+            climbing_since: Visibility::from_str(&record.climbing_since_visibility)?,
+            preferred_grade_scale: Visibility::from_str(&record.preferred_grade_scale_visibility)?,
+            avatar: Visibility::from_str(&record.avatar_visibility)?,
+            logbook: Visibility::from_str(&record.logbook_visibility)?,
+            activity: Visibility::from_str(&record.activity_visibility)?,
+        };
+        climber.hide_from(viewer_id == Some(record.id), record.viewer_follows);
+        Ok((climber, record.version))
+    })
+    .collect()
+}
+
+/// Whether a viewer can see a climber's logbook, reviews and climb photos. Ok(None) if the
+/// climber doesn't exist.
+pub async fn logbook_visible(
+    conn: &mut PgConnection,
+    climber_id: i32,
+    viewer_id: Option<i32>,
+) -> Result<Option<bool>, DatabaseError> {
+    match query!(
+        r#"SELECT logbook_visibility, EXISTS (SELECT 1 FROM follows WHERE follower_id = ($2) AND followed_id = climbers.id) as "viewer_follows!"
+        FROM climbers WHERE id = ($1) AND deleted_at IS NULL"#,
+        climber_id,
+        viewer_id,
+    )
+    .fetch_optional(conn)
+    .await?
+    {
+        Some(record) => Ok(Some(
+            Visibility::from_str(&record.logbook_visibility)?
+                .visible_to(viewer_id == Some(climber_id), record.viewer_follows),
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Check that a climber's home area exists, if they have one
+async fn check_home_area(
+    conn: &mut PgConnection,
+    profile: &Profile,
+) -> Result<Result<(), DatabaseError>, DatabaseError> {
+    if let Some(area_id) = profile.home_area_id {
+        if query!("SELECT id FROM areas WHERE id = ($1)", area_id)
+            .fetch_optional(conn)
+            .await?
+            .is_none()
+        {
+            return Ok(Err(DatabaseError::AreaNotFound));
+        }
+    }
+    Ok(Ok(()))
+}
 
 #[get("")]
-async fn get_recent_climbers(number_climbers: Option<web::Json<NumberClimbers>>) -> impl Responder {
+async fn get_recent_climbers(
+    number_climbers: Option<web::Json<NumberClimbers>>,
+    viewer: web::Query<Viewer>,
+) -> impl Responder {
     let number_of_climbers_to_request = match number_climbers {
         Some(n) => n.0.number_climbers,
         None => 5,
     };
 
     if let Ok(mut conn) = conn().await {
-        if let Ok(query_result) = query!(
-            r#"SELECT id as "id!", username as "username!" FROM climbers ORDER BY created_at DESC LIMIT ($1)"#,
-            number_of_climbers_to_request,
-        )
-        .fetch_all(&mut conn)
-        .await
-        {
-            let climbers = query_result
-                .iter()
-                .map(|record| Climber {
-                    id: Some(record.id),
-                    username: record.username.to_owned(),
-                })
+        let filter = ClimberFilter {
+            limit: Some(number_of_climbers_to_request),
+            ..Default::default()
+        };
+        if let Ok(climbers) = select_climbers(&mut conn, &filter, viewer.viewer_id).await {
+            let climbers = climbers
+                .into_iter()
+                .map(|(climber, _)| climber)
                 .collect::<Vec<Climber>>();
             HttpResponse::Ok().json(climbers)
         } else {
@@ -41,37 +161,77 @@ async fn get_recent_climbers(number_climbers: Option<web::Json<NumberClimbers>>)
 #[post("")]
 async fn add_new_climber(json: web::Json<Climber>) -> impl Responder {
     if let Ok(mut conn) = conn().await {
-        if let Ok(_query_result) = query!(
-            "INSERT INTO climbers (username) VALUES ($1)",
+        match check_home_area(&mut conn, &json.profile).await {
+            Ok(Ok(())) => (),
+            Ok(Err(e)) => return HttpResponse::BadRequest().body(e.to_string()),
+            Err(_) => {
+                error!("SELECT query failed in add_new_climber()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        let (profile, privacy) = (&json.0.profile, &json.0.privacy);
+        if let Ok(query_result) = query!(
+            r#"INSERT INTO climbers (username, display_name, bio, home_area_id, climbing_since, preferred_grade_scale, avatar_url,
+            display_name_visibility, bio_visibility, home_area_visibility, climbing_since_visibility, preferred_grade_scale_visibility, avatar_visibility, logbook_visibility, activity_visibility)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15) RETURNING id"#,
             json.0.username,
+            profile.display_name,
+            profile.bio,
+            profile.home_area_id,
+            profile.climbing_since,
+            profile.preferred_grade_scale.map(|scale| scale.to_string()),
+            profile.avatar_url,
+            privacy.display_name.to_string(),
+            privacy.bio.to_string(),
+            privacy.home_area.to_string(),
+            privacy.climbing_since.to_string(),
+            privacy.preferred_grade_scale.to_string(),
+            privacy.avatar.to_string(),
+            privacy.logbook.to_string(),
+            privacy.activity.to_string(),
         )
-        .execute(&mut conn)
+        .fetch_one(&mut conn)
         .await
         {
-            HttpResponse::Ok()
+            let mut climber = json.into_inner();
+            climber.id = Some(query_result.id);
+            HttpResponse::Created()
+                .insert_header((header::LOCATION, format!("/climbers/{}", query_result.id)))
+                .json(climber)
         } else {
             error!("INSERT query failed in add_new_climber()");
-            HttpResponse::BadGateway()
+            HttpResponse::BadGateway().finish()
         }
     } else {
         error!("Failed to connect to the database in add_new_climber()");
-        HttpResponse::BadGateway()
+        HttpResponse::BadGateway().finish()
     }
 }
 
 #[get("/{id}")]
-async fn get_climber_recent_climbs(path: web::Path<i32>) -> impl Responder {
+async fn get_climber_recent_climbs(
+    path: web::Path<i32>,
+    request: HttpRequest,
+    viewer: web::Query<Viewer>,
+) -> impl Responder {
     let id = path.into_inner();
     if let Ok(mut conn) = conn().await {
-        if let Ok(query_result) = query!(
-            r#"SELECT id, username as "username!" FROM climbers WHERE id = ($1)"#,
-            id
-        )
-        .fetch_one(&mut conn)
-        .await
+        let filter = ClimberFilter {
+            id: Some(id),
+            ..Default::default()
+        };
+        if let Ok(Some((climber, version))) = select_climbers(&mut conn, &filter, viewer.viewer_id)
+            .await
+            .map(|climbers| climbers.into_iter().next())
         {
-            let climber = Climber::new(Some(query_result.id), query_result.username);
-            HttpResponse::Ok().json(climber)
+            if not_modified(&request, &etag(version)) {
+                return HttpResponse::NotModified()
+                    .insert_header(header::ETag(etag(version)))
+                    .finish();
+            }
+            HttpResponse::Ok()
+                .insert_header(header::ETag(etag(version)))
+                .json(climber)
         } else {
             error!("SELECT query failed in get_climber_recent_climbs()");
             HttpResponse::BadRequest().finish()
@@ -82,49 +242,614 @@ async fn get_climber_recent_climbs(path: web::Path<i32>) -> impl Responder {
     }
 }
 
-#[delete("/{id}")]
-async fn delete_climber(path: web::Path<i32>) -> impl Responder {
+/// Replace a climber's username, profile and privacy settings
+#[put("/{id}")]
+async fn update_climber(
+    path: web::Path<i32>,
+    request: HttpRequest,
+    json: web::Json<Climber>,
+) -> impl Responder {
     let id = path.into_inner();
+    let if_match = if_match_versions(&request);
     if let Ok(mut conn) = conn().await {
-        if query!(r#"DELETE FROM climbers WHERE id = ($1)"#, id,)
-            .execute(&mut conn)
-            .await
-            .is_ok()
+        match check_home_area(&mut conn, &json.profile).await {
+            Ok(Ok(())) => (),
+            Ok(Err(e)) => return HttpResponse::BadRequest().body(e.to_string()),
+            Err(_) => {
+                error!("SELECT query failed in update_climber()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        let (profile, privacy) = (&json.0.profile, &json.0.privacy);
+        match query!(
+            r#"UPDATE climbers SET username = $1, display_name = $2, bio = $3, home_area_id = $4, climbing_since = $5, preferred_grade_scale = $6, avatar_url = $7,
+            display_name_visibility = $8, bio_visibility = $9, home_area_visibility = $10, climbing_since_visibility = $11, preferred_grade_scale_visibility = $12,
+            avatar_visibility = $13, logbook_visibility = $14, activity_visibility = $15, version = version + 1
+            WHERE id = ($16) AND deleted_at IS NULL AND ($17::int4[] IS NULL OR version = ANY($17)) RETURNING version"#,
+            json.0.username,
+            profile.display_name,
+            profile.bio,
+            profile.home_area_id,
+            profile.climbing_since,
+            profile.preferred_grade_scale.map(|scale| scale.to_string()),
+            profile.avatar_url,
+            privacy.display_name.to_string(),
+            privacy.bio.to_string(),
+            privacy.home_area.to_string(),
+            privacy.climbing_since.to_string(),
+            privacy.preferred_grade_scale.to_string(),
+            privacy.avatar.to_string(),
+            privacy.logbook.to_string(),
+            privacy.activity.to_string(),
+            id,
+            if_match.as_deref(),
+        )
+        .fetch_optional(&mut conn)
+        .await
         {
-            HttpResponse::Ok()
+            Ok(Some(query_result)) => HttpResponse::NoContent()
+                .insert_header(header::ETag(etag(query_result.version)))
+                .finish(),
+            Ok(None) => climber_not_written(&mut conn, id).await,
+            Err(_) => {
+                error!("UPDATE query failed in update_climber()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in update_climber()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Every climb a climber has logged along with its route, most recent first. Reviews hidden by a
+/// moderator are only included when the climber is the viewer.
+async fn select_logbook_entries(
+    conn: &mut PgConnection,
+    id: i32,
+    viewer_id: Option<i32>,
+) -> Result<Vec<LogbookEntry>, DatabaseError> {
+    query!(
+        r#"SELECT climbs.id as "id!", climbs.rating as "rating!", climbs.review as "review!", climbs.completion_date as "completion_date!", climbs.led_pitches as "led_pitches!", climbs.hidden_at IS NOT NULL as "hidden!",
+        routes.id as route_id, routes.name as "name!", routes.discipline, routes.difficulty as "difficulty!", routes.latitude as "latitude!", routes.longitude as "longitude!", routes.number_pitches, routes.length_meters,
+        routes.bolts, routes.first_ascent_party, routes.first_ascent_date, routes.setter_id, routes.hold_color, routes.set_date, routes.strip_date,
+        routes.wall_id, routes.route_set_id, routes.area_id, routes.archived_at IS NOT NULL as "archived!"
+        FROM active_climbs climbs JOIN routes ON routes.id = climbs.route_id
+        WHERE climbs.climber_id = ($1) AND (climbs.hidden_at IS NULL OR climbs.climber_id = $2)
+        ORDER BY climbs.completion_date DESC, climbs.id DESC"#,
+        id,
+        viewer_id,
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|record| {
+        let mut review = Review::new(record.rating, record.review, record.completion_date);
+        review.led_pitches = record.led_pitches;
+        let mut route = Route::new(
+            Some(record.route_id),
+            record.name,
+            Discipline::from_str(&record.discipline)?,
+            DifficultyRating::from_str(&record.difficulty)?,
+            record.latitude,
+            record.longitude,
+        );
+        route.number_pitches = record.number_pitches;
+        route.length_meters = record.length_meters;
+        route.metadata = RouteMetadata {
+            area_id: record.area_id,
+            bolts: record.bolts,
+            first_ascent_party: record.first_ascent_party,
+            first_ascent_date: record.first_ascent_date,
+            setter_id: record.setter_id,
+            hold_color: record.hold_color,
+            set_date: record.set_date,
+            strip_date: record.strip_date,
+            wall_id: record.wall_id,
+            route_set_id: record.route_set_id,
+        };
+        route.archived = record.archived;
+        Ok(LogbookEntry {
+            climb: Climb {
+                id: Some(record.id),
+                climber_id: id,
+                route_id: record.route_id,
+                review,
+                hidden: record.hidden,
+            },
+            route,
+        })
+    })
+    .collect()
+}
+
+#[get("/{id}/logbook")]
+async fn get_climber_logbook(path: web::Path<i32>, viewer: web::Query<Viewer>) -> impl Responder {
+    let id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match logbook_visible(&mut conn, id, viewer.viewer_id).await {
+            Ok(Some(true)) => (),
+            Ok(Some(false)) => return HttpResponse::Forbidden().finish(),
+            Ok(None) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in get_climber_logbook()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        if let Ok(entries) = select_logbook_entries(&mut conn, id, viewer.viewer_id).await {
+            HttpResponse::Ok().json(Logbook::new(entries))
+        } else {
+            error!("SELECT query for climbs failed in get_climber_logbook()");
+            HttpResponse::BadGateway().finish()
+        }
+    } else {
+        error!("Failed to connect to the database in get_climber_logbook()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Statistics computed from a climber's logbook, so they follow the logbook's privacy setting
+#[get("/{id}/stats")]
+async fn get_climber_stats(path: web::Path<i32>, viewer: web::Query<Viewer>) -> impl Responder {
+    let id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match logbook_visible(&mut conn, id, viewer.viewer_id).await {
+            Ok(Some(true)) => (),
+            Ok(Some(false)) => return HttpResponse::Forbidden().finish(),
+            Ok(None) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in get_climber_stats()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        if let Ok(entries) = select_logbook_entries(&mut conn, id, viewer.viewer_id).await {
+            HttpResponse::Ok().json(ClimberStats::new(&entries))
+        } else {
+            error!("SELECT query for climbs failed in get_climber_stats()");
+            HttpResponse::BadGateway().finish()
+        }
+    } else {
+        error!("Failed to connect to the database in get_climber_stats()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Routes the climber hasn't climbed, near the grades and places in their logbook and rated well
+/// by the community and by climbers who rate routes like them. Made from the logbook, so they
+/// follow the logbook's privacy setting.
+#[get("/{id}/recommendations")]
+async fn get_climber_recommendations(
+    path: web::Path<i32>,
+    query: web::Query<RecommendationQuery>,
+) -> impl Responder {
+    let id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match logbook_visible(&mut conn, id, query.viewer_id).await {
+            Ok(Some(true)) => (),
+            Ok(Some(false)) => return HttpResponse::Forbidden().finish(),
+            Ok(None) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in get_climber_recommendations()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        // Hidden reviews still count as climbed, and the entries themselves aren't returned
+        let entries = match select_logbook_entries(&mut conn, id, Some(id)).await {
+            Ok(entries) => entries,
+            Err(_) => {
+                error!("SELECT query for climbs failed in get_climber_recommendations()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        let envelopes = GradeEnvelope::from_logbook(&entries);
+        let mut candidates = Vec::new();
+        for envelope in &envelopes {
+            let filter = envelope.candidate_filter(id, &entries, query.max_distance_km());
+            match select_routes(&mut conn, Some(MAX_CANDIDATES), &filter).await {
+                Ok(routes) => candidates.extend(routes),
+                Err(_) => {
+                    error!("SELECT query for routes failed in get_climber_recommendations()");
+                    return HttpResponse::BadGateway().finish();
+                }
+            }
+        }
+        // Ratings by other climbers with public logbooks who have climbed a route in common
+        match query!(
+            r#"SELECT others.climber_id as "climber_id!", others.route_id as "route_id!", others.rating as "rating!"
+            FROM active_climbs others JOIN climbers ON climbers.id = others.climber_id
+            WHERE others.climber_id <> ($1) AND others.hidden_at IS NULL AND climbers.deleted_at IS NULL AND climbers.logbook_visibility = ($2)
+            AND others.climber_id IN (
+                SELECT climber_id FROM active_climbs WHERE route_id IN (SELECT route_id FROM active_climbs WHERE climber_id = ($1))
+            )"#,
+            id,
+            Visibility::Public.to_string(),
+        )
+        .fetch_all(&mut conn)
+        .await
+        {
+            Ok(query_result) => {
+                let other_ratings = query_result
+                    .into_iter()
+                    .map(|record| OtherRating {
+                        climber_id: record.climber_id,
+                        route_id: record.route_id,
+                        rating: record.rating,
+                    })
+                    .collect::<Vec<OtherRating>>();
+                let recommendations = recommend(
+                    &entries,
+                    &envelopes,
+                    candidates,
+                    &other_ratings,
+                    query.max_distance_km(),
+                    query.limit(),
+                );
+                HttpResponse::Ok().json(Recommendations {
+                    climber_id: id,
+                    envelopes,
+                    recommendations,
+                })
+            }
+            Err(_) => {
+                error!("SELECT query for ratings failed in get_climber_recommendations()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in get_climber_recommendations()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+pub async fn climber_exists(conn: &mut PgConnection, id: i32) -> Result<bool, DatabaseError> {
+    Ok(query!(
+        "SELECT id FROM climbers WHERE id = ($1) AND deleted_at IS NULL",
+        id
+    )
+    .fetch_optional(conn)
+    .await?
+    .is_some())
+}
+
+/// Whether a climber is a moderator, or None if there is no such climber
+pub async fn climber_is_moderator(
+    conn: &mut PgConnection,
+    id: i32,
+) -> Result<Option<bool>, DatabaseError> {
+    Ok(query!(
+        "SELECT is_moderator FROM climbers WHERE id = ($1) AND deleted_at IS NULL",
+        id
+    )
+    .fetch_optional(conn)
+    .await?
+    .map(|record| record.is_moderator))
+}
+
+/// Follow another climber. Following someone already followed changes nothing.
+#[put("/{id}/following/{followed_id}")]
+async fn follow_climber(path: web::Path<(i32, i32)>) -> impl Responder {
+    let (id, followed_id) = path.into_inner();
+    if id == followed_id {
+        return HttpResponse::BadRequest().body(DatabaseError::CannotFollowSelf.to_string());
+    }
+    if let Ok(mut conn) = conn().await {
+        match (
+            climber_exists(&mut conn, id).await,
+            climber_exists(&mut conn, followed_id).await,
+        ) {
+            (Ok(true), Ok(true)) => (),
+            (Ok(_), Ok(_)) => return HttpResponse::NotFound().finish(),
+            _ => {
+                error!("SELECT query failed in follow_climber()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        if query!(
+            "INSERT INTO follows (follower_id, followed_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            id,
+            followed_id,
+        )
+        .execute(&mut conn)
+        .await
+        .is_ok()
+        {
+            HttpResponse::NoContent().finish()
+        } else {
+            error!("INSERT query failed in follow_climber()");
+            HttpResponse::BadGateway().finish()
+        }
+    } else {
+        error!("Failed to connect to the database in follow_climber()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+#[delete("/{id}/following/{followed_id}")]
+async fn unfollow_climber(path: web::Path<(i32, i32)>) -> impl Responder {
+    let (id, followed_id) = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match query!(
+            "DELETE FROM follows WHERE follower_id = ($1) AND followed_id = ($2)",
+            id,
+            followed_id,
+        )
+        .execute(&mut conn)
+        .await
+        {
+            Ok(query_result) if query_result.rows_affected() == 0 => {
+                HttpResponse::NotFound().finish()
+            }
+            Ok(_) => HttpResponse::NoContent().finish(),
+            Err(_) => {
+                error!("DELETE query failed in unfollow_climber()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in unfollow_climber()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+async fn list_follows(id: i32, filter: ClimberFilter, viewer_id: Option<i32>) -> HttpResponse {
+    if let Ok(mut conn) = conn().await {
+        match climber_exists(&mut conn, id).await {
+            Ok(true) => (),
+            Ok(false) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in list_follows()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        if let Ok(climbers) = select_climbers(&mut conn, &filter, viewer_id).await {
+            let climbers = climbers
+                .into_iter()
+                .map(|(climber, _)| climber)
+                .collect::<Vec<Climber>>();
+            HttpResponse::Ok().json(climbers)
+        } else {
+            error!("SELECT query for follows failed in list_follows()");
+            HttpResponse::BadGateway().finish()
+        }
+    } else {
+        error!("Failed to connect to the database in list_follows()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+#[get("/{id}/following")]
+async fn get_following(path: web::Path<i32>, viewer: web::Query<Viewer>) -> impl Responder {
+    let id = path.into_inner();
+    let filter = ClimberFilter {
+        followed_by: Some(id),
+        ..Default::default()
+    };
+    list_follows(id, filter, viewer.viewer_id).await
+}
+
+#[get("/{id}/followers")]
+async fn get_followers(path: web::Path<i32>, viewer: web::Query<Viewer>) -> impl Responder {
+    let id = path.into_inner();
+    let filter = ClimberFilter {
+        following: Some(id),
+        ..Default::default()
+    };
+    list_follows(id, filter, viewer.viewer_id).await
+}
+
+/// Newest first: ascents and reviews logged by the climbers someone follows, and routes they set.
+/// Climbers whose activity is private and reviews hidden by a moderator are left out. Only the
+/// climber themself, as the `viewer_id`, can read their feed.
+#[get("/{id}/feed")]
+async fn get_climber_feed(
+    path: web::Path<i32>,
+    query: web::Query<Page>,
+    viewer: web::Query<Viewer>,
+) -> impl Responder {
+    let id = path.into_inner();
+    let (page, per_page) = query.page_and_size();
+    if let Ok(mut conn) = conn().await {
+        match climber_exists(&mut conn, id).await {
+            Ok(true) => (),
+            Ok(false) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in get_climber_feed()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        if viewer.viewer_id != Some(id) {
+            return HttpResponse::Forbidden().finish();
+        }
+        // Everyone in a feed is followed by its reader, so anything that isn't private is visible
+        if let Ok(query_result) = query!(
+            r#"SELECT kind as "kind!", climb_id, climber_id as "climber_id!", username as "username!", route_id as "route_id!", route_name as "route_name!", difficulty as "difficulty!",
+            rating, review, completion_date, led_pitches, occurred_at as "occurred_at!"
+            FROM (
+                SELECT CASE WHEN COALESCE(climbs.review, '') = '' THEN 'ascent' ELSE 'review' END as kind, climbs.id as climb_id, climbers.id as climber_id, climbers.username,
+                routes.id as route_id, routes.name as route_name, routes.difficulty, climbs.rating, climbs.review, climbs.completion_date, climbs.led_pitches, climbs.created_at as occurred_at
+                FROM active_climbs climbs
+                JOIN follows ON follows.followed_id = climbs.climber_id
+                JOIN climbers ON climbers.id = climbs.climber_id
+                JOIN routes ON routes.id = climbs.route_id
+                WHERE follows.follower_id = ($1) AND climbers.activity_visibility <> ($4) AND climbs.hidden_at IS NULL
+                UNION ALL
+                SELECT 'route_set', NULL, climbers.id, climbers.username,
+                routes.id, routes.name, routes.difficulty, NULL, NULL, NULL, NULL, routes.created_at
+                FROM routes
+                JOIN follows ON follows.followed_id = routes.setter_id
+                JOIN climbers ON climbers.id = routes.setter_id
+                WHERE follows.follower_id = ($1) AND routes.deleted_at IS NULL AND climbers.deleted_at IS NULL AND climbers.activity_visibility <> ($4)
+            ) feed
+            ORDER BY occurred_at DESC, climb_id DESC NULLS LAST, route_id DESC
+            LIMIT ($2) OFFSET ($3)"#,
+            id,
+            per_page + 1,
+            query.offset(),
+            Visibility::Private.to_string(),
+        )
+        .fetch_all(&mut conn)
+        .await
+        {
+            let next_page = (query_result.len() as i64 > per_page).then_some(page + 1);
+            if let Ok(events) = query_result
+                .into_iter()
+                .take(per_page as usize)
+                .map(|record| {
+                    let kind = match record.kind.as_str() {
+                        "ascent" => FeedEventKind::Ascent,
+                        "review" => FeedEventKind::Review,
+                        _ => FeedEventKind::RouteSet,
+                    };
+                    let climb = match (
+                        record.climb_id,
+                        record.rating,
+                        record.completion_date,
+                    ) {
+                        (Some(climb_id), Some(rating), Some(completion_date)) => {
+                            let mut review = Review::new(
+                                rating,
+                                record.review.unwrap_or_default(),
+                                completion_date,
+                            );
+                            review.led_pitches = record.led_pitches.unwrap_or_default();
+                            Some(Climb {
+                                id: Some(climb_id),
+                                climber_id: record.climber_id,
+                                route_id: record.route_id,
+                                review,
+                                hidden: false,
+                            })
+                        }
+                        _ => None,
+                    };
+                    Ok(FeedEvent {
+                        kind,
+                        climber_id: record.climber_id,
+                        username: record.username,
+                        route_id: record.route_id,
+                        route_name: record.route_name,
+                        difficulty: DifficultyRating::from_str(&record.difficulty)?,
+                        climb,
+                        occurred_at: record.occurred_at,
+                    })
+                })
+                .collect::<Result<Vec<FeedEvent>, DatabaseError>>()
+            {
+                HttpResponse::Ok().json(Feed {
+                    events,
+                    page,
+                    per_page,
+                    next_page,
+                })
+            } else {
+                error!("Failed to parse difficulty column in get_climber_feed()");
+                HttpResponse::BadGateway().finish()
+            }
         } else {
-            error!("DELETE query failed in delete_climber()");
-            HttpResponse::BadGateway()
+            error!("SELECT query failed in get_climber_feed()");
+            HttpResponse::BadGateway().finish()
+        }
+    } else {
+        error!("Failed to connect to the database in get_climber_feed()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Response for a conditional write to a climber that matched no rows, which is either because
+/// the climber doesn't exist or because If-Match didn't have its current version
+async fn climber_not_written(conn: &mut PgConnection, id: i32) -> HttpResponse {
+    match query!(
+        "SELECT id FROM climbers WHERE id = ($1) AND deleted_at IS NULL",
+        id
+    )
+    .fetch_optional(conn)
+    .await
+    {
+        Ok(Some(_)) => HttpResponse::PreconditionFailed().finish(),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(_) => {
+            error!("SELECT query failed in climber_not_written()");
+            HttpResponse::BadGateway().finish()
+        }
+    }
+}
+
+#[delete("/{id}")]
+async fn delete_climber(path: web::Path<i32>, request: HttpRequest) -> impl Responder {
+    let id = path.into_inner();
+    let if_match = if_match_versions(&request);
+    if let Ok(mut conn) = conn().await {
+        match query!(
+            r#"UPDATE climbers SET deleted_at = current_timestamp, version = version + 1 WHERE id = ($1) AND deleted_at IS NULL AND ($2::int4[] IS NULL OR version = ANY($2))"#,
+            id,
+            if_match.as_deref(),
+        )
+        .execute(&mut conn)
+        .await
+        {
+            Ok(query_result) if query_result.rows_affected() == 0 => {
+                climber_not_written(&mut conn, id).await
+            }
+            Ok(_) => HttpResponse::NoContent().finish(),
+            Err(_) => {
+                error!("UPDATE query failed in delete_climber()");
+                HttpResponse::BadGateway().finish()
+            }
         }
     } else {
         error!("Failed to connect to the database in delete_climber()");
-        HttpResponse::BadGateway()
+        HttpResponse::BadGateway().finish()
     }
 }
 
+/// A climber's review of a route. Only the climber sees it if a moderator has hidden it.
 #[get("/{climber_id}/{route_id}")]
-async fn get_climbers_review_by_route_id(path: web::Path<(i32, i32)>) -> impl Responder {
+async fn get_climbers_review_by_route_id(
+    path: web::Path<(i32, i32)>,
+    request: HttpRequest,
+    viewer: web::Query<Viewer>,
+) -> impl Responder {
     let (climber_id, route_id) = path.into_inner();
     if let Ok(mut conn) = conn().await {
+        match logbook_visible(&mut conn, climber_id, viewer.viewer_id).await {
+            Ok(Some(false)) => return HttpResponse::Forbidden().finish(),
+            Ok(_) => (),
+            Err(_) => {
+                error!("SELECT query failed in get_climbers_review_by_route_id()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
         if let Ok(query_result) = query!(
-            r#"SELECT id, climber_id as "climber_id!", route_id as "route_id!", rating as "rating!", review as "review!", completion_date as "completion_date!" FROM climbs WHERE climber_id = ($1) and route_id = ($2)"#,
+            r#"SELECT id as "id!", climber_id as "climber_id!", route_id as "route_id!", rating as "rating!", review as "review!", completion_date as "completion_date!", led_pitches as "led_pitches!", version as "version!", hidden_at IS NOT NULL as "hidden!"
+            FROM active_climbs WHERE climber_id = ($1) and route_id = ($2) AND (hidden_at IS NULL OR climber_id = $3)"#,
             climber_id,
             route_id,
+            viewer.viewer_id,
         )
         .fetch_one(&mut conn)
         .await
         {
+            if not_modified(&request, &etag(query_result.version)) {
+                return HttpResponse::NotModified()
+                    .insert_header(header::ETag(etag(query_result.version)))
+                    .finish();
+            }
+            let mut review = Review::new(
+                query_result.rating,
+                query_result.review,
+                query_result.completion_date,
+            );
+            review.led_pitches = query_result.led_pitches;
             let climb = Climb {
                 id: Some(query_result.id),
                 climber_id: query_result.climber_id,
                 route_id: query_result.route_id,
-                review: Review::new(
-                    query_result.rating,
-                    query_result.review,
-                    query_result.completion_date,
-                )
+                review,
+                hidden: query_result.hidden,
             };
-            HttpResponse::Ok().json(climb)
+            HttpResponse::Ok()
+                .insert_header(header::ETag(etag(query_result.version)))
+                .json(climb)
         } else {
             error!("INSERT query failed in add_review()");
             HttpResponse::BadRequest().finish()
@@ -139,22 +864,53 @@ async fn get_climbers_review_by_route_id(path: web::Path<(i32, i32)>) -> impl Re
 async fn add_review(path: web::Path<(i32, i32)>, json: web::Json<Review>) -> impl Responder {
     let (climber_id, route_id) = path.into_inner();
     if let Ok(mut conn) = conn().await {
-        if query!(
-            r#"INSERT INTO climbs (climber_id, route_id, rating, review, completion_date) VALUES ($1, $2, $3, $4, $5)"#,
+        if !json.led_pitches.is_empty() {
+            match check_route_led_pitches(&mut conn, route_id, &json.led_pitches).await {
+                Ok(Some(Ok(()))) => (),
+                Ok(Some(Err(e))) => return HttpResponse::BadRequest().body(e.to_string()),
+                Ok(None) => return HttpResponse::NotFound().finish(),
+                Err(_) => {
+                    error!("SELECT query failed in add_review()");
+                    return HttpResponse::BadGateway().finish();
+                }
+            }
+        }
+        match query!(
+            r#"INSERT INTO climbs (climber_id, route_id, rating, review, completion_date, led_pitches)
+            SELECT $1, $2, $3, $4, $5, $6
+            WHERE EXISTS (SELECT 1 FROM climbers WHERE id = ($1) AND deleted_at IS NULL)
+            AND EXISTS (SELECT 1 FROM routes WHERE id = ($2) AND deleted_at IS NULL)
+            RETURNING id"#,
             climber_id,
             route_id,
             json.0.rating.i32(),
             json.0.review,
             json.0.completion_date,
+            &json.0.led_pitches,
         )
-        .execute(&mut conn)
+        .fetch_optional(&mut conn)
         .await
-        .is_ok()
         {
-            HttpResponse::Ok().finish()
-        } else {
-            error!("INSERT query failed in add_review()");
-            HttpResponse::BadRequest().finish()
+            Ok(Some(query_result)) => {
+                let climb = Climb {
+                    id: Some(query_result.id),
+                    climber_id,
+                    route_id,
+                    review: json.into_inner(),
+                    hidden: false,
+                };
+                HttpResponse::Created()
+                    .insert_header((
+                        header::LOCATION,
+                        format!("/climbers/{climber_id}/{route_id}"),
+                    ))
+                    .json(climb)
+            }
+            Ok(None) => HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("INSERT query failed in add_review()");
+                HttpResponse::BadRequest().finish()
+            }
         }
     } else {
         error!("Failed to connect to the database in add_review()");
@@ -162,26 +918,113 @@ async fn add_review(path: web::Path<(i32, i32)>, json: web::Json<Review>) -> imp
     }
 }
 
+/// Check that every led pitch is one of the route's pitches. Ok(None) if the route doesn't exist.
+async fn check_route_led_pitches(
+    conn: &mut PgConnection,
+    route_id: i32,
+    led_pitches: &[i32],
+) -> Result<Option<Result<(), DatabaseError>>, DatabaseError> {
+    Ok(query!(
+        "SELECT number_pitches FROM routes WHERE id = ($1) AND deleted_at IS NULL",
+        route_id
+    )
+    .fetch_optional(conn)
+    .await?
+    .map(|record| check_led_pitches(led_pitches, record.number_pitches)))
+}
+
+/// Response for a conditional write to a review that matched no rows, which is either because
+/// the review doesn't exist or because If-Match didn't have its current version
+async fn review_not_written(
+    conn: &mut PgConnection,
+    climber_id: i32,
+    route_id: i32,
+) -> HttpResponse {
+    match query!(
+        "SELECT id FROM active_climbs WHERE climber_id = ($1) AND route_id = ($2)",
+        climber_id,
+        route_id,
+    )
+    .fetch_optional(conn)
+    .await
+    {
+        Ok(Some(_)) => HttpResponse::PreconditionFailed().finish(),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(_) => {
+            error!("SELECT query failed in review_not_written()");
+            HttpResponse::BadGateway().finish()
+        }
+    }
+}
+
+/// Response for an edit of a review that matched no rows, which is either because a moderator has
+/// hidden the review or for one of the reasons in review_not_written
+async fn review_not_edited(
+    conn: &mut PgConnection,
+    climber_id: i32,
+    route_id: i32,
+) -> HttpResponse {
+    match query!(
+        "SELECT id FROM active_climbs WHERE climber_id = ($1) AND route_id = ($2) AND hidden_at IS NOT NULL",
+        climber_id,
+        route_id,
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    {
+        Ok(Some(_)) => {
+            HttpResponse::Conflict().body(DatabaseError::CannotEditHiddenReview.to_string())
+        }
+        Ok(None) => review_not_written(conn, climber_id, route_id).await,
+        Err(_) => {
+            error!("SELECT query failed in review_not_edited()");
+            HttpResponse::BadGateway().finish()
+        }
+    }
+}
+
+/// Replace a review, unless a moderator has hidden it
 #[put("/{climber_id}/{route_id}")]
-async fn update_review(path: web::Path<(i32, i32)>, json: web::Json<Review>) -> impl Responder {
+async fn update_review(
+    path: web::Path<(i32, i32)>,
+    request: HttpRequest,
+    json: web::Json<Review>,
+) -> impl Responder {
     let (climber_id, route_id) = path.into_inner();
+    let if_match = if_match_versions(&request);
     if let Ok(mut conn) = conn().await {
-        if query!(
-            r#"UPDATE climbs SET rating = $1, review = $2, completion_date = $3 WHERE climber_id = ($4) AND route_id = ($5)"#,
+        if !json.led_pitches.is_empty() {
+            match check_route_led_pitches(&mut conn, route_id, &json.led_pitches).await {
+                Ok(Some(Ok(()))) => (),
+                Ok(Some(Err(e))) => return HttpResponse::BadRequest().body(e.to_string()),
+                Ok(None) => return HttpResponse::NotFound().finish(),
+                Err(_) => {
+                    error!("SELECT query failed in update_review()");
+                    return HttpResponse::BadGateway().finish();
+                }
+            }
+        }
+        match query!(
+            r#"UPDATE climbs SET rating = $1, review = $2, completion_date = $3, led_pitches = $7, version = version + 1 WHERE id IN (SELECT id FROM active_climbs WHERE climber_id = ($4) AND route_id = ($5)) AND hidden_at IS NULL AND ($6::int4[] IS NULL OR version = ANY($6)) RETURNING version"#,
             json.0.rating.i32(),
             json.0.review,
             json.0.completion_date,
             climber_id,
             route_id,
+            if_match.as_deref(),
+            &json.0.led_pitches,
         )
-        .execute(&mut conn)
+        .fetch_optional(&mut conn)
         .await
-        .is_ok()
         {
-            HttpResponse::Ok().finish()
-        } else {
-            error!("INSERT query failed in update_review()");
-            HttpResponse::BadRequest().finish()
+            Ok(Some(query_result)) => HttpResponse::NoContent()
+                .insert_header(header::ETag(etag(query_result.version)))
+                .finish(),
+            Ok(None) => review_not_edited(&mut conn, climber_id, route_id).await,
+            Err(_) => {
+                error!("UPDATE query failed in update_review()");
+                HttpResponse::BadRequest().finish()
+            }
         }
     } else {
         error!("Failed to connect to the database in update_review()");
@@ -189,26 +1032,83 @@ async fn update_review(path: web::Path<(i32, i32)>, json: web::Json<Review>) ->
     }
 }
 
+/// Update the fields of a review given in the patch, unless a moderator has hidden it
+#[patch("/{climber_id}/{route_id}")]
+async fn patch_review(
+    path: web::Path<(i32, i32)>,
+    request: HttpRequest,
+    json: web::Json<ReviewPatch>,
+) -> impl Responder {
+    let (climber_id, route_id) = path.into_inner();
+    let if_match = if_match_versions(&request);
+    if let Err(e) = json.check_not_empty() {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+    if let Ok(mut conn) = conn().await {
+        if let Some(led_pitches) = &json.led_pitches {
+            match check_route_led_pitches(&mut conn, route_id, led_pitches).await {
+                Ok(Some(Ok(()))) => (),
+                Ok(Some(Err(e))) => return HttpResponse::BadRequest().body(e.to_string()),
+                Ok(None) => return HttpResponse::NotFound().finish(),
+                Err(_) => {
+                    error!("SELECT query failed in patch_review()");
+                    return HttpResponse::BadGateway().finish();
+                }
+            }
+        }
+        match query!(
+            r#"UPDATE climbs SET rating = COALESCE($1, rating), review = COALESCE($2, review), completion_date = COALESCE($3, completion_date), led_pitches = COALESCE($7, led_pitches), version = version + 1 WHERE id IN (SELECT id FROM active_climbs WHERE climber_id = ($4) AND route_id = ($5)) AND hidden_at IS NULL AND ($6::int4[] IS NULL OR version = ANY($6)) RETURNING version"#,
+            json.0.rating.as_ref().map(Rating::i32),
+            json.0.review,
+            json.0.completion_date,
+            climber_id,
+            route_id,
+            if_match.as_deref(),
+            json.0.led_pitches.as_deref(),
+        )
+        .fetch_optional(&mut conn)
+        .await
+        {
+            Ok(Some(query_result)) => HttpResponse::NoContent()
+                .insert_header(header::ETag(etag(query_result.version)))
+                .finish(),
+            Ok(None) => review_not_edited(&mut conn, climber_id, route_id).await,
+            Err(_) => {
+                error!("UPDATE query failed in patch_review()");
+                HttpResponse::BadRequest().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in patch_review()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
 #[delete("/{climber_id}/{route_id}")]
-async fn delete_review(path: web::Path<(i32, i32)>) -> impl Responder {
+async fn delete_review(path: web::Path<(i32, i32)>, request: HttpRequest) -> impl Responder {
     let (climber_id, route_id) = path.into_inner();
+    let if_match = if_match_versions(&request);
     if let Ok(mut conn) = conn().await {
-        if query!(
-            r#"DELETE FROM climbs WHERE climber_id = ($1) AND route_id = ($2)"#,
+        match query!(
+            r#"UPDATE climbs SET deleted_at = current_timestamp, version = version + 1 WHERE id IN (SELECT id FROM active_climbs WHERE climber_id = ($1) AND route_id = ($2)) AND ($3::int4[] IS NULL OR version = ANY($3))"#,
             climber_id,
             route_id,
+            if_match.as_deref(),
         )
         .execute(&mut conn)
         .await
-        .is_ok()
         {
-            HttpResponse::Ok()
-        } else {
-            error!("DELETE query failed in delete_review()");
-            HttpResponse::BadGateway()
+            Ok(query_result) if query_result.rows_affected() == 0 => {
+                review_not_written(&mut conn, climber_id, route_id).await
+            }
+            Ok(_) => HttpResponse::NoContent().finish(),
+            Err(_) => {
+                error!("UPDATE query failed in delete_review()");
+                HttpResponse::BadGateway().finish()
+            }
         }
     } else {
         error!("Failed to connect to the database in delete_review()");
-        HttpResponse::BadGateway()
+        HttpResponse::BadGateway().finish()
     }
 }
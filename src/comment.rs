@@ -0,0 +1,218 @@
+/// module for threaded comments on routes and areas, handled by the API endpoints /comments,
+/// /routes/{id}/comments and /areas/{id}/comments
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::NaiveDateTime;
+use std::collections::BTreeMap;
+
+use crate::error::DatabaseError;
+use crate::page::Page;
+
+/// Deepest a reply can be, where a comment that isn't a reply has a depth of 0
+pub const MAX_COMMENT_DEPTH: i32 = 4;
+/// Longest a comment can be, in characters
+pub const MAX_COMMENT_LENGTH: usize = 2000;
+
+/// What a comment is about
+#[derive(Debug, Clone, Copy)]
+pub enum CommentOwner {
+    Route(i32),
+    Area(i32),
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Comment {
+    pub id: Option<i32>,
+    /// The climber who wrote the comment
+    pub climber_id: i32,
+    /// Ignored on writes
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Taken from the path when a comment is posted
+    #[serde(default)]
+    pub route_id: Option<i32>,
+    /// Taken from the path when a comment is posted
+    #[serde(default)]
+    pub area_id: Option<i32>,
+    /// The comment this one replies to. Ignored on edits.
+    #[serde(default)]
+    pub parent_id: Option<i32>,
+    /// Ignored on writes
+    #[serde(default)]
+    pub depth: i32,
+    pub body: String,
+    /// Whether a moderator has hidden the comment, in which case only its climber sees what it
+    /// said. Ignored on writes.
+    #[serde(default)]
+    pub hidden: bool,
+    /// Deleted comments with replies are kept so that the thread still makes sense, without
+    /// what they said. Ignored on writes.
+    #[serde(default)]
+    pub deleted: bool,
+    /// Ignored on writes
+    #[serde(default)]
+    pub created_at: Option<NaiveDateTime>,
+    /// When the comment was last edited by its climber or a moderator. Ignored on writes.
+    #[serde(default)]
+    pub edited_at: Option<NaiveDateTime>,
+    /// Oldest first. Ignored on writes.
+    #[serde(default)]
+    pub replies: Vec<Comment>,
+}
+
+/// Check that the text of a comment says something, and not too much
+pub fn check_comment_body(body: &str) -> Result<(), DatabaseError> {
+    if body.trim().is_empty() || body.chars().count() > MAX_COMMENT_LENGTH {
+        Err(DatabaseError::CommentBodyInvalid)
+    } else {
+        Ok(())
+    }
+}
+
+impl Comment {
+    pub fn check_body(&self) -> Result<(), DatabaseError> {
+        check_comment_body(&self.body)
+    }
+
+    /// Whether the viewer only gets to see that the comment was there
+    fn is_removed_for(&self, viewer_id: Option<i32>) -> bool {
+        self.deleted || (self.hidden && viewer_id != Some(self.climber_id))
+    }
+}
+
+/// Nest comments, oldest first, under the comments they reply to. Comments the viewer can't see
+/// lose their body, and are left out altogether unless they have replies the viewer can see.
+/// Comments whose parent isn't in the list are treated as the tops of their threads.
+pub fn build_threads(comments: Vec<Comment>, viewer_id: Option<i32>) -> Vec<Comment> {
+    let ids = comments
+        .iter()
+        .filter_map(|comment| comment.id)
+        .collect::<Vec<i32>>();
+    let mut by_parent: BTreeMap<Option<i32>, Vec<Comment>> = BTreeMap::new();
+    for comment in comments {
+        let parent_id = comment
+            .parent_id
+            .filter(|parent_id| ids.contains(parent_id));
+        by_parent.entry(parent_id).or_default().push(comment);
+    }
+
+    fn attach(
+        parent_id: Option<i32>,
+        by_parent: &mut BTreeMap<Option<i32>, Vec<Comment>>,
+        viewer_id: Option<i32>,
+    ) -> Vec<Comment> {
+        let mut comments = by_parent.remove(&parent_id).unwrap_or_default();
+        comments.sort_by_key(|comment| (comment.created_at, comment.id));
+        comments
+            .into_iter()
+            .filter_map(|mut comment| {
+                comment.replies = attach(comment.id, by_parent, viewer_id);
+                if comment.is_removed_for(viewer_id) {
+                    if comment.replies.is_empty() {
+                        return None;
+                    }
+                    comment.body = String::new();
+                }
+                Some(comment)
+            })
+            .collect()
+    }
+
+    attach(None, &mut by_parent, viewer_id)
+}
+
+/// Query parameters for listing the comments on a route or area. Threads are paginated newest
+/// first, and each comes with all of its replies.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+pub struct CommentQuery {
+    pub viewer_id: Option<i32>,
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+impl CommentQuery {
+    pub fn page(&self) -> Page {
+        Page {
+            page: self.page,
+            per_page: self.per_page,
+        }
+    }
+}
+
+/// Body of GET /routes/{id}/comments and GET /areas/{id}/comments
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CommentThreads {
+    pub comments: Vec<Comment>,
+    pub page: i64,
+    pub per_page: i64,
+    /// The next page number, if there are more threads
+    pub next_page: Option<i64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_comment(id: i32, parent_id: Option<i32>, climber_id: i32) -> Comment {
+        Comment {
+            id: Some(id),
+            climber_id,
+            username: None,
+            route_id: Some(1),
+            area_id: None,
+            parent_id,
+            depth: 0,
+            body: format!("comment {id}"),
+            hidden: false,
+            deleted: false,
+            created_at: None,
+            edited_at: None,
+            replies: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_check_body() {
+        let mut comment = test_comment(1, None, 1);
+        assert!(comment.check_body().is_ok());
+        comment.body = " ".to_string();
+        assert!(comment.check_body().is_err());
+        comment.body = "a".repeat(MAX_COMMENT_LENGTH + 1);
+        assert!(comment.check_body().is_err());
+    }
+
+    #[test]
+    fn test_build_threads() {
+        let mut deleted = test_comment(2, Some(1), 1);
+        deleted.deleted = true;
+        let mut hidden = test_comment(4, Some(1), 2);
+        hidden.hidden = true;
+        let mut deleted_leaf = test_comment(6, None, 1);
+        deleted_leaf.deleted = true;
+        let comments = vec![
+            test_comment(1, None, 1),
+            deleted,
+            test_comment(3, Some(2), 1),
+            hidden,
+            test_comment(5, None, 2),
+            deleted_leaf,
+        ];
+
+        let threads = build_threads(comments.clone(), None);
+        assert_eq!(
+            threads
+                .iter()
+                .map(|comment| comment.id)
+                .collect::<Vec<Option<i32>>>(),
+            vec![Some(1), Some(5)]
+        );
+        // The deleted reply stays, without its body, for the sake of its own reply
+        assert_eq!(threads[0].replies.len(), 1);
+        assert_eq!(threads[0].replies[0].body, "");
+        assert_eq!(threads[0].replies[0].replies[0].id, Some(3));
+
+        // Only the climber who wrote the hidden reply still sees it
+        let threads = build_threads(comments, Some(2));
+        assert_eq!(threads[0].replies.len(), 2);
+        assert_eq!(threads[0].replies[1].body, "comment 4");
+    }
+}
@@ -0,0 +1,401 @@
+use crate::climber::Viewer;
+use crate::climbers::climber_exists;
+use crate::comment::{
+    build_threads, Comment, CommentOwner, CommentQuery, CommentThreads, MAX_COMMENT_DEPTH,
+};
+use crate::error::DatabaseError;
+use crate::pg::conn;
+use actix_web::{delete, get, http::header, post, put, web, HttpResponse, Responder};
+use log::error;
+/// Functions for the API endpoints /comments, /routes/{id}/comments and /areas/{id}/comments
+use sqlx::{self, postgres::PgConnection, query};
+
+/// Whether the route or area a comment is about exists
+async fn owner_exists(conn: &mut PgConnection, owner: CommentOwner) -> Result<bool, DatabaseError> {
+    Ok(match owner {
+        CommentOwner::Route(id) => query!(
+            "SELECT id FROM routes WHERE id = ($1) AND deleted_at IS NULL",
+            id
+        )
+        .fetch_optional(conn)
+        .await?
+        .is_some(),
+        CommentOwner::Area(id) => query!("SELECT id FROM areas WHERE id = ($1)", id)
+            .fetch_optional(conn)
+            .await?
+            .is_some(),
+    })
+}
+
+/// Shared SELECT behind every endpoint that returns comments: the comments with the given IDs,
+/// and every reply under them if `include_replies`, oldest first and not yet nested. Comments by
+/// deleted climbers count as deleted, and comments on deleted routes are left out.
+pub async fn select_comments(
+    conn: &mut PgConnection,
+    ids: &[i32],
+    include_replies: bool,
+) -> Result<Vec<Comment>, DatabaseError> {
+    Ok(query!(
+        r#"WITH RECURSIVE thread AS (
+            SELECT * FROM comments WHERE id = ANY($1)
+            UNION ALL SELECT comments.* FROM comments JOIN thread ON comments.parent_id = thread.id WHERE $2
+        )
+        SELECT thread.id as "id!", thread.climber_id as "climber_id!", climbers.username as "username!", thread.route_id, thread.area_id, thread.parent_id, thread.depth as "depth!", thread.body as "body!",
+        thread.hidden_at IS NOT NULL as "hidden!", (thread.deleted_at IS NOT NULL OR climbers.deleted_at IS NOT NULL) as "deleted!", thread.created_at as "created_at!", thread.edited_at
+        FROM thread JOIN climbers ON climbers.id = thread.climber_id
+        WHERE thread.route_id IS NULL OR thread.route_id IN (SELECT id FROM routes WHERE deleted_at IS NULL)
+        ORDER BY thread.created_at, thread.id"#,
+        ids,
+        include_replies,
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|record| Comment {
+        id: Some(record.id),
+        climber_id: record.climber_id,
+        username: Some(record.username),
+        route_id: record.route_id,
+        area_id: record.area_id,
+        parent_id: record.parent_id,
+        depth: record.depth,
+        body: record.body,
+        hidden: record.hidden,
+        deleted: record.deleted,
+        created_at: Some(record.created_at),
+        edited_at: record.edited_at,
+        replies: Vec::new(),
+    })
+    .collect())
+}
+
+/// A comment with its replies, as the viewer sees it. None if they can't see it at all.
+async fn select_thread(
+    conn: &mut PgConnection,
+    id: i32,
+    viewer_id: Option<i32>,
+) -> Result<Option<Comment>, DatabaseError> {
+    let comments = select_comments(conn, &[id], true).await?;
+    Ok(build_threads(comments, viewer_id).pop())
+}
+
+/// The climber who wrote a comment that hasn't been deleted, and whether a moderator has hidden it
+async fn comment_author(
+    conn: &mut PgConnection,
+    id: i32,
+) -> Result<Option<(i32, bool)>, DatabaseError> {
+    Ok(query!(
+        r#"SELECT climber_id, hidden_at IS NOT NULL as "hidden!" FROM comments WHERE id = ($1) AND deleted_at IS NULL"#,
+        id
+    )
+    .fetch_optional(conn)
+    .await?
+    .map(|record| (record.climber_id, record.hidden)))
+}
+
+async fn add_comment(owner: CommentOwner, json: web::Json<Comment>) -> HttpResponse {
+    if let Err(e) = json.check_body() {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+    let (route_id, area_id) = match owner {
+        CommentOwner::Route(id) => (Some(id), None),
+        CommentOwner::Area(id) => (None, Some(id)),
+    };
+    if let Ok(mut conn) = conn().await {
+        match owner_exists(&mut conn, owner).await {
+            Ok(true) => (),
+            Ok(false) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in add_comment()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        match climber_exists(&mut conn, json.climber_id).await {
+            Ok(true) => (),
+            Ok(false) => {
+                return HttpResponse::BadRequest().body(DatabaseError::ClimberNotFound.to_string())
+            }
+            Err(_) => {
+                error!("SELECT query for the climber failed in add_comment()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        // A reply has to be to a comment on the same route or area that can still be seen
+        let depth = match json.parent_id {
+            Some(parent_id) => match query!(
+                r#"SELECT depth FROM comments WHERE id = ($1) AND route_id IS NOT DISTINCT FROM ($2) AND area_id IS NOT DISTINCT FROM ($3)
+                AND deleted_at IS NULL AND hidden_at IS NULL"#,
+                parent_id,
+                route_id,
+                area_id,
+            )
+            .fetch_optional(&mut conn)
+            .await
+            {
+                Ok(Some(query_result)) if query_result.depth < MAX_COMMENT_DEPTH => {
+                    query_result.depth + 1
+                }
+                Ok(Some(_)) => {
+                    return HttpResponse::BadRequest()
+                        .body(DatabaseError::CommentTooDeep.to_string())
+                }
+                Ok(None) => {
+                    return HttpResponse::BadRequest()
+                        .body(DatabaseError::ParentCommentNotFound.to_string())
+                }
+                Err(_) => {
+                    error!("SELECT query for the parent comment failed in add_comment()");
+                    return HttpResponse::BadGateway().finish();
+                }
+            },
+            None => 0,
+        };
+        match query!(
+            r#"INSERT INTO comments (climber_id, route_id, area_id, parent_id, depth, body)
+            VALUES ($1, $2, $3, $4, $5, $6) RETURNING id, created_at"#,
+            json.0.climber_id,
+            route_id,
+            area_id,
+            json.0.parent_id,
+            depth,
+            json.0.body,
+        )
+        .fetch_one(&mut conn)
+        .await
+        {
+            Ok(query_result) => {
+                let mut comment = json.into_inner();
+                comment.id = Some(query_result.id);
+                comment.username = None;
+                comment.route_id = route_id;
+                comment.area_id = area_id;
+                comment.depth = depth;
+                comment.hidden = false;
+                comment.deleted = false;
+                comment.created_at = Some(query_result.created_at);
+                comment.edited_at = None;
+                comment.replies = Vec::new();
+                HttpResponse::Created()
+                    .insert_header((header::LOCATION, format!("/comments/{}", query_result.id)))
+                    .json(comment)
+            }
+            Err(_) => {
+                error!("INSERT query failed in add_comment()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in add_comment()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+async fn list_comments(owner: CommentOwner, query: &CommentQuery) -> HttpResponse {
+    let (route_id, area_id) = match owner {
+        CommentOwner::Route(id) => (Some(id), None),
+        CommentOwner::Area(id) => (None, Some(id)),
+    };
+    let (page, per_page) = query.page().page_and_size();
+    if let Ok(mut conn) = conn().await {
+        match owner_exists(&mut conn, owner).await {
+            Ok(true) => (),
+            Ok(false) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in list_comments()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        // Threads whose first comment the viewer can't see only count if they can see one of
+        // its replies, at any depth, as build_threads() drops the thread otherwise
+        let ids = match query!(
+            r#"SELECT id FROM comments threads
+            WHERE parent_id IS NULL
+            AND ($1::int4 IS NULL OR route_id = $1)
+            AND ($2::int4 IS NULL OR area_id = $2)
+            AND ((deleted_at IS NULL AND (hidden_at IS NULL OR climber_id = $3) AND climber_id IN (SELECT id FROM climbers WHERE deleted_at IS NULL))
+                OR EXISTS (
+                    WITH RECURSIVE replies AS (
+                        SELECT * FROM comments WHERE parent_id = threads.id
+                        UNION ALL SELECT comments.* FROM comments JOIN replies ON comments.parent_id = replies.id
+                    )
+                    SELECT 1 FROM replies
+                    WHERE deleted_at IS NULL AND (hidden_at IS NULL OR climber_id = $3) AND climber_id IN (SELECT id FROM climbers WHERE deleted_at IS NULL)
+                ))
+            ORDER BY created_at DESC, id DESC
+            LIMIT ($4) OFFSET ($5)"#,
+            route_id,
+            area_id,
+            query.viewer_id,
+            per_page + 1,
+            query.page().offset(),
+        )
+        .fetch_all(&mut conn)
+        .await
+        {
+            Ok(query_result) => query_result
+                .into_iter()
+                .map(|record| record.id)
+                .collect::<Vec<i32>>(),
+            Err(_) => {
+                error!("SELECT query for threads failed in list_comments()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        let next_page = (ids.len() as i64 > per_page).then_some(page + 1);
+        let ids = &ids[..ids.len().min(per_page as usize)];
+        match select_comments(&mut conn, ids, true).await {
+            Ok(comments) => {
+                let mut comments = build_threads(comments, query.viewer_id);
+                comments.sort_by_key(|comment| ids.iter().position(|id| Some(*id) == comment.id));
+                HttpResponse::Ok().json(CommentThreads {
+                    comments,
+                    page,
+                    per_page,
+                    next_page,
+                })
+            }
+            Err(_) => {
+                error!("SELECT query for comments failed in list_comments()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in list_comments()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Comment on a route, or reply to one of its comments with a `parent_id`
+#[post("/{id}/comments")]
+async fn add_route_comment(path: web::Path<i32>, json: web::Json<Comment>) -> impl Responder {
+    add_comment(CommentOwner::Route(path.into_inner()), json).await
+}
+
+/// The threads of comments on a route, newest first
+#[get("/{id}/comments")]
+async fn get_route_comments(
+    path: web::Path<i32>,
+    query: web::Query<CommentQuery>,
+) -> impl Responder {
+    list_comments(CommentOwner::Route(path.into_inner()), &query).await
+}
+
+/// Comment on an area, or reply to one of its comments with a `parent_id`
+#[post("/{id}/comments")]
+async fn add_area_comment(path: web::Path<i32>, json: web::Json<Comment>) -> impl Responder {
+    add_comment(CommentOwner::Area(path.into_inner()), json).await
+}
+
+/// The threads of comments on an area, newest first. Comments on its routes are listed under the
+/// routes.
+#[get("/{id}/comments")]
+async fn get_area_comments(
+    path: web::Path<i32>,
+    query: web::Query<CommentQuery>,
+) -> impl Responder {
+    list_comments(CommentOwner::Area(path.into_inner()), &query).await
+}
+
+/// A comment along with its replies
+#[get("/{id}")]
+async fn get_comment_by_id(path: web::Path<i32>, viewer: web::Query<Viewer>) -> impl Responder {
+    let id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match select_thread(&mut conn, id, viewer.viewer_id).await {
+            Ok(Some(comment)) => HttpResponse::Ok().json(comment),
+            Ok(None) => HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in get_comment_by_id()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in get_comment_by_id()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Replace what a comment says. Only the climber who wrote it can edit it, and not while a
+/// moderator has it hidden.
+#[put("/{id}")]
+async fn update_comment(path: web::Path<i32>, json: web::Json<Comment>) -> impl Responder {
+    let id = path.into_inner();
+    if let Err(e) = json.check_body() {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+    if let Ok(mut conn) = conn().await {
+        match comment_author(&mut conn, id).await {
+            Ok(Some((climber_id, false))) if climber_id == json.climber_id => (),
+            Ok(Some((climber_id, true))) if climber_id == json.climber_id => {
+                return HttpResponse::Conflict()
+                    .body(DatabaseError::CannotEditHiddenComment.to_string())
+            }
+            Ok(Some(_)) => return HttpResponse::Forbidden().finish(),
+            Ok(None) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in update_comment()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        if query!(
+            "UPDATE comments SET body = ($2), edited_at = current_timestamp WHERE id = ($1)",
+            id,
+            json.0.body,
+        )
+        .execute(&mut conn)
+        .await
+        .is_err()
+        {
+            error!("UPDATE query failed in update_comment()");
+            return HttpResponse::BadGateway().finish();
+        }
+        match select_thread(&mut conn, id, Some(json.climber_id)).await {
+            Ok(Some(comment)) => HttpResponse::Ok().json(comment),
+            Ok(None) => HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query for the updated comment failed in update_comment()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in update_comment()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Delete a comment, which only the climber who wrote it (the `viewer_id`) can do. Its replies
+/// are kept.
+#[delete("/{id}")]
+async fn delete_comment(path: web::Path<i32>, viewer: web::Query<Viewer>) -> impl Responder {
+    let id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match comment_author(&mut conn, id).await {
+            Ok(Some((climber_id, _))) if Some(climber_id) == viewer.viewer_id => (),
+            Ok(Some(_)) => return HttpResponse::Forbidden().finish(),
+            Ok(None) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in delete_comment()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        match query!(
+            "UPDATE comments SET deleted_at = current_timestamp WHERE id = ($1) AND deleted_at IS NULL",
+            id,
+        )
+        .execute(&mut conn)
+        .await
+        {
+            Ok(query_result) if query_result.rows_affected() == 0 => {
+                HttpResponse::NotFound().finish()
+            }
+            Ok(_) => HttpResponse::NoContent().finish(),
+            Err(_) => {
+                error!("UPDATE query failed in delete_comment()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in delete_comment()");
+        HttpResponse::BadGateway().finish()
+    }
+}
@@ -0,0 +1,407 @@
+/// module for scored bouldering competitions, handled by the API endpoint /competitions
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::NaiveDate;
+use std::cmp::{Ordering, Reverse};
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::error::DatabaseError;
+
+/// How the results of a competition are ranked
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum ScoringFormat {
+    /// Most tops, then most zones, then fewest attempts to top, then fewest attempts to zone
+    #[default]
+    Ifsc,
+    /// Most points from topped problems, then most tops, then fewest attempts to top
+    Redpoint,
+}
+
+impl Display for ScoringFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let display_str = match self {
+            Self::Ifsc => "ifsc",
+            Self::Redpoint => "redpoint",
+        };
+        write!(f, "{}", display_str)
+    }
+}
+
+impl FromStr for ScoringFormat {
+    type Err = DatabaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ifsc" => Ok(Self::Ifsc),
+            "redpoint" => Ok(Self::Redpoint),
+            _ => Err(DatabaseError::FailedParseScoringFormat),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct Competition {
+    pub id: Option<i32>,
+    pub name: String,
+    pub gym_id: Option<i32>,
+    #[serde(default)]
+    pub format: ScoringFormat,
+    pub starts_on: NaiveDate,
+    pub ends_on: NaiveDate,
+    /// Only filled in on competition detail
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<Category>,
+    /// Only filled in on competition detail
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub problems: Vec<Problem>,
+}
+
+impl Competition {
+    pub fn check_dates(&self) -> Result<(), DatabaseError> {
+        if self.ends_on < self.starts_on {
+            Err(DatabaseError::CompetitionEndsBeforeStart)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A group of competitors ranked against each other, such as "Open" or "Youth B"
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Category {
+    pub id: Option<i32>,
+    pub name: String,
+}
+
+/// A route in a competition's problem set
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Problem {
+    pub route_id: i32,
+    /// Defaults to the next number after the competition's other problems
+    pub number: Option<i32>,
+    /// What topping the problem scores in the redpoint format. Defaults to the points of the
+    /// route's difficulty.
+    pub points: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct Registration {
+    pub climber_id: i32,
+    pub category_id: i32,
+}
+
+/// One go at a problem. Topping a problem also counts as reaching its zone.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Attempt {
+    pub route_id: i32,
+    /// Numbered from 1 for each problem when the attempt is recorded
+    #[serde(default)]
+    pub attempt_number: Option<i32>,
+    #[serde(default)]
+    pub zone: bool,
+    #[serde(default)]
+    pub top: bool,
+}
+
+/// How a climber has done on one problem
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct ProblemScore {
+    pub number: i32,
+    pub route_id: i32,
+    pub points: i32,
+    /// Attempts made, stopping at the top
+    pub attempts: i32,
+    /// The attempt the zone was first reached on
+    pub zone_attempt: Option<i32>,
+    /// The attempt the problem was topped on
+    pub top_attempt: Option<i32>,
+}
+
+impl ProblemScore {
+    /// Score a problem from a climber's attempts at it, which may be in any order
+    pub fn new(problem: &Problem, attempts: &[Attempt]) -> Self {
+        let mut attempts = attempts
+            .iter()
+            .filter(|attempt| attempt.route_id == problem.route_id)
+            .collect::<Vec<&Attempt>>();
+        attempts.sort_by_key(|attempt| attempt.attempt_number);
+        let attempt_number =
+            |(i, attempt): (usize, &&Attempt)| attempt.attempt_number.unwrap_or(i as i32 + 1);
+        let top_attempt = attempts
+            .iter()
+            .enumerate()
+            .find(|(_, attempt)| attempt.top)
+            .map(attempt_number);
+        let zone_attempt = attempts
+            .iter()
+            .enumerate()
+            .find(|(_, attempt)| attempt.zone || attempt.top)
+            .map(attempt_number);
+        Self {
+            number: problem.number.unwrap_or_default(),
+            route_id: problem.route_id,
+            points: problem.points.unwrap_or_default(),
+            attempts: top_attempt.unwrap_or(attempts.len() as i32),
+            zone_attempt,
+            top_attempt,
+        }
+    }
+}
+
+/// Every problem in a competition, scored for one climber
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct Scorecard {
+    pub climber_id: i32,
+    pub username: String,
+    pub category_id: i32,
+    pub problems: Vec<ProblemScore>,
+}
+
+impl Scorecard {
+    pub fn new(
+        climber_id: i32,
+        username: String,
+        category_id: i32,
+        problems: &[Problem],
+        attempts: &[Attempt],
+    ) -> Self {
+        Self {
+            climber_id,
+            username,
+            category_id,
+            problems: problems
+                .iter()
+                .map(|problem| ProblemScore::new(problem, attempts))
+                .collect(),
+        }
+    }
+
+    /// Totals of the scorecard, not yet ranked
+    pub fn result(&self) -> CompetitorResult {
+        let mut result = CompetitorResult {
+            rank: 0,
+            climber_id: self.climber_id,
+            username: self.username.clone(),
+            tops: 0,
+            zones: 0,
+            top_attempts: 0,
+            zone_attempts: 0,
+            points: 0,
+        };
+        for problem in self.problems.iter() {
+            if let Some(top_attempt) = problem.top_attempt {
+                result.tops += 1;
+                result.top_attempts += top_attempt;
+                result.points += i64::from(problem.points);
+            }
+            if let Some(zone_attempt) = problem.zone_attempt {
+                result.zones += 1;
+                result.zone_attempts += zone_attempt;
+            }
+        }
+        result
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct CompetitorResult {
+    pub rank: i64,
+    pub climber_id: i32,
+    pub username: String,
+    pub tops: i32,
+    pub zones: i32,
+    /// Attempts taken to reach every top, added up
+    pub top_attempts: i32,
+    /// Attempts taken to reach every zone, added up
+    pub zone_attempts: i32,
+    /// Points of every topped problem
+    pub points: i64,
+}
+
+impl CompetitorResult {
+    /// Compare two results best first. Results that are equal share a rank.
+    fn compare(&self, other: &Self, format: ScoringFormat) -> Ordering {
+        match format {
+            ScoringFormat::Ifsc => (
+                Reverse(self.tops),
+                Reverse(self.zones),
+                self.top_attempts,
+                self.zone_attempts,
+            )
+                .cmp(&(
+                    Reverse(other.tops),
+                    Reverse(other.zones),
+                    other.top_attempts,
+                    other.zone_attempts,
+                )),
+            ScoringFormat::Redpoint => {
+                (Reverse(self.points), Reverse(self.tops), self.top_attempts).cmp(&(
+                    Reverse(other.points),
+                    Reverse(other.tops),
+                    other.top_attempts,
+                ))
+            }
+        }
+    }
+}
+
+/// Rank the scorecards of one category, best first
+pub fn rank_competitors(format: ScoringFormat, scorecards: &[Scorecard]) -> Vec<CompetitorResult> {
+    let mut results = scorecards
+        .iter()
+        .map(Scorecard::result)
+        .collect::<Vec<CompetitorResult>>();
+    results.sort_by(|a, b| a.compare(b, format).then(a.climber_id.cmp(&b.climber_id)));
+    for i in 0..results.len() {
+        results[i].rank = if i > 0 && results[i].compare(&results[i - 1], format).is_eq() {
+            results[i - 1].rank
+        } else {
+            i as i64 + 1
+        };
+    }
+    results
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CategoryResults {
+    pub category: Category,
+    pub results: Vec<CompetitorResult>,
+}
+
+/// Body of GET /competitions/{id}/results, ranked from every attempt recorded so far
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CompetitionResults {
+    pub competition_id: i32,
+    pub format: ScoringFormat,
+    pub categories: Vec<CategoryResults>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_problem(route_id: i32, points: i32) -> Problem {
+        Problem {
+            route_id,
+            number: Some(route_id),
+            points: Some(points),
+        }
+    }
+
+    fn test_attempt(route_id: i32, attempt_number: i32, zone: bool, top: bool) -> Attempt {
+        Attempt {
+            route_id,
+            attempt_number: Some(attempt_number),
+            zone,
+            top,
+        }
+    }
+
+    #[test]
+    fn test_problem_score() {
+        let problem = test_problem(1, 100);
+        let attempts = vec![
+            test_attempt(1, 3, false, true),
+            test_attempt(1, 1, false, false),
+            test_attempt(1, 2, true, false),
+            test_attempt(2, 1, true, true),
+        ];
+        let score = ProblemScore::new(&problem, &attempts);
+        assert_eq!(score.attempts, 3);
+        assert_eq!(score.zone_attempt, Some(2));
+        assert_eq!(score.top_attempt, Some(3));
+
+        // a flash reaches the zone on the same attempt
+        let score = ProblemScore::new(&test_problem(2, 100), &attempts);
+        assert_eq!((score.zone_attempt, score.top_attempt), (Some(1), Some(1)));
+
+        let score = ProblemScore::new(&test_problem(3, 100), &attempts);
+        assert_eq!((score.attempts, score.zone_attempt), (0, None));
+    }
+
+    #[test]
+    fn test_rank_competitors() {
+        let problems = vec![test_problem(1, 100), test_problem(2, 500)];
+        let scorecard = |climber_id, attempts: Vec<Attempt>| {
+            Scorecard::new(
+                climber_id,
+                format!("climber{climber_id}"),
+                1,
+                &problems,
+                &attempts,
+            )
+        };
+        let scorecards = vec![
+            // 1: flashed the easy problem, zone on the hard one
+            scorecard(
+                1,
+                vec![
+                    test_attempt(1, 1, true, true),
+                    test_attempt(2, 1, true, false),
+                ],
+            ),
+            // 2: topped the hard problem on the fourth try
+            scorecard(
+                2,
+                vec![
+                    test_attempt(2, 1, false, false),
+                    test_attempt(2, 2, false, false),
+                    test_attempt(2, 3, true, false),
+                    test_attempt(2, 4, true, true),
+                ],
+            ),
+            // 3: exactly the same as 2
+            scorecard(
+                3,
+                vec![
+                    test_attempt(2, 1, false, false),
+                    test_attempt(2, 2, false, false),
+                    test_attempt(2, 3, true, false),
+                    test_attempt(2, 4, true, true),
+                ],
+            ),
+            // 4: nothing yet
+            scorecard(4, vec![]),
+        ];
+
+        let ranked = |format| {
+            rank_competitors(format, &scorecards)
+                .into_iter()
+                .map(|result| (result.rank, result.climber_id))
+                .collect::<Vec<(i64, i32)>>()
+        };
+        assert_eq!(
+            ranked(ScoringFormat::Ifsc),
+            vec![(1, 1), (2, 2), (2, 3), (4, 4)]
+        );
+        assert_eq!(
+            ranked(ScoringFormat::Redpoint),
+            vec![(1, 2), (1, 3), (3, 1), (4, 4)]
+        );
+
+        let result = scorecards[0].result();
+        assert_eq!((result.tops, result.zones), (1, 2));
+        assert_eq!((result.top_attempts, result.zone_attempts), (1, 2));
+        assert_eq!(result.points, 100);
+    }
+
+    #[test]
+    fn test_competition_dates() {
+        let competition = |ends_on| Competition {
+            id: None,
+            name: String::from("Summer Bouldering Series"),
+            gym_id: None,
+            format: ScoringFormat::Ifsc,
+            starts_on: NaiveDate::from_ymd_opt(2023, 6, 3).unwrap(),
+            ends_on,
+            categories: Vec::new(),
+            problems: Vec::new(),
+        };
+        assert!(competition(NaiveDate::from_ymd_opt(2023, 6, 3).unwrap())
+            .check_dates()
+            .is_ok());
+        assert!(competition(NaiveDate::from_ymd_opt(2023, 6, 2).unwrap())
+            .check_dates()
+            .is_err());
+    }
+}
@@ -0,0 +1,582 @@
+use crate::climbers::climber_exists;
+use crate::competition::{
+    rank_competitors, Attempt, Category, CategoryResults, Competition, CompetitionResults, Problem,
+    Registration, Scorecard, ScoringFormat,
+};
+use crate::error::DatabaseError;
+use crate::pg::conn;
+use crate::route::DifficultyRating;
+use actix_web::{get, http::header, post, web, HttpResponse, Responder};
+use log::error;
+/// Functions for the API endpoint /competitions
+use sqlx::{self, postgres::PgConnection, query, Connection};
+use std::str::FromStr;
+
+/// A competition without its categories and problems
+async fn select_competition(
+    conn: &mut PgConnection,
+    id: i32,
+) -> Result<Option<Competition>, DatabaseError> {
+    query!(
+        "SELECT id, name, gym_id, format, starts_on, ends_on FROM competitions WHERE id = ($1)",
+        id
+    )
+    .fetch_optional(conn)
+    .await?
+    .map(|record| {
+        Ok(Competition {
+            id: Some(record.id),
+            name: record.name,
+            gym_id: record.gym_id,
+            format: ScoringFormat::from_str(&record.format)?,
+            starts_on: record.starts_on,
+            ends_on: record.ends_on,
+            categories: Vec::new(),
+            problems: Vec::new(),
+        })
+    })
+    .transpose()
+}
+
+async fn select_categories(
+    conn: &mut PgConnection,
+    competition_id: i32,
+) -> Result<Vec<Category>, DatabaseError> {
+    Ok(query!(
+        "SELECT id, name FROM competition_categories WHERE competition_id = ($1) ORDER BY id",
+        competition_id
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|record| Category {
+        id: Some(record.id),
+        name: record.name,
+    })
+    .collect())
+}
+
+async fn select_problems(
+    conn: &mut PgConnection,
+    competition_id: i32,
+) -> Result<Vec<Problem>, DatabaseError> {
+    Ok(query!(
+        "SELECT route_id, number, points FROM competition_problems WHERE competition_id = ($1) ORDER BY number",
+        competition_id
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|record| Problem {
+        route_id: record.route_id,
+        number: Some(record.number),
+        points: Some(record.points),
+    })
+    .collect())
+}
+
+/// Scorecards of every climber registered for a competition, or of just one of them
+async fn select_scorecards(
+    conn: &mut PgConnection,
+    competition_id: i32,
+    climber_id: Option<i32>,
+) -> Result<Vec<Scorecard>, DatabaseError> {
+    let problems = select_problems(&mut *conn, competition_id).await?;
+    let attempts = query!(
+        r#"SELECT climber_id, route_id, attempt_number, zone, top FROM competition_attempts
+        WHERE competition_id = ($1) AND ($2::int4 IS NULL OR climber_id = $2)"#,
+        competition_id,
+        climber_id,
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|record| {
+        (
+            record.climber_id,
+            Attempt {
+                route_id: record.route_id,
+                attempt_number: Some(record.attempt_number),
+                zone: record.zone,
+                top: record.top,
+            },
+        )
+    })
+    .collect::<Vec<(i32, Attempt)>>();
+    Ok(query!(
+        r#"SELECT registrations.climber_id, climbers.username as "username!", registrations.category_id
+        FROM competition_registrations registrations
+        JOIN climbers ON climbers.id = registrations.climber_id
+        WHERE registrations.competition_id = ($1) AND climbers.deleted_at IS NULL
+        AND ($2::int4 IS NULL OR registrations.climber_id = $2)"#,
+        competition_id,
+        climber_id,
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|record| {
+        let climber_attempts = attempts
+            .iter()
+            .filter(|(attempt_climber_id, _)| *attempt_climber_id == record.climber_id)
+            .map(|(_, attempt)| attempt.clone())
+            .collect::<Vec<Attempt>>();
+        Scorecard::new(
+            record.climber_id,
+            record.username,
+            record.category_id,
+            &problems,
+            &climber_attempts,
+        )
+    })
+    .collect())
+}
+
+#[get("")]
+async fn get_competitions() -> impl Responder {
+    if let Ok(mut conn) = conn().await {
+        if let Ok(query_result) = query!(
+            "SELECT id, name, gym_id, format, starts_on, ends_on FROM competitions ORDER BY starts_on DESC, id"
+        )
+        .fetch_all(&mut conn)
+        .await
+        {
+            let competitions = query_result
+                .into_iter()
+                .map(|record| {
+                    Ok(Competition {
+                        id: Some(record.id),
+                        name: record.name,
+                        gym_id: record.gym_id,
+                        format: ScoringFormat::from_str(&record.format)?,
+                        starts_on: record.starts_on,
+                        ends_on: record.ends_on,
+                        categories: Vec::new(),
+                        problems: Vec::new(),
+                    })
+                })
+                .collect::<Result<Vec<Competition>, DatabaseError>>();
+            match competitions {
+                Ok(competitions) => HttpResponse::Ok().json(competitions),
+                Err(_) => {
+                    error!("Failed to parse a competition in get_competitions()");
+                    HttpResponse::BadGateway().finish()
+                }
+            }
+        } else {
+            error!("SELECT query failed in get_competitions()");
+            HttpResponse::BadGateway().finish()
+        }
+    } else {
+        error!("Failed to connect to the database in get_competitions()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Create a competition. Its categories and problems are added afterwards.
+#[post("")]
+async fn add_new_competition(json: web::Json<Competition>) -> impl Responder {
+    if let Err(e) = json.check_dates() {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+    if let Ok(mut conn) = conn().await {
+        match query!(
+            r#"INSERT INTO competitions (name, gym_id, format, starts_on, ends_on)
+            SELECT $1, $2, $3, $4, $5 WHERE $2::int4 IS NULL OR EXISTS (SELECT 1 FROM gyms WHERE id = $2)
+            RETURNING id"#,
+            json.0.name,
+            json.0.gym_id,
+            json.0.format.to_string(),
+            json.0.starts_on,
+            json.0.ends_on,
+        )
+        .fetch_optional(&mut conn)
+        .await
+        {
+            Ok(Some(query_result)) => {
+                let mut competition = json.into_inner();
+                competition.id = Some(query_result.id);
+                competition.categories = Vec::new();
+                competition.problems = Vec::new();
+                HttpResponse::Created()
+                    .insert_header((
+                        header::LOCATION,
+                        format!("/competitions/{}", query_result.id),
+                    ))
+                    .json(competition)
+            }
+            Ok(None) => HttpResponse::BadRequest().body(DatabaseError::GymNotFound.to_string()),
+            Err(_) => {
+                error!("INSERT query failed in add_new_competition()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in add_new_competition()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+#[get("/{id}")]
+async fn get_competition_by_id(path: web::Path<i32>) -> impl Responder {
+    let id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        let mut competition = match select_competition(&mut conn, id).await {
+            Ok(Some(competition)) => competition,
+            Ok(None) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in get_competition_by_id()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        match (
+            select_categories(&mut conn, id).await,
+            select_problems(&mut conn, id).await,
+        ) {
+            (Ok(categories), Ok(problems)) => {
+                competition.categories = categories;
+                competition.problems = problems;
+                HttpResponse::Ok().json(competition)
+            }
+            _ => {
+                error!(
+                    "SELECT query for categories and problems failed in get_competition_by_id()"
+                );
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in get_competition_by_id()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+#[post("/{id}/categories")]
+async fn add_new_category(path: web::Path<i32>, json: web::Json<Category>) -> impl Responder {
+    let competition_id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match query!(
+            r#"INSERT INTO competition_categories (competition_id, name)
+            SELECT $1, $2 WHERE EXISTS (SELECT 1 FROM competitions WHERE id = ($1))
+            RETURNING id"#,
+            competition_id,
+            json.0.name,
+        )
+        .fetch_optional(&mut conn)
+        .await
+        {
+            Ok(Some(query_result)) => {
+                let mut category = json.into_inner();
+                category.id = Some(query_result.id);
+                HttpResponse::Created()
+                    .insert_header((header::LOCATION, format!("/competitions/{competition_id}")))
+                    .json(category)
+            }
+            Ok(None) => HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("INSERT query failed in add_new_category()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in add_new_category()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Add a route to a competition's problem set, numbered after the other problems and worth the
+/// points of its difficulty unless the body says otherwise
+#[post("/{id}/problems")]
+async fn add_new_problem(path: web::Path<i32>, json: web::Json<Problem>) -> impl Responder {
+    let competition_id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match select_competition(&mut conn, competition_id).await {
+            Ok(Some(_)) => (),
+            Ok(None) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in add_new_problem()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        let difficulty = match query!(
+            r#"SELECT difficulty as "difficulty!" FROM routes WHERE id = ($1) AND deleted_at IS NULL"#,
+            json.0.route_id
+        )
+        .fetch_optional(&mut conn)
+        .await
+        {
+            Ok(Some(record)) => match DifficultyRating::from_str(&record.difficulty) {
+                Ok(difficulty) => difficulty,
+                Err(_) => {
+                    error!("Failed to parse a difficulty in add_new_problem()");
+                    return HttpResponse::BadGateway().finish();
+                }
+            },
+            Ok(None) => {
+                return HttpResponse::BadRequest().body(DatabaseError::RouteNotFound.to_string())
+            }
+            Err(_) => {
+                error!("SELECT query for the route failed in add_new_problem()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        let points = json.0.points.unwrap_or(difficulty.points() as i32);
+        match query!(
+            r#"INSERT INTO competition_problems (competition_id, route_id, number, points)
+            VALUES ($1, $2, COALESCE($3, (SELECT COALESCE(max(number), 0) + 1 FROM competition_problems WHERE competition_id = $1)), $4)
+            ON CONFLICT DO NOTHING
+            RETURNING number"#,
+            competition_id,
+            json.0.route_id,
+            json.0.number,
+            points,
+        )
+        .fetch_optional(&mut conn)
+        .await
+        {
+            Ok(Some(query_result)) => HttpResponse::Created()
+                .insert_header((
+                    header::LOCATION,
+                    format!("/competitions/{competition_id}"),
+                ))
+                .json(Problem {
+                    route_id: json.0.route_id,
+                    number: Some(query_result.number),
+                    points: Some(points),
+                }),
+            Ok(None) => HttpResponse::Conflict()
+                .body(DatabaseError::ProblemAlreadyInCompetition.to_string()),
+            Err(_) => {
+                error!("INSERT query failed in add_new_problem()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in add_new_problem()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Register a climber in one of a competition's categories. Registering again moves them to the
+/// new category.
+#[post("/{id}/registrations")]
+async fn add_new_registration(
+    path: web::Path<i32>,
+    json: web::Json<Registration>,
+) -> impl Responder {
+    let competition_id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match select_competition(&mut conn, competition_id).await {
+            Ok(Some(_)) => (),
+            Ok(None) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in add_new_registration()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        match climber_exists(&mut conn, json.0.climber_id).await {
+            Ok(true) => (),
+            Ok(false) => {
+                return HttpResponse::BadRequest().body(DatabaseError::ClimberNotFound.to_string())
+            }
+            Err(_) => {
+                error!("SELECT query for the climber failed in add_new_registration()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        match query!(
+            r#"INSERT INTO competition_registrations (competition_id, climber_id, category_id)
+            SELECT $1, $2, $3 WHERE EXISTS (SELECT 1 FROM competition_categories WHERE id = ($3) AND competition_id = ($1))
+            ON CONFLICT (competition_id, climber_id) DO UPDATE SET category_id = EXCLUDED.category_id
+            RETURNING climber_id"#,
+            competition_id,
+            json.0.climber_id,
+            json.0.category_id,
+        )
+        .fetch_optional(&mut conn)
+        .await
+        {
+            Ok(Some(query_result)) => HttpResponse::Created()
+                .insert_header((
+                    header::LOCATION,
+                    format!(
+                        "/competitions/{competition_id}/scorecards/{}",
+                        query_result.climber_id
+                    ),
+                ))
+                .json(json.into_inner()),
+            Ok(None) => HttpResponse::BadRequest()
+                .body(DatabaseError::CategoryNotInCompetition.to_string()),
+            Err(_) => {
+                error!("INSERT query failed in add_new_registration()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in add_new_registration()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Insert a climber's next attempt at a problem, returning its number, or the response to give
+/// if it can't be recorded. The climber's registration is locked first, so that attempts sent at
+/// the same time are numbered one after the other and can't both top the problem.
+async fn insert_attempt(
+    conn: &mut PgConnection,
+    competition_id: i32,
+    climber_id: i32,
+    attempt: &Attempt,
+) -> Result<Result<i32, HttpResponse>, DatabaseError> {
+    let mut transaction = conn.begin().await?;
+    let registered = query!(
+        "SELECT climber_id FROM competition_registrations WHERE competition_id = ($1) AND climber_id = ($2) FOR UPDATE",
+        competition_id,
+        climber_id,
+    )
+    .fetch_optional(&mut transaction)
+    .await?
+    .is_some();
+    let record = query!(
+        r#"SELECT
+        EXISTS (SELECT 1 FROM competitions WHERE id = ($1)) as "competition_exists!",
+        EXISTS (SELECT 1 FROM competitions WHERE id = ($1) AND current_date BETWEEN starts_on AND ends_on) as "running!",
+        EXISTS (SELECT 1 FROM competition_problems WHERE competition_id = ($1) AND route_id = ($3)) as "problem!",
+        EXISTS (SELECT 1 FROM competition_attempts WHERE competition_id = ($1) AND climber_id = ($2) AND route_id = ($3) AND top) as "topped!",
+        (SELECT COALESCE(max(attempt_number), 0) + 1 FROM competition_attempts WHERE competition_id = ($1) AND climber_id = ($2) AND route_id = ($3)) as "attempt_number!""#,
+        competition_id,
+        climber_id,
+        attempt.route_id,
+    )
+    .fetch_one(&mut transaction)
+    .await?;
+    if !record.competition_exists {
+        return Ok(Err(HttpResponse::NotFound().finish()));
+    }
+    let bad_request = if !record.running {
+        Some(DatabaseError::CompetitionNotRunning)
+    } else if !registered {
+        Some(DatabaseError::ClimberNotRegistered)
+    } else if !record.problem {
+        Some(DatabaseError::RouteNotAProblem)
+    } else if record.topped {
+        Some(DatabaseError::ProblemAlreadyTopped)
+    } else {
+        None
+    };
+    if let Some(e) = bad_request {
+        return Ok(Err(HttpResponse::BadRequest().body(e.to_string())));
+    }
+    query!(
+        r#"INSERT INTO competition_attempts (competition_id, climber_id, route_id, attempt_number, zone, top)
+        VALUES ($1, $2, $3, $4, $5, $6)"#,
+        competition_id,
+        climber_id,
+        attempt.route_id,
+        record.attempt_number,
+        attempt.zone,
+        attempt.top,
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(Ok(record.attempt_number))
+}
+
+/// Record a registered climber's next attempt at a problem, until they top it. Attempts are only
+/// recorded from the day the competition starts to the day it ends.
+#[post("/{id}/scorecards/{climber_id}/attempts")]
+async fn add_new_attempt(path: web::Path<(i32, i32)>, json: web::Json<Attempt>) -> impl Responder {
+    let (competition_id, climber_id) = path.into_inner();
+    let mut attempt = json.into_inner();
+    attempt.zone = attempt.zone || attempt.top;
+    if let Ok(mut conn) = conn().await {
+        match insert_attempt(&mut conn, competition_id, climber_id, &attempt).await {
+            Ok(Ok(attempt_number)) => {
+                attempt.attempt_number = Some(attempt_number);
+                HttpResponse::Created()
+                    .insert_header((
+                        header::LOCATION,
+                        format!("/competitions/{competition_id}/scorecards/{climber_id}"),
+                    ))
+                    .json(attempt)
+            }
+            Ok(Err(response)) => response,
+            Err(_) => {
+                error!("INSERT query failed in add_new_attempt()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in add_new_attempt()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+#[get("/{id}/scorecards/{climber_id}")]
+async fn get_scorecard(path: web::Path<(i32, i32)>) -> impl Responder {
+    let (competition_id, climber_id) = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match select_scorecards(&mut conn, competition_id, Some(climber_id)).await {
+            Ok(mut scorecards) => match scorecards.pop() {
+                Some(scorecard) => HttpResponse::Ok().json(scorecard),
+                None => HttpResponse::NotFound().finish(),
+            },
+            Err(_) => {
+                error!("SELECT query failed in get_scorecard()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in get_scorecard()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Live results of every category, ranked from the attempts recorded so far
+#[get("/{id}/results")]
+async fn get_competition_results(path: web::Path<i32>) -> impl Responder {
+    let competition_id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        let competition = match select_competition(&mut conn, competition_id).await {
+            Ok(Some(competition)) => competition,
+            Ok(None) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in get_competition_results()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        match (
+            select_categories(&mut conn, competition_id).await,
+            select_scorecards(&mut conn, competition_id, None).await,
+        ) {
+            (Ok(categories), Ok(mut scorecards)) => {
+                let categories = categories
+                    .into_iter()
+                    .map(|category| {
+                        let (category_scorecards, rest): (Vec<Scorecard>, Vec<Scorecard>) =
+                            scorecards
+                                .drain(..)
+                                .partition(|scorecard| Some(scorecard.category_id) == category.id);
+                        scorecards = rest;
+                        CategoryResults {
+                            results: rank_competitors(competition.format, &category_scorecards),
+                            category,
+                        }
+                    })
+                    .collect();
+                HttpResponse::Ok().json(CompetitionResults {
+                    competition_id,
+                    format: competition.format,
+                    categories,
+                })
+            }
+            _ => {
+                error!("SELECT query for scorecards failed in get_competition_results()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in get_competition_results()");
+        HttpResponse::BadGateway().finish()
+    }
+}
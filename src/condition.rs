@@ -0,0 +1,167 @@
+/// module for condition and access reports on routes and areas, handled by the API endpoints
+/// /routes/{id}/conditions and /areas/{id}/conditions
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::NaiveDateTime;
+use std::env;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::error::DatabaseError;
+
+pub const DEFAULT_CONDITION_REPORT_HOURS: i32 = 72;
+/// Longest a report can be posted for
+pub const MAX_CONDITION_REPORT_HOURS: i32 = 365 * 24;
+
+/// How long reports last unless they say otherwise, from the CONDITION_REPORT_HOURS environment
+/// variable, or DEFAULT_CONDITION_REPORT_HOURS if it is missing or not a number
+pub fn condition_report_hours() -> i32 {
+    env::var("CONDITION_REPORT_HOURS")
+        .ok()
+        .and_then(|hours| hours.parse::<i32>().ok())
+        .unwrap_or(DEFAULT_CONDITION_REPORT_HOURS)
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ConditionStatus {
+    Dry,
+    Wet,
+    Closed,
+    /// Closed while raptors are nesting
+    RaptorClosure,
+    /// Open again after a closure
+    Open,
+}
+
+impl ConditionStatus {
+    /// Statuses that close a route, which outrank newer reports of any other status until a newer
+    /// report says the route is Open again
+    pub fn closures() -> Vec<String> {
+        vec![Self::Closed.to_string(), Self::RaptorClosure.to_string()]
+    }
+}
+
+impl Display for ConditionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let display_str = match self {
+            Self::Dry => "dry",
+            Self::Wet => "wet",
+            Self::Closed => "closed",
+            Self::RaptorClosure => "raptor-closure",
+            Self::Open => "open",
+        };
+        write!(f, "{}", display_str)
+    }
+}
+
+impl FromStr for ConditionStatus {
+    type Err = DatabaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dry" => Ok(Self::Dry),
+            "wet" => Ok(Self::Wet),
+            "closed" => Ok(Self::Closed),
+            "raptor-closure" => Ok(Self::RaptorClosure),
+            "open" => Ok(Self::Open),
+            _ => Err(DatabaseError::FailedParseConditionStatus),
+        }
+    }
+}
+
+/// What a condition report is about
+#[derive(Debug, Clone, Copy)]
+pub enum ConditionOwner {
+    Route(i32),
+    Area(i32),
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct ConditionReport {
+    pub id: Option<i32>,
+    /// The climber reporting the conditions
+    pub climber_id: i32,
+    /// Taken from the path when a report is posted
+    #[serde(default)]
+    pub route_id: Option<i32>,
+    /// Taken from the path when a report is posted
+    #[serde(default)]
+    pub area_id: Option<i32>,
+    pub status: ConditionStatus,
+    #[serde(default)]
+    pub note: String,
+    /// Only used when a report is posted: how long until it expires, defaulting to
+    /// CONDITION_REPORT_HOURS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_in_hours: Option<i32>,
+    /// Ignored on writes
+    #[serde(default)]
+    pub created_at: Option<NaiveDateTime>,
+    /// Ignored on writes
+    #[serde(default)]
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+impl ConditionReport {
+    /// Hours until the report expires, if it's between an hour and a year
+    pub fn expiry_hours(&self) -> Result<i32, DatabaseError> {
+        let hours = self.expires_in_hours.unwrap_or_else(condition_report_hours);
+        if (1..=MAX_CONDITION_REPORT_HOURS).contains(&hours) {
+            Ok(hours)
+        } else {
+            Err(DatabaseError::ConditionExpiryOutOfRange)
+        }
+    }
+}
+
+/// Query parameters for listing condition reports
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+pub struct ConditionQuery {
+    /// Also list reports that have expired
+    pub include_expired: Option<bool>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_report(expires_in_hours: Option<i32>) -> ConditionReport {
+        ConditionReport {
+            id: None,
+            climber_id: 1,
+            route_id: None,
+            area_id: None,
+            status: ConditionStatus::RaptorClosure,
+            note: "peregrines nesting on the upper wall".to_string(),
+            expires_in_hours,
+            created_at: None,
+            expires_at: None,
+        }
+    }
+
+    #[test]
+    fn test_condition_status_round_trip() {
+        for status in [
+            ConditionStatus::Dry,
+            ConditionStatus::Wet,
+            ConditionStatus::Closed,
+            ConditionStatus::RaptorClosure,
+            ConditionStatus::Open,
+        ] {
+            assert_eq!(
+                ConditionStatus::from_str(&status.to_string()).unwrap(),
+                status
+            );
+        }
+        assert!(ConditionStatus::from_str("icy").is_err());
+    }
+
+    #[test]
+    fn test_expiry_hours() {
+        assert_eq!(test_report(Some(24)).expiry_hours().unwrap(), 24);
+        assert!(test_report(None).expiry_hours().is_ok());
+        assert!(test_report(Some(0)).expiry_hours().is_err());
+        assert!(test_report(Some(MAX_CONDITION_REPORT_HOURS + 1))
+            .expiry_hours()
+            .is_err());
+    }
+}
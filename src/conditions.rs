@@ -0,0 +1,187 @@
+use crate::climbers::climber_exists;
+use crate::condition::{ConditionOwner, ConditionQuery, ConditionReport, ConditionStatus};
+use crate::error::DatabaseError;
+use crate::pg::conn;
+use actix_web::{get, http::header, post, web, HttpResponse, Responder};
+use log::error;
+/// Functions for the API endpoints /routes/{id}/conditions and /areas/{id}/conditions
+use sqlx::{self, postgres::PgConnection, query};
+use std::str::FromStr;
+
+/// Whether the route or area a report is about exists
+async fn owner_exists(
+    conn: &mut PgConnection,
+    owner: ConditionOwner,
+) -> Result<bool, DatabaseError> {
+    Ok(match owner {
+        ConditionOwner::Route(id) => query!(
+            "SELECT id FROM routes WHERE id = ($1) AND deleted_at IS NULL",
+            id
+        )
+        .fetch_optional(conn)
+        .await?
+        .is_some(),
+        ConditionOwner::Area(id) => query!("SELECT id FROM areas WHERE id = ($1)", id)
+            .fetch_optional(conn)
+            .await?
+            .is_some(),
+    })
+}
+
+async fn add_report(owner: ConditionOwner, json: web::Json<ConditionReport>) -> HttpResponse {
+    let hours = match json.expiry_hours() {
+        Ok(hours) => hours,
+        Err(e) => return HttpResponse::BadRequest().body(e.to_string()),
+    };
+    let (route_id, area_id, location) = match owner {
+        ConditionOwner::Route(id) => (Some(id), None, format!("/routes/{id}/conditions")),
+        ConditionOwner::Area(id) => (None, Some(id), format!("/areas/{id}/conditions")),
+    };
+    if let Ok(mut conn) = conn().await {
+        match owner_exists(&mut conn, owner).await {
+            Ok(true) => (),
+            Ok(false) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in add_report()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        match climber_exists(&mut conn, json.climber_id).await {
+            Ok(true) => (),
+            Ok(false) => {
+                return HttpResponse::BadRequest().body(DatabaseError::ClimberNotFound.to_string())
+            }
+            Err(_) => {
+                error!("SELECT query for the climber failed in add_report()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        match query!(
+            r#"INSERT INTO condition_reports (climber_id, route_id, area_id, status, note, expires_at)
+            VALUES ($1, $2, $3, $4, $5, current_timestamp + make_interval(hours => $6))
+            RETURNING id, created_at, expires_at"#,
+            json.0.climber_id,
+            route_id,
+            area_id,
+            json.0.status.to_string(),
+            json.0.note,
+            hours,
+        )
+        .fetch_one(&mut conn)
+        .await
+        {
+            Ok(query_result) => {
+                let mut report = json.into_inner();
+                report.id = Some(query_result.id);
+                report.route_id = route_id;
+                report.area_id = area_id;
+                report.expires_in_hours = None;
+                report.created_at = Some(query_result.created_at);
+                report.expires_at = Some(query_result.expires_at);
+                HttpResponse::Created()
+                    .insert_header((header::LOCATION, location))
+                    .json(report)
+            }
+            Err(_) => {
+                error!("INSERT query failed in add_report()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in add_report()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+async fn list_reports(owner: ConditionOwner, filter: &ConditionQuery) -> HttpResponse {
+    let (route_id, area_id) = match owner {
+        ConditionOwner::Route(id) => (Some(id), None),
+        ConditionOwner::Area(id) => (None, Some(id)),
+    };
+    if let Ok(mut conn) = conn().await {
+        match owner_exists(&mut conn, owner).await {
+            Ok(true) => (),
+            Ok(false) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in list_reports()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        let reports = query!(
+            r#"SELECT id, climber_id, route_id, area_id, status, note, created_at, expires_at FROM condition_reports
+            WHERE ($1::int4 IS NULL OR route_id = $1)
+            AND ($2::int4 IS NULL OR area_id = $2)
+            AND ($3::bool IS TRUE OR expires_at > current_timestamp)
+            ORDER BY created_at DESC, id DESC"#,
+            route_id,
+            area_id,
+            filter.include_expired,
+        )
+        .fetch_all(&mut conn)
+        .await
+        .map_err(DatabaseError::from)
+        .and_then(|query_result| {
+            query_result
+                .into_iter()
+                .map(|record| {
+                    Ok(ConditionReport {
+                        id: Some(record.id),
+                        climber_id: record.climber_id,
+                        route_id: record.route_id,
+                        area_id: record.area_id,
+                        status: ConditionStatus::from_str(&record.status)?,
+                        note: record.note,
+                        expires_in_hours: None,
+                        created_at: Some(record.created_at),
+                        expires_at: Some(record.expires_at),
+                    })
+                })
+                .collect::<Result<Vec<ConditionReport>, DatabaseError>>()
+        });
+        if let Ok(reports) = reports {
+            HttpResponse::Ok().json(reports)
+        } else {
+            error!("SELECT query failed in list_reports()");
+            HttpResponse::BadGateway().finish()
+        }
+    } else {
+        error!("Failed to connect to the database in list_reports()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Report the conditions on a route
+#[post("/{id}/conditions")]
+async fn add_route_condition(
+    path: web::Path<i32>,
+    json: web::Json<ConditionReport>,
+) -> impl Responder {
+    add_report(ConditionOwner::Route(path.into_inner()), json).await
+}
+
+/// Condition reports on a route that haven't expired, newest first. Reports on its areas are
+/// listed under the areas.
+#[get("/{id}/conditions")]
+async fn get_route_conditions(
+    path: web::Path<i32>,
+    query: web::Query<ConditionQuery>,
+) -> impl Responder {
+    list_reports(ConditionOwner::Route(path.into_inner()), &query).await
+}
+
+/// Report the conditions or access in an area, which apply to every route in it and its subareas
+#[post("/{id}/conditions")]
+async fn add_area_condition(
+    path: web::Path<i32>,
+    json: web::Json<ConditionReport>,
+) -> impl Responder {
+    add_report(ConditionOwner::Area(path.into_inner()), json).await
+}
+
+#[get("/{id}/conditions")]
+async fn get_area_conditions(
+    path: web::Path<i32>,
+    query: web::Query<ConditionQuery>,
+) -> impl Responder {
+    list_reports(ConditionOwner::Area(path.into_inner()), &query).await
+}
@@ -5,7 +5,70 @@ use thiserror::Error;
 pub enum DatabaseError {
     FailedConnection(#[from] sqlx::Error),
     FailedParseDifficultyRating,
+    FailedParseDiscipline,
+    FailedParseGradeScale,
+    FailedParseLeaderboard,
+    FailedParseScoringFormat,
+    FailedParsePriority,
+    FailedParseConditionStatus,
+    FailedParseFlagReason,
+    FailedParseModerationAction,
+    FailedParseRevisionAction,
+    FailedParseRevision,
+    FailedParseSearchResultType,
+    CannotFollowSelf,
+    FailedParseTopo,
+    FailedParseVisibility,
+    GradeNotValidForDiscipline,
     UpdateRouteAllNone,
+    PatchDerivedFromPitches,
+    UpdateReviewAllNone,
+    LedPitchNotOnRoute,
+    StripDateBeforeSetDate,
+    FromDateAfterToDate,
+    LeaderboardNeedsGradeScale,
+    GradeScaleNotInDiscipline,
+    SetterNotFound,
+    AreaNotFound,
+    WallNotFound,
+    RouteSetNotFound,
+    RouteSetNotOnWall,
+    CompetitionEndsBeforeStart,
+    GymNotFound,
+    ClimberNotFound,
+    RouteNotFound,
+    CategoryNotInCompetition,
+    ProblemAlreadyInCompetition,
+    ClimberNotRegistered,
+    CompetitionNotRunning,
+    RouteNotAProblem,
+    ProblemAlreadyTopped,
+    RouteAlreadyOnTicklist,
+    ConditionExpiryOutOfRange,
+    CannotFlagOwnReview,
+    ReviewAlreadyFlagged,
+    ModeratorMissing,
+    NotAModerator,
+    EditorRequired,
+    ModerationReasonMissing,
+    ModerationReasonTooLong,
+    ReviewTooLong,
+    ReviewOnlyForEdits,
+    ReviewAlreadyHidden,
+    ReviewNotHidden,
+    CannotVoteOwnReview,
+    SearchQueryEmpty,
+    CommentBodyInvalid,
+    CommentTooDeep,
+    ParentCommentNotFound,
+    CannotFlagOwnComment,
+    CommentAlreadyFlagged,
+    CommentAlreadyHidden,
+    CommentNotHidden,
+    CannotEditHiddenComment,
+    CannotEditHiddenReview,
+    CannotMergeRouteIntoItself,
+    MergedGradeNotValidForTarget,
 }
 
 impl Display for DatabaseError {
@@ -15,9 +78,156 @@ impl Display for DatabaseError {
             Self::FailedParseDifficultyRating => {
                 "Failed to parse a difficulty rating from the database"
             }
+            Self::FailedParseDiscipline => "Failed to parse a discipline from the database",
+            Self::FailedParseGradeScale => "Failed to parse a grade scale from the database",
+            Self::FailedParseLeaderboard => {
+                "Failed to parse a cached leaderboard from the database"
+            }
+            Self::FailedParseScoringFormat => {
+                "Failed to parse a competition's scoring format from the database"
+            }
+            Self::FailedParsePriority => "Failed to parse a ticklist priority from the database",
+            Self::FailedParseConditionStatus => {
+                "Failed to parse a condition report's status from the database"
+            }
+            Self::FailedParseFlagReason => {
+                "Failed to parse a review flag's reason from the database"
+            }
+            Self::FailedParseModerationAction => {
+                "Failed to parse a moderation action from the database"
+            }
+            Self::FailedParseRevisionAction => {
+                "Failed to parse a revision action from the database"
+            }
+            Self::FailedParseRevision => "Failed to read or write the changes of a route revision",
+            Self::FailedParseSearchResultType => {
+                "Failed to parse a search result type from the database"
+            }
+            Self::CannotFollowSelf => "A climber can't follow themselves",
+            Self::FailedParseTopo => "Failed to parse a photo's topo from the database",
+            Self::FailedParseVisibility => "Failed to parse a visibility from the database",
+            Self::GradeNotValidForDiscipline => {
+                "The route's difficulty is not in a grade scale used by its discipline"
+            }
             Self::UpdateRouteAllNone => {
                 "All fields were None when trying to update a route in the database"
             }
+            Self::PatchDerivedFromPitches => {
+                "A route with pitches takes its difficulty, length and bolts from them, so they can only be changed by replacing its pitches"
+            }
+            Self::UpdateReviewAllNone => {
+                "All fields were None when trying to update a review in the database"
+            }
+            Self::LedPitchNotOnRoute => "A led pitch is not one of the route's pitches",
+            Self::FromDateAfterToDate => "The from date is after the to date",
+            Self::LeaderboardNeedsGradeScale => {
+                "Ranking by points or the hardest climb needs a scale, or a discipline with only one"
+            }
+            Self::GradeScaleNotInDiscipline => "The grade scale is not used by the discipline",
+            Self::StripDateBeforeSetDate => "The route's strip date is before its set date",
+            Self::SetterNotFound => "The route's setter is not a climber",
+            Self::AreaNotFound => "The area does not exist",
+            Self::WallNotFound => "The route's wall does not exist",
+            Self::RouteSetNotFound => "The route's route set does not exist",
+            Self::RouteSetNotOnWall => "The route's route set is on a different wall",
+            Self::CompetitionEndsBeforeStart => "The competition ends before it starts",
+            Self::GymNotFound => "The gym does not exist",
+            Self::ClimberNotFound => "The climber does not exist",
+            Self::RouteNotFound => "The route does not exist",
+            Self::CategoryNotInCompetition => "The category is not one of the competition's",
+            Self::ProblemAlreadyInCompetition => {
+                "The route or problem number is already in the competition"
+            }
+            Self::ClimberNotRegistered => "The climber is not registered for the competition",
+            Self::CompetitionNotRunning => {
+                "Attempts can only be recorded while the competition is running"
+            }
+            Self::RouteNotAProblem => "The route is not one of the competition's problems",
+            Self::ProblemAlreadyTopped => "The climber has already topped the problem",
+            Self::RouteAlreadyOnTicklist => "The route is already on the climber's ticklist",
+            Self::ConditionExpiryOutOfRange => {
+                "A condition report has to expire between 1 hour and 1 year after it is posted"
+            }
+            Self::CannotFlagOwnReview => "A climber can't flag their own review",
+            Self::ReviewAlreadyFlagged => "The climber has already flagged the review",
+            Self::ModeratorMissing => "A moderation action needs the moderator's climber id",
+            Self::NotAModerator => "Only moderators can moderate reviews and comments",
+            Self::EditorRequired => "A route can only be changed by a climber given as the editor_id",
+            Self::ModerationReasonMissing => "A moderation action needs a reason",
+            Self::ModerationReasonTooLong => "A moderation reason can be at most 1000 characters",
+            Self::ReviewTooLong => "A review can be at most 1000 characters",
+            Self::ReviewOnlyForEdits => "Only an edit has a new review, and every edit needs one",
+            Self::ReviewAlreadyHidden => "The review is already hidden",
+            Self::ReviewNotHidden => "The review is not hidden",
+            Self::CannotVoteOwnReview => "A climber can't mark their own review helpful",
+            Self::SearchQueryEmpty => "The search has no words to search for",
+            Self::CommentBodyInvalid => {
+                "A comment has to say something, in at most 2000 characters"
+            }
+            Self::CommentTooDeep => "The comment is already as deep in its thread as replies go",
+            Self::ParentCommentNotFound => {
+                "The comment being replied to doesn't exist or isn't on the same route or area"
+            }
+            Self::CannotFlagOwnComment => "A climber can't flag their own comment",
+            Self::CommentAlreadyFlagged => "The climber has already flagged the comment",
+            Self::CommentAlreadyHidden => "The comment is already hidden",
+            Self::CommentNotHidden => "The comment is not hidden",
+            Self::CannotEditHiddenComment => {
+                "A comment hidden by a moderator can't be edited unless it is restored"
+            }
+            Self::CannotEditHiddenReview => {
+                "A review hidden by a moderator can't be edited unless it is restored"
+            }
+            Self::CannotMergeRouteIntoItself => "A route can't be merged into itself",
+            Self::MergedGradeNotValidForTarget => {
+                "The grade of the merged route isn't valid for the discipline of the route it is merged into"
+            }
+        };
+        write!(f, "{}", display_str)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum GpsFileError {
+    MalformedXml(#[from] quick_xml::Error),
+    InvalidCoordinates,
+    WrongDocumentType,
+}
+
+impl Display for GpsFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let display_str = match self {
+            Self::MalformedXml(_) => "Failed to parse the file as XML",
+            Self::InvalidCoordinates => {
+                "A waypoint in the file is missing a valid latitude or longitude"
+            }
+            Self::WrongDocumentType => {
+                "The file is not the GPX or KML document its content type claims"
+            }
+        };
+        write!(f, "{}", display_str)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum PhotoError {
+    InvalidImage(#[from] image::ImageError),
+    ImageTooLarge,
+    TopoPointsInvalid,
+    TopoOnlyOnRoutePhotos,
+}
+
+impl Display for PhotoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let display_str = match self {
+            Self::InvalidImage(_) => {
+                "The file is not the JPEG or PNG image its content type claims"
+            }
+            Self::ImageTooLarge => "The photo is more than 8192 pixels across or down",
+            Self::TopoPointsInvalid => {
+                "Every topo line needs at least two points, each between 0 and 1 across and down"
+            }
+            Self::TopoOnlyOnRoutePhotos => "Only photos of routes can have a topo",
         };
         write!(f, "{}", display_str)
     }
@@ -0,0 +1,77 @@
+/// module for the ETag, If-Match and If-None-Match handling shared by /routes and /climbers. Each
+/// row's version column is its ETag, and is incremented by every write to the row.
+use actix_web::http::header::{EntityTag, Header, IfMatch, IfNoneMatch};
+use actix_web::HttpRequest;
+
+/// Strong ETag for a row's version column
+pub fn etag(version: i32) -> EntityTag {
+    EntityTag::new_strong(version.to_string())
+}
+
+/// Strong ETag for a row shown along with values derived from other tables, which change without
+/// changing the row's version. The version comes first, so the tag still works in If-Match.
+pub fn derived_etag(version: i32, derived: &[&str]) -> EntityTag {
+    EntityTag::new_strong(format!("{version}-{}", derived.join("-")))
+}
+
+/// Row versions allowed by the request's If-Match header, to be used in a `version = ANY($n)`
+/// condition. None if there is no If-Match header or it is `*`, meaning any version is allowed.
+/// Derived values in a tag are ignored, since they can't be written.
+pub fn if_match_versions(request: &HttpRequest) -> Option<Vec<i32>> {
+    match IfMatch::parse(request) {
+        Ok(IfMatch::Items(etags)) if !etags.is_empty() => Some(
+            etags
+                .iter()
+                .filter(|etag| !etag.weak)
+                .filter_map(|etag| etag.tag().split('-').next()?.parse::<i32>().ok())
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+/// Whether the request's If-None-Match header already has the row's current ETag, in which case a
+/// GET can respond 304 Not Modified
+pub fn not_modified(request: &HttpRequest, current: &EntityTag) -> bool {
+    match IfNoneMatch::parse(request) {
+        Ok(IfNoneMatch::Any) => true,
+        Ok(IfNoneMatch::Items(etags)) => etags.iter().any(|tag| tag.weak_eq(current)),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::header, test::TestRequest};
+
+    #[test]
+    fn test_if_match_versions() {
+        let request = TestRequest::default().to_http_request();
+        assert_eq!(if_match_versions(&request), None);
+
+        let request = TestRequest::default()
+            .insert_header((header::IF_MATCH, "*"))
+            .to_http_request();
+        assert_eq!(if_match_versions(&request), None);
+
+        let request = TestRequest::default()
+            .insert_header((header::IF_MATCH, r#""3", W/"4", "five", "6-closed""#))
+            .to_http_request();
+        assert_eq!(if_match_versions(&request), Some(vec![3, 6]));
+    }
+
+    #[test]
+    fn test_not_modified() {
+        let request = TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, r#""1", W/"2""#))
+            .to_http_request();
+        assert!(not_modified(&request, &etag(1)));
+        assert!(not_modified(&request, &etag(2)));
+        assert!(!not_modified(&request, &etag(3)));
+        assert!(!not_modified(&request, &derived_etag(1, &["closed"])));
+
+        let request = TestRequest::default().to_http_request();
+        assert!(!not_modified(&request, &etag(1)));
+    }
+}
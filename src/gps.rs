@@ -0,0 +1,361 @@
+/// module for reading and writing the GPX and KML files used by GPS devices and mapping apps
+use quick_xml::escape::escape;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+
+use crate::error::GpsFileError;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum GpsFormat {
+    Gpx,
+    Kml,
+}
+
+impl GpsFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Gpx => "application/gpx+xml",
+            Self::Kml => "application/vnd.google-earth.kml+xml",
+        }
+    }
+
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            Self::Gpx => "gpx",
+            Self::Kml => "kml",
+        }
+    }
+
+    /// Pick a format from a Content-Type header, ignoring any parameters such as charset
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        match content_type.split(';').next()?.trim() {
+            "application/gpx+xml" => Some(Self::Gpx),
+            "application/vnd.google-earth.kml+xml" => Some(Self::Kml),
+            _ => None,
+        }
+    }
+
+    pub fn write(&self, waypoints: &[Waypoint]) -> String {
+        match self {
+            Self::Gpx => write_gpx(waypoints),
+            Self::Kml => write_kml(waypoints),
+        }
+    }
+
+    pub fn read(&self, document: &str) -> Result<Vec<Waypoint>, GpsFileError> {
+        match self {
+            Self::Gpx => read_gpx(document),
+            Self::Kml => read_kml(document),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Waypoint {
+    pub name: String,
+    pub description: Option<String>,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Waypoint {
+    pub fn new(name: String, description: Option<String>, latitude: f64, longitude: f64) -> Self {
+        Self {
+            name,
+            description,
+            latitude,
+            longitude,
+        }
+    }
+}
+
+pub fn write_gpx(waypoints: &[Waypoint]) -> String {
+    let mut gpx = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<gpx version=\"1.1\" creator=\"restful-climbing\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n",
+    );
+    for waypoint in waypoints {
+        gpx.push_str(&format!(
+            "  <wpt lat=\"{}\" lon=\"{}\">\n    <name>{}</name>\n",
+            waypoint.latitude,
+            waypoint.longitude,
+            escape(&waypoint.name)
+        ));
+        if let Some(description) = &waypoint.description {
+            gpx.push_str(&format!("    <desc>{}</desc>\n", escape(description)));
+        }
+        gpx.push_str("  </wpt>\n");
+    }
+    gpx.push_str("</gpx>\n");
+    gpx
+}
+
+pub fn write_kml(waypoints: &[Waypoint]) -> String {
+    let mut kml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<kml xmlns=\"http://www.opengis.net/kml/2.2\">\n  <Document>\n",
+    );
+    for waypoint in waypoints {
+        kml.push_str(&format!(
+            "    <Placemark>\n      <name>{}</name>\n",
+            escape(&waypoint.name)
+        ));
+        if let Some(description) = &waypoint.description {
+            kml.push_str(&format!(
+                "      <description>{}</description>\n",
+                escape(description)
+            ));
+        }
+        // KML puts longitude first
+        kml.push_str(&format!(
+            "      <Point><coordinates>{},{}</coordinates></Point>\n    </Placemark>\n",
+            waypoint.longitude, waypoint.latitude
+        ));
+    }
+    kml.push_str("  </Document>\n</kml>\n");
+    kml
+}
+
+/// Which text element we are currently inside of while reading a file
+enum Field {
+    Name,
+    Description,
+    Coordinates,
+    Other,
+}
+
+/// In-progress waypoint while reading a file
+#[derive(Default)]
+struct PartialWaypoint {
+    name: Option<String>,
+    description: Option<String>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+}
+
+impl PartialWaypoint {
+    fn finish(self) -> Result<Waypoint, GpsFileError> {
+        match (self.latitude, self.longitude) {
+            (Some(latitude), Some(longitude)) => Ok(Waypoint::new(
+                self.name.unwrap_or_default(),
+                self.description,
+                latitude,
+                longitude,
+            )),
+            _ => Err(GpsFileError::InvalidCoordinates),
+        }
+    }
+}
+
+fn parse_coordinate(value: &str) -> Result<f64, GpsFileError> {
+    value
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| GpsFileError::InvalidCoordinates)
+}
+
+fn gpx_position(element: &BytesStart) -> Result<(f64, f64), GpsFileError> {
+    let latitude = element
+        .try_get_attribute("lat")?
+        .ok_or(GpsFileError::InvalidCoordinates)?;
+    let longitude = element
+        .try_get_attribute("lon")?
+        .ok_or(GpsFileError::InvalidCoordinates)?;
+    Ok((
+        parse_coordinate(&latitude.unescape_value()?)?,
+        parse_coordinate(&longitude.unescape_value()?)?,
+    ))
+}
+
+/// Read every waypoint (`wpt`) and route point (`rtept`) out of a GPX document
+pub fn read_gpx(document: &str) -> Result<Vec<Waypoint>, GpsFileError> {
+    let mut reader = Reader::from_str(document);
+    reader.trim_text(true);
+    let mut waypoints = Vec::new();
+    let mut current: Option<PartialWaypoint> = None;
+    let mut field = Field::Other;
+    let mut saw_root = false;
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => match element.local_name().as_ref() {
+                b"gpx" => saw_root = true,
+                b"wpt" | b"rtept" => {
+                    let (latitude, longitude) = gpx_position(&element)?;
+                    current = Some(PartialWaypoint {
+                        latitude: Some(latitude),
+                        longitude: Some(longitude),
+                        ..Default::default()
+                    });
+                }
+                b"name" => field = Field::Name,
+                b"desc" => field = Field::Description,
+                _ => field = Field::Other,
+            },
+            Event::Empty(element) => {
+                if let b"wpt" | b"rtept" = element.local_name().as_ref() {
+                    let (latitude, longitude) = gpx_position(&element)?;
+                    waypoints.push(Waypoint::new(String::new(), None, latitude, longitude));
+                }
+            }
+            Event::Text(text) => {
+                let text = text.unescape()?.into_owned();
+                set_field(&mut current, &field, text)?;
+            }
+            Event::CData(text) => {
+                let text = String::from_utf8_lossy(&text.into_inner()).into_owned();
+                set_field(&mut current, &field, text)?;
+            }
+            Event::End(element) => {
+                if let b"wpt" | b"rtept" = element.local_name().as_ref() {
+                    if let Some(waypoint) = current.take() {
+                        waypoints.push(waypoint.finish()?);
+                    }
+                }
+                field = Field::Other;
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+    if saw_root {
+        Ok(waypoints)
+    } else {
+        Err(GpsFileError::WrongDocumentType)
+    }
+}
+
+/// Read every Placemark with a Point out of a KML document
+pub fn read_kml(document: &str) -> Result<Vec<Waypoint>, GpsFileError> {
+    let mut reader = Reader::from_str(document);
+    reader.trim_text(true);
+    let mut waypoints = Vec::new();
+    let mut current: Option<PartialWaypoint> = None;
+    let mut field = Field::Other;
+    let mut saw_root = false;
+    // Only a Point's coordinates are a waypoint; a LineString or Polygon has a list of them
+    let mut in_point = false;
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => match element.local_name().as_ref() {
+                b"kml" => saw_root = true,
+                b"Placemark" => current = Some(PartialWaypoint::default()),
+                b"Point" => in_point = true,
+                b"name" => field = Field::Name,
+                b"description" => field = Field::Description,
+                b"coordinates" if in_point => field = Field::Coordinates,
+                _ => field = Field::Other,
+            },
+            Event::Text(text) => {
+                let text = text.unescape()?.into_owned();
+                set_field(&mut current, &field, text)?;
+            }
+            Event::CData(text) => {
+                let text = String::from_utf8_lossy(&text.into_inner()).into_owned();
+                set_field(&mut current, &field, text)?;
+            }
+            Event::End(element) => {
+                if element.local_name().as_ref() == b"Point" {
+                    in_point = false;
+                }
+                if element.local_name().as_ref() == b"Placemark" {
+                    // Placemarks without a Point (lines, polygons) are not waypoints, so skip them
+                    if let Some(waypoint) = current.take() {
+                        if waypoint.latitude.is_some() {
+                            waypoints.push(waypoint.finish()?);
+                        }
+                    }
+                }
+                field = Field::Other;
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+    if saw_root {
+        Ok(waypoints)
+    } else {
+        Err(GpsFileError::WrongDocumentType)
+    }
+}
+
+fn set_field(
+    current: &mut Option<PartialWaypoint>,
+    field: &Field,
+    text: String,
+) -> Result<(), GpsFileError> {
+    if let Some(waypoint) = current {
+        match field {
+            Field::Name => waypoint.name = Some(text),
+            Field::Description => waypoint.description = Some(text),
+            Field::Coordinates => {
+                // KML coordinates are "longitude,latitude[,altitude]"
+                let mut parts = text.split(',');
+                let longitude = parts.next().ok_or(GpsFileError::InvalidCoordinates)?;
+                let latitude = parts.next().ok_or(GpsFileError::InvalidCoordinates)?;
+                waypoint.longitude = Some(parse_coordinate(longitude)?);
+                waypoint.latitude = Some(parse_coordinate(latitude)?);
+            }
+            Field::Other => (),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_waypoints() -> Vec<Waypoint> {
+        vec![
+            Waypoint::new(
+                "parking".to_string(),
+                Some("Pull-out on the left <past the gate>".to_string()),
+                37.7341,
+                -119.6377,
+            ),
+            Waypoint::new("base of wall".to_string(), None, 37.7352, -119.6381),
+        ]
+    }
+
+    #[test]
+    fn test_gpx_round_trip() {
+        let gpx = write_gpx(&test_waypoints());
+        assert_eq!(read_gpx(&gpx).unwrap(), test_waypoints());
+    }
+
+    #[test]
+    fn test_kml_round_trip() {
+        let kml = write_kml(&test_waypoints());
+        assert_eq!(read_kml(&kml).unwrap(), test_waypoints());
+    }
+
+    #[test]
+    fn test_read_kml_skips_lines() {
+        let kml = r#"<kml><Document>
+            <Placemark><name>approach trail</name><LineString>
+                <coordinates>-119.6377,37.7341,0 -119.6381,37.7352,0</coordinates>
+            </LineString></Placemark>
+            <Placemark><name>base of wall</name><Point>
+                <coordinates>-119.6381,37.7352</coordinates>
+            </Point></Placemark>
+        </Document></kml>"#;
+        assert_eq!(read_kml(kml).unwrap(), test_waypoints()[1..]);
+    }
+
+    #[test]
+    fn test_read_wrong_document_type() {
+        let kml = write_kml(&test_waypoints());
+        assert!(matches!(
+            read_gpx(&kml),
+            Err(GpsFileError::WrongDocumentType)
+        ));
+    }
+
+    #[test]
+    fn test_read_gpx_bad_coordinates() {
+        let gpx = r#"<gpx><wpt lat="north" lon="-119.6"><name>oops</name></wpt></gpx>"#;
+        assert!(matches!(
+            read_gpx(gpx),
+            Err(GpsFileError::InvalidCoordinates)
+        ));
+    }
+}
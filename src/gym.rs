@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+/// module for structs related to climbing gyms, handled by the API endpoints /gyms and /walls
+use sqlx::types::chrono::NaiveDate;
+
+use crate::error::DatabaseError;
+use crate::route::Route;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct Gym {
+    pub id: Option<i32>,
+    pub name: String,
+    /// Only filled in on gym detail
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub walls: Vec<Wall>,
+}
+
+impl Gym {
+    pub fn new(id: Option<i32>, name: String) -> Self {
+        Self {
+            id,
+            name,
+            walls: Vec::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct Wall {
+    pub id: Option<i32>,
+    /// Taken from the path when a wall is created
+    #[serde(default)]
+    pub gym_id: Option<i32>,
+    pub name: String,
+}
+
+impl Wall {
+    pub fn new(id: Option<i32>, gym_id: Option<i32>, name: String) -> Self {
+        Self { id, gym_id, name }
+    }
+}
+
+/// A batch of routes set on a wall at the same time. Routes added to a set take its set and strip
+/// dates unless they have their own.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct RouteSet {
+    pub id: Option<i32>,
+    /// Taken from the path when a route set is created
+    #[serde(default)]
+    pub wall_id: Option<i32>,
+    pub name: Option<String>,
+    pub set_date: NaiveDate,
+    pub strip_date: Option<NaiveDate>,
+}
+
+impl RouteSet {
+    pub fn check_dates(&self) -> Result<(), DatabaseError> {
+        match self.strip_date {
+            Some(strip_date) if strip_date < self.set_date => {
+                Err(DatabaseError::StripDateBeforeSetDate)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// What is on a wall today: its most recent route set that hasn't been stripped, and every route
+/// on the wall that is currently up
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CurrentSet {
+    pub wall: Wall,
+    pub route_set: Option<RouteSet>,
+    pub routes: Vec<Route>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route_set_dates() {
+        let mut route_set = RouteSet {
+            id: None,
+            wall_id: None,
+            name: Some("spring reset".to_string()),
+            set_date: NaiveDate::from_ymd_opt(2023, 4, 1).unwrap(),
+            strip_date: NaiveDate::from_ymd_opt(2023, 3, 1),
+        };
+        assert!(matches!(
+            route_set.check_dates(),
+            Err(DatabaseError::StripDateBeforeSetDate)
+        ));
+        route_set.strip_date = NaiveDate::from_ymd_opt(2023, 6, 1);
+        assert!(route_set.check_dates().is_ok());
+    }
+}
@@ -0,0 +1,286 @@
+use crate::gym::{CurrentSet, Gym, RouteSet, Wall};
+use crate::pg::conn;
+use crate::route::{Route, RouteFilter};
+use crate::routes::select_routes;
+use actix_web::{get, http::header, post, web, HttpResponse, Responder};
+use log::error;
+/// Functions for the API endpoints /gyms and /walls
+use sqlx::{self, query};
+
+#[get("")]
+async fn get_gyms() -> impl Responder {
+    if let Ok(mut conn) = conn().await {
+        if let Ok(query_result) = query!("SELECT id, name FROM gyms ORDER BY name")
+            .fetch_all(&mut conn)
+            .await
+        {
+            let gyms = query_result
+                .into_iter()
+                .map(|record| Gym::new(Some(record.id), record.name))
+                .collect::<Vec<Gym>>();
+            HttpResponse::Ok().json(gyms)
+        } else {
+            error!("SELECT query failed in get_gyms()");
+            HttpResponse::BadGateway().finish()
+        }
+    } else {
+        error!("Failed to connect to the database in get_gyms()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+#[post("")]
+async fn add_new_gym(json: web::Json<Gym>) -> impl Responder {
+    if let Ok(mut conn) = conn().await {
+        if let Ok(query_result) = query!(
+            "INSERT INTO gyms (name) VALUES ($1) RETURNING id",
+            json.0.name,
+        )
+        .fetch_one(&mut conn)
+        .await
+        {
+            let mut gym = json.into_inner();
+            gym.id = Some(query_result.id);
+            gym.walls = Vec::new();
+            HttpResponse::Created()
+                .insert_header((header::LOCATION, format!("/gyms/{}", query_result.id)))
+                .json(gym)
+        } else {
+            error!("INSERT query failed in add_new_gym()");
+            HttpResponse::BadGateway().finish()
+        }
+    } else {
+        error!("Failed to connect to the database in add_new_gym()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+#[get("/{id}")]
+async fn get_gym_by_id(path: web::Path<i32>) -> impl Responder {
+    let id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        let mut gym = match query!("SELECT id, name FROM gyms WHERE id = ($1)", id)
+            .fetch_optional(&mut conn)
+            .await
+        {
+            Ok(Some(record)) => Gym::new(Some(record.id), record.name),
+            Ok(None) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in get_gym_by_id()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        if let Ok(query_result) = query!(
+            "SELECT id, name FROM walls WHERE gym_id = ($1) ORDER BY name",
+            id
+        )
+        .fetch_all(&mut conn)
+        .await
+        {
+            gym.walls = query_result
+                .into_iter()
+                .map(|record| Wall::new(Some(record.id), Some(id), record.name))
+                .collect();
+            HttpResponse::Ok().json(gym)
+        } else {
+            error!("SELECT query for walls failed in get_gym_by_id()");
+            HttpResponse::BadGateway().finish()
+        }
+    } else {
+        error!("Failed to connect to the database in get_gym_by_id()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+#[post("/{id}/walls")]
+async fn add_new_wall(path: web::Path<i32>, json: web::Json<Wall>) -> impl Responder {
+    let gym_id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match query!(
+            r#"INSERT INTO walls (gym_id, name)
+            SELECT $1, $2 WHERE EXISTS (SELECT 1 FROM gyms WHERE id = ($1))
+            RETURNING id"#,
+            gym_id,
+            json.0.name,
+        )
+        .fetch_optional(&mut conn)
+        .await
+        {
+            Ok(Some(query_result)) => {
+                let mut wall = json.into_inner();
+                wall.id = Some(query_result.id);
+                wall.gym_id = Some(gym_id);
+                HttpResponse::Created()
+                    .insert_header((header::LOCATION, format!("/walls/{}", query_result.id)))
+                    .json(wall)
+            }
+            Ok(None) => HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("INSERT query failed in add_new_wall()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in add_new_wall()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+#[get("/{id}")]
+async fn get_wall_by_id(path: web::Path<i32>) -> impl Responder {
+    let id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match query!("SELECT id, gym_id, name FROM walls WHERE id = ($1)", id)
+            .fetch_optional(&mut conn)
+            .await
+        {
+            Ok(Some(record)) => HttpResponse::Ok().json(Wall::new(
+                Some(record.id),
+                Some(record.gym_id),
+                record.name,
+            )),
+            Ok(None) => HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in get_wall_by_id()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in get_wall_by_id()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+#[post("/{id}/sets")]
+async fn add_new_route_set(path: web::Path<i32>, json: web::Json<RouteSet>) -> impl Responder {
+    let wall_id = path.into_inner();
+    if let Err(e) = json.check_dates() {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+    if let Ok(mut conn) = conn().await {
+        match query!(
+            r#"INSERT INTO route_sets (wall_id, name, set_date, strip_date)
+            SELECT $1, $2, $3, $4 WHERE EXISTS (SELECT 1 FROM walls WHERE id = ($1))
+            RETURNING id"#,
+            wall_id,
+            json.0.name,
+            json.0.set_date,
+            json.0.strip_date,
+        )
+        .fetch_optional(&mut conn)
+        .await
+        {
+            Ok(Some(query_result)) => {
+                let mut route_set = json.into_inner();
+                route_set.id = Some(query_result.id);
+                route_set.wall_id = Some(wall_id);
+                HttpResponse::Created()
+                    .insert_header((
+                        header::LOCATION,
+                        format!("/walls/{wall_id}/sets/{}", query_result.id),
+                    ))
+                    .json(route_set)
+            }
+            Ok(None) => HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("INSERT query failed in add_new_route_set()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in add_new_route_set()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+#[get("/{wall_id}/sets/{id}")]
+async fn get_route_set_by_id(path: web::Path<(i32, i32)>) -> impl Responder {
+    let (wall_id, id) = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match query!(
+            "SELECT id, name, set_date, strip_date FROM route_sets WHERE id = ($1) AND wall_id = ($2)",
+            id,
+            wall_id,
+        )
+        .fetch_optional(&mut conn)
+        .await
+        {
+            Ok(Some(record)) => HttpResponse::Ok().json(RouteSet {
+                id: Some(record.id),
+                wall_id: Some(wall_id),
+                name: record.name,
+                set_date: record.set_date,
+                strip_date: record.strip_date,
+            }),
+            Ok(None) => HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in get_route_set_by_id()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in get_route_set_by_id()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+#[get("/{id}/current")]
+async fn get_current_set(path: web::Path<i32>) -> impl Responder {
+    let id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        let wall = match query!("SELECT id, gym_id, name FROM walls WHERE id = ($1)", id)
+            .fetch_optional(&mut conn)
+            .await
+        {
+            Ok(Some(record)) => Wall::new(Some(record.id), Some(record.gym_id), record.name),
+            Ok(None) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in get_current_set()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        let route_set = match query!(
+            r#"SELECT id, name, set_date, strip_date FROM route_sets
+            WHERE wall_id = ($1) AND set_date <= current_date AND (strip_date IS NULL OR strip_date > current_date)
+            ORDER BY set_date DESC, id DESC LIMIT 1"#,
+            id
+        )
+        .fetch_optional(&mut conn)
+        .await
+        {
+            Ok(record) => record.map(|record| RouteSet {
+                id: Some(record.id),
+                wall_id: Some(id),
+                name: record.name,
+                set_date: record.set_date,
+                strip_date: record.strip_date,
+            }),
+            Err(_) => {
+                error!("SELECT query for route sets failed in get_current_set()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        let filter = RouteFilter {
+            wall_id: Some(id),
+            currently_up: Some(true),
+            ..Default::default()
+        };
+        if let Ok(rated_routes) = select_routes(&mut conn, None, &filter).await {
+            let routes = rated_routes
+                .into_iter()
+                .map(|rated_route| rated_route.route)
+                .collect::<Vec<Route>>();
+            HttpResponse::Ok().json(CurrentSet {
+                wall,
+                route_set,
+                routes,
+            })
+        } else {
+            error!("SELECT query for routes failed in get_current_set()");
+            HttpResponse::BadGateway().finish()
+        }
+    } else {
+        error!("Failed to connect to the database in get_current_set()");
+        HttpResponse::BadGateway().finish()
+    }
+}
@@ -0,0 +1,340 @@
+/// module for leaderboards ranked from climbs, handled by the API endpoint /leaderboards
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::{NaiveDate, NaiveDateTime};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::error::DatabaseError;
+use crate::page::Page;
+use crate::route::{DifficultyRating, Discipline, GradeScale};
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum LeaderboardMetric {
+    /// Most climbs
+    Ascents,
+    /// Hardest single climb, by its points
+    Hardest,
+    /// Most points in total, from DifficultyRating::points
+    #[default]
+    Points,
+}
+
+impl LeaderboardMetric {
+    /// Whether the metric compares grades, which can only be compared within one GradeScale
+    pub fn compares_grades(&self) -> bool {
+        !matches!(self, Self::Ascents)
+    }
+}
+
+impl Display for LeaderboardMetric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let display_str = match self {
+            Self::Ascents => "ascents",
+            Self::Hardest => "hardest",
+            Self::Points => "points",
+        };
+        write!(f, "{}", display_str)
+    }
+}
+
+impl FromStr for LeaderboardMetric {
+    type Err = DatabaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ascents" => Ok(Self::Ascents),
+            "hardest" => Ok(Self::Hardest),
+            "points" => Ok(Self::Points),
+            _ => Err(DatabaseError::FailedParseLeaderboard),
+        }
+    }
+}
+
+/// Which climbs a leaderboard is ranked from. Every field is optional, and an area includes its
+/// subareas, except that a metric that compares grades needs a grade scale.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct LeaderboardScope {
+    #[serde(default)]
+    pub metric: LeaderboardMetric,
+    pub discipline: Option<Discipline>,
+    /// Only climbs graded on this scale count. Defaults to the discipline's scale if it only has
+    /// one.
+    pub scale: Option<GradeScale>,
+    pub area_id: Option<i32>,
+    pub gym_id: Option<i32>,
+    /// First completion date counted
+    pub from: Option<NaiveDate>,
+    /// Last completion date counted
+    pub to: Option<NaiveDate>,
+}
+
+impl LeaderboardScope {
+    /// Fill in the grade scale from the discipline, and check that the scope has one if its
+    /// metric compares grades, so that grades from different scales are never ranked together
+    pub fn check_grade_scale(&mut self) -> Result<(), DatabaseError> {
+        if let Some(discipline) = self.discipline {
+            match (self.scale, discipline.grade_scales()) {
+                (None, [scale]) => self.scale = Some(*scale),
+                (Some(scale), scales) if !scales.contains(&scale) => {
+                    return Err(DatabaseError::GradeScaleNotInDiscipline)
+                }
+                _ => (),
+            }
+        }
+        if self.scale.is_none() && self.metric.compares_grades() {
+            Err(DatabaseError::LeaderboardNeedsGradeScale)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Key that the ranked leaderboard for this scope is cached under
+    pub fn cache_key(&self) -> String {
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        format!(
+            "{}/{}/{}/{}/{}/{}/{}",
+            self.metric,
+            optional(self.discipline.map(|discipline| discipline.to_string())),
+            optional(self.scale.map(|scale| scale.to_string())),
+            optional(self.area_id.map(|id| id.to_string())),
+            optional(self.gym_id.map(|id| id.to_string())),
+            optional(self.from.map(|date| date.to_string())),
+            optional(self.to.map(|date| date.to_string())),
+        )
+    }
+}
+
+/// Query parameters for GET /leaderboards, which are a LeaderboardScope and a Page. They are
+/// spelled out because flattened fields can't be parsed from a query string.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+pub struct LeaderboardQuery {
+    #[serde(default)]
+    pub metric: LeaderboardMetric,
+    pub discipline: Option<Discipline>,
+    pub scale: Option<GradeScale>,
+    pub area_id: Option<i32>,
+    pub gym_id: Option<i32>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+impl LeaderboardQuery {
+    pub fn scope(&self) -> LeaderboardScope {
+        LeaderboardScope {
+            metric: self.metric,
+            discipline: self.discipline,
+            scale: self.scale,
+            area_id: self.area_id,
+            gym_id: self.gym_id,
+            from: self.from,
+            to: self.to,
+        }
+    }
+
+    pub fn page(&self) -> Page {
+        Page {
+            page: self.page,
+            per_page: self.per_page,
+        }
+    }
+}
+
+/// One climb counted towards a leaderboard
+#[derive(Debug, Clone)]
+pub struct LeaderboardClimb {
+    pub climber_id: i32,
+    pub username: String,
+    pub difficulty: DifficultyRating,
+    pub completion_date: NaiveDate,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct LeaderboardEntry {
+    pub rank: i64,
+    pub climber_id: i32,
+    pub username: String,
+    pub number_climbs: i64,
+    pub points: i64,
+    pub hardest: DifficultyRating,
+    /// When the climber first climbed their hardest grade
+    pub hardest_date: NaiveDate,
+    pub last_climb_date: NaiveDate,
+}
+
+impl LeaderboardEntry {
+    /// Compare two entries best first. After the metric itself, ties go to whoever did more
+    /// by the other metrics, then to whoever got there first. Entries that are still equal share
+    /// a rank.
+    fn compare(&self, other: &Self, metric: LeaderboardMetric) -> Ordering {
+        let hardest = |entry: &Self| Reverse(entry.hardest.points());
+        let points = |entry: &Self| Reverse(entry.points);
+        let number_climbs = |entry: &Self| Reverse(entry.number_climbs);
+        match metric {
+            LeaderboardMetric::Ascents => (number_climbs(self), points(self), self.last_climb_date)
+                .cmp(&(number_climbs(other), points(other), other.last_climb_date)),
+            LeaderboardMetric::Hardest => (hardest(self), self.hardest_date, number_climbs(self))
+                .cmp(&(hardest(other), other.hardest_date, number_climbs(other))),
+            LeaderboardMetric::Points => (points(self), hardest(self), self.last_climb_date)
+                .cmp(&(points(other), hardest(other), other.last_climb_date)),
+        }
+    }
+}
+
+/// Rank every climber with climbs in `climbs`, best first. The climbs' grades are compared by their
+/// points, so they should all be on the same scale.
+pub fn rank_climbers(
+    metric: LeaderboardMetric,
+    climbs: &[LeaderboardClimb],
+) -> Vec<LeaderboardEntry> {
+    let mut by_climber: BTreeMap<i32, LeaderboardEntry> = BTreeMap::new();
+    for climb in climbs {
+        let entry = by_climber
+            .entry(climb.climber_id)
+            .or_insert_with(|| LeaderboardEntry {
+                rank: 0,
+                climber_id: climb.climber_id,
+                username: climb.username.clone(),
+                number_climbs: 0,
+                points: 0,
+                hardest: climb.difficulty.clone(),
+                hardest_date: climb.completion_date,
+                last_climb_date: climb.completion_date,
+            });
+        let points = climb.difficulty.points();
+        entry.number_climbs += 1;
+        entry.points += points;
+        if points > entry.hardest.points()
+            || (points == entry.hardest.points() && climb.completion_date < entry.hardest_date)
+        {
+            entry.hardest = climb.difficulty.clone();
+            entry.hardest_date = climb.completion_date;
+        }
+        entry.last_climb_date = entry.last_climb_date.max(climb.completion_date);
+    }
+
+    let mut entries = by_climber.into_values().collect::<Vec<LeaderboardEntry>>();
+    entries.sort_by(|a, b| a.compare(b, metric).then(a.climber_id.cmp(&b.climber_id)));
+    for i in 0..entries.len() {
+        entries[i].rank = if i > 0 && entries[i].compare(&entries[i - 1], metric).is_eq() {
+            entries[i - 1].rank
+        } else {
+            i as i64 + 1
+        };
+    }
+    entries
+}
+
+/// Body of GET /leaderboards. Rankings are cached, and `computed_at` says how fresh they are.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Leaderboard {
+    #[serde(flatten)]
+    pub scope: LeaderboardScope,
+    pub entries: Vec<LeaderboardEntry>,
+    pub page: i64,
+    pub per_page: i64,
+    /// The next page number, if there are more entries
+    pub next_page: Option<i64>,
+    pub computed_at: NaiveDateTime,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_climb(climber_id: i32, difficulty: DifficultyRating, day: u32) -> LeaderboardClimb {
+        LeaderboardClimb {
+            climber_id,
+            username: format!("climber{climber_id}"),
+            difficulty,
+            completion_date: NaiveDate::from_ymd_opt(2023, 5, day).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_rank_climbers() {
+        let climbs = vec![
+            // 1: three easy climbs for 300 points
+            test_climb(1, DifficultyRating::Rating59, 1),
+            test_climb(1, DifficultyRating::Rating59, 2),
+            test_climb(1, DifficultyRating::Rating59, 3),
+            // 2: one hard climb for 500 points
+            test_climb(2, DifficultyRating::Rating512, 10),
+            // 3: the same hard climb, sent first
+            test_climb(3, DifficultyRating::Rating512, 4),
+            // 4: exactly the same as 3
+            test_climb(4, DifficultyRating::Rating512, 4),
+        ];
+
+        let ranked = |metric| {
+            rank_climbers(metric, &climbs)
+                .into_iter()
+                .map(|entry| (entry.rank, entry.climber_id))
+                .collect::<Vec<(i64, i32)>>()
+        };
+        assert_eq!(
+            ranked(LeaderboardMetric::Ascents),
+            vec![(1, 1), (2, 3), (2, 4), (4, 2)]
+        );
+        assert_eq!(
+            ranked(LeaderboardMetric::Hardest),
+            vec![(1, 3), (1, 4), (3, 2), (4, 1)]
+        );
+        assert_eq!(
+            ranked(LeaderboardMetric::Points),
+            vec![(1, 3), (1, 4), (3, 2), (4, 1)]
+        );
+
+        let entries = rank_climbers(LeaderboardMetric::Points, &climbs);
+        let first_climber = entries.iter().find(|entry| entry.climber_id == 1).unwrap();
+        assert_eq!(first_climber.points, 300);
+        assert_eq!(first_climber.hardest_date.to_string(), "2023-05-01");
+        assert_eq!(first_climber.last_climb_date.to_string(), "2023-05-03");
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let scope = LeaderboardScope {
+            gym_id: Some(3),
+            from: NaiveDate::from_ymd_opt(2023, 5, 1),
+            ..Default::default()
+        };
+        assert_eq!(scope.cache_key(), "points////3/2023-05-01/");
+    }
+
+    #[test]
+    fn test_check_grade_scale() {
+        let mut scope = LeaderboardScope {
+            discipline: Some(Discipline::Boulder),
+            ..Default::default()
+        };
+        scope.check_grade_scale().unwrap();
+        assert_eq!(scope.scale, Some(GradeScale::VScale));
+
+        // Mixed routes are graded on two scales, so one has to be picked
+        scope.discipline = Some(Discipline::Mixed);
+        scope.scale = None;
+        assert!(matches!(
+            scope.check_grade_scale(),
+            Err(DatabaseError::LeaderboardNeedsGradeScale)
+        ));
+        scope.scale = Some(GradeScale::VScale);
+        assert!(matches!(
+            scope.check_grade_scale(),
+            Err(DatabaseError::GradeScaleNotInDiscipline)
+        ));
+        scope.scale = Some(GradeScale::WaterIce);
+        scope.check_grade_scale().unwrap();
+
+        // Ascents don't compare grades
+        let mut scope = LeaderboardScope {
+            metric: LeaderboardMetric::Ascents,
+            ..Default::default()
+        };
+        scope.check_grade_scale().unwrap();
+        assert_eq!(scope.scale, None);
+    }
+}
@@ -0,0 +1,263 @@
+use crate::climber::Visibility;
+use crate::error::DatabaseError;
+use crate::leaderboard::{
+    rank_climbers, Leaderboard, LeaderboardClimb, LeaderboardEntry, LeaderboardMetric,
+    LeaderboardQuery, LeaderboardScope,
+};
+use crate::pg::conn;
+use crate::route::{DifficultyRating, Discipline, GradeScale};
+use actix_web::{get, web, HttpResponse, Responder};
+use log::{error, info};
+/// Functions for the API endpoint /leaderboards, and the background job that keeps cached
+/// leaderboards fresh
+use sqlx::{self, postgres::PgConnection, query, types::chrono::NaiveDateTime};
+use std::str::FromStr;
+use std::time::Duration;
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// Cached leaderboards older than this are recomputed when they are requested
+const MAX_CACHE_AGE_SECONDS: f64 = 10.0 * 60.0;
+/// Cached leaderboards nobody has requested for this long stop being refreshed
+const CACHE_IDLE_DAYS: i32 = 1;
+
+/// Check that the area and gym of a scope exist, if it has them
+async fn scope_exists(
+    conn: &mut PgConnection,
+    scope: &LeaderboardScope,
+) -> Result<bool, DatabaseError> {
+    Ok(query!(
+        r#"SELECT ($1::int4 IS NULL OR EXISTS (SELECT 1 FROM areas WHERE id = $1))
+        AND ($2::int4 IS NULL OR EXISTS (SELECT 1 FROM gyms WHERE id = $2)) as "exists!""#,
+        scope.area_id,
+        scope.gym_id,
+    )
+    .fetch_one(conn)
+    .await?
+    .exists)
+}
+
+/// Rank climbers for a scope straight from their climbs. Only climbers whose logbooks are public
+/// are ranked, climbs a moderator hid don't count, and neither do climbs graded on a different
+/// scale than the scope's.
+async fn compute_leaderboard(
+    conn: &mut PgConnection,
+    scope: &LeaderboardScope,
+) -> Result<Vec<LeaderboardEntry>, DatabaseError> {
+    let mut climbs = query!(
+        r#"SELECT climbs.climber_id as "climber_id!", climbers.username as "username!", routes.difficulty as "difficulty!", climbs.completion_date as "completion_date!"
+        FROM active_climbs climbs
+        JOIN climbers ON climbers.id = climbs.climber_id
+        JOIN routes ON routes.id = climbs.route_id
+        WHERE climbers.logbook_visibility = ($1)
+        AND climbs.hidden_at IS NULL
+        AND ($2::varchar IS NULL OR routes.discipline = $2)
+        AND ($3::int4 IS NULL OR routes.area_id IN (
+            WITH RECURSIVE subareas AS (
+                SELECT id FROM areas WHERE id = $3
+                UNION SELECT areas.id FROM areas JOIN subareas ON areas.parent_id = subareas.id
+            ) SELECT id FROM subareas
+        ))
+        AND ($4::int4 IS NULL OR routes.wall_id IN (SELECT id FROM walls WHERE gym_id = $4))
+        AND ($5::date IS NULL OR climbs.completion_date >= $5)
+        AND ($6::date IS NULL OR climbs.completion_date <= $6)"#,
+        Visibility::Public.to_string(),
+        scope.discipline.map(|discipline| discipline.to_string()),
+        scope.area_id,
+        scope.gym_id,
+        scope.from,
+        scope.to,
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|record| {
+        Ok(LeaderboardClimb {
+            climber_id: record.climber_id,
+            username: record.username,
+            difficulty: DifficultyRating::from_str(&record.difficulty)?,
+            completion_date: record.completion_date,
+        })
+    })
+    .collect::<Result<Vec<LeaderboardClimb>, DatabaseError>>()?;
+    if let Some(scale) = scope.scale {
+        climbs.retain(|climb| climb.difficulty.scale() == scale);
+    }
+    Ok(rank_climbers(scope.metric, &climbs))
+}
+
+/// Compute a leaderboard and cache it, returning it along with when it was computed
+async fn refresh_leaderboard(
+    conn: &mut PgConnection,
+    scope: &LeaderboardScope,
+) -> Result<(Vec<LeaderboardEntry>, NaiveDateTime), DatabaseError> {
+    let entries = compute_leaderboard(conn, scope).await?;
+    let entries_json =
+        serde_json::to_string(&entries).map_err(|_| DatabaseError::FailedParseLeaderboard)?;
+    let computed_at = query!(
+        r#"INSERT INTO leaderboard_cache (cache_key, metric, discipline, grade_scale, area_id, gym_id, from_date, to_date, entries)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9::text::jsonb)
+        ON CONFLICT (cache_key) DO UPDATE SET entries = EXCLUDED.entries, computed_at = current_timestamp
+        RETURNING computed_at"#,
+        scope.cache_key(),
+        scope.metric.to_string(),
+        scope.discipline.map(|discipline| discipline.to_string()),
+        scope.scale.map(|scale| scale.to_string()),
+        scope.area_id,
+        scope.gym_id,
+        scope.from,
+        scope.to,
+        entries_json,
+    )
+    .fetch_one(conn)
+    .await?
+    .computed_at;
+    Ok((entries, computed_at))
+}
+
+/// A leaderboard from the cache, or freshly computed if it isn't cached or is too old
+async fn cached_leaderboard(
+    conn: &mut PgConnection,
+    scope: &LeaderboardScope,
+) -> Result<(Vec<LeaderboardEntry>, NaiveDateTime), DatabaseError> {
+    match query!(
+        r#"UPDATE leaderboard_cache SET requested_at = current_timestamp
+        WHERE cache_key = ($1) AND computed_at > current_timestamp - make_interval(secs => $2)
+        RETURNING entries::text as "entries!", computed_at"#,
+        scope.cache_key(),
+        MAX_CACHE_AGE_SECONDS,
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    {
+        Some(record) => Ok((
+            serde_json::from_str(&record.entries)
+                .map_err(|_| DatabaseError::FailedParseLeaderboard)?,
+            record.computed_at,
+        )),
+        None => refresh_leaderboard(conn, scope).await,
+    }
+}
+
+/// Climbers ranked by a metric from their climbs, optionally in a discipline or grade scale, an
+/// area or gym, and a window of completion dates
+#[get("")]
+async fn get_leaderboards(query: web::Query<LeaderboardQuery>) -> impl Responder {
+    let mut scope = query.scope();
+    let page = query.page();
+    if let (Some(from), Some(to)) = (scope.from, scope.to) {
+        if from > to {
+            return HttpResponse::BadRequest().body(DatabaseError::FromDateAfterToDate.to_string());
+        }
+    }
+    if let Err(e) = scope.check_grade_scale() {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+    if let Ok(mut conn) = conn().await {
+        match scope_exists(&mut conn, &scope).await {
+            Ok(true) => (),
+            Ok(false) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in get_leaderboards()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        if let Ok((entries, computed_at)) = cached_leaderboard(&mut conn, &scope).await {
+            let (page_number, per_page) = page.page_and_size();
+            let next_page =
+                (entries.len() as i64 > page.offset() + per_page).then_some(page_number + 1);
+            let entries = entries
+                .into_iter()
+                .skip(page.offset() as usize)
+                .take(per_page as usize)
+                .collect();
+            HttpResponse::Ok().json(Leaderboard {
+                scope,
+                entries,
+                page: page_number,
+                per_page,
+                next_page,
+                computed_at,
+            })
+        } else {
+            error!("Failed to get a leaderboard in get_leaderboards()");
+            HttpResponse::BadGateway().finish()
+        }
+    } else {
+        error!("Failed to connect to the database in get_leaderboards()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Recompute every cached leaderboard that has been requested recently, and drop the rest,
+/// returning the number of leaderboards recomputed. A leaderboard that fails to recompute is
+/// logged and skipped, so that it doesn't hold up the others.
+pub async fn refresh_leaderboards(conn: &mut PgConnection) -> Result<u64, DatabaseError> {
+    query!(
+        "DELETE FROM leaderboard_cache WHERE requested_at < current_timestamp - make_interval(days => $1)",
+        CACHE_IDLE_DAYS,
+    )
+    .execute(&mut *conn)
+    .await?;
+    let scopes = query!(
+        "SELECT cache_key, metric, discipline, grade_scale, area_id, gym_id, from_date, to_date FROM leaderboard_cache"
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|record| {
+        let scope = LeaderboardMetric::from_str(&record.metric).and_then(|metric| {
+            Ok(LeaderboardScope {
+                metric,
+                discipline: record
+                    .discipline
+                    .as_deref()
+                    .map(Discipline::from_str)
+                    .transpose()?,
+                scale: record
+                    .grade_scale
+                    .as_deref()
+                    .map(GradeScale::from_str)
+                    .transpose()?,
+                area_id: record.area_id,
+                gym_id: record.gym_id,
+                from: record.from_date,
+                to: record.to_date,
+            })
+        });
+        (record.cache_key, scope)
+    })
+    .collect::<Vec<(String, Result<LeaderboardScope, DatabaseError>)>>();
+    let mut leaderboards_refreshed = 0;
+    for (cache_key, scope) in scopes {
+        let refreshed = match scope {
+            Ok(scope) => refresh_leaderboard(conn, &scope).await,
+            Err(e) => Err(e),
+        };
+        match refreshed {
+            Ok(_) => leaderboards_refreshed += 1,
+            Err(e) => {
+                error!(
+                    "Failed to refresh the leaderboard {cache_key} in refresh_leaderboards(): {e}"
+                )
+            }
+        }
+    }
+    Ok(leaderboards_refreshed)
+}
+
+pub async fn leaderboard_job() {
+    let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Ok(mut conn) = conn().await {
+            match refresh_leaderboards(&mut conn).await {
+                Ok(leaderboards_refreshed) => {
+                    info!("Refreshed {leaderboards_refreshed} cached leaderboards")
+                }
+                Err(_) => error!("Failed to refresh leaderboards in leaderboard_job()"),
+            }
+        } else {
+            error!("Failed to connect to the database in leaderboard_job()");
+        }
+    }
+}
@@ -1,14 +1,48 @@
-use actix_web::{middleware::Logger, web, App, HttpServer};
+use actix_web::{
+    middleware::{self, Logger},
+    web, App, HttpServer,
+};
 use dotenvy::dotenv;
 use env_logger::Env;
 
+mod admin;
+pub mod archive;
+pub mod area;
+mod areas;
 pub mod climb;
 pub mod climber;
 mod climbers;
+pub mod comment;
+mod comments;
+pub mod competition;
+mod competitions;
+pub mod condition;
+mod conditions;
 pub mod error;
+pub mod etag;
+pub mod gps;
+pub mod gym;
+mod gyms;
+pub mod leaderboard;
+mod leaderboards;
+pub mod moderation;
+mod moderations;
+pub mod page;
 pub mod pg;
+pub mod photo;
+mod photos;
+pub mod purge;
+pub mod recommendation;
+mod reviews;
+pub mod revision;
+mod revisions;
 pub mod route;
 mod routes;
+pub mod search;
+mod searches;
+pub mod storage;
+pub mod ticklist;
+mod ticklists;
 
 #[cfg(test)]
 mod tests;
@@ -21,30 +55,152 @@ macro_rules! app (
         let _ = env_logger::try_init_from_env(Env::default().default_filter_or("info"));  // assign to _ because Result<(), SetLoggerError> is intentionally unused; SetLoggerError indicates set_logger was already called, which is fine.
         App::new()
             .wrap(Logger::default())
+            .app_data(web::Data::from(storage::storage()))
+            .app_data(web::PayloadConfig::new(photo::max_photo_bytes()))
             .service(
                 web::scope("/routes")
+                    .wrap(middleware::from_fn(routes::redirect_merged_route))
                     .service(routes::add_new_route)
                     .service(routes::get_recent_routes)
+                    .service(routes::export_routes_gpx)
+                    .service(routes::export_routes_kml)
                     .service(routes::get_route_by_id)
                     .service(routes::delete_route_by_id)
                     .service(routes::update_route_by_id)
+                    .service(routes::patch_route_by_id)
+                    .service(routes::import_approach_waypoints)
+                    .service(routes::get_approach_waypoints)
+                    .service(photos::add_route_photo)
+                    .service(photos::get_route_photos)
+                    .service(conditions::add_route_condition)
+                    .service(conditions::get_route_conditions)
+                    .service(reviews::get_route_reviews)
+                    .service(comments::add_route_comment)
+                    .service(comments::get_route_comments)
+                    .service(revisions::get_route_history)
+            )
+            .service(
+                web::scope("/areas")
+                    .service(areas::get_areas)
+                    .service(areas::add_new_area)
+                    .service(areas::get_area_by_id)
+                    .service(photos::add_area_photo)
+                    .service(photos::get_area_photos)
+                    .service(conditions::add_area_condition)
+                    .service(conditions::get_area_conditions)
+                    .service(comments::add_area_comment)
+                    .service(comments::get_area_comments)
             )
             .service(
                 web::scope("/climbers")
                     .service(climbers::get_recent_climbers)
                     .service(climbers::add_new_climber)
                     .service(climbers::get_climber_recent_climbs)
+                    .service(climbers::update_climber)
                     .service(climbers::delete_climber)
+                    .service(climbers::get_climber_logbook)
+                    .service(climbers::get_climber_stats)
+                    .service(climbers::get_climber_recommendations)
+                    .service(ticklists::get_ticklist)
+                    .service(ticklists::add_ticklist_entry)
+                    .service(ticklists::get_ticklist_entry)
+                    .service(ticklists::update_ticklist_entry)
+                    .service(ticklists::delete_ticklist_entry)
+                    .service(climbers::get_following)
+                    .service(climbers::get_followers)
+                    .service(climbers::get_climber_feed)
+                    .service(climbers::follow_climber)
+                    .service(climbers::unfollow_climber)
+                    .service(moderations::add_review_flag)
+                    .service(reviews::mark_review_helpful)
+                    .service(reviews::unmark_review_helpful)
                     .service(climbers::get_climbers_review_by_route_id)
                     .service(climbers::add_review)
                     .service(climbers::update_review)
+                    .service(climbers::patch_review)
                     .service(climbers::delete_review)
+                    .service(photos::add_climb_photo)
+                    .service(photos::get_climb_photos)
+            )
+            .service(
+                web::scope("/reviews")
+                    .service(reviews::search_reviews)
+            )
+            .service(
+                web::scope("/search")
+                    .service(searches::autocomplete)
+                    .service(searches::search)
+            )
+            .service(
+                web::scope("/comments")
+                    .service(comments::get_comment_by_id)
+                    .service(comments::update_comment)
+                    .service(comments::delete_comment)
+                    .service(moderations::add_comment_flag)
+            )
+            .service(
+                web::scope("/leaderboards")
+                    .service(leaderboards::get_leaderboards)
+            )
+            .service(
+                web::scope("/competitions")
+                    .service(competitions::get_competitions)
+                    .service(competitions::add_new_competition)
+                    .service(competitions::get_competition_by_id)
+                    .service(competitions::add_new_category)
+                    .service(competitions::add_new_problem)
+                    .service(competitions::add_new_registration)
+                    .service(competitions::add_new_attempt)
+                    .service(competitions::get_scorecard)
+                    .service(competitions::get_competition_results)
+            )
+            .service(
+                web::scope("/photos")
+                    .service(photos::get_photo_by_id)
+                    .service(photos::get_photo_file)
+                    .service(photos::get_photo_thumbnail)
+                    .service(photos::update_photo_topo)
+                    .service(photos::delete_photo)
+            )
+            .service(
+                web::scope("/gyms")
+                    .service(gyms::get_gyms)
+                    .service(gyms::add_new_gym)
+                    .service(gyms::get_gym_by_id)
+                    .service(gyms::add_new_wall)
+            )
+            .service(
+                web::scope("/walls")
+                    .service(gyms::get_wall_by_id)
+                    .service(gyms::add_new_route_set)
+                    .service(gyms::get_route_set_by_id)
+                    .service(gyms::get_current_set)
+            )
+            .service(
+                web::scope("/admin")
+                    .service(admin::restore_route)
+                    .service(admin::merge_route)
+                    .service(revisions::revert_route)
+                    .service(admin::restore_climber)
+                    .service(admin::restore_review)
+                    .service(admin::add_moderator)
+                    .service(admin::remove_moderator)
+                    .service(moderations::get_moderation_queue)
+                    .service(moderations::get_comment_moderation_queue)
+                    .service(moderations::get_moderated_comment)
+                    .service(moderations::moderate_comment)
+                    .service(moderations::get_moderated_review)
+                    .service(moderations::moderate_review)
             )
     });
 );
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    dotenv().ok();
+    actix_web::rt::spawn(purge::purge_job());
+    actix_web::rt::spawn(archive::archive_job());
+    actix_web::rt::spawn(leaderboards::leaderboard_job());
     HttpServer::new(|| app!())
         .bind(("0.0.0.0", 8080))?
         .run()
@@ -0,0 +1,279 @@
+/// module for flagging reviews and comments and moderating them, handled by the API endpoints
+/// /climbers/{climber_id}/{route_id}/flags, /comments/{id}/flags and /admin/moderation
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::NaiveDateTime;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::climb::Climb;
+use crate::comment::Comment;
+use crate::error::DatabaseError;
+
+/// Longest a moderator's reason can be, in characters
+const MAX_REASON_LENGTH: usize = 1000;
+
+/// Why a climber flagged a review or comment
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum FlagReason {
+    Spam,
+    Offensive,
+    OffTopic,
+    /// The review misdescribes the route, e.g. missing bolts or loose rock
+    Inaccurate,
+    Other,
+}
+
+impl Display for FlagReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let display_str = match self {
+            Self::Spam => "spam",
+            Self::Offensive => "offensive",
+            Self::OffTopic => "off-topic",
+            Self::Inaccurate => "inaccurate",
+            Self::Other => "other",
+        };
+        write!(f, "{}", display_str)
+    }
+}
+
+impl FromStr for FlagReason {
+    type Err = DatabaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "spam" => Ok(Self::Spam),
+            "offensive" => Ok(Self::Offensive),
+            "off-topic" => Ok(Self::OffTopic),
+            "inaccurate" => Ok(Self::Inaccurate),
+            "other" => Ok(Self::Other),
+            _ => Err(DatabaseError::FailedParseFlagReason),
+        }
+    }
+}
+
+/// A climber's flag on a review or comment
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct ReviewFlag {
+    pub id: Option<i32>,
+    /// The climber flagging the review
+    pub climber_id: i32,
+    pub reason: FlagReason,
+    #[serde(default)]
+    pub note: String,
+    /// Ignored on writes
+    #[serde(default)]
+    pub created_at: Option<NaiveDateTime>,
+    /// When a moderator acted on the review or comment. Ignored on writes.
+    #[serde(default)]
+    pub resolved_at: Option<NaiveDateTime>,
+}
+
+/// What a flag or moderation action is about
+#[derive(Debug, Clone, Copy)]
+pub enum ModerationTarget {
+    /// A review, by the ID of its climb
+    Review(i32),
+    Comment(i32),
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ModerationAction {
+    /// Hide the review or comment from everyone but its climber
+    Hide,
+    /// Show a hidden review or comment again
+    Restore,
+    /// Replace the text of the review or comment
+    Edit,
+    /// Leave the review or comment as it is
+    Dismiss,
+}
+
+impl Display for ModerationAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let display_str = match self {
+            Self::Hide => "hide",
+            Self::Restore => "restore",
+            Self::Edit => "edit",
+            Self::Dismiss => "dismiss",
+        };
+        write!(f, "{}", display_str)
+    }
+}
+
+impl FromStr for ModerationAction {
+    type Err = DatabaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hide" => Ok(Self::Hide),
+            "restore" => Ok(Self::Restore),
+            "edit" => Ok(Self::Edit),
+            "dismiss" => Ok(Self::Dismiss),
+            _ => Err(DatabaseError::FailedParseModerationAction),
+        }
+    }
+}
+
+/// Query parameter for the moderators' endpoints under /admin, for the climber acting as a
+/// moderator. Like a route's editor it is taken at its word, but it has to be a moderator.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+pub struct Moderator {
+    pub moderator_id: Option<i32>,
+}
+
+/// A moderator's action on a review or comment, which resolves every open flag on it. Kept in its
+/// moderation log.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct Moderation {
+    pub id: Option<i32>,
+    /// The review's climb, taken from the path
+    #[serde(default)]
+    pub climb_id: Option<i32>,
+    /// Taken from the path
+    #[serde(default)]
+    pub comment_id: Option<i32>,
+    /// None if the moderator's account has since been purged
+    pub moderator_id: Option<i32>,
+    pub action: ModerationAction,
+    pub reason: String,
+    /// The new text of the review or comment, only for edits
+    #[serde(default)]
+    pub review: Option<String>,
+    /// The text before an edit. Ignored on writes.
+    #[serde(default)]
+    pub previous_review: Option<String>,
+    /// Ignored on writes
+    #[serde(default)]
+    pub created_at: Option<NaiveDateTime>,
+}
+
+impl Moderation {
+    /// Check that the action has a moderator and a reason that isn't too long, and that only edits
+    /// have a new review
+    pub fn check(&self) -> Result<(), DatabaseError> {
+        if self.moderator_id.is_none() {
+            Err(DatabaseError::ModeratorMissing)
+        } else if self.reason.trim().is_empty() {
+            Err(DatabaseError::ModerationReasonMissing)
+        } else if self.reason.chars().count() > MAX_REASON_LENGTH {
+            Err(DatabaseError::ModerationReasonTooLong)
+        } else if (self.action == ModerationAction::Edit) != self.review.is_some() {
+            Err(DatabaseError::ReviewOnlyForEdits)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A review along with its flags and moderation log
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ModeratedReview {
+    pub climb: Climb,
+    pub flags: Vec<ReviewFlag>,
+    pub log: Vec<Moderation>,
+}
+
+/// A comment along with its flags and moderation log. Its replies are left out.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ModeratedComment {
+    pub comment: Comment,
+    pub flags: Vec<ReviewFlag>,
+    pub log: Vec<Moderation>,
+}
+
+/// Reviews with open flags, most flagged first and then longest waiting
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ModerationQueue {
+    /// Only the open flags of each review, and no logs
+    pub reviews: Vec<ModeratedReview>,
+    pub page: i64,
+    pub per_page: i64,
+    /// The next page number, if there are more reviews
+    pub next_page: Option<i64>,
+}
+
+/// Comments with open flags, in the same order as the ModerationQueue
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CommentModerationQueue {
+    /// Only the open flags of each comment, and no logs
+    pub comments: Vec<ModeratedComment>,
+    pub page: i64,
+    pub per_page: i64,
+    /// The next page number, if there are more comments
+    pub next_page: Option<i64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_moderation(action: ModerationAction, review: Option<&str>) -> Moderation {
+        Moderation {
+            id: None,
+            climb_id: Some(1),
+            comment_id: None,
+            moderator_id: Some(1),
+            action,
+            reason: "personal attack on the first ascensionist".to_string(),
+            review: review.map(str::to_string),
+            previous_review: None,
+            created_at: None,
+        }
+    }
+
+    #[test]
+    fn test_flag_reason_round_trip() {
+        for reason in [
+            FlagReason::Spam,
+            FlagReason::Offensive,
+            FlagReason::OffTopic,
+            FlagReason::Inaccurate,
+            FlagReason::Other,
+        ] {
+            assert_eq!(FlagReason::from_str(&reason.to_string()).unwrap(), reason);
+        }
+        assert!(FlagReason::from_str("rude").is_err());
+    }
+
+    #[test]
+    fn test_moderation_action_round_trip() {
+        for action in [
+            ModerationAction::Hide,
+            ModerationAction::Restore,
+            ModerationAction::Edit,
+            ModerationAction::Dismiss,
+        ] {
+            assert_eq!(
+                ModerationAction::from_str(&action.to_string()).unwrap(),
+                action
+            );
+        }
+        assert!(ModerationAction::from_str("delete").is_err());
+    }
+
+    #[test]
+    fn test_moderation_check() {
+        assert!(test_moderation(ModerationAction::Hide, None)
+            .check()
+            .is_ok());
+        assert!(test_moderation(ModerationAction::Edit, Some("Great route"))
+            .check()
+            .is_ok());
+        assert!(test_moderation(ModerationAction::Edit, None)
+            .check()
+            .is_err());
+        assert!(
+            test_moderation(ModerationAction::Restore, Some("Great route"))
+                .check()
+                .is_err()
+        );
+        let mut moderation = test_moderation(ModerationAction::Dismiss, None);
+        moderation.reason = "  ".to_string();
+        assert!(moderation.check().is_err());
+        moderation.reason = "a".repeat(MAX_REASON_LENGTH + 1);
+        assert!(moderation.check().is_err());
+        moderation.reason = "not spam".to_string();
+        moderation.moderator_id = None;
+        assert!(moderation.check().is_err());
+    }
+}
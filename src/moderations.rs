@@ -0,0 +1,787 @@
+use crate::climb::{check_review_length, Climb, Review};
+use crate::climbers::{climber_exists, climber_is_moderator, logbook_visible};
+use crate::comment::{check_comment_body, Comment};
+use crate::comments::select_comments;
+use crate::error::DatabaseError;
+use crate::moderation::{
+    CommentModerationQueue, FlagReason, ModeratedComment, ModeratedReview, Moderation,
+    ModerationAction, ModerationQueue, ModerationTarget, Moderator, ReviewFlag,
+};
+use crate::page::Page;
+use crate::pg::conn;
+use actix_web::{get, http::header, post, web, HttpResponse, Responder};
+use log::error;
+/// Functions for flagging reviews at /climbers/{climber_id}/{route_id}/flags and comments at
+/// /comments/{id}/flags, and for the API endpoint /admin/moderation
+use sqlx::{self, postgres::PgConnection, query, Connection};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// The response to give if there is no climber acting as a moderator, or they don't exist or
+/// aren't a moderator
+pub async fn check_moderator(
+    conn: &mut PgConnection,
+    moderator_id: Option<i32>,
+) -> Option<HttpResponse> {
+    let Some(moderator_id) = moderator_id else {
+        return Some(HttpResponse::Forbidden().body(DatabaseError::NotAModerator.to_string()));
+    };
+    match climber_is_moderator(conn, moderator_id).await {
+        Ok(Some(true)) => None,
+        Ok(Some(false)) => {
+            Some(HttpResponse::Forbidden().body(DatabaseError::NotAModerator.to_string()))
+        }
+        Ok(None) => {
+            Some(HttpResponse::BadRequest().body(DatabaseError::ClimberNotFound.to_string()))
+        }
+        Err(_) => {
+            error!("SELECT query for the moderator failed in check_moderator()");
+            Some(HttpResponse::BadGateway().finish())
+        }
+    }
+}
+
+/// Reviews that haven't been deleted, hidden or not
+async fn select_reviews(
+    conn: &mut PgConnection,
+    climb_ids: &[i32],
+) -> Result<Vec<Climb>, DatabaseError> {
+    Ok(query!(
+        r#"SELECT id as "id!", climber_id as "climber_id!", route_id as "route_id!", rating as "rating!", review as "review!", completion_date as "completion_date!", led_pitches as "led_pitches!", hidden_at IS NOT NULL as "hidden!"
+        FROM active_climbs WHERE id = ANY($1)"#,
+        climb_ids,
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|record| {
+        let mut review = Review::new(record.rating, record.review, record.completion_date);
+        review.led_pitches = record.led_pitches;
+        Climb {
+            id: Some(record.id),
+            climber_id: record.climber_id,
+            route_id: record.route_id,
+            review,
+            hidden: record.hidden,
+        }
+    })
+    .collect())
+}
+
+/// Flags on reviews, or on comments if `comments`, along with the IDs of what they flag, oldest
+/// first
+async fn select_flags(
+    conn: &mut PgConnection,
+    ids: &[i32],
+    comments: bool,
+    open_only: bool,
+) -> Result<Vec<(i32, ReviewFlag)>, DatabaseError> {
+    query!(
+        r#"SELECT id as "id!", climb_id as "target_id!", climber_id as "climber_id!", reason as "reason!", note as "note!", created_at as "created_at!", resolved_at FROM review_flags
+        WHERE $2 IS FALSE AND climb_id = ANY($1) AND ($3 IS FALSE OR resolved_at IS NULL)
+        UNION ALL SELECT id, comment_id, climber_id, reason, note, created_at, resolved_at FROM comment_flags
+        WHERE $2 AND comment_id = ANY($1) AND ($3 IS FALSE OR resolved_at IS NULL)
+        ORDER BY 6, 1"#,
+        ids,
+        comments,
+        open_only,
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|record| {
+        Ok((
+            record.target_id,
+            ReviewFlag {
+                id: Some(record.id),
+                climber_id: record.climber_id,
+                reason: FlagReason::from_str(&record.reason)?,
+                note: record.note,
+                created_at: Some(record.created_at),
+                resolved_at: record.resolved_at,
+            },
+        ))
+    })
+    .collect()
+}
+
+/// The IDs of the climb and comment a flag or moderation action is about, one of which is None
+fn target_ids(target: ModerationTarget) -> (Option<i32>, Option<i32>) {
+    match target {
+        ModerationTarget::Review(climb_id) => (Some(climb_id), None),
+        ModerationTarget::Comment(comment_id) => (None, Some(comment_id)),
+    }
+}
+
+/// A review's or comment's moderation log, oldest first
+async fn select_log(
+    conn: &mut PgConnection,
+    target: ModerationTarget,
+) -> Result<Vec<Moderation>, DatabaseError> {
+    let (climb_id, comment_id) = target_ids(target);
+    query!(
+        r#"SELECT id as "id!", climb_id, NULL::int4 as comment_id, moderator_id, action as "action!", reason as "reason!", previous_review, created_at as "created_at!" FROM moderation_log
+        WHERE climb_id = ($1)
+        UNION ALL SELECT id, NULL, comment_id, moderator_id, action, reason, previous_body, created_at FROM comment_moderation_log
+        WHERE comment_id = ($2)
+        ORDER BY 8, 1"#,
+        climb_id,
+        comment_id,
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|record| {
+        Ok(Moderation {
+            id: Some(record.id),
+            climb_id: record.climb_id,
+            comment_id: record.comment_id,
+            moderator_id: record.moderator_id,
+            action: ModerationAction::from_str(&record.action)?,
+            reason: record.reason,
+            review: None,
+            previous_review: record.previous_review,
+            created_at: Some(record.created_at),
+        })
+    })
+    .collect()
+}
+
+/// Record a climber's flag, giving back the flag with its ID, or None if they already have an
+/// open flag on the same review or comment
+async fn insert_flag(
+    conn: &mut PgConnection,
+    target: ModerationTarget,
+    flag: ReviewFlag,
+) -> Result<Option<ReviewFlag>, DatabaseError> {
+    let inserted = match target {
+        ModerationTarget::Review(climb_id) => query!(
+            r#"INSERT INTO review_flags (climb_id, climber_id, reason, note) VALUES ($1, $2, $3, $4)
+            ON CONFLICT DO NOTHING
+            RETURNING id, created_at"#,
+            climb_id,
+            flag.climber_id,
+            flag.reason.to_string(),
+            flag.note,
+        )
+        .fetch_optional(conn)
+        .await?
+        .map(|query_result| (query_result.id, query_result.created_at)),
+        ModerationTarget::Comment(comment_id) => query!(
+            r#"INSERT INTO comment_flags (comment_id, climber_id, reason, note) VALUES ($1, $2, $3, $4)
+            ON CONFLICT DO NOTHING
+            RETURNING id, created_at"#,
+            comment_id,
+            flag.climber_id,
+            flag.reason.to_string(),
+            flag.note,
+        )
+        .fetch_optional(conn)
+        .await?
+        .map(|query_result| (query_result.id, query_result.created_at)),
+    };
+    Ok(inserted.map(|(id, created_at)| ReviewFlag {
+        id: Some(id),
+        created_at: Some(created_at),
+        resolved_at: None,
+        ..flag
+    }))
+}
+
+/// Carry out a moderator's action on a review or comment, resolve its open flags and log the
+/// action, all or nothing. The review or comment is locked while its hidden state is checked, so
+/// that two moderators can't both hide or restore it. Gives back the logged action, or the
+/// response to give if the review or comment doesn't exist or is already hidden or shown.
+async fn record_moderation(
+    conn: &mut PgConnection,
+    target: ModerationTarget,
+    mut moderation: Moderation,
+) -> Result<Result<Moderation, HttpResponse>, DatabaseError> {
+    let mut transaction = conn.begin().await?;
+    let current = match target {
+        ModerationTarget::Review(climb_id) => query!(
+            r#"SELECT hidden_at IS NOT NULL as "hidden!", review as "review!" FROM climbs
+            WHERE id = ($1) AND id IN (SELECT id FROM active_climbs) FOR UPDATE"#,
+            climb_id,
+        )
+        .fetch_optional(&mut transaction)
+        .await?
+        .map(|record| (record.hidden, record.review)),
+        ModerationTarget::Comment(comment_id) => query!(
+            r#"SELECT hidden_at IS NOT NULL as "hidden!", body as "body!" FROM comments
+            WHERE id = ($1) AND deleted_at IS NULL
+            AND climber_id IN (SELECT id FROM climbers WHERE deleted_at IS NULL)
+            AND (route_id IS NULL OR route_id IN (SELECT id FROM routes WHERE deleted_at IS NULL))
+            FOR UPDATE"#,
+            comment_id,
+        )
+        .fetch_optional(&mut transaction)
+        .await?
+        .map(|record| (record.hidden, record.body)),
+    };
+    let Some((hidden, text)) = current else {
+        return Ok(Err(HttpResponse::NotFound().finish()));
+    };
+    let conflict = match (target, moderation.action, hidden) {
+        (ModerationTarget::Review(_), ModerationAction::Hide, true) => {
+            Some(DatabaseError::ReviewAlreadyHidden)
+        }
+        (ModerationTarget::Review(_), ModerationAction::Restore, false) => {
+            Some(DatabaseError::ReviewNotHidden)
+        }
+        (ModerationTarget::Comment(_), ModerationAction::Hide, true) => {
+            Some(DatabaseError::CommentAlreadyHidden)
+        }
+        (ModerationTarget::Comment(_), ModerationAction::Restore, false) => {
+            Some(DatabaseError::CommentNotHidden)
+        }
+        _ => None,
+    };
+    if let Some(e) = conflict {
+        return Ok(Err(HttpResponse::Conflict().body(e.to_string())));
+    }
+    let previous_review = (moderation.action == ModerationAction::Edit).then_some(text);
+    match (target, moderation.action) {
+        (_, ModerationAction::Dismiss) => (),
+        (ModerationTarget::Review(climb_id), ModerationAction::Hide) => {
+            query!(
+                "UPDATE climbs SET hidden_at = current_timestamp, version = version + 1 WHERE id = ($1)",
+                climb_id,
+            )
+            .execute(&mut transaction)
+            .await?;
+        }
+        (ModerationTarget::Review(climb_id), ModerationAction::Restore) => {
+            query!(
+                "UPDATE climbs SET hidden_at = NULL, version = version + 1 WHERE id = ($1)",
+                climb_id,
+            )
+            .execute(&mut transaction)
+            .await?;
+        }
+        (ModerationTarget::Review(climb_id), ModerationAction::Edit) => {
+            query!(
+                "UPDATE climbs SET review = ($2), version = version + 1 WHERE id = ($1)",
+                climb_id,
+                moderation.review,
+            )
+            .execute(&mut transaction)
+            .await?;
+        }
+        (ModerationTarget::Comment(comment_id), ModerationAction::Hide) => {
+            query!(
+                "UPDATE comments SET hidden_at = current_timestamp WHERE id = ($1)",
+                comment_id,
+            )
+            .execute(&mut transaction)
+            .await?;
+        }
+        (ModerationTarget::Comment(comment_id), ModerationAction::Restore) => {
+            query!(
+                "UPDATE comments SET hidden_at = NULL WHERE id = ($1)",
+                comment_id,
+            )
+            .execute(&mut transaction)
+            .await?;
+        }
+        (ModerationTarget::Comment(comment_id), ModerationAction::Edit) => {
+            query!(
+                "UPDATE comments SET body = ($2), edited_at = current_timestamp WHERE id = ($1)",
+                comment_id,
+                moderation.review,
+            )
+            .execute(&mut transaction)
+            .await?;
+        }
+    }
+    let (id, created_at) = match target {
+        ModerationTarget::Review(climb_id) => {
+            query!(
+                "UPDATE review_flags SET resolved_at = current_timestamp WHERE climb_id = ($1) AND resolved_at IS NULL",
+                climb_id,
+            )
+            .execute(&mut transaction)
+            .await?;
+            let query_result = query!(
+                r#"INSERT INTO moderation_log (climb_id, moderator_id, action, reason, previous_review)
+                VALUES ($1, $2, $3, $4, $5) RETURNING id, created_at"#,
+                climb_id,
+                moderation.moderator_id,
+                moderation.action.to_string(),
+                moderation.reason,
+                previous_review,
+            )
+            .fetch_one(&mut transaction)
+            .await?;
+            (query_result.id, query_result.created_at)
+        }
+        ModerationTarget::Comment(comment_id) => {
+            query!(
+                "UPDATE comment_flags SET resolved_at = current_timestamp WHERE comment_id = ($1) AND resolved_at IS NULL",
+                comment_id,
+            )
+            .execute(&mut transaction)
+            .await?;
+            let query_result = query!(
+                r#"INSERT INTO comment_moderation_log (comment_id, moderator_id, action, reason, previous_body)
+                VALUES ($1, $2, $3, $4, $5) RETURNING id, created_at"#,
+                comment_id,
+                moderation.moderator_id,
+                moderation.action.to_string(),
+                moderation.reason,
+                previous_review,
+            )
+            .fetch_one(&mut transaction)
+            .await?;
+            (query_result.id, query_result.created_at)
+        }
+    };
+    transaction.commit().await?;
+    let (climb_id, comment_id) = target_ids(target);
+    moderation.id = Some(id);
+    moderation.climb_id = climb_id;
+    moderation.comment_id = comment_id;
+    moderation.previous_review = previous_review;
+    moderation.created_at = Some(created_at);
+    Ok(Ok(moderation))
+}
+
+/// Flag a climber's review of a route for the moderators. The review has to be visible to the
+/// climber flagging it.
+#[post("/{climber_id}/{route_id}/flags")]
+async fn add_review_flag(
+    path: web::Path<(i32, i32)>,
+    json: web::Json<ReviewFlag>,
+) -> impl Responder {
+    let (climber_id, route_id) = path.into_inner();
+    if json.climber_id == climber_id {
+        return HttpResponse::BadRequest().body(DatabaseError::CannotFlagOwnReview.to_string());
+    }
+    if let Ok(mut conn) = conn().await {
+        match climber_exists(&mut conn, json.climber_id).await {
+            Ok(true) => (),
+            Ok(false) => {
+                return HttpResponse::BadRequest().body(DatabaseError::ClimberNotFound.to_string())
+            }
+            Err(_) => {
+                error!("SELECT query for the flagger failed in add_review_flag()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        match logbook_visible(&mut conn, climber_id, Some(json.climber_id)).await {
+            Ok(Some(true)) => (),
+            Ok(Some(false)) => return HttpResponse::Forbidden().finish(),
+            Ok(None) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in add_review_flag()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        let climb_id = match query!(
+            r#"SELECT id as "id!" FROM active_climbs WHERE climber_id = ($1) AND route_id = ($2) AND hidden_at IS NULL"#,
+            climber_id,
+            route_id,
+        )
+        .fetch_optional(&mut conn)
+        .await
+        {
+            Ok(Some(query_result)) => query_result.id,
+            Ok(None) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query for the review failed in add_review_flag()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        match insert_flag(
+            &mut conn,
+            ModerationTarget::Review(climb_id),
+            json.into_inner(),
+        )
+        .await
+        {
+            Ok(Some(flag)) => HttpResponse::Created()
+                .insert_header((header::LOCATION, format!("/admin/moderation/{climb_id}")))
+                .json(flag),
+            Ok(None) => {
+                HttpResponse::Conflict().body(DatabaseError::ReviewAlreadyFlagged.to_string())
+            }
+            Err(_) => {
+                error!("INSERT query failed in add_review_flag()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in add_review_flag()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Reviews waiting for a moderator, with their open flags
+#[get("/moderation")]
+async fn get_moderation_queue(
+    query: web::Query<Page>,
+    moderator: web::Query<Moderator>,
+) -> impl Responder {
+    let (page, per_page) = query.page_and_size();
+    if let Ok(mut conn) = conn().await {
+        if let Some(response) = check_moderator(&mut conn, moderator.moderator_id).await {
+            return response;
+        }
+        let climb_ids = match query!(
+            r#"SELECT climb_id as "climb_id!" FROM review_flags
+            WHERE resolved_at IS NULL AND climb_id IN (SELECT id FROM active_climbs)
+            GROUP BY climb_id ORDER BY COUNT(*) DESC, MIN(created_at), climb_id
+            LIMIT ($1) OFFSET ($2)"#,
+            per_page + 1,
+            query.offset(),
+        )
+        .fetch_all(&mut conn)
+        .await
+        {
+            Ok(query_result) => query_result
+                .into_iter()
+                .map(|record| record.climb_id)
+                .collect::<Vec<i32>>(),
+            Err(_) => {
+                error!("SELECT query failed in get_moderation_queue()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        let next_page = (climb_ids.len() as i64 > per_page).then_some(page + 1);
+        let climb_ids = &climb_ids[..climb_ids.len().min(per_page as usize)];
+        let reviews = match select_reviews(&mut conn, climb_ids).await {
+            Ok(reviews) => reviews,
+            Err(_) => {
+                error!("SELECT query for reviews failed in get_moderation_queue()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        let flags = match select_flags(&mut conn, climb_ids, false, true).await {
+            Ok(flags) => flags,
+            Err(_) => {
+                error!("SELECT query for flags failed in get_moderation_queue()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        let mut flags_by_review: BTreeMap<i32, Vec<ReviewFlag>> = BTreeMap::new();
+        for (climb_id, flag) in flags {
+            flags_by_review.entry(climb_id).or_default().push(flag);
+        }
+        let mut reviews_by_id = reviews
+            .into_iter()
+            .filter_map(|climb| climb.id.map(|id| (id, climb)))
+            .collect::<BTreeMap<i32, Climb>>();
+        // In the queue's order, which the SELECT of the reviews doesn't keep
+        let reviews = climb_ids
+            .iter()
+            .filter_map(|climb_id| {
+                reviews_by_id.remove(climb_id).map(|climb| ModeratedReview {
+                    climb,
+                    flags: flags_by_review.remove(climb_id).unwrap_or_default(),
+                    log: Vec::new(),
+                })
+            })
+            .collect::<Vec<ModeratedReview>>();
+        HttpResponse::Ok().json(ModerationQueue {
+            reviews,
+            page,
+            per_page,
+            next_page,
+        })
+    } else {
+        error!("Failed to connect to the database in get_moderation_queue()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// A review, hidden or not, with every flag on it and its moderation log
+#[get("/moderation/{climb_id}")]
+async fn get_moderated_review(
+    path: web::Path<i32>,
+    moderator: web::Query<Moderator>,
+) -> impl Responder {
+    let climb_id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        if let Some(response) = check_moderator(&mut conn, moderator.moderator_id).await {
+            return response;
+        }
+        let climb = match select_reviews(&mut conn, &[climb_id]).await {
+            Ok(mut reviews) => match reviews.pop() {
+                Some(climb) => climb,
+                None => return HttpResponse::NotFound().finish(),
+            },
+            Err(_) => {
+                error!("SELECT query failed in get_moderated_review()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        let flags = match select_flags(&mut conn, &[climb_id], false, false).await {
+            Ok(flags) => flags.into_iter().map(|(_, flag)| flag).collect(),
+            Err(_) => {
+                error!("SELECT query for flags failed in get_moderated_review()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        match select_log(&mut conn, ModerationTarget::Review(climb_id)).await {
+            Ok(log) => HttpResponse::Ok().json(ModeratedReview { climb, flags, log }),
+            Err(_) => {
+                error!("SELECT query for the log failed in get_moderated_review()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in get_moderated_review()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Hide, restore, edit or dismiss the flags on a review, resolving its open flags and logging the
+/// moderator's reason
+#[post("/moderation/{climb_id}")]
+async fn moderate_review(path: web::Path<i32>, json: web::Json<Moderation>) -> impl Responder {
+    let climb_id = path.into_inner();
+    if let Err(e) = json.check() {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+    if let Some(Err(e)) = json.review.as_deref().map(check_review_length) {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+    if let Ok(mut conn) = conn().await {
+        if let Some(response) = check_moderator(&mut conn, json.moderator_id).await {
+            return response;
+        }
+        match record_moderation(
+            &mut conn,
+            ModerationTarget::Review(climb_id),
+            json.into_inner(),
+        )
+        .await
+        {
+            Ok(Ok(moderation)) => HttpResponse::Created()
+                .insert_header((header::LOCATION, format!("/admin/moderation/{climb_id}")))
+                .json(moderation),
+            Ok(Err(response)) => response,
+            Err(_) => {
+                error!("Failed to record the moderation in moderate_review()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in moderate_review()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Flag a comment for the moderators. Deleted and hidden comments can't be flagged.
+#[post("/{id}/flags")]
+async fn add_comment_flag(path: web::Path<i32>, json: web::Json<ReviewFlag>) -> impl Responder {
+    let id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match climber_exists(&mut conn, json.climber_id).await {
+            Ok(true) => (),
+            Ok(false) => {
+                return HttpResponse::BadRequest().body(DatabaseError::ClimberNotFound.to_string())
+            }
+            Err(_) => {
+                error!("SELECT query for the flagger failed in add_comment_flag()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        match select_comments(&mut conn, &[id], false).await {
+            Ok(mut comments) => match comments.pop() {
+                Some(comment) if comment.deleted || comment.hidden => {
+                    return HttpResponse::NotFound().finish()
+                }
+                Some(comment) if comment.climber_id == json.climber_id => {
+                    return HttpResponse::BadRequest()
+                        .body(DatabaseError::CannotFlagOwnComment.to_string())
+                }
+                Some(_) => (),
+                None => return HttpResponse::NotFound().finish(),
+            },
+            Err(_) => {
+                error!("SELECT query for the comment failed in add_comment_flag()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        match insert_flag(&mut conn, ModerationTarget::Comment(id), json.into_inner()).await {
+            Ok(Some(flag)) => HttpResponse::Created()
+                .insert_header((header::LOCATION, format!("/admin/moderation/comments/{id}")))
+                .json(flag),
+            Ok(None) => {
+                HttpResponse::Conflict().body(DatabaseError::CommentAlreadyFlagged.to_string())
+            }
+            Err(_) => {
+                error!("INSERT query failed in add_comment_flag()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in add_comment_flag()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Comments waiting for a moderator, with their open flags
+#[get("/moderation/comments")]
+async fn get_comment_moderation_queue(
+    query: web::Query<Page>,
+    moderator: web::Query<Moderator>,
+) -> impl Responder {
+    let (page, per_page) = query.page_and_size();
+    if let Ok(mut conn) = conn().await {
+        if let Some(response) = check_moderator(&mut conn, moderator.moderator_id).await {
+            return response;
+        }
+        let comment_ids = match query!(
+            r#"SELECT comment_id FROM comment_flags
+            WHERE resolved_at IS NULL AND comment_id IN (SELECT id FROM comments WHERE deleted_at IS NULL)
+            GROUP BY comment_id ORDER BY COUNT(*) DESC, MIN(created_at), comment_id
+            LIMIT ($1) OFFSET ($2)"#,
+            per_page + 1,
+            query.offset(),
+        )
+        .fetch_all(&mut conn)
+        .await
+        {
+            Ok(query_result) => query_result
+                .into_iter()
+                .map(|record| record.comment_id)
+                .collect::<Vec<i32>>(),
+            Err(_) => {
+                error!("SELECT query failed in get_comment_moderation_queue()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        let next_page = (comment_ids.len() as i64 > per_page).then_some(page + 1);
+        let comment_ids = &comment_ids[..comment_ids.len().min(per_page as usize)];
+        let comments = match select_comments(&mut conn, comment_ids, false).await {
+            Ok(comments) => comments,
+            Err(_) => {
+                error!("SELECT query for comments failed in get_comment_moderation_queue()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        let flags = match select_flags(&mut conn, comment_ids, true, true).await {
+            Ok(flags) => flags,
+            Err(_) => {
+                error!("SELECT query for flags failed in get_comment_moderation_queue()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        let mut flags_by_comment: BTreeMap<i32, Vec<ReviewFlag>> = BTreeMap::new();
+        for (comment_id, flag) in flags {
+            flags_by_comment.entry(comment_id).or_default().push(flag);
+        }
+        let mut comments_by_id = comments
+            .into_iter()
+            .filter_map(|comment| comment.id.map(|id| (id, comment)))
+            .collect::<BTreeMap<i32, Comment>>();
+        let comments = comment_ids
+            .iter()
+            .filter_map(|comment_id| {
+                comments_by_id
+                    .remove(comment_id)
+                    .map(|comment| ModeratedComment {
+                        comment,
+                        flags: flags_by_comment.remove(comment_id).unwrap_or_default(),
+                        log: Vec::new(),
+                    })
+            })
+            .collect::<Vec<ModeratedComment>>();
+        HttpResponse::Ok().json(CommentModerationQueue {
+            comments,
+            page,
+            per_page,
+            next_page,
+        })
+    } else {
+        error!("Failed to connect to the database in get_comment_moderation_queue()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// A comment, hidden or deleted or not, with every flag on it and its moderation log
+#[get("/moderation/comments/{comment_id}")]
+async fn get_moderated_comment(
+    path: web::Path<i32>,
+    moderator: web::Query<Moderator>,
+) -> impl Responder {
+    let comment_id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        if let Some(response) = check_moderator(&mut conn, moderator.moderator_id).await {
+            return response;
+        }
+        let comment = match select_comments(&mut conn, &[comment_id], false).await {
+            Ok(mut comments) => match comments.pop() {
+                Some(comment) => comment,
+                None => return HttpResponse::NotFound().finish(),
+            },
+            Err(_) => {
+                error!("SELECT query failed in get_moderated_comment()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        let flags = match select_flags(&mut conn, &[comment_id], true, false).await {
+            Ok(flags) => flags.into_iter().map(|(_, flag)| flag).collect(),
+            Err(_) => {
+                error!("SELECT query for flags failed in get_moderated_comment()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        match select_log(&mut conn, ModerationTarget::Comment(comment_id)).await {
+            Ok(log) => HttpResponse::Ok().json(ModeratedComment {
+                comment,
+                flags,
+                log,
+            }),
+            Err(_) => {
+                error!("SELECT query for the log failed in get_moderated_comment()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in get_moderated_comment()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Hide, restore, edit or dismiss the flags on a comment, in the same way as for reviews. Deleted
+/// comments can't be moderated.
+#[post("/moderation/comments/{comment_id}")]
+async fn moderate_comment(path: web::Path<i32>, json: web::Json<Moderation>) -> impl Responder {
+    let comment_id = path.into_inner();
+    if let Err(e) = json.check() {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+    if let Some(Err(e)) = json.review.as_deref().map(check_comment_body) {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+    if let Ok(mut conn) = conn().await {
+        if let Some(response) = check_moderator(&mut conn, json.moderator_id).await {
+            return response;
+        }
+        match record_moderation(
+            &mut conn,
+            ModerationTarget::Comment(comment_id),
+            json.into_inner(),
+        )
+        .await
+        {
+            Ok(Ok(moderation)) => HttpResponse::Created()
+                .insert_header((
+                    header::LOCATION,
+                    format!("/admin/moderation/comments/{comment_id}"),
+                ))
+                .json(moderation),
+            Ok(Err(response)) => response,
+            Err(_) => {
+                error!("Failed to record the moderation in moderate_comment()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in moderate_comment()");
+        HttpResponse::BadGateway().finish()
+    }
+}
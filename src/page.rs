@@ -0,0 +1,64 @@
+/// module for paginating listings with the `page` and `per_page` query parameters
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_PAGE_SIZE: i64 = 20;
+pub const MAX_PAGE_SIZE: i64 = 100;
+/// Past this, a page's offset (or the next page's number) would overflow. Such pages are empty.
+const MAX_PAGE: i64 = i64::MAX / MAX_PAGE_SIZE;
+
+/// Query parameters for a paginated listing. Pages are numbered from 1.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone, Copy)]
+pub struct Page {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+impl Page {
+    /// The page number and page size to use, clamped to sensible values
+    pub fn page_and_size(&self) -> (i64, i64) {
+        (
+            self.page.unwrap_or(1).clamp(1, MAX_PAGE),
+            self.per_page
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+        )
+    }
+
+    /// Number of items to skip to get to this page
+    pub fn offset(&self) -> i64 {
+        let (page, per_page) = self.page_and_size();
+        (page - 1) * per_page
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_and_size() {
+        assert_eq!(Page::default().page_and_size(), (1, DEFAULT_PAGE_SIZE));
+        let page = Page {
+            page: Some(0),
+            per_page: Some(1000),
+        };
+        assert_eq!(page.page_and_size(), (1, MAX_PAGE_SIZE));
+        let page = Page {
+            page: Some(3),
+            per_page: Some(10),
+        };
+        assert_eq!(page.offset(), 20);
+    }
+
+    #[test]
+    fn test_huge_page_does_not_overflow() {
+        let page = Page {
+            page: Some(i64::MAX),
+            per_page: Some(i64::MAX),
+        };
+        let (number, per_page) = page.page_and_size();
+        assert_eq!(per_page, MAX_PAGE_SIZE);
+        assert!(number.checked_add(1).is_some());
+        assert_eq!(page.offset(), (MAX_PAGE - 1) * MAX_PAGE_SIZE);
+    }
+}
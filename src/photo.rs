@@ -0,0 +1,249 @@
+/// module for photos and topos of routes, areas and climbs, handled by the API endpoint /photos
+use image::{
+    imageops::FilterType,
+    io::{Limits, Reader},
+    DynamicImage, ImageError, ImageFormat, ImageOutputFormat,
+};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::io::Cursor;
+
+use crate::error::PhotoError;
+
+pub const DEFAULT_MAX_PHOTO_BYTES: usize = 10 * 1024 * 1024;
+/// Widest or tallest photo that will be decoded, since a small compressed upload can claim to be
+/// enormous
+pub const MAX_PHOTO_DIMENSION: u32 = 8192;
+/// Most memory decoding one photo can take, enough for a MAX_PHOTO_DIMENSION square in RGBA
+const MAX_PHOTO_DECODE_BYTES: u64 = 256 * 1024 * 1024;
+/// Thumbnails fit in a square this many pixels across
+pub const THUMBNAIL_SIZE: u32 = 256;
+const JPEG_QUALITY: u8 = 85;
+
+/// Largest photo upload from the MAX_PHOTO_BYTES environment variable, or DEFAULT_MAX_PHOTO_BYTES
+/// if it is missing or not a number
+pub fn max_photo_bytes() -> usize {
+    env::var("MAX_PHOTO_BYTES")
+        .ok()
+        .and_then(|bytes| bytes.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_PHOTO_BYTES)
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub enum PhotoFormat {
+    Jpeg,
+    Png,
+}
+
+impl PhotoFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Jpeg => "image/jpeg",
+            Self::Png => "image/png",
+        }
+    }
+
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::Png => "png",
+        }
+    }
+
+    /// Pick a format from a Content-Type header, ignoring any parameters
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        match content_type.split(';').next()?.trim() {
+            "image/jpeg" => Some(Self::Jpeg),
+            "image/png" => Some(Self::Png),
+            _ => None,
+        }
+    }
+
+    fn image_format(&self) -> ImageFormat {
+        match self {
+            Self::Jpeg => ImageFormat::Jpeg,
+            Self::Png => ImageFormat::Png,
+        }
+    }
+
+    fn output_format(&self) -> ImageOutputFormat {
+        match self {
+            Self::Jpeg => ImageOutputFormat::Jpeg(JPEG_QUALITY),
+            Self::Png => ImageOutputFormat::Png,
+        }
+    }
+
+    pub fn encode(&self, image: &DynamicImage) -> Result<Vec<u8>, PhotoError> {
+        let mut encoded = Cursor::new(Vec::new());
+        image.write_to(&mut encoded, self.output_format())?;
+        Ok(encoded.into_inner())
+    }
+
+    /// Check that an upload really is a photo in this format and isn't too big to decode,
+    /// returning its width and height along with a thumbnail in the same format. This is slow, so
+    /// it shouldn't be run on an async worker.
+    pub fn process(&self, data: &[u8]) -> Result<ProcessedPhoto, PhotoError> {
+        let mut limits = Limits::default();
+        limits.max_image_width = Some(MAX_PHOTO_DIMENSION);
+        limits.max_image_height = Some(MAX_PHOTO_DIMENSION);
+        limits.max_alloc = Some(MAX_PHOTO_DECODE_BYTES);
+        let mut reader = Reader::with_format(Cursor::new(data), self.image_format());
+        reader.limits(limits);
+        let image = match reader.decode() {
+            Ok(image) => image,
+            Err(ImageError::Limits(_)) => return Err(PhotoError::ImageTooLarge),
+            Err(e) => return Err(e.into()),
+        };
+        let thumbnail = image.resize(THUMBNAIL_SIZE, THUMBNAIL_SIZE, FilterType::Triangle);
+        Ok(ProcessedPhoto {
+            width: image.width() as i32,
+            height: image.height() as i32,
+            thumbnail: self.encode(&thumbnail)?,
+        })
+    }
+}
+
+pub struct ProcessedPhoto {
+    pub width: i32,
+    pub height: i32,
+    pub thumbnail: Vec<u8>,
+}
+
+/// A point on a topo, measured from the top left corner of the photo as a fraction of its width
+/// and height, so that it doesn't depend on the size the photo is drawn at
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct TopoPoint {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// One line on a topo, usually following a single route
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct TopoLine {
+    pub route_id: Option<i32>,
+    pub label: Option<String>,
+    pub points: Vec<TopoPoint>,
+}
+
+/// Lines for clients to draw over a route photo
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct Topo {
+    pub lines: Vec<TopoLine>,
+}
+
+impl Topo {
+    pub fn check_points(&self) -> Result<(), PhotoError> {
+        let on_photo =
+            |point: &TopoPoint| (0.0..=1.0).contains(&point.x) && (0.0..=1.0).contains(&point.y);
+        if self
+            .lines
+            .iter()
+            .all(|line| line.points.len() >= 2 && line.points.iter().all(on_photo))
+        {
+            Ok(())
+        } else {
+            Err(PhotoError::TopoPointsInvalid)
+        }
+    }
+}
+
+/// What a photo is attached to. A climb is found by its climber and route, like a review.
+#[derive(Debug, Clone, Copy)]
+pub enum PhotoOwner {
+    Route(i32),
+    Area(i32),
+    Climb { climber_id: i32, route_id: i32 },
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct Photo {
+    pub id: i32,
+    pub route_id: Option<i32>,
+    pub area_id: Option<i32>,
+    pub climb_id: Option<i32>,
+    pub content_type: String,
+    pub size_bytes: i32,
+    pub width: i32,
+    pub height: i32,
+    pub caption: Option<String>,
+    pub topo: Option<Topo>,
+    pub url: String,
+    pub thumbnail_url: String,
+}
+
+impl Photo {
+    pub fn url(id: i32) -> String {
+        format!("/photos/{id}/file")
+    }
+
+    pub fn thumbnail_url(id: i32) -> String {
+        format!("/photos/{id}/thumbnail")
+    }
+
+    /// Key of the uploaded file in FileStorage
+    pub fn file_key(id: i32, format: PhotoFormat) -> String {
+        format!("photos/{id}.{}", format.file_extension())
+    }
+
+    pub fn thumbnail_key(id: i32, format: PhotoFormat) -> String {
+        format!("photos/{id}-thumbnail.{}", format.file_extension())
+    }
+}
+
+/// Optional query parameters for uploading a photo
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+pub struct PhotoUpload {
+    pub caption: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    #[test]
+    fn test_process_photo() {
+        let png = PhotoFormat::Png
+            .encode(&DynamicImage::ImageRgb8(RgbImage::new(1024, 512)))
+            .unwrap();
+        let processed = PhotoFormat::Png.process(&png).unwrap();
+        assert_eq!((processed.width, processed.height), (1024, 512));
+        let thumbnail = image::load_from_memory(&processed.thumbnail).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (256, 128));
+
+        // too wide to decode
+        let png = PhotoFormat::Png
+            .encode(&DynamicImage::ImageRgb8(RgbImage::new(
+                MAX_PHOTO_DIMENSION + 1,
+                1,
+            )))
+            .unwrap();
+        assert!(matches!(
+            PhotoFormat::Png.process(&png),
+            Err(PhotoError::ImageTooLarge)
+        ));
+
+        // a PNG is not a JPEG, whatever the content type says
+        assert!(matches!(
+            PhotoFormat::Jpeg.process(&png),
+            Err(PhotoError::InvalidImage(_))
+        ));
+    }
+
+    #[test]
+    fn test_topo_points() {
+        let mut topo = Topo {
+            lines: vec![TopoLine {
+                route_id: None,
+                label: Some("1".to_string()),
+                points: vec![TopoPoint { x: 0.5, y: 1.0 }, TopoPoint { x: 0.4, y: 0.1 }],
+            }],
+        };
+        assert!(topo.check_points().is_ok());
+        topo.lines[0].points[1].y = -0.1;
+        assert!(matches!(
+            topo.check_points(),
+            Err(PhotoError::TopoPointsInvalid)
+        ));
+    }
+}
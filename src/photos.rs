@@ -0,0 +1,470 @@
+use crate::climber::Viewer;
+use crate::climbers::logbook_visible;
+use crate::error::{DatabaseError, PhotoError};
+use crate::pg::conn;
+use crate::photo::{Photo, PhotoFormat, PhotoOwner, PhotoUpload, Topo};
+use crate::storage::FileStorage;
+use actix_web::{delete, get, http::header, post, put, web, HttpRequest, HttpResponse, Responder};
+use log::error;
+/// Functions for the API endpoint /photos, and for uploading photos of routes, areas and climbs
+use sqlx::{self, postgres::PgConnection, query, Connection};
+
+/// The route, area and climb IDs to store for a photo's owner. Ok(None) if the owner doesn't
+/// exist.
+async fn owner_ids(
+    conn: &mut PgConnection,
+    owner: PhotoOwner,
+) -> Result<Option<(Option<i32>, Option<i32>, Option<i32>)>, DatabaseError> {
+    Ok(match owner {
+        PhotoOwner::Route(id) => query!(
+            "SELECT id FROM routes WHERE id = ($1) AND deleted_at IS NULL",
+            id
+        )
+        .fetch_optional(conn)
+        .await?
+        .map(|record| (Some(record.id), None, None)),
+        PhotoOwner::Area(id) => query!("SELECT id FROM areas WHERE id = ($1)", id)
+            .fetch_optional(conn)
+            .await?
+            .map(|record| (None, Some(record.id), None)),
+        PhotoOwner::Climb {
+            climber_id,
+            route_id,
+        } => query!(
+            r#"SELECT id as "id!" FROM active_climbs WHERE climber_id = ($1) AND route_id = ($2) ORDER BY id DESC LIMIT 1"#,
+            climber_id,
+            route_id,
+        )
+        .fetch_optional(conn)
+        .await?
+        .map(|record| (None, None, Some(record.id))),
+    })
+}
+
+/// Shared SELECT behind every endpoint that returns photos. Photos of deleted routes and climbs
+/// are left out.
+async fn select_photos(
+    conn: &mut PgConnection,
+    id: Option<i32>,
+    owner: Option<PhotoOwner>,
+) -> Result<Vec<Photo>, DatabaseError> {
+    let (route_id, area_id, climb) = match owner {
+        Some(PhotoOwner::Route(route_id)) => (Some(route_id), None, None),
+        Some(PhotoOwner::Area(area_id)) => (None, Some(area_id), None),
+        Some(PhotoOwner::Climb {
+            climber_id,
+            route_id,
+        }) => (None, None, Some((climber_id, route_id))),
+        None => (None, None, None),
+    };
+    query!(
+        r#"SELECT id, route_id, area_id, climb_id, content_type, size_bytes, width, height, caption, topo::text as topo
+        FROM photos
+        WHERE ($1::int4 IS NULL OR id = $1)
+        AND ($2::int4 IS NULL OR route_id = $2)
+        AND ($3::int4 IS NULL OR area_id = $3)
+        AND ($4::int4 IS NULL OR climb_id IN (SELECT id FROM active_climbs WHERE climber_id = $4 AND route_id = $5))
+        AND (route_id IS NULL OR route_id IN (SELECT id FROM routes WHERE deleted_at IS NULL))
+        AND (climb_id IS NULL OR climb_id IN (SELECT id FROM active_climbs))
+        ORDER BY created_at, id"#,
+        id,
+        route_id,
+        area_id,
+        climb.map(|(climber_id, _)| climber_id),
+        climb.map(|(_, route_id)| route_id),
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|record| {
+        Ok(Photo {
+            id: record.id,
+            route_id: record.route_id,
+            area_id: record.area_id,
+            climb_id: record.climb_id,
+            content_type: record.content_type,
+            size_bytes: record.size_bytes,
+            width: record.width,
+            height: record.height,
+            caption: record.caption,
+            topo: record
+                .topo
+                .map(|topo| serde_json::from_str::<Topo>(&topo))
+                .transpose()
+                .map_err(|_| DatabaseError::FailedParseTopo)?,
+            url: Photo::url(record.id),
+            thumbnail_url: Photo::thumbnail_url(record.id),
+        })
+    })
+    .collect()
+}
+
+/// Store an uploaded photo and its thumbnail. Decoding and the file writes block, so they run on
+/// the blocking thread pool rather than the worker.
+async fn upload_photo(
+    owner: PhotoOwner,
+    request: &HttpRequest,
+    upload: &PhotoUpload,
+    body: web::Bytes,
+    storage: web::Data<dyn FileStorage>,
+) -> HttpResponse {
+    let format = match request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .and_then(PhotoFormat::from_content_type)
+    {
+        Some(format) => format,
+        None => return HttpResponse::UnsupportedMediaType().finish(),
+    };
+    let data = body.clone();
+    let processed = match web::block(move || format.process(&data)).await {
+        Ok(Ok(processed)) => processed,
+        Ok(Err(e)) => {
+            error!("Failed to read photo in upload_photo(): {e}");
+            return HttpResponse::BadRequest().body(e.to_string());
+        }
+        Err(_) => {
+            error!("Failed to run the photo processing in upload_photo()");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    if let Ok(mut conn) = conn().await {
+        let (route_id, area_id, climb_id) = match owner_ids(&mut conn, owner).await {
+            Ok(Some(ids)) => ids,
+            Ok(None) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in upload_photo()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        let Ok(mut transaction) = conn.begin().await else {
+            error!("Failed to start a transaction in upload_photo()");
+            return HttpResponse::BadGateway().finish();
+        };
+        let id = match query!(
+            "INSERT INTO photos (route_id, area_id, climb_id, content_type, size_bytes, width, height, caption) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING id",
+            route_id,
+            area_id,
+            climb_id,
+            format.content_type(),
+            body.len() as i32,
+            processed.width,
+            processed.height,
+            upload.caption,
+        )
+        .fetch_one(&mut transaction)
+        .await
+        {
+            Ok(query_result) => query_result.id,
+            Err(_) => {
+                error!("INSERT query failed in upload_photo()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        // Only commit once the files are stored, so that every photo row has its files
+        let data = body.clone();
+        let thumbnail = processed.thumbnail;
+        match web::block(move || {
+            storage
+                .save(&Photo::file_key(id, format), &data)
+                .and_then(|()| storage.save(&Photo::thumbnail_key(id, format), &thumbnail))
+        })
+        .await
+        {
+            Ok(Ok(())) => (),
+            Ok(Err(e)) => {
+                error!("Failed to store photo in upload_photo(): {e}");
+                return HttpResponse::InternalServerError().finish();
+            }
+            Err(_) => {
+                error!("Failed to run the photo storage in upload_photo()");
+                return HttpResponse::InternalServerError().finish();
+            }
+        }
+        if transaction.commit().await.is_err() {
+            error!("Failed to commit transaction in upload_photo()");
+            return HttpResponse::BadGateway().finish();
+        }
+        let photo = Photo {
+            id,
+            route_id,
+            area_id,
+            climb_id,
+            content_type: format.content_type().to_string(),
+            size_bytes: body.len() as i32,
+            width: processed.width,
+            height: processed.height,
+            caption: upload.caption.clone(),
+            topo: None,
+            url: Photo::url(id),
+            thumbnail_url: Photo::thumbnail_url(id),
+        };
+        HttpResponse::Created()
+            .insert_header((header::LOCATION, format!("/photos/{id}")))
+            .json(photo)
+    } else {
+        error!("Failed to connect to the database in upload_photo()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Remove stored files, logging any that can't be removed rather than failing, since the rows
+/// they belonged to are already gone
+pub fn remove_files(storage: &dyn FileStorage, keys: &[String]) {
+    for key in keys {
+        if let Err(e) = storage.remove(key) {
+            error!("Failed to remove {key} in remove_files(): {e}");
+        }
+    }
+}
+
+async fn list_photos(owner: PhotoOwner) -> HttpResponse {
+    if let Ok(mut conn) = conn().await {
+        if let Ok(photos) = select_photos(&mut conn, None, Some(owner)).await {
+            HttpResponse::Ok().json(photos)
+        } else {
+            error!("SELECT query failed in list_photos()");
+            HttpResponse::BadGateway().finish()
+        }
+    } else {
+        error!("Failed to connect to the database in list_photos()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Upload a JPEG (`image/jpeg`) or PNG (`image/png`) photo of a route
+#[post("/{id}/photos")]
+async fn add_route_photo(
+    path: web::Path<i32>,
+    request: HttpRequest,
+    upload: web::Query<PhotoUpload>,
+    body: web::Bytes,
+    storage: web::Data<dyn FileStorage>,
+) -> impl Responder {
+    let owner = PhotoOwner::Route(path.into_inner());
+    upload_photo(owner, &request, &upload, body, storage).await
+}
+
+#[get("/{id}/photos")]
+async fn get_route_photos(path: web::Path<i32>) -> impl Responder {
+    list_photos(PhotoOwner::Route(path.into_inner())).await
+}
+
+#[post("/{id}/photos")]
+async fn add_area_photo(
+    path: web::Path<i32>,
+    request: HttpRequest,
+    upload: web::Query<PhotoUpload>,
+    body: web::Bytes,
+    storage: web::Data<dyn FileStorage>,
+) -> impl Responder {
+    let owner = PhotoOwner::Area(path.into_inner());
+    upload_photo(owner, &request, &upload, body, storage).await
+}
+
+#[get("/{id}/photos")]
+async fn get_area_photos(path: web::Path<i32>) -> impl Responder {
+    list_photos(PhotoOwner::Area(path.into_inner())).await
+}
+
+#[post("/{climber_id}/{route_id}/photos")]
+async fn add_climb_photo(
+    path: web::Path<(i32, i32)>,
+    request: HttpRequest,
+    upload: web::Query<PhotoUpload>,
+    body: web::Bytes,
+    storage: web::Data<dyn FileStorage>,
+) -> impl Responder {
+    let (climber_id, route_id) = path.into_inner();
+    let owner = PhotoOwner::Climb {
+        climber_id,
+        route_id,
+    };
+    upload_photo(owner, &request, &upload, body, storage).await
+}
+
+#[get("/{climber_id}/{route_id}/photos")]
+async fn get_climb_photos(
+    path: web::Path<(i32, i32)>,
+    viewer: web::Query<Viewer>,
+) -> impl Responder {
+    let (climber_id, route_id) = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match logbook_visible(&mut conn, climber_id, viewer.viewer_id).await {
+            Ok(Some(false)) => return HttpResponse::Forbidden().finish(),
+            Ok(_) => (),
+            Err(_) => {
+                error!("SELECT query failed in get_climb_photos()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in get_climb_photos()");
+        return HttpResponse::BadGateway().finish();
+    }
+    list_photos(PhotoOwner::Climb {
+        climber_id,
+        route_id,
+    })
+    .await
+}
+
+#[get("/{id}")]
+async fn get_photo_by_id(path: web::Path<i32>) -> impl Responder {
+    let id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match select_photos(&mut conn, Some(id), None).await {
+            Ok(mut photos) if !photos.is_empty() => HttpResponse::Ok().json(photos.remove(0)),
+            Ok(_) => HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in get_photo_by_id()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in get_photo_by_id()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Respond with a stored file of a photo, either the upload itself or its thumbnail
+async fn photo_file(id: i32, thumbnail: bool, storage: web::Data<dyn FileStorage>) -> HttpResponse {
+    if let Ok(mut conn) = conn().await {
+        let format = match select_photos(&mut conn, Some(id), None).await {
+            Ok(photos) => match photos
+                .first()
+                .and_then(|photo| PhotoFormat::from_content_type(&photo.content_type))
+            {
+                Some(format) => format,
+                None => return HttpResponse::NotFound().finish(),
+            },
+            Err(_) => {
+                error!("SELECT query failed in photo_file()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        let key = if thumbnail {
+            Photo::thumbnail_key(id, format)
+        } else {
+            Photo::file_key(id, format)
+        };
+        let loaded_key = key.clone();
+        match web::block(move || storage.load(&loaded_key)).await {
+            Ok(Ok(data)) => HttpResponse::Ok()
+                .content_type(format.content_type())
+                .body(data),
+            Ok(Err(e)) => {
+                error!("Failed to load {key} in photo_file(): {e}");
+                HttpResponse::InternalServerError().finish()
+            }
+            Err(_) => {
+                error!("Failed to run the file load in photo_file()");
+                HttpResponse::InternalServerError().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in photo_file()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+#[get("/{id}/file")]
+async fn get_photo_file(
+    path: web::Path<i32>,
+    storage: web::Data<dyn FileStorage>,
+) -> impl Responder {
+    photo_file(path.into_inner(), false, storage).await
+}
+
+#[get("/{id}/thumbnail")]
+async fn get_photo_thumbnail(
+    path: web::Path<i32>,
+    storage: web::Data<dyn FileStorage>,
+) -> impl Responder {
+    photo_file(path.into_inner(), true, storage).await
+}
+
+/// Replace the topo lines drawn over a photo of a route
+#[put("/{id}/topo")]
+async fn update_photo_topo(path: web::Path<i32>, json: web::Json<Topo>) -> impl Responder {
+    let id = path.into_inner();
+    if let Err(e) = json.check_points() {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+    let Ok(topo) = serde_json::to_string(&json.0) else {
+        error!("Failed to serialize topo in update_photo_topo()");
+        return HttpResponse::BadRequest().finish();
+    };
+    if let Ok(mut conn) = conn().await {
+        match query!(
+            "UPDATE photos SET topo = $1::text::jsonb WHERE id = ($2) AND route_id IS NOT NULL",
+            topo,
+            id,
+        )
+        .execute(&mut conn)
+        .await
+        {
+            Ok(query_result) if query_result.rows_affected() == 0 => {
+                match query!("SELECT id FROM photos WHERE id = ($1)", id)
+                    .fetch_optional(&mut conn)
+                    .await
+                {
+                    Ok(Some(_)) => HttpResponse::BadRequest()
+                        .body(PhotoError::TopoOnlyOnRoutePhotos.to_string()),
+                    Ok(None) => HttpResponse::NotFound().finish(),
+                    Err(_) => {
+                        error!("SELECT query failed in update_photo_topo()");
+                        HttpResponse::BadGateway().finish()
+                    }
+                }
+            }
+            Ok(_) => HttpResponse::NoContent().finish(),
+            Err(_) => {
+                error!("UPDATE query failed in update_photo_topo()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in update_photo_topo()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+#[delete("/{id}")]
+async fn delete_photo(path: web::Path<i32>, storage: web::Data<dyn FileStorage>) -> impl Responder {
+    let id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match query!(
+            "DELETE FROM photos WHERE id = ($1) RETURNING content_type",
+            id
+        )
+        .fetch_optional(&mut conn)
+        .await
+        {
+            Ok(Some(query_result)) => {
+                if let Some(format) = PhotoFormat::from_content_type(&query_result.content_type) {
+                    let keys = vec![
+                        Photo::file_key(id, format),
+                        Photo::thumbnail_key(id, format),
+                    ];
+                    if web::block(move || remove_files(storage.as_ref(), &keys))
+                        .await
+                        .is_err()
+                    {
+                        error!("Failed to run the file removal in delete_photo()");
+                    }
+                }
+                HttpResponse::NoContent().finish()
+            }
+            Ok(None) => HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("DELETE query failed in delete_photo()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in delete_photo()");
+        HttpResponse::BadGateway().finish()
+    }
+}
@@ -0,0 +1,127 @@
+use crate::error::DatabaseError;
+use crate::pg::conn;
+use crate::photo::{Photo, PhotoFormat};
+use crate::photos::remove_files;
+use crate::storage::{storage, FileStorage};
+use actix_web::web;
+use log::{error, info};
+/// Background job that hard-deletes rows that have been soft deleted for longer than the
+/// retention period
+use sqlx::{self, postgres::PgConnection, query, Connection};
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const DEFAULT_RETENTION_DAYS: i32 = 30;
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Retention period from the SOFT_DELETE_RETENTION_DAYS environment variable, or
+/// DEFAULT_RETENTION_DAYS if it is missing or not a number
+pub fn retention_days() -> i32 {
+    env::var("SOFT_DELETE_RETENTION_DAYS")
+        .ok()
+        .and_then(|days| days.parse::<i32>().ok())
+        .unwrap_or(DEFAULT_RETENTION_DAYS)
+}
+
+/// Hard-delete every climb, route and climber soft deleted more than `retention_days` ago,
+/// returning the number of rows removed. Routes merged into another are kept for their redirect,
+/// and so are routes that other climbers still have climbs of or that are a competition's
+/// problem, as the foreign keys to routes don't cascade. Photos go with their route, climb or
+/// climber, files and all.
+pub async fn purge_deleted(
+    conn: &mut PgConnection,
+    retention_days: i32,
+    storage: Arc<dyn FileStorage>,
+) -> Result<u64, DatabaseError> {
+    let mut transaction = conn.begin().await?;
+    // Photos would go anyway when their climb does, but their files have to go too
+    let mut photos = query!(
+        r#"DELETE FROM photos WHERE climb_id IN (
+            SELECT id FROM climbs WHERE deleted_at < current_timestamp - make_interval(days => $1)
+            OR climber_id IN (SELECT id FROM climbers WHERE deleted_at < current_timestamp - make_interval(days => $1))
+        )
+        RETURNING id, content_type"#,
+        retention_days,
+    )
+    .fetch_all(&mut transaction)
+    .await?
+    .into_iter()
+    .map(|photo| (photo.id, photo.content_type))
+    .collect::<Vec<(i32, String)>>();
+    let mut rows_purged = query!(
+        "DELETE FROM climbs WHERE deleted_at < current_timestamp - make_interval(days => $1)",
+        retention_days,
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    rows_purged += query!(
+        "DELETE FROM climbers WHERE deleted_at < current_timestamp - make_interval(days => $1)",
+        retention_days,
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    // Only now that the purged climbs are gone can a route's remaining climbs keep it
+    photos.extend(
+        query!(
+            r#"DELETE FROM photos WHERE route_id IN (
+                SELECT id FROM routes WHERE deleted_at < current_timestamp - make_interval(days => $1) AND merged_into IS NULL
+                AND NOT EXISTS (SELECT 1 FROM climbs WHERE route_id = routes.id)
+                AND NOT EXISTS (SELECT 1 FROM competition_problems WHERE route_id = routes.id)
+            )
+            RETURNING id, content_type"#,
+            retention_days,
+        )
+        .fetch_all(&mut transaction)
+        .await?
+        .into_iter()
+        .map(|photo| (photo.id, photo.content_type)),
+    );
+    rows_purged += query!(
+        r#"DELETE FROM routes WHERE deleted_at < current_timestamp - make_interval(days => $1) AND merged_into IS NULL
+        AND NOT EXISTS (SELECT 1 FROM climbs WHERE route_id = routes.id)
+        AND NOT EXISTS (SELECT 1 FROM competition_problems WHERE route_id = routes.id)"#,
+        retention_days,
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    rows_purged += photos.len() as u64;
+    transaction.commit().await?;
+    let keys = photos
+        .into_iter()
+        .filter_map(|(id, content_type)| {
+            let format = PhotoFormat::from_content_type(&content_type)?;
+            Some([
+                Photo::file_key(id, format),
+                Photo::thumbnail_key(id, format),
+            ])
+        })
+        .flatten()
+        .collect::<Vec<String>>();
+    if web::block(move || remove_files(storage.as_ref(), &keys))
+        .await
+        .is_err()
+    {
+        error!("Failed to run the file removal in purge_deleted()");
+    }
+    Ok(rows_purged)
+}
+
+pub async fn purge_job() {
+    let retention_days = retention_days();
+    let mut interval = tokio::time::interval(PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Ok(mut conn) = conn().await {
+            match purge_deleted(&mut conn, retention_days, storage()).await {
+                Ok(rows_purged) => info!("Purged {rows_purged} soft deleted rows"),
+                Err(_) => error!("DELETE query failed in purge_job()"),
+            }
+        } else {
+            error!("Failed to connect to the database in purge_job()");
+        }
+    }
+}
@@ -0,0 +1,531 @@
+/// module for route recommendations made from a climber's logbook, handled by the API endpoint
+/// /climbers/{id}/recommendations
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::climber::LogbookEntry;
+use crate::route::{DifficultyRating, Discipline, GradeScale, RatedRoute, RouteFilter};
+
+const DEFAULT_MAX_DISTANCE_KM: f64 = 100.0;
+const DEFAULT_LIMIT: i64 = 10;
+const MAX_LIMIT: i64 = 50;
+/// Grades below the climber's hardest send that are still recommended
+const GRADES_BELOW: i64 = 2;
+/// Grades above the climber's hardest send that are still recommended
+const GRADES_ABOVE: i64 = 1;
+/// Climbers who agree with each other's ratings at least this much count as similar
+const SIMILARITY_THRESHOLD: f64 = 0.5;
+const EARTH_RADIUS_KM: f64 = 6371.0;
+/// A little less than the shortest a degree of latitude gets, so that boxes err on the large side
+const KM_PER_DEGREE_LATITUDE: f64 = 110.5;
+/// Routes read from the database for each grade envelope, newest first
+pub const MAX_CANDIDATES: i64 = 500;
+
+/// Query parameters for GET /climbers/{id}/recommendations
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+pub struct RecommendationQuery {
+    pub viewer_id: Option<i32>,
+    /// How far a route may be from the routes the climber has climbed. Defaults to 100 km.
+    pub max_distance_km: Option<f64>,
+    pub limit: Option<i64>,
+}
+
+impl RecommendationQuery {
+    pub fn max_distance_km(&self) -> f64 {
+        self.max_distance_km
+            .unwrap_or(DEFAULT_MAX_DISTANCE_KM)
+            .max(0.0)
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT) as usize
+    }
+}
+
+/// The grades recommended in one discipline and grade scale: from two grades below the climber's
+/// hardest send on that scale up to one grade above
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct GradeEnvelope {
+    pub discipline: Discipline,
+    pub hardest: DifficultyRating,
+    pub min_points: i64,
+    pub max_points: i64,
+}
+
+impl GradeEnvelope {
+    /// One envelope for each discipline and grade scale in the logbook
+    pub fn from_logbook(entries: &[LogbookEntry]) -> Vec<Self> {
+        let mut hardest: BTreeMap<(Discipline, GradeScale), &DifficultyRating> = BTreeMap::new();
+        for entry in entries {
+            let difficulty = &entry.route.difficulty;
+            hardest
+                .entry((entry.route.discipline, difficulty.scale()))
+                .and_modify(|current| *current = (*current).max(difficulty))
+                .or_insert(difficulty);
+        }
+        hardest
+            .into_iter()
+            .map(|((discipline, _), hardest)| Self {
+                discipline,
+                min_points: hardest.points() - 100 * GRADES_BELOW,
+                max_points: hardest.points() + 100 * GRADES_ABOVE,
+                hardest: hardest.clone(),
+            })
+            .collect()
+    }
+
+    /// The routes worth scoring for this envelope, so that only those are read from the
+    /// database: routes of its discipline and grades that the climber hasn't climbed, in a box
+    /// around the routes in their logbook that holds every point within `max_distance_km` of them
+    pub fn candidate_filter(
+        &self,
+        climber_id: i32,
+        entries: &[LogbookEntry],
+        max_distance_km: f64,
+    ) -> RouteFilter {
+        let difficulties = self
+            .hardest
+            .scale()
+            .grades()
+            .into_iter()
+            .filter(|grade| (self.min_points..=self.max_points).contains(&grade.points()))
+            .collect();
+        let mut filter = RouteFilter {
+            discipline: Some(self.discipline),
+            difficulties: Some(difficulties),
+            not_climbed_by: Some(climber_id),
+            ..Default::default()
+        };
+        let latitudes = entries.iter().map(|entry| entry.route.latitude);
+        let longitudes = entries.iter().map(|entry| entry.route.longitude);
+        if entries.is_empty() {
+            return filter;
+        }
+        let margin = max_distance_km / KM_PER_DEGREE_LATITUDE;
+        let min_latitude = latitudes.clone().fold(f64::INFINITY, f64::min) - margin;
+        let max_latitude = latitudes.fold(f64::NEG_INFINITY, f64::max) + margin;
+        filter.min_latitude = Some(min_latitude);
+        filter.max_latitude = Some(max_latitude);
+        // A degree of longitude is shortest furthest from the equator. The box is left open from
+        // east to west near the poles or where it would cross the antimeridian.
+        let widest = min_latitude.abs().max(max_latitude.abs());
+        if widest < 89.0 {
+            let margin = margin / widest.to_radians().cos();
+            let min_longitude = longitudes.clone().fold(f64::INFINITY, f64::min) - margin;
+            let max_longitude = longitudes.fold(f64::NEG_INFINITY, f64::max) + margin;
+            if min_longitude >= -180.0 && max_longitude <= 180.0 {
+                filter.min_longitude = Some(min_longitude);
+                filter.max_longitude = Some(max_longitude);
+            }
+        }
+        filter
+    }
+
+    /// Grades between `difficulty` and the hardest send, if the difficulty is in the envelope
+    fn grades_from_hardest(
+        &self,
+        discipline: Discipline,
+        difficulty: &DifficultyRating,
+    ) -> Option<i64> {
+        (discipline == self.discipline
+            && difficulty.scale() == self.hardest.scale()
+            && (self.min_points..=self.max_points).contains(&difficulty.points()))
+        .then(|| (difficulty.points() - self.hardest.points()) / 100)
+    }
+}
+
+/// Another climber's rating of a route
+#[derive(Debug, Clone)]
+pub struct OtherRating {
+    pub climber_id: i32,
+    pub route_id: i32,
+    pub rating: i32,
+}
+
+/// Great-circle distance between two points, in kilometers
+pub fn distance_km(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (latitude_from, latitude_to) = (from.0.to_radians(), to.0.to_radians());
+    let half_latitude = (latitude_to - latitude_from) / 2.0;
+    let half_longitude = (to.1 - from.1).to_radians() / 2.0;
+    let a = half_latitude.sin().powi(2)
+        + latitude_from.cos() * latitude_to.cos() * half_longitude.sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin()
+}
+
+/// Average rating of each route, keyed by climber then route, so repeat climbs count once
+fn ratings_by_climber(ratings: &[OtherRating]) -> HashMap<i32, HashMap<i32, f64>> {
+    let mut sums: HashMap<i32, HashMap<i32, (f64, f64)>> = HashMap::new();
+    for rating in ratings {
+        let (sum, count) = sums
+            .entry(rating.climber_id)
+            .or_default()
+            .entry(rating.route_id)
+            .or_default();
+        *sum += f64::from(rating.rating);
+        *count += 1.0;
+    }
+    sums.into_iter()
+        .map(|(climber_id, routes)| {
+            let averages = routes
+                .into_iter()
+                .map(|(route_id, (sum, count))| (route_id, sum / count))
+                .collect();
+            (climber_id, averages)
+        })
+        .collect()
+}
+
+/// What climbers who rate routes like this climber would rate each route, weighted by how
+/// similar they are, along with how many similar climbers rated it. Two climbers' similarity is
+/// 1 when they gave every route they have both climbed the same rating, and 0 when their ratings
+/// are as far apart as they can be.
+pub fn similar_climber_ratings(
+    own_ratings: &HashMap<i32, f64>,
+    other_ratings: &[OtherRating],
+) -> HashMap<i32, (f64, i64)> {
+    let mut weighted: HashMap<i32, (f64, f64, i64)> = HashMap::new();
+    for (_, ratings) in ratings_by_climber(other_ratings) {
+        let differences = ratings
+            .iter()
+            .filter_map(|(route_id, rating)| {
+                own_ratings.get(route_id).map(|own| (own - rating).abs())
+            })
+            .collect::<Vec<f64>>();
+        if differences.is_empty() {
+            continue;
+        }
+        let similarity = 1.0 - differences.iter().sum::<f64>() / differences.len() as f64 / 9.0;
+        if similarity < SIMILARITY_THRESHOLD {
+            continue;
+        }
+        for (route_id, rating) in ratings {
+            if own_ratings.contains_key(&route_id) {
+                continue;
+            }
+            let (sum, weights, count) = weighted.entry(route_id).or_default();
+            *sum += similarity * rating;
+            *weights += similarity;
+            *count += 1;
+        }
+    }
+    weighted
+        .into_iter()
+        .map(|(route_id, (sum, weights, count))| (route_id, (sum / weights, count)))
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Recommendation {
+    #[serde(flatten)]
+    pub route: RatedRoute,
+    /// How far the route is from the nearest route the climber has climbed
+    pub distance_km: f64,
+    /// What similar climbers rated the route, weighted by how similar they are
+    pub similar_climbers_rating: Option<f64>,
+    /// Between 0 and 1, best first
+    pub score: f64,
+    pub explanation: String,
+}
+
+/// Body of GET /climbers/{id}/recommendations
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Recommendations {
+    pub climber_id: i32,
+    pub envelopes: Vec<GradeEnvelope>,
+    pub recommendations: Vec<Recommendation>,
+}
+
+fn plural(count: i64, noun: &str) -> String {
+    if count == 1 {
+        format!("{count} {noun}")
+    } else {
+        format!("{count} {noun}s")
+    }
+}
+
+/// Recommend routes the climber hasn't climbed from `candidates`. A route has to be in one of the
+/// climber's grade `envelopes` and within `max_distance_km` of a route they have climbed, and is
+/// scored by its community rating, similar climbers' ratings, how close it is to their hardest
+/// send, and how close it is.
+pub fn recommend(
+    entries: &[LogbookEntry],
+    envelopes: &[GradeEnvelope],
+    candidates: Vec<RatedRoute>,
+    other_ratings: &[OtherRating],
+    max_distance_km: f64,
+    limit: usize,
+) -> Vec<Recommendation> {
+    let mut own_ratings: HashMap<i32, (f64, f64)> = HashMap::new();
+    for entry in entries {
+        if let Some(route_id) = entry.route.id {
+            let (sum, count) = own_ratings.entry(route_id).or_default();
+            *sum += f64::from(entry.climb.review.rating.i32());
+            *count += 1.0;
+        }
+    }
+    let own_ratings = own_ratings
+        .into_iter()
+        .map(|(route_id, (sum, count))| (route_id, sum / count))
+        .collect::<HashMap<i32, f64>>();
+    let similar_ratings = similar_climber_ratings(&own_ratings, other_ratings);
+    let climbed_locations = entries
+        .iter()
+        .map(|entry| (entry.route.latitude, entry.route.longitude))
+        .collect::<Vec<(f64, f64)>>();
+
+    let mut recommendations = candidates
+        .into_iter()
+        .filter(|candidate| {
+            candidate
+                .route
+                .id
+                .is_some_and(|id| !own_ratings.contains_key(&id))
+        })
+        .filter_map(|candidate| {
+            let (envelope, grades) = envelopes.iter().find_map(|envelope| {
+                envelope
+                    .grades_from_hardest(candidate.route.discipline, &candidate.route.difficulty)
+                    .map(|grades| (envelope, grades))
+            })?;
+            let location = (candidate.route.latitude, candidate.route.longitude);
+            let distance_km = climbed_locations
+                .iter()
+                .map(|&climbed| distance_km(climbed, location))
+                .fold(f64::INFINITY, f64::min);
+            if distance_km > max_distance_km {
+                return None;
+            }
+            let similar = candidate
+                .route
+                .id
+                .and_then(|id| similar_ratings.get(&id).copied());
+
+            let number_ratings = candidate.number_ratings as f64;
+            let community_score = candidate.average_rating.unwrap_or_default() / 10.0
+                * (number_ratings / (number_ratings + 2.0));
+            let similar_score = similar.map_or(0.0, |(rating, count)| {
+                rating / 10.0 * (count as f64 / (count as f64 + 1.0))
+            });
+            let grade_score = 1.0 - grades.abs() as f64 / (GRADES_BELOW + 1) as f64;
+            let distance_score = if max_distance_km > 0.0 {
+                1.0 - distance_km / max_distance_km
+            } else {
+                1.0
+            };
+            let score = 0.35 * community_score
+                + 0.35 * similar_score
+                + 0.2 * grade_score
+                + 0.1 * distance_score;
+
+            let grade = match grades {
+                0 => format!(
+                    "the same grade as your hardest {} send",
+                    envelope.discipline
+                ),
+                1.. => format!(
+                    "{} above your hardest {} send ({})",
+                    plural(grades, "grade"),
+                    envelope.discipline,
+                    envelope.hardest
+                ),
+                _ => format!(
+                    "{} below your hardest {} send ({})",
+                    plural(-grades, "grade"),
+                    envelope.discipline,
+                    envelope.hardest
+                ),
+            };
+            let mut reasons = vec![
+                format!("{} is {grade}", candidate.route.difficulty),
+                format!("{distance_km:.0} km from routes you've climbed"),
+            ];
+            if let Some(average_rating) = candidate.average_rating {
+                reasons.push(format!(
+                    "rated {average_rating:.1}/10 by {}",
+                    plural(candidate.number_ratings, "climber")
+                ));
+            }
+            if let Some((rating, count)) = similar {
+                reasons.push(format!(
+                    "{} who rate routes like you gave it {rating:.1}/10",
+                    plural(count, "climber")
+                ));
+            }
+            Some(Recommendation {
+                route: candidate,
+                distance_km,
+                similar_climbers_rating: similar.map(|(rating, _)| rating),
+                score,
+                explanation: reasons.join("; "),
+            })
+        })
+        .collect::<Vec<Recommendation>>();
+    recommendations.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then(a.route.route.id.cmp(&b.route.route.id))
+    });
+    recommendations.truncate(limit);
+    recommendations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::climb::{Climb, Review};
+    use crate::route::Route;
+    use sqlx::types::chrono::NaiveDate;
+
+    fn test_route(id: i32, difficulty: DifficultyRating, latitude: f64) -> Route {
+        Route::new(
+            Some(id),
+            format!("route{id}"),
+            Discipline::Boulder,
+            difficulty,
+            latitude,
+            -105.0,
+        )
+    }
+
+    fn test_entry(route: Route, rating: i32) -> LogbookEntry {
+        LogbookEntry {
+            climb: Climb {
+                id: None,
+                climber_id: 1,
+                route_id: route.id.unwrap(),
+                review: Review::new(rating, String::new(), NaiveDate::default()),
+                hidden: false,
+            },
+            route,
+        }
+    }
+
+    fn test_rated_route(route: Route, average_rating: f64, number_ratings: i64) -> RatedRoute {
+        RatedRoute {
+            route,
+            average_rating: Some(average_rating),
+            number_ratings,
+        }
+    }
+
+    #[test]
+    fn test_distance_km() {
+        assert_eq!(distance_km((40.0, -105.0), (40.0, -105.0)), 0.0);
+        // a degree of latitude is about 111 km
+        let degree = distance_km((40.0, -105.0), (41.0, -105.0));
+        assert!((degree - 111.2).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_similar_climber_ratings() {
+        let own_ratings = HashMap::from([(1, 8.0), (2, 3.0)]);
+        let rating = |climber_id, route_id, rating| OtherRating {
+            climber_id,
+            route_id,
+            rating,
+        };
+        let other_ratings = vec![
+            // 2 agrees exactly
+            rating(2, 1, 8),
+            rating(2, 2, 3),
+            rating(2, 3, 10),
+            // 3 disagrees completely, so is ignored
+            rating(3, 1, 1),
+            rating(3, 2, 10),
+            rating(3, 3, 1),
+            // 4 has climbed nothing in common
+            rating(4, 3, 1),
+        ];
+        let similar = similar_climber_ratings(&own_ratings, &other_ratings);
+        assert_eq!(similar.len(), 1);
+        assert_eq!(similar[&3], (10.0, 1));
+    }
+
+    #[test]
+    fn test_envelopes_per_grade_scale() {
+        let mut ice = test_route(1, DifficultyRating::RatingWI5, 40.0);
+        ice.discipline = Discipline::Mixed;
+        let mut mixed = test_route(2, DifficultyRating::RatingM4, 40.0);
+        mixed.discipline = Discipline::Mixed;
+        let entries = vec![
+            test_entry(ice, 8),
+            test_entry(mixed, 6),
+            test_entry(test_route(3, DifficultyRating::RatingV1, 41.0), 5),
+        ];
+        let envelopes = GradeEnvelope::from_logbook(&entries);
+        assert_eq!(
+            envelopes
+                .iter()
+                .map(|envelope| (envelope.discipline, envelope.hardest.clone()))
+                .collect::<Vec<(Discipline, DifficultyRating)>>(),
+            vec![
+                (Discipline::Boulder, DifficultyRating::RatingV1),
+                (Discipline::Mixed, DifficultyRating::RatingWI5),
+                (Discipline::Mixed, DifficultyRating::RatingM4),
+            ]
+        );
+
+        let filter = envelopes[0].candidate_filter(7, &entries, 111.0);
+        assert_eq!(filter.discipline, Some(Discipline::Boulder));
+        assert_eq!(
+            filter.difficulties,
+            Some(vec![
+                DifficultyRating::RatingV0,
+                DifficultyRating::RatingV1,
+                DifficultyRating::RatingV2
+            ])
+        );
+        assert_eq!(filter.not_climbed_by, Some(7));
+        let (min_latitude, max_latitude) =
+            (filter.min_latitude.unwrap(), filter.max_latitude.unwrap());
+        assert!(min_latitude < 39.0 && min_latitude > 38.9);
+        assert!(max_latitude > 42.0 && max_latitude < 42.1);
+        // a degree of longitude is shorter than a degree of latitude away from the equator
+        assert!(filter.min_longitude.unwrap() < -105.0 - (40.0 - min_latitude));
+        let filter = envelopes[2].candidate_filter(7, &entries, 111.0);
+        assert_eq!(
+            filter.difficulties,
+            Some(vec![
+                DifficultyRating::RatingM2,
+                DifficultyRating::RatingM3,
+                DifficultyRating::RatingM4,
+                DifficultyRating::RatingM5,
+            ])
+        );
+    }
+
+    #[test]
+    fn test_recommend() {
+        let entries = vec![
+            test_entry(test_route(1, DifficultyRating::RatingV4, 40.0), 8),
+            test_entry(test_route(2, DifficultyRating::RatingV2, 40.0), 5),
+        ];
+        let candidates = vec![
+            // already climbed
+            test_rated_route(test_route(1, DifficultyRating::RatingV4, 40.0), 9.0, 10),
+            // too hard, and too easy
+            test_rated_route(test_route(3, DifficultyRating::RatingV6, 40.0), 9.0, 10),
+            test_rated_route(test_route(4, DifficultyRating::RatingV1, 40.0), 9.0, 10),
+            // too far away
+            test_rated_route(test_route(5, DifficultyRating::RatingV4, 45.0), 9.0, 10),
+            // in the envelope, and the best rated is recommended first
+            test_rated_route(test_route(6, DifficultyRating::RatingV5, 40.1), 4.0, 2),
+            test_rated_route(test_route(7, DifficultyRating::RatingV3, 40.1), 9.0, 10),
+        ];
+        let envelopes = GradeEnvelope::from_logbook(&entries);
+        let recommendations = recommend(&entries, &envelopes, candidates, &[], 100.0, 10);
+        assert_eq!(envelopes.len(), 1);
+        assert_eq!(envelopes[0].hardest, DifficultyRating::RatingV4);
+        assert_eq!(
+            recommendations
+                .iter()
+                .map(|recommendation| recommendation.route.route.id.unwrap())
+                .collect::<Vec<i32>>(),
+            vec![7, 6]
+        );
+        assert_eq!(
+            recommendations[1].explanation,
+            "V5 is 1 grade above your hardest boulder send (V4); 11 km from routes you've climbed; rated 4.0/10 by 2 climbers"
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use crate::climb::{
+    Climb, PublishedReview, Review, ReviewSearchQuery, ReviewSort, Reviews, RouteReviewQuery,
+};
+use crate::climber::Visibility;
+use crate::climbers::{climber_exists, logbook_visible};
+use crate::error::DatabaseError;
+use crate::page::Page;
+use crate::pg::conn;
+use actix_web::{delete, get, put, web, HttpResponse, Responder};
+use log::error;
+/// Functions for the API endpoints /reviews and /routes/{id}/reviews, and for marking reviews
+/// helpful
+use sqlx::{self, postgres::PgConnection, query};
+
+/// Filters for select_reviews
+struct ReviewFilter<'a> {
+    route_id: Option<i32>,
+    /// Words to search for
+    search: Option<&'a str>,
+    viewer_id: Option<i32>,
+    sort: ReviewSort,
+}
+
+/// Shared SELECT behind every endpoint that lists reviews, along with whether there is another
+/// page. Climbs without a review are left out, as are reviews in logbooks the viewer can't see
+/// and hidden reviews that aren't the viewer's.
+async fn select_reviews(
+    conn: &mut PgConnection,
+    filter: &ReviewFilter<'_>,
+    page: Page,
+) -> Result<(Vec<PublishedReview>, bool), DatabaseError> {
+    let (_, per_page) = page.page_and_size();
+    let records = query!(
+        r#"SELECT climbs.id as "id!", climbs.climber_id as "climber_id!", climbers.username as "username!", climbs.route_id as "route_id!", routes.name as "route_name!",
+        climbs.rating as "rating!", climbs.review as "review!", climbs.completion_date as "completion_date!", climbs.led_pitches as "led_pitches!",
+        climbs.hidden_at IS NOT NULL as "hidden!", climbs.created_at as "created_at!", COALESCE(votes.helpful_count, 0) as "helpful_count!",
+        EXISTS (SELECT 1 FROM review_votes WHERE climb_id = climbs.id AND climber_id = ($3)) as "viewer_found_helpful!",
+        CASE WHEN $2::text IS NULL THEN NULL ELSE ts_headline('english', escape_html(climbs.review), websearch_to_tsquery('english', $2)) END as headline
+        FROM active_climbs climbs
+        JOIN climbers ON climbers.id = climbs.climber_id
+        JOIN routes ON routes.id = climbs.route_id
+        LEFT JOIN (SELECT climb_id, COUNT(*) as helpful_count FROM review_votes GROUP BY climb_id) votes ON votes.climb_id = climbs.id
+        WHERE climbs.review <> ''
+        AND ($1::int4 IS NULL OR climbs.route_id = $1)
+        AND ($2::text IS NULL OR to_tsvector('english', climbs.review) @@ websearch_to_tsquery('english', $2))
+        AND (climbs.hidden_at IS NULL OR climbs.climber_id = $3)
+        AND (climbs.climber_id = $3 OR climbers.logbook_visibility = ($5) OR (
+            climbers.logbook_visibility = ($6) AND EXISTS (SELECT 1 FROM follows WHERE follower_id = ($3) AND followed_id = climbers.id)
+        ))
+        ORDER BY
+        CASE WHEN $4 = 'relevance' AND $2::text IS NOT NULL THEN ts_rank(to_tsvector('english', climbs.review), websearch_to_tsquery('english', $2)) END DESC,
+        CASE WHEN $4 = 'helpful' THEN COALESCE(votes.helpful_count, 0) END DESC,
+        CASE WHEN $4 = 'rating' THEN climbs.rating END DESC,
+        climbs.created_at DESC, climbs.id DESC
+        LIMIT ($7) OFFSET ($8)"#,
+        filter.route_id,
+        filter.search,
+        filter.viewer_id,
+        filter.sort.to_string(),
+        Visibility::Public.to_string(),
+        Visibility::Followers.to_string(),
+        per_page + 1,
+        page.offset(),
+    )
+    .fetch_all(conn)
+    .await?;
+    let more = records.len() as i64 > per_page;
+    let reviews = records
+        .into_iter()
+        .take(per_page as usize)
+        .map(|record| {
+            let mut review = Review::new(record.rating, record.review, record.completion_date);
+            review.led_pitches = record.led_pitches;
+            PublishedReview {
+                climb: Climb {
+                    id: Some(record.id),
+                    climber_id: record.climber_id,
+                    route_id: record.route_id,
+                    review,
+                    hidden: record.hidden,
+                },
+                username: record.username,
+                route_name: record.route_name,
+                created_at: record.created_at,
+                helpful_count: record.helpful_count,
+                viewer_found_helpful: record.viewer_found_helpful,
+                headline: record.headline,
+            }
+        })
+        .collect();
+    Ok((reviews, more))
+}
+
+/// Reviews of a route, newest first unless sorted by rating or helpfulness
+#[get("/{id}/reviews")]
+async fn get_route_reviews(
+    path: web::Path<i32>,
+    query: web::Query<RouteReviewQuery>,
+) -> impl Responder {
+    let route_id = path.into_inner();
+    let (page, per_page) = query.page().page_and_size();
+    if let Ok(mut conn) = conn().await {
+        match query!(
+            "SELECT id FROM routes WHERE id = ($1) AND deleted_at IS NULL",
+            route_id
+        )
+        .fetch_optional(&mut conn)
+        .await
+        {
+            Ok(Some(_)) => (),
+            Ok(None) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query for the route failed in get_route_reviews()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        let filter = ReviewFilter {
+            route_id: Some(route_id),
+            search: None,
+            viewer_id: query.viewer_id,
+            sort: query.sort,
+        };
+        if let Ok((reviews, more)) = select_reviews(&mut conn, &filter, query.page()).await {
+            HttpResponse::Ok().json(Reviews {
+                reviews,
+                page,
+                per_page,
+                next_page: more.then_some(page + 1),
+            })
+        } else {
+            error!("SELECT query failed in get_route_reviews()");
+            HttpResponse::BadGateway().finish()
+        }
+    } else {
+        error!("Failed to connect to the database in get_route_reviews()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Full-text search of every review, best matches first
+#[get("")]
+async fn search_reviews(query: web::Query<ReviewSearchQuery>) -> impl Responder {
+    let (page, per_page) = query.page().page_and_size();
+    if query.q.trim().is_empty() {
+        return HttpResponse::BadRequest().body(DatabaseError::SearchQueryEmpty.to_string());
+    }
+    if let Ok(mut conn) = conn().await {
+        let filter = ReviewFilter {
+            route_id: None,
+            search: Some(&query.q),
+            viewer_id: query.viewer_id,
+            sort: query.sort(),
+        };
+        if let Ok((reviews, more)) = select_reviews(&mut conn, &filter, query.page()).await {
+            HttpResponse::Ok().json(Reviews {
+                reviews,
+                page,
+                per_page,
+                next_page: more.then_some(page + 1),
+            })
+        } else {
+            error!("SELECT query failed in search_reviews()");
+            HttpResponse::BadGateway().finish()
+        }
+    } else {
+        error!("Failed to connect to the database in search_reviews()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// The ID of a climber's review of a route, if the voter can see it. Ok(Err) is the response
+/// to give if they can't.
+async fn visible_review_id(
+    conn: &mut PgConnection,
+    climber_id: i32,
+    route_id: i32,
+    voter_id: i32,
+) -> Result<Result<i32, HttpResponse>, DatabaseError> {
+    match logbook_visible(conn, climber_id, Some(voter_id)).await? {
+        Some(true) => (),
+        Some(false) => return Ok(Err(HttpResponse::Forbidden().finish())),
+        None => return Ok(Err(HttpResponse::NotFound().finish())),
+    }
+    Ok(query!(
+        r#"SELECT id as "id!" FROM active_climbs WHERE climber_id = ($1) AND route_id = ($2) AND hidden_at IS NULL"#,
+        climber_id,
+        route_id,
+    )
+    .fetch_optional(conn)
+    .await?
+    .map(|record| record.id)
+    .ok_or_else(|| HttpResponse::NotFound().finish()))
+}
+
+/// Mark a climber's review of a route helpful. Marking it again changes nothing.
+#[put("/{climber_id}/{route_id}/helpful/{voter_id}")]
+async fn mark_review_helpful(path: web::Path<(i32, i32, i32)>) -> impl Responder {
+    let (climber_id, route_id, voter_id) = path.into_inner();
+    if climber_id == voter_id {
+        return HttpResponse::BadRequest().body(DatabaseError::CannotVoteOwnReview.to_string());
+    }
+    if let Ok(mut conn) = conn().await {
+        match climber_exists(&mut conn, voter_id).await {
+            Ok(true) => (),
+            Ok(false) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query for the voter failed in mark_review_helpful()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        let climb_id = match visible_review_id(&mut conn, climber_id, route_id, voter_id).await {
+            Ok(Ok(climb_id)) => climb_id,
+            Ok(Err(response)) => return response,
+            Err(_) => {
+                error!("SELECT query failed in mark_review_helpful()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        if query!(
+            "INSERT INTO review_votes (climb_id, climber_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            climb_id,
+            voter_id,
+        )
+        .execute(&mut conn)
+        .await
+        .is_ok()
+        {
+            HttpResponse::NoContent().finish()
+        } else {
+            error!("INSERT query failed in mark_review_helpful()");
+            HttpResponse::BadGateway().finish()
+        }
+    } else {
+        error!("Failed to connect to the database in mark_review_helpful()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+#[delete("/{climber_id}/{route_id}/helpful/{voter_id}")]
+async fn unmark_review_helpful(path: web::Path<(i32, i32, i32)>) -> impl Responder {
+    let (climber_id, route_id, voter_id) = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match query!(
+            r#"DELETE FROM review_votes WHERE climber_id = ($3)
+            AND climb_id IN (SELECT id FROM active_climbs WHERE climber_id = ($1) AND route_id = ($2))"#,
+            climber_id,
+            route_id,
+            voter_id,
+        )
+        .execute(&mut conn)
+        .await
+        {
+            Ok(query_result) if query_result.rows_affected() == 0 => {
+                HttpResponse::NotFound().finish()
+            }
+            Ok(_) => HttpResponse::NoContent().finish(),
+            Err(_) => {
+                error!("DELETE query failed in unmark_review_helpful()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in unmark_review_helpful()");
+        HttpResponse::BadGateway().finish()
+    }
+}
@@ -0,0 +1,253 @@
+/// module for the edit history of routes, handled by the API endpoints /routes/{id}/history and
+/// /admin/routes/{id}/revert
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::types::chrono::NaiveDateTime;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::error::DatabaseError;
+use crate::route::Route;
+
+/// Fields of a Route that aren't edited, and so aren't part of its history
+const UNEDITED_FIELDS: [&str; 5] = [
+    "id",
+    "archived",
+    "ticklist_count",
+    "current_condition",
+    "possible_duplicates",
+];
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum RevisionAction {
+    Create,
+    Update,
+    Patch,
+    /// A moderator put the route back the way it was after an earlier revision
+    Revert,
+    Delete,
+    /// An admin undid the route's soft delete
+    Restore,
+    /// An admin merged a duplicate into the route, or merged the route into another. On the
+    /// route merged into, the changes are those from taking the duplicate's pitches.
+    Merge,
+    /// The route's approach waypoints were replaced, which changes none of its fields
+    Approach,
+}
+
+impl Display for RevisionAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let display_str = match self {
+            Self::Create => "create",
+            Self::Update => "update",
+            Self::Patch => "patch",
+            Self::Revert => "revert",
+            Self::Delete => "delete",
+            Self::Restore => "restore",
+            Self::Merge => "merge",
+            Self::Approach => "approach",
+        };
+        write!(f, "{}", display_str)
+    }
+}
+
+impl FromStr for RevisionAction {
+    type Err = DatabaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "create" => Ok(Self::Create),
+            "update" => Ok(Self::Update),
+            "patch" => Ok(Self::Patch),
+            "revert" => Ok(Self::Revert),
+            "delete" => Ok(Self::Delete),
+            "restore" => Ok(Self::Restore),
+            "merge" => Ok(Self::Merge),
+            "approach" => Ok(Self::Approach),
+            _ => Err(DatabaseError::FailedParseRevisionAction),
+        }
+    }
+}
+
+/// One field of a route changed by a revision, with its values as they are in a Route's JSON
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct FieldChange {
+    pub field: String,
+    pub from: Value,
+    pub to: Value,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct Revision {
+    pub id: i32,
+    pub route_id: i32,
+    /// The climber who made the change, unless they have been purged since. Only a revert checks
+    /// that they are who they say.
+    pub editor_id: Option<i32>,
+    pub action: RevisionAction,
+    /// Ordered by field
+    pub changes: Vec<FieldChange>,
+    pub created_at: NaiveDateTime,
+}
+
+/// The editable fields of a route as they are in its JSON, with its pitches even if it has none
+pub fn route_fields(route: &Route) -> Result<Map<String, Value>, DatabaseError> {
+    let Ok(Value::Object(mut fields)) = serde_json::to_value(route) else {
+        return Err(DatabaseError::FailedParseRevision);
+    };
+    for field in UNEDITED_FIELDS {
+        fields.remove(field);
+    }
+    fields
+        .entry("pitches")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    Ok(fields)
+}
+
+/// Every field that differs between a route before and after a change. A new route has no
+/// `before`, so every field changes from null.
+pub fn route_changes(
+    before: Option<&Route>,
+    after: &Route,
+) -> Result<Vec<FieldChange>, DatabaseError> {
+    let before = match before {
+        Some(route) => route_fields(route)?,
+        None => Map::new(),
+    };
+    Ok(route_fields(after)?
+        .into_iter()
+        .filter_map(|(field, to)| {
+            let from = before.get(&field).cloned().unwrap_or(Value::Null);
+            (from != to).then_some(FieldChange { field, from, to })
+        })
+        .collect())
+}
+
+/// A route as it was just after a revision, from the route as it is now and every later revision,
+/// oldest first. Each field changed since is put back to the value it had before the first later
+/// change to it.
+pub fn route_at_revision(current: &Route, later: &[Revision]) -> Result<Route, DatabaseError> {
+    let mut fields = route_fields(current)?;
+    for revision in later.iter().rev() {
+        for change in &revision.changes {
+            fields.insert(change.field.clone(), change.from.clone());
+        }
+    }
+    serde_json::from_value(Value::Object(fields)).map_err(|_| DatabaseError::FailedParseRevision)
+}
+
+/// Query parameter for the endpoints that change a route, for the climber making the change,
+/// which every change needs. Nothing authenticates it: any existing climber's ID is taken at its word, so a revision's
+/// editor is only who the client said it was. Reverts check that the editor is a moderator.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+pub struct RouteEditor {
+    pub editor_id: Option<i32>,
+}
+
+/// Body of GET /routes/{id}/history, newest first
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RouteHistory {
+    pub revisions: Vec<Revision>,
+    pub page: i64,
+    pub per_page: i64,
+    /// The next page number, if there are older revisions
+    pub next_page: Option<i64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::route::{DifficultyRating, Discipline, Pitch};
+
+    fn test_route() -> Route {
+        Route::new(
+            Some(1),
+            "funky monkey".to_string(),
+            Discipline::Sport,
+            DifficultyRating::Rating59,
+            45.0,
+            -120.0,
+        )
+    }
+
+    fn test_revision(id: i32, changes: Vec<FieldChange>) -> Revision {
+        Revision {
+            id,
+            route_id: 1,
+            editor_id: None,
+            action: RevisionAction::Patch,
+            changes,
+            created_at: NaiveDateTime::default(),
+        }
+    }
+
+    #[test]
+    fn test_revision_action_round_trip() {
+        for action in [
+            RevisionAction::Create,
+            RevisionAction::Update,
+            RevisionAction::Patch,
+            RevisionAction::Revert,
+            RevisionAction::Delete,
+            RevisionAction::Restore,
+            RevisionAction::Merge,
+        ] {
+            assert_eq!(
+                RevisionAction::from_str(&action.to_string()).unwrap(),
+                action
+            );
+        }
+        assert!(RevisionAction::from_str("purge").is_err());
+    }
+
+    #[test]
+    fn test_route_changes() {
+        let before = test_route();
+        let mut after = test_route();
+        after.name = "Funky Monkey".to_string();
+        after.metadata.bolts = Some(8);
+        after.archived = true;
+        let changes = route_changes(Some(&before), &after).unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                FieldChange {
+                    field: "bolts".to_string(),
+                    from: Value::Null,
+                    to: Value::from(8),
+                },
+                FieldChange {
+                    field: "name".to_string(),
+                    from: Value::from("funky monkey"),
+                    to: Value::from("Funky Monkey"),
+                },
+            ]
+        );
+        assert!(route_changes(Some(&before), &test_route())
+            .unwrap()
+            .is_empty());
+        // A new route has every field that isn't null
+        assert!(route_changes(None, &before)
+            .unwrap()
+            .iter()
+            .all(|change| change.from.is_null() && !change.to.is_null()));
+    }
+
+    #[test]
+    fn test_route_at_revision() {
+        let mut first = test_route();
+        first.name = "Funky Monkey".to_string();
+        let mut second = test_route();
+        second.name = "Funky Monkey Direct".to_string();
+        second.pitches = vec![Pitch::new(DifficultyRating::Rating510, Some(30))];
+        let later = vec![
+            test_revision(2, route_changes(Some(&test_route()), &first).unwrap()),
+            test_revision(3, route_changes(Some(&first), &second).unwrap()),
+        ];
+        let reverted = route_at_revision(&second, &later).unwrap();
+        assert_eq!(reverted.name, "funky monkey");
+        assert!(reverted.pitches.is_empty());
+        let reverted = route_at_revision(&second, &later[1..]).unwrap();
+        assert_eq!(reverted.name, "Funky Monkey");
+    }
+}
@@ -0,0 +1,227 @@
+use crate::climbers::climber_exists;
+use crate::error::DatabaseError;
+use crate::etag::etag;
+use crate::moderations::check_moderator;
+use crate::page::Page;
+use crate::pg::conn;
+use crate::revision::{
+    route_at_revision, route_changes, Revision, RevisionAction, RouteEditor, RouteHistory,
+};
+use crate::route::Route;
+use crate::routes::{is_invalid_route_details, select_route, update_route};
+use actix_web::{get, http::header, post, web, HttpResponse, Responder};
+use log::error;
+/// Functions for the edit history of routes at /routes/{id}/history and for reverting routes at
+/// /admin/routes/{id}/revert/{revision_id}
+use sqlx::{self, postgres::PgConnection, query};
+use std::str::FromStr;
+
+/// The response to give if a route edit has no editor, or its editor doesn't exist
+pub async fn check_editor(conn: &mut PgConnection, editor_id: Option<i32>) -> Option<HttpResponse> {
+    let editor_id = match editor_id {
+        Some(editor_id) => editor_id,
+        None => {
+            return Some(HttpResponse::BadRequest().body(DatabaseError::EditorRequired.to_string()))
+        }
+    };
+    match climber_exists(conn, editor_id).await {
+        Ok(true) => None,
+        Ok(false) => {
+            Some(HttpResponse::BadRequest().body(DatabaseError::ClimberNotFound.to_string()))
+        }
+        Err(_) => {
+            error!("SELECT query for the editor failed in check_editor()");
+            Some(HttpResponse::BadGateway().finish())
+        }
+    }
+}
+
+/// Add a revision to a route's history, unless an edit left every field as it was. Deleting,
+/// restoring, merging a route and replacing its approach are recorded even though they change
+/// none of its fields. Runs
+/// inside the caller's transaction so that the history always agrees with the route.
+pub async fn record_revision(
+    conn: &mut PgConnection,
+    route_id: i32,
+    editor_id: Option<i32>,
+    action: RevisionAction,
+    before: Option<&Route>,
+    after: &Route,
+) -> Result<(), DatabaseError> {
+    let changes = route_changes(before, after)?;
+    let is_edit = !matches!(
+        action,
+        RevisionAction::Delete
+            | RevisionAction::Restore
+            | RevisionAction::Merge
+            | RevisionAction::Approach
+    );
+    if changes.is_empty() && is_edit {
+        return Ok(());
+    }
+    let changes_json =
+        serde_json::to_string(&changes).map_err(|_| DatabaseError::FailedParseRevision)?;
+    query!(
+        "INSERT INTO route_revisions (route_id, editor_id, action, changes) VALUES ($1, $2, $3, $4::text::jsonb)",
+        route_id,
+        editor_id,
+        action.to_string(),
+        changes_json,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// A route's revisions, newest first, or oldest first from just after `since` if there is one
+async fn select_revisions(
+    conn: &mut PgConnection,
+    route_id: i32,
+    since: Option<i32>,
+    limit: Option<i64>,
+    offset: i64,
+) -> Result<Vec<Revision>, DatabaseError> {
+    query!(
+        r#"SELECT id, route_id, editor_id, action, changes::text as "changes!", created_at FROM route_revisions
+        WHERE route_id = ($1) AND ($2::int4 IS NULL OR id > $2)
+        ORDER BY CASE WHEN $2::int4 IS NULL THEN -id ELSE id END
+        LIMIT ($3) OFFSET ($4)"#,
+        route_id,
+        since,
+        limit,
+        offset,
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|record| {
+        Ok(Revision {
+            id: record.id,
+            route_id: record.route_id,
+            editor_id: record.editor_id,
+            action: RevisionAction::from_str(&record.action)?,
+            changes: serde_json::from_str(&record.changes)
+                .map_err(|_| DatabaseError::FailedParseRevision)?,
+            created_at: record.created_at,
+        })
+    })
+    .collect()
+}
+
+/// Every change to a route, newest first
+#[get("/{id}/history")]
+async fn get_route_history(path: web::Path<i32>, query: web::Query<Page>) -> impl Responder {
+    let id = path.into_inner();
+    let (page, per_page) = query.page_and_size();
+    if let Ok(mut conn) = conn().await {
+        match query!(
+            "SELECT id FROM routes WHERE id = ($1) AND deleted_at IS NULL",
+            id
+        )
+        .fetch_optional(&mut conn)
+        .await
+        {
+            Ok(Some(_)) => (),
+            Ok(None) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query for the route failed in get_route_history()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        match select_revisions(&mut conn, id, None, Some(per_page + 1), query.offset()).await {
+            Ok(mut revisions) => {
+                let next_page = (revisions.len() as i64 > per_page).then_some(page + 1);
+                revisions.truncate(per_page as usize);
+                HttpResponse::Ok().json(RouteHistory {
+                    revisions,
+                    page,
+                    per_page,
+                    next_page,
+                })
+            }
+            Err(_) => {
+                error!("SELECT query failed in get_route_history()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in get_route_history()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Put a route back the way it was just after one of its revisions. The revert is itself a
+/// revision, so it can be reverted too. Only a moderator can revert, as the `editor_id`.
+#[post("/routes/{id}/revert/{revision_id}")]
+async fn revert_route(
+    path: web::Path<(i32, i32)>,
+    editor: web::Query<RouteEditor>,
+) -> impl Responder {
+    let (id, revision_id) = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        if let Some(response) = check_moderator(&mut conn, editor.editor_id).await {
+            return response;
+        }
+        let version = match query!(
+            r#"SELECT routes.version FROM routes JOIN route_revisions ON route_revisions.route_id = routes.id
+            WHERE routes.id = ($1) AND route_revisions.id = ($2) AND routes.deleted_at IS NULL"#,
+            id,
+            revision_id,
+        )
+        .fetch_optional(&mut conn)
+        .await
+        {
+            Ok(Some(query_result)) => query_result.version,
+            Ok(None) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in revert_route()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        let current = match select_route(&mut conn, id).await {
+            Ok(Some(current)) => current,
+            Ok(None) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query for the route failed in revert_route()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        let later = match select_revisions(&mut conn, id, Some(revision_id), None, 0).await {
+            Ok(later) => later,
+            Err(_) => {
+                error!("SELECT query for revisions failed in revert_route()");
+                return HttpResponse::BadGateway().finish();
+            }
+        };
+        let Ok(mut route) = route_at_revision(&current, &later) else {
+            error!("Failed to rebuild the route from its revisions in revert_route()");
+            return HttpResponse::BadGateway().finish();
+        };
+        // Only if the route hasn't changed since its revisions were read
+        match update_route(
+            &mut conn,
+            id,
+            Some(&[version]),
+            &mut route,
+            editor.editor_id,
+            RevisionAction::Revert,
+        )
+        .await
+        {
+            Ok(Some(version)) => HttpResponse::NoContent()
+                .insert_header(header::ETag(etag(version)))
+                .finish(),
+            Ok(None) => HttpResponse::PreconditionFailed().finish(),
+            Err(e) if is_invalid_route_details(&e) => {
+                HttpResponse::BadRequest().body(e.to_string())
+            }
+            Err(_) => {
+                error!("UPDATE query failed in revert_route()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in revert_route()");
+        HttpResponse::BadGateway().finish()
+    }
+}
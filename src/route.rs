@@ -1,17 +1,109 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use sqlx::types::chrono::NaiveDate;
 /// module for structs related to Route, mostly handled by the API endpoint /routes
+use std::env;
 use std::fmt::Display;
 use std::str::FromStr;
 
+use crate::condition::ConditionStatus;
 use crate::error::DatabaseError;
+use crate::gps::Waypoint;
 
-#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+/// Grades are declared easiest to hardest within each GradeScale, so comparing two grades from the
+/// same scale tells you which is harder
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
 pub enum DifficultyRating {
     Rating59,
     Rating510,
     Rating511,
     Rating511plus,
     Rating512,
+    RatingV0,
+    RatingV1,
+    RatingV2,
+    RatingV3,
+    RatingV4,
+    RatingV5,
+    RatingV6,
+    RatingV7,
+    RatingV8,
+    RatingV9,
+    RatingV10,
+    RatingWI1,
+    RatingWI2,
+    RatingWI3,
+    RatingWI4,
+    RatingWI5,
+    RatingWI6,
+    RatingWI7,
+    RatingM1,
+    RatingM2,
+    RatingM3,
+    RatingM4,
+    RatingM5,
+    RatingM6,
+    RatingM7,
+    RatingM8,
+    RatingM9,
+    RatingM10,
+    RatingA0,
+    RatingA1,
+    RatingA2,
+    RatingA3,
+    RatingA4,
+    RatingA5,
+}
+
+impl DifficultyRating {
+    pub fn scale(&self) -> GradeScale {
+        match self {
+            Self::Rating59
+            | Self::Rating510
+            | Self::Rating511
+            | Self::Rating511plus
+            | Self::Rating512 => GradeScale::Yds,
+            Self::RatingV0
+            | Self::RatingV1
+            | Self::RatingV2
+            | Self::RatingV3
+            | Self::RatingV4
+            | Self::RatingV5
+            | Self::RatingV6
+            | Self::RatingV7
+            | Self::RatingV8
+            | Self::RatingV9
+            | Self::RatingV10 => GradeScale::VScale,
+            Self::RatingWI1
+            | Self::RatingWI2
+            | Self::RatingWI3
+            | Self::RatingWI4
+            | Self::RatingWI5
+            | Self::RatingWI6
+            | Self::RatingWI7 => GradeScale::WaterIce,
+            Self::RatingM1
+            | Self::RatingM2
+            | Self::RatingM3
+            | Self::RatingM4
+            | Self::RatingM5
+            | Self::RatingM6
+            | Self::RatingM7
+            | Self::RatingM8
+            | Self::RatingM9
+            | Self::RatingM10 => GradeScale::Mixed,
+            Self::RatingA0
+            | Self::RatingA1
+            | Self::RatingA2
+            | Self::RatingA3
+            | Self::RatingA4
+            | Self::RatingA5 => GradeScale::Aid,
+        }
+    }
+
+    /// Points for a climb of this grade on a leaderboard: 100 for the easiest grade of its scale,
+    /// and 100 more for each grade above that
+    pub fn points(&self) -> i64 {
+        100 * (self.clone() as i64 - self.scale().easiest() as i64 + 1)
+    }
 }
 
 impl Display for DifficultyRating {
@@ -22,6 +114,40 @@ impl Display for DifficultyRating {
             Self::Rating511 => "5.11",
             Self::Rating511plus => "5.11+",
             Self::Rating512 => "5.12",
+            Self::RatingV0 => "V0",
+            Self::RatingV1 => "V1",
+            Self::RatingV2 => "V2",
+            Self::RatingV3 => "V3",
+            Self::RatingV4 => "V4",
+            Self::RatingV5 => "V5",
+            Self::RatingV6 => "V6",
+            Self::RatingV7 => "V7",
+            Self::RatingV8 => "V8",
+            Self::RatingV9 => "V9",
+            Self::RatingV10 => "V10",
+            Self::RatingWI1 => "WI1",
+            Self::RatingWI2 => "WI2",
+            Self::RatingWI3 => "WI3",
+            Self::RatingWI4 => "WI4",
+            Self::RatingWI5 => "WI5",
+            Self::RatingWI6 => "WI6",
+            Self::RatingWI7 => "WI7",
+            Self::RatingM1 => "M1",
+            Self::RatingM2 => "M2",
+            Self::RatingM3 => "M3",
+            Self::RatingM4 => "M4",
+            Self::RatingM5 => "M5",
+            Self::RatingM6 => "M6",
+            Self::RatingM7 => "M7",
+            Self::RatingM8 => "M8",
+            Self::RatingM9 => "M9",
+            Self::RatingM10 => "M10",
+            Self::RatingA0 => "A0",
+            Self::RatingA1 => "A1",
+            Self::RatingA2 => "A2",
+            Self::RatingA3 => "A3",
+            Self::RatingA4 => "A4",
+            Self::RatingA5 => "A5",
         };
         write!(f, "{}", display_str)
     }
@@ -37,29 +163,279 @@ impl FromStr for DifficultyRating {
             "5.11" => Ok(Self::Rating511),
             "5.11+" => Ok(Self::Rating511plus),
             "5.12" => Ok(Self::Rating512),
+            "V0" => Ok(Self::RatingV0),
+            "V1" => Ok(Self::RatingV1),
+            "V2" => Ok(Self::RatingV2),
+            "V3" => Ok(Self::RatingV3),
+            "V4" => Ok(Self::RatingV4),
+            "V5" => Ok(Self::RatingV5),
+            "V6" => Ok(Self::RatingV6),
+            "V7" => Ok(Self::RatingV7),
+            "V8" => Ok(Self::RatingV8),
+            "V9" => Ok(Self::RatingV9),
+            "V10" => Ok(Self::RatingV10),
+            "WI1" => Ok(Self::RatingWI1),
+            "WI2" => Ok(Self::RatingWI2),
+            "WI3" => Ok(Self::RatingWI3),
+            "WI4" => Ok(Self::RatingWI4),
+            "WI5" => Ok(Self::RatingWI5),
+            "WI6" => Ok(Self::RatingWI6),
+            "WI7" => Ok(Self::RatingWI7),
+            "M1" => Ok(Self::RatingM1),
+            "M2" => Ok(Self::RatingM2),
+            "M3" => Ok(Self::RatingM3),
+            "M4" => Ok(Self::RatingM4),
+            "M5" => Ok(Self::RatingM5),
+            "M6" => Ok(Self::RatingM6),
+            "M7" => Ok(Self::RatingM7),
+            "M8" => Ok(Self::RatingM8),
+            "M9" => Ok(Self::RatingM9),
+            "M10" => Ok(Self::RatingM10),
+            "A0" => Ok(Self::RatingA0),
+            "A1" => Ok(Self::RatingA1),
+            "A2" => Ok(Self::RatingA2),
+            "A3" => Ok(Self::RatingA3),
+            "A4" => Ok(Self::RatingA4),
+            "A5" => Ok(Self::RatingA5),
             _ => Err(DatabaseError::FailedParseDifficultyRating),
         }
     }
 }
 
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
+pub enum GradeScale {
+    Yds,
+    VScale,
+    WaterIce,
+    Mixed,
+    Aid,
+}
+
+impl GradeScale {
+    pub fn easiest(&self) -> DifficultyRating {
+        match self {
+            Self::Yds => DifficultyRating::Rating59,
+            Self::VScale => DifficultyRating::RatingV0,
+            Self::WaterIce => DifficultyRating::RatingWI1,
+            Self::Mixed => DifficultyRating::RatingM1,
+            Self::Aid => DifficultyRating::RatingA0,
+        }
+    }
+
+    /// Every grade of the scale, easiest first
+    pub fn grades(&self) -> Vec<DifficultyRating> {
+        use DifficultyRating::*;
+        match self {
+            Self::Yds => vec![Rating59, Rating510, Rating511, Rating511plus, Rating512],
+            Self::VScale => vec![
+                RatingV0, RatingV1, RatingV2, RatingV3, RatingV4, RatingV5, RatingV6, RatingV7,
+                RatingV8, RatingV9, RatingV10,
+            ],
+            Self::WaterIce => vec![
+                RatingWI1, RatingWI2, RatingWI3, RatingWI4, RatingWI5, RatingWI6, RatingWI7,
+            ],
+            Self::Mixed => vec![
+                RatingM1, RatingM2, RatingM3, RatingM4, RatingM5, RatingM6, RatingM7, RatingM8,
+                RatingM9, RatingM10,
+            ],
+            Self::Aid => vec![RatingA0, RatingA1, RatingA2, RatingA3, RatingA4, RatingA5],
+        }
+    }
+}
+
+impl Display for GradeScale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let display_str = match self {
+            Self::Yds => "yds",
+            Self::VScale => "v-scale",
+            Self::WaterIce => "water-ice",
+            Self::Mixed => "mixed",
+            Self::Aid => "aid",
+        };
+        write!(f, "{}", display_str)
+    }
+}
+
+impl FromStr for GradeScale {
+    type Err = DatabaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "yds" => Ok(Self::Yds),
+            "v-scale" => Ok(Self::VScale),
+            "water-ice" => Ok(Self::WaterIce),
+            "mixed" => Ok(Self::Mixed),
+            "aid" => Ok(Self::Aid),
+            _ => Err(DatabaseError::FailedParseGradeScale),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, Default)]
+pub enum Discipline {
+    #[default]
+    Sport,
+    Trad,
+    TopRope,
+    Boulder,
+    Ice,
+    Mixed,
+    Aid,
+}
+
+impl Discipline {
+    /// Grade scales that routes of this discipline can be graded in
+    pub fn grade_scales(&self) -> &'static [GradeScale] {
+        match self {
+            Self::Sport | Self::Trad | Self::TopRope => &[GradeScale::Yds],
+            Self::Boulder => &[GradeScale::VScale],
+            Self::Ice => &[GradeScale::WaterIce],
+            Self::Mixed => &[GradeScale::Mixed, GradeScale::WaterIce],
+            Self::Aid => &[GradeScale::Aid],
+        }
+    }
+
+    pub fn check_grade(&self, difficulty: &DifficultyRating) -> Result<(), DatabaseError> {
+        if self.grade_scales().contains(&difficulty.scale()) {
+            Ok(())
+        } else {
+            Err(DatabaseError::GradeNotValidForDiscipline)
+        }
+    }
+}
+
+impl Display for Discipline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let display_str = match self {
+            Self::Sport => "sport",
+            Self::Trad => "trad",
+            Self::TopRope => "top-rope",
+            Self::Boulder => "boulder",
+            Self::Ice => "ice",
+            Self::Mixed => "mixed",
+            Self::Aid => "aid",
+        };
+        write!(f, "{}", display_str)
+    }
+}
+
+impl FromStr for Discipline {
+    type Err = DatabaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sport" => Ok(Self::Sport),
+            "trad" => Ok(Self::Trad),
+            "top-rope" => Ok(Self::TopRope),
+            "boulder" => Ok(Self::Boulder),
+            "ice" => Ok(Self::Ice),
+            "mixed" => Ok(Self::Mixed),
+            "aid" => Ok(Self::Aid),
+            _ => Err(DatabaseError::FailedParseDiscipline),
+        }
+    }
+}
+
 // There are some crates that do some actual work with GPS coordinates, but I want to narrow the
 // scope of the project here, and just assume any pair of f64 is a valid GPS position
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub struct GpsPosition(f64, f64);
 
+/// One pitch of a multi-pitch route. Pitches are numbered from 1 in the order they are listed.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Pitch {
+    pub difficulty: DifficultyRating,
+    pub length_meters: Option<i32>,
+    pub bolts: Option<i32>,
+    pub gear_notes: Option<String>,
+    pub anchor: Option<String>,
+}
+
+impl Pitch {
+    pub fn new(difficulty: impl Into<DifficultyRating>, length_meters: Option<i32>) -> Self {
+        Self {
+            difficulty: difficulty.into(),
+            length_meters,
+            bolts: None,
+            gear_notes: None,
+            anchor: None,
+        }
+    }
+}
+
+/// Optional details about a route. The setter, hold color, wall, route set, and set and strip dates
+/// are only used by gym routes, which are "currently up" between the set and strip dates.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+#[serde(default)]
+pub struct RouteMetadata {
+    pub area_id: Option<i32>,
+    pub bolts: Option<i32>,
+    pub first_ascent_party: Option<String>,
+    pub first_ascent_date: Option<NaiveDate>,
+    pub setter_id: Option<i32>,
+    pub hold_color: Option<String>,
+    pub set_date: Option<NaiveDate>,
+    pub strip_date: Option<NaiveDate>,
+    pub wall_id: Option<i32>,
+    pub route_set_id: Option<i32>,
+}
+
+impl RouteMetadata {
+    pub fn check_dates(&self) -> Result<(), DatabaseError> {
+        match (self.set_date, self.strip_date) {
+            (Some(set_date), Some(strip_date)) if strip_date < set_date => {
+                Err(DatabaseError::StripDateBeforeSetDate)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub struct Route {
     pub id: Option<i32>,
     pub name: String,
+    #[serde(default)]
+    pub discipline: Discipline,
     pub difficulty: DifficultyRating,
     pub latitude: f64,
     pub longitude: f64,
+    /// Only filled in on route detail; listings carry number_pitches and length_meters instead
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pitches: Vec<Pitch>,
+    #[serde(default = "one_pitch")]
+    pub number_pitches: i32,
+    #[serde(default)]
+    pub length_meters: Option<i32>,
+    #[serde(flatten)]
+    pub metadata: RouteMetadata,
+    /// Set once a gym route's strip date has passed. Archived routes are left out of listings, but
+    /// are kept along with the climbs logged on them. Ignored on writes.
+    #[serde(default)]
+    pub archived: bool,
+    /// Only filled in on route detail: how many climbers have the route on their ticklist and
+    /// haven't climbed it yet. Ignored on writes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ticklist_count: Option<i64>,
+    /// From the newest condition report on the route or any of its areas that hasn't expired,
+    /// except that closures outrank every other status. Ignored on writes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_condition: Option<ConditionStatus>,
+    /// Only filled in when a route is added: routes nearby with a similar name, which it may be a
+    /// duplicate of. Ignored on writes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub possible_duplicates: Vec<DuplicateRoute>,
+}
+
+fn one_pitch() -> i32 {
+    1
 }
 
 impl Route {
     pub fn new(
         id: Option<i32>,
         name: String,
+        discipline: Discipline,
         difficulty: impl Into<DifficultyRating>,
         latitude: f64,
         longitude: f64,
@@ -67,18 +443,302 @@ impl Route {
         Self {
             id,
             name,
+            discipline,
             difficulty: difficulty.into(),
             latitude,
             longitude,
+            pitches: Vec::new(),
+            number_pitches: 1,
+            length_meters: None,
+            metadata: RouteMetadata::default(),
+            archived: false,
+            ticklist_count: None,
+            current_condition: None,
+            possible_duplicates: Vec::new(),
+        }
+    }
+
+    pub fn check_grade(&self) -> Result<(), DatabaseError> {
+        self.discipline.check_grade(&self.difficulty)?;
+        self.pitches
+            .iter()
+            .try_for_each(|pitch| self.discipline.check_grade(&pitch.difficulty))
+    }
+
+    /// A route with pitches takes its grade from the hardest pitch and its length and bolt count
+    /// from their sums, each of which is left unknown unless every pitch has one
+    pub fn derive_from_pitches(&mut self) {
+        if let Some(hardest) = self.pitches.iter().map(|pitch| &pitch.difficulty).max() {
+            self.difficulty = hardest.clone();
+            self.number_pitches = self.pitches.len() as i32;
+            self.length_meters = self
+                .pitches
+                .iter()
+                .map(|pitch| pitch.length_meters)
+                .sum::<Option<i32>>();
+            self.metadata.bolts = self
+                .pitches
+                .iter()
+                .map(|pitch| pitch.bolts)
+                .sum::<Option<i32>>();
+        } else {
+            self.number_pitches = 1;
+        }
+    }
+}
+
+/// Deserialize a field of a JSON Merge Patch that can be cleared, so that a null (`Some(None)`) is
+/// told apart from a missing field (`None`)
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// The RouteMetadata in a RoutePatch. Every detail is optional, so a null clears it.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct RouteMetadataPatch {
+    #[serde(deserialize_with = "nullable", skip_serializing_if = "Option::is_none")]
+    pub area_id: Option<Option<i32>>,
+    #[serde(deserialize_with = "nullable", skip_serializing_if = "Option::is_none")]
+    pub bolts: Option<Option<i32>>,
+    #[serde(deserialize_with = "nullable", skip_serializing_if = "Option::is_none")]
+    pub first_ascent_party: Option<Option<String>>,
+    #[serde(deserialize_with = "nullable", skip_serializing_if = "Option::is_none")]
+    pub first_ascent_date: Option<Option<NaiveDate>>,
+    #[serde(deserialize_with = "nullable", skip_serializing_if = "Option::is_none")]
+    pub setter_id: Option<Option<i32>>,
+    #[serde(deserialize_with = "nullable", skip_serializing_if = "Option::is_none")]
+    pub hold_color: Option<Option<String>>,
+    #[serde(deserialize_with = "nullable", skip_serializing_if = "Option::is_none")]
+    pub set_date: Option<Option<NaiveDate>>,
+    #[serde(deserialize_with = "nullable", skip_serializing_if = "Option::is_none")]
+    pub strip_date: Option<Option<NaiveDate>>,
+    #[serde(deserialize_with = "nullable", skip_serializing_if = "Option::is_none")]
+    pub wall_id: Option<Option<i32>>,
+    #[serde(deserialize_with = "nullable", skip_serializing_if = "Option::is_none")]
+    pub route_set_id: Option<Option<i32>>,
+}
+
+impl RouteMetadataPatch {
+    /// The details the patch sets, leaving out the ones it clears or doesn't mention
+    pub fn values(&self) -> RouteMetadata {
+        RouteMetadata {
+            area_id: self.area_id.flatten(),
+            bolts: self.bolts.flatten(),
+            first_ascent_party: self.first_ascent_party.clone().flatten(),
+            first_ascent_date: self.first_ascent_date.flatten(),
+            setter_id: self.setter_id.flatten(),
+            hold_color: self.hold_color.clone().flatten(),
+            set_date: self.set_date.flatten(),
+            strip_date: self.strip_date.flatten(),
+            wall_id: self.wall_id.flatten(),
+            route_set_id: self.route_set_id.flatten(),
         }
     }
 }
 
+/// Body of PATCH /routes/{id} as a JSON Merge Patch. Missing fields are left unchanged, and a null
+/// clears the length or one of the details. The name, discipline, grade and location can't be
+/// cleared, so a null leaves them unchanged too. Pitches are only replaced by a PUT.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+pub struct RoutePatch {
+    pub name: Option<String>,
+    pub discipline: Option<Discipline>,
+    pub difficulty: Option<DifficultyRating>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub length_meters: Option<Option<i32>>,
+    #[serde(flatten)]
+    pub metadata: RouteMetadataPatch,
+}
+
+impl RoutePatch {
+    pub fn check_not_empty(&self) -> Result<(), DatabaseError> {
+        if self.name.is_none()
+            && self.discipline.is_none()
+            && self.difficulty.is_none()
+            && self.latitude.is_none()
+            && self.longitude.is_none()
+            && self.length_meters.is_none()
+            && self.metadata == RouteMetadataPatch::default()
+        {
+            Err(DatabaseError::UpdateRouteAllNone)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The columns the patch clears with a null
+    pub fn cleared(&self) -> Vec<String> {
+        let metadata = &self.metadata;
+        [
+            ("length_meters", self.length_meters == Some(None)),
+            ("area_id", metadata.area_id == Some(None)),
+            ("bolts", metadata.bolts == Some(None)),
+            (
+                "first_ascent_party",
+                metadata.first_ascent_party == Some(None),
+            ),
+            (
+                "first_ascent_date",
+                metadata.first_ascent_date == Some(None),
+            ),
+            ("setter_id", metadata.setter_id == Some(None)),
+            ("hold_color", metadata.hold_color == Some(None)),
+            ("set_date", metadata.set_date == Some(None)),
+            ("strip_date", metadata.strip_date == Some(None)),
+            ("wall_id", metadata.wall_id == Some(None)),
+            ("route_set_id", metadata.route_set_id == Some(None)),
+        ]
+        .into_iter()
+        .filter(|(_, cleared)| *cleared)
+        .map(|(column, _)| column.to_string())
+        .collect()
+    }
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub struct NumberRoutes {
     pub number_routes: i64,
 }
 
+/// Optional query parameters for narrowing down a list of routes. The min/max latitude and
+/// longitude fields make up a bounding box; any side of it may be left open.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+pub struct RouteFilter {
+    pub discipline: Option<Discipline>,
+    pub difficulty: Option<DifficultyRating>,
+    pub min_latitude: Option<f64>,
+    pub max_latitude: Option<f64>,
+    pub min_longitude: Option<f64>,
+    pub max_longitude: Option<f64>,
+    /// Only gym routes that have been set and not yet stripped
+    pub currently_up: Option<bool>,
+    /// Routes in the area or any of its subareas
+    pub area_id: Option<i32>,
+    pub wall_id: Option<i32>,
+    pub include_archived: Option<bool>,
+    /// Only routes of one of these grades. Not a query parameter.
+    #[serde(skip)]
+    pub difficulties: Option<Vec<DifficultyRating>>,
+    /// Leave out the routes this climber has climbed. Not a query parameter.
+    #[serde(skip)]
+    pub not_climbed_by: Option<i32>,
+}
+
+pub const DEFAULT_DUPLICATE_ROUTE_METERS: f64 = 50.0;
+/// How similar two route names have to be, as a trigram similarity from 0 to 1, for the routes to
+/// be possible duplicates. Case and punctuation are ignored.
+pub const SIMILAR_NAME_THRESHOLD: f32 = 0.5;
+const METERS_PER_DEGREE_LATITUDE: f64 = 111_320.0;
+
+/// How close a route with a similar name has to be to a new route for them to be possible
+/// duplicates, from the DUPLICATE_ROUTE_METERS environment variable, or
+/// DEFAULT_DUPLICATE_ROUTE_METERS if it is missing or not a number
+pub fn duplicate_route_meters() -> f64 {
+    env::var("DUPLICATE_ROUTE_METERS")
+        .ok()
+        .and_then(|meters| meters.parse::<f64>().ok())
+        .unwrap_or(DEFAULT_DUPLICATE_ROUTE_METERS)
+}
+
+/// Smallest bounding box (min_latitude, max_latitude, min_longitude, max_longitude) around
+/// every point within `meters` of a point
+pub fn bounding_box(latitude: f64, longitude: f64, meters: f64) -> (f64, f64, f64, f64) {
+    let latitude_delta = meters / METERS_PER_DEGREE_LATITUDE;
+    let longitude_delta = (latitude_delta / latitude.to_radians().cos().abs()).min(180.0);
+    (
+        latitude - latitude_delta,
+        latitude + latitude_delta,
+        longitude - longitude_delta,
+        longitude + longitude_delta,
+    )
+}
+
+/// A route that a new route may be a duplicate of
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct DuplicateRoute {
+    pub id: i32,
+    pub name: String,
+    pub distance_meters: f64,
+}
+
+/// Body of POST /admin/routes/{id}/merge, which moves everything logged on a route to the
+/// `target_id` route, and its response with how many rows were moved
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+pub struct RouteMerge {
+    pub target_id: i32,
+    /// Ignored on writes
+    #[serde(default)]
+    pub climbs: u64,
+    /// Climbs soft deleted because their climber had already logged the target route. Ignored on
+    /// writes.
+    #[serde(default)]
+    pub duplicate_climbs: u64,
+    /// Pitches moved to a target route that had none. Ignored on writes.
+    #[serde(default)]
+    pub pitches: u64,
+    /// Approach waypoints moved to a target route that had none. Ignored on writes.
+    #[serde(default)]
+    pub approach_waypoints: u64,
+    /// Ignored on writes
+    #[serde(default)]
+    pub photos: u64,
+    /// Entries on the ticklists of climbers who didn't already have the target route on theirs.
+    /// Ignored on writes.
+    #[serde(default)]
+    pub ticklist_entries: u64,
+    /// Ignored on writes
+    #[serde(default)]
+    pub comments: u64,
+    /// Ignored on writes
+    #[serde(default)]
+    pub condition_reports: u64,
+    /// Problems moved in competitions that didn't already have the target route, along with their
+    /// attempts. Ignored on writes.
+    #[serde(default)]
+    pub competition_problems: u64,
+}
+
+/// A Route along with the community's average Rating of it from the climbs table
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct RatedRoute {
+    #[serde(flatten)]
+    pub route: Route,
+    pub average_rating: Option<f64>,
+    pub number_ratings: i64,
+}
+
+impl RatedRoute {
+    pub fn to_waypoint(&self) -> Waypoint {
+        let rating = match self.average_rating {
+            Some(average_rating) => format!(
+                "{:.1}/10 from {} ratings",
+                average_rating, self.number_ratings
+            ),
+            None => "not yet rated".to_string(),
+        };
+        Waypoint::new(
+            format!("{} ({})", self.route.name, self.route.difficulty),
+            Some(format!(
+                "Grade: {}, Rating: {}",
+                self.route.difficulty, rating
+            )),
+            self.route.latitude,
+            self.route.longitude,
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -87,6 +747,7 @@ mod tests {
         Route::new(
             None,
             "funky monkey".to_string(),
+            Discipline::Sport,
             DifficultyRating::Rating59,
             123.45,
             52.310,
@@ -101,10 +762,162 @@ mod tests {
             Route {
                 id: None,
                 name: "funky monkey".to_string(),
+                discipline: Discipline::Sport,
                 difficulty: DifficultyRating::Rating59,
                 latitude: 123.45,
                 longitude: 52.310,
+                pitches: Vec::new(),
+                number_pitches: 1,
+                length_meters: None,
+                metadata: RouteMetadata::default(),
+                archived: false,
+                ticklist_count: None,
+                current_condition: None,
+                possible_duplicates: Vec::new(),
             }
         )
     }
+
+    #[test]
+    fn test_bounding_box() {
+        let (min_latitude, max_latitude, min_longitude, max_longitude) =
+            bounding_box(60.0, 10.0, 1000.0);
+        assert!((max_latitude - min_latitude - 2000.0 / METERS_PER_DEGREE_LATITUDE).abs() < 1e-9);
+        // A degree of longitude is half as long at 60 degrees north
+        assert!((max_longitude - min_longitude - 2.0 * (max_latitude - min_latitude)).abs() < 1e-9);
+        let (_, _, min_longitude, max_longitude) = bounding_box(90.0, 10.0, 1000.0);
+        assert!(max_longitude - min_longitude <= 360.0);
+    }
+
+    #[test]
+    fn test_difficulty_rating_round_trip() {
+        for grade in ["5.11+", "V0", "V10", "WI4", "M7", "A2"] {
+            assert_eq!(
+                DifficultyRating::from_str(grade).unwrap().to_string(),
+                grade
+            );
+        }
+        assert!(DifficultyRating::RatingV3 < DifficultyRating::RatingV4);
+    }
+
+    #[test]
+    fn test_discipline_grade_scales() {
+        let mut route = test_route();
+        assert!(route.check_grade().is_ok());
+        route.discipline = Discipline::Boulder;
+        assert!(matches!(
+            route.check_grade(),
+            Err(DatabaseError::GradeNotValidForDiscipline)
+        ));
+        route.difficulty = DifficultyRating::RatingV5;
+        assert!(route.check_grade().is_ok());
+        assert!(Discipline::Mixed
+            .check_grade(&DifficultyRating::RatingWI5)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_difficulty_points() {
+        assert_eq!(DifficultyRating::Rating59.points(), 100);
+        assert_eq!(DifficultyRating::Rating512.points(), 500);
+        assert_eq!(DifficultyRating::RatingV0.points(), 100);
+        assert_eq!(DifficultyRating::RatingV10.points(), 1100);
+        assert_eq!(DifficultyRating::RatingA5.points(), 600);
+    }
+
+    #[test]
+    fn test_derive_from_pitches() {
+        let mut route = test_route();
+        route.discipline = Discipline::Trad;
+        route.pitches = vec![
+            Pitch::new(DifficultyRating::Rating510, Some(30)),
+            Pitch::new(DifficultyRating::Rating511plus, Some(45)),
+            Pitch::new(DifficultyRating::Rating59, Some(25)),
+        ];
+        route.derive_from_pitches();
+        assert_eq!(route.difficulty, DifficultyRating::Rating511plus);
+        assert_eq!(route.number_pitches, 3);
+        assert_eq!(route.length_meters, Some(100));
+
+        route
+            .pitches
+            .push(Pitch::new(DifficultyRating::Rating59, None));
+        route.derive_from_pitches();
+        assert_eq!(route.number_pitches, 4);
+        assert_eq!(route.length_meters, None);
+
+        route.pitches[0].difficulty = DifficultyRating::RatingV2;
+        assert!(matches!(
+            route.check_grade(),
+            Err(DatabaseError::GradeNotValidForDiscipline)
+        ));
+    }
+
+    #[test]
+    fn test_empty_route_patch() {
+        assert!(matches!(
+            RoutePatch::default().check_not_empty(),
+            Err(DatabaseError::UpdateRouteAllNone)
+        ));
+        let patch = RoutePatch {
+            latitude: Some(1.0),
+            ..Default::default()
+        };
+        assert!(patch.check_not_empty().is_ok());
+        let patch = RoutePatch {
+            metadata: RouteMetadataPatch {
+                hold_color: Some(None),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(patch.check_not_empty().is_ok());
+    }
+
+    #[test]
+    fn test_route_patch_nulls() {
+        let patch: RoutePatch = serde_json::from_str(
+            r#"{"name": null, "strip_date": null, "hold_color": "orange", "length_meters": null}"#,
+        )
+        .unwrap();
+        assert_eq!(patch.name, None);
+        assert_eq!(patch.metadata.strip_date, Some(None));
+        assert_eq!(patch.metadata.hold_color, Some(Some("orange".to_string())));
+        assert_eq!(patch.metadata.set_date, None);
+        assert_eq!(patch.cleared(), vec!["length_meters", "strip_date"]);
+        assert_eq!(
+            patch.metadata.values().hold_color,
+            Some("orange".to_string())
+        );
+    }
+
+    #[test]
+    fn test_strip_date_before_set_date() {
+        let mut metadata = RouteMetadata {
+            set_date: NaiveDate::from_ymd_opt(2023, 5, 1),
+            strip_date: NaiveDate::from_ymd_opt(2023, 4, 1),
+            ..Default::default()
+        };
+        assert!(matches!(
+            metadata.check_dates(),
+            Err(DatabaseError::StripDateBeforeSetDate)
+        ));
+        metadata.strip_date = None;
+        assert!(metadata.check_dates().is_ok());
+    }
+
+    #[test]
+    fn test_rated_route_to_waypoint() {
+        let rated_route = RatedRoute {
+            route: test_route(),
+            average_rating: Some(7.25),
+            number_ratings: 4,
+        };
+        let waypoint = rated_route.to_waypoint();
+        assert_eq!(waypoint.name, "funky monkey (5.9)");
+        assert_eq!(
+            waypoint.description,
+            Some("Grade: 5.9, Rating: 7.2/10 from 4 ratings".to_string())
+        );
+    }
 }
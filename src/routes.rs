@@ -1,99 +1,664 @@
+use crate::condition::ConditionStatus;
 use crate::error::DatabaseError;
+use crate::etag::{derived_etag, etag, if_match_versions, not_modified};
+use crate::gps::{GpsFormat, Waypoint};
 use crate::pg::conn;
-use crate::route::{DifficultyRating, NumberRoutes, Route};
-use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+use crate::recommendation::distance_km;
+use crate::revision::{RevisionAction, RouteEditor};
+use crate::revisions::{check_editor, record_revision};
+use crate::route::{
+    bounding_box, duplicate_route_meters, DifficultyRating, Discipline, DuplicateRoute,
+    NumberRoutes, Pitch, RatedRoute, Route, RouteFilter, RouteMetadata, RoutePatch,
+    SIMILAR_NAME_THRESHOLD,
+};
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    delete,
+    dev::{ServiceRequest, ServiceResponse},
+    get,
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    patch, post, put, web, HttpRequest, HttpResponse, Responder,
+};
 use log::error;
-use sqlx::{self, query};
+use sqlx::{self, postgres::PgConnection, query, Connection};
 /// Functions for the API endpoint /routes
 use std::str::FromStr;
 
-#[post("")]
-async fn add_new_route(json: web::Json<Route>) -> impl Responder {
-    if let Ok(mut conn) = conn().await {
-        if let Ok(_query_result) = query!(
-            "INSERT INTO routes (name, difficulty, latitude, longitude) VALUES ($1, $2, $3, $4)",
-            json.0.name,
-            format!("{}", json.0.difficulty),
-            json.0.latitude,
-            json.0.longitude
+/// Middleware for /routes that points requests for a route merged into another at the same path
+/// under the target route, so that the duplicate's other endpoints keep working like
+/// get_route_by_id does. Only requests that would otherwise be a 404 are looked up. A GET is
+/// answered with a 301, and anything else with a 308 so that the client repeats its method and
+/// body.
+pub async fn redirect_merged_route(
+    request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let path = request.path().to_owned();
+    let query_string = request.query_string().to_owned();
+    let method = request.method().clone();
+    let response = next.call(request).await?.map_into_boxed_body();
+    if response.status() != StatusCode::NOT_FOUND {
+        return Ok(response);
+    }
+    let (id, rest) = match path.strip_prefix("/routes/").map(|path| {
+        let (id, rest) = path.split_at(path.find('/').unwrap_or(path.len()));
+        (i32::from_str(id), rest)
+    }) {
+        Some((Ok(id), rest)) => (id, rest),
+        _ => return Ok(response),
+    };
+    let merged_into = if let Ok(mut conn) = conn().await {
+        match query!(
+            r#"SELECT merged_into as "merged_into!" FROM routes WHERE id = ($1) AND merged_into IS NOT NULL"#,
+            id
         )
-        .execute(&mut conn)
+        .fetch_optional(&mut conn)
         .await
         {
-            HttpResponse::Ok()
-        } else {
-            error!("INSERT query failed in add_new_route()");
-            HttpResponse::BadGateway()
+            Ok(merged_route) => merged_route.map(|merged_route| merged_route.merged_into),
+            Err(_) => {
+                error!("SELECT query for a merged route failed in redirect_merged_route()");
+                None
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in redirect_merged_route()");
+        None
+    };
+    Ok(match merged_into {
+        Some(merged_into) => {
+            let mut location = format!("/routes/{}{}", merged_into, rest);
+            if !query_string.is_empty() {
+                location = format!("{}?{}", location, query_string);
+            }
+            let mut redirect = if method == Method::GET {
+                HttpResponse::MovedPermanently()
+            } else {
+                HttpResponse::PermanentRedirect()
+            };
+            response.into_response(
+                redirect
+                    .insert_header((header::LOCATION, location))
+                    .finish(),
+            )
+        }
+        None => response,
+    })
+}
+
+/// Add a route. It is added even if it looks like a duplicate, but the response lists the routes
+/// it may be a duplicate of.
+#[post("")]
+async fn add_new_route(json: web::Json<Route>, editor: web::Query<RouteEditor>) -> impl Responder {
+    let mut route = json.into_inner();
+    route.derive_from_pitches();
+    if let Err(e) = route.check_grade() {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+    if let Ok(mut conn) = conn().await {
+        if let Some(response) = check_editor(&mut conn, editor.editor_id).await {
+            return response;
+        }
+        match select_possible_duplicates(&mut conn, &route, duplicate_route_meters()).await {
+            Ok(possible_duplicates) => route.possible_duplicates = possible_duplicates,
+            Err(_) => {
+                error!("SELECT query for duplicates failed in add_new_route()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        match insert_route(&mut conn, &mut route, editor.editor_id).await {
+            Ok(id) => {
+                route.id = Some(id);
+                HttpResponse::Created()
+                    .insert_header((header::LOCATION, format!("/routes/{id}")))
+                    .json(route)
+            }
+            Err(e) if is_invalid_route_details(&e) => {
+                HttpResponse::BadRequest().body(e.to_string())
+            }
+            Err(_) => {
+                error!("INSERT query failed in add_new_route()");
+                HttpResponse::BadGateway().finish()
+            }
         }
     } else {
         error!("Failed to connect to the database in add_new_route()");
-        HttpResponse::BadGateway()
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Routes within `meters` of a route whose names are similar to its name, nearest first
+async fn select_possible_duplicates(
+    conn: &mut PgConnection,
+    route: &Route,
+    meters: f64,
+) -> Result<Vec<DuplicateRoute>, DatabaseError> {
+    let (min_latitude, max_latitude, min_longitude, max_longitude) =
+        bounding_box(route.latitude, route.longitude, meters);
+    let mut duplicates = query!(
+        r#"SELECT id, name as "name!", latitude as "latitude!", longitude as "longitude!" FROM routes
+        WHERE deleted_at IS NULL AND latitude BETWEEN $1 AND $2 AND longitude BETWEEN $3 AND $4
+        AND similarity(name, $5) >= $6"#,
+        min_latitude,
+        max_latitude,
+        min_longitude,
+        max_longitude,
+        route.name,
+        SIMILAR_NAME_THRESHOLD,
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|record| DuplicateRoute {
+        id: record.id,
+        name: record.name,
+        distance_meters: distance_km(
+            (route.latitude, route.longitude),
+            (record.latitude, record.longitude),
+        ) * 1000.0,
+    })
+    .filter(|duplicate| duplicate.distance_meters <= meters)
+    .collect::<Vec<DuplicateRoute>>();
+    duplicates.sort_by(|a, b| a.distance_meters.total_cmp(&b.distance_meters));
+    Ok(duplicates)
+}
+
+async fn insert_route(
+    conn: &mut PgConnection,
+    route: &mut Route,
+    editor_id: Option<i32>,
+) -> Result<i32, DatabaseError> {
+    let mut transaction = conn.begin().await?;
+    check_route_details(&mut transaction, &mut route.metadata).await?;
+    let id = query!(
+        "INSERT INTO routes (name, discipline, difficulty, latitude, longitude, number_pitches, length_meters, bolts, first_ascent_party, first_ascent_date, setter_id, hold_color, set_date, strip_date, wall_id, route_set_id, area_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17) RETURNING id",
+        route.name,
+        route.discipline.to_string(),
+        format!("{}", route.difficulty),
+        route.latitude,
+        route.longitude,
+        route.number_pitches,
+        route.length_meters,
+        route.metadata.bolts,
+        route.metadata.first_ascent_party,
+        route.metadata.first_ascent_date,
+        route.metadata.setter_id,
+        route.metadata.hold_color,
+        route.metadata.set_date,
+        route.metadata.strip_date,
+        route.metadata.wall_id,
+        route.metadata.route_set_id,
+        route.metadata.area_id,
+    )
+    .fetch_one(&mut transaction)
+    .await?
+    .id;
+    replace_pitches(&mut transaction, id, &route.pitches).await?;
+    if let Some(inserted) = select_route(&mut transaction, id).await? {
+        record_revision(
+            &mut transaction,
+            id,
+            editor_id,
+            RevisionAction::Create,
+            None,
+            &inserted,
+        )
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(id)
+}
+
+/// Check the rows a route's details refer to. A route in a route set goes on the set's wall, and
+/// takes the set's set and strip dates unless it has its own.
+async fn check_route_details(
+    conn: &mut PgConnection,
+    metadata: &mut RouteMetadata,
+) -> Result<(), DatabaseError> {
+    if let Some(route_set_id) = metadata.route_set_id {
+        let route_set = query!(
+            "SELECT wall_id, set_date, strip_date FROM route_sets WHERE id = ($1)",
+            route_set_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(DatabaseError::RouteSetNotFound)?;
+        if metadata.wall_id.unwrap_or(route_set.wall_id) != route_set.wall_id {
+            return Err(DatabaseError::RouteSetNotOnWall);
+        }
+        metadata.wall_id = Some(route_set.wall_id);
+        metadata.set_date = metadata.set_date.or(Some(route_set.set_date));
+        metadata.strip_date = metadata.strip_date.or(route_set.strip_date);
+    } else if let Some(wall_id) = metadata.wall_id {
+        query!("SELECT id FROM walls WHERE id = ($1)", wall_id)
+            .fetch_optional(&mut *conn)
+            .await?
+            .ok_or(DatabaseError::WallNotFound)?;
+    }
+    if let Some(area_id) = metadata.area_id {
+        query!("SELECT id FROM areas WHERE id = ($1)", area_id)
+            .fetch_optional(&mut *conn)
+            .await?
+            .ok_or(DatabaseError::AreaNotFound)?;
+    }
+    if let Some(setter_id) = metadata.setter_id {
+        query!(
+            "SELECT id FROM climbers WHERE id = ($1) AND deleted_at IS NULL",
+            setter_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(DatabaseError::SetterNotFound)?;
+    }
+    metadata.check_dates()
+}
+
+/// Errors from check_route_details, which are the client's fault rather than the database's
+pub fn is_invalid_route_details(e: &DatabaseError) -> bool {
+    matches!(
+        e,
+        DatabaseError::AreaNotFound
+            | DatabaseError::SetterNotFound
+            | DatabaseError::WallNotFound
+            | DatabaseError::RouteSetNotFound
+            | DatabaseError::RouteSetNotOnWall
+            | DatabaseError::StripDateBeforeSetDate
+    )
+}
+
+/// Write a route's pitches in order, replacing any it had before. Runs inside the caller's
+/// transaction so that the pitches always agree with the grade and length derived from them.
+async fn replace_pitches(
+    conn: &mut PgConnection,
+    route_id: i32,
+    pitches: &[Pitch],
+) -> Result<(), DatabaseError> {
+    query!("DELETE FROM pitches WHERE route_id = ($1)", route_id)
+        .execute(&mut *conn)
+        .await?;
+    for (position, pitch) in pitches.iter().enumerate() {
+        query!(
+            "INSERT INTO pitches (route_id, position, difficulty, length_meters, bolts, gear_notes, anchor) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            route_id,
+            position as i32 + 1,
+            pitch.difficulty.to_string(),
+            pitch.length_meters,
+            pitch.bolts,
+            pitch.gear_notes,
+            pitch.anchor,
+        )
+        .execute(&mut *conn)
+        .await?;
     }
+    Ok(())
+}
+
+async fn select_pitches(
+    conn: &mut PgConnection,
+    route_id: i32,
+) -> Result<Vec<Pitch>, DatabaseError> {
+    query!(
+        r#"SELECT difficulty as "difficulty!", length_meters, bolts, gear_notes, anchor FROM pitches WHERE route_id = ($1) ORDER BY position"#,
+        route_id
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|record| {
+        Ok(Pitch {
+            difficulty: DifficultyRating::from_str(&record.difficulty)?,
+            length_meters: record.length_meters,
+            bolts: record.bolts,
+            gear_notes: record.gear_notes,
+            anchor: record.anchor,
+        })
+    })
+    .collect()
+}
+
+/// A route that hasn't been deleted, with its pitches, as it was written. Fields that aren't
+/// written are left at their defaults.
+pub async fn select_route(
+    conn: &mut PgConnection,
+    id: i32,
+) -> Result<Option<Route>, DatabaseError> {
+    let Some(record) = query!(
+        r#"SELECT id, name as "name!", discipline, difficulty as "difficulty!", latitude as "latitude!", longitude as "longitude!", number_pitches, length_meters,
+        bolts, first_ascent_party, first_ascent_date, setter_id, hold_color, set_date, strip_date, wall_id, route_set_id, area_id
+        FROM routes WHERE id = ($1) AND deleted_at IS NULL"#,
+        id
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let mut route = Route::new(
+        Some(record.id),
+        record.name,
+        Discipline::from_str(&record.discipline)?,
+        DifficultyRating::from_str(&record.difficulty)?,
+        record.latitude,
+        record.longitude,
+    );
+    route.number_pitches = record.number_pitches;
+    route.length_meters = record.length_meters;
+    route.metadata = RouteMetadata {
+        area_id: record.area_id,
+        bolts: record.bolts,
+        first_ascent_party: record.first_ascent_party,
+        first_ascent_date: record.first_ascent_date,
+        setter_id: record.setter_id,
+        hold_color: record.hold_color,
+        set_date: record.set_date,
+        strip_date: record.strip_date,
+        wall_id: record.wall_id,
+        route_set_id: record.route_set_id,
+    };
+    route.pitches = select_pitches(conn, id).await?;
+    Ok(Some(route))
+}
+
+/// Shared SELECT behind the route listing, the GPX/KML exports and route recommendations, so that
+/// they all honor the same RouteFilter. A `limit` of None returns every matching route.
+pub async fn select_routes(
+    conn: &mut PgConnection,
+    limit: Option<i64>,
+    filter: &RouteFilter,
+) -> Result<Vec<RatedRoute>, DatabaseError> {
+    let difficulties = filter.difficulties.as_ref().map(|difficulties| {
+        difficulties
+            .iter()
+            .map(|difficulty| difficulty.to_string())
+            .collect::<Vec<String>>()
+    });
+    query!(
+        r#"SELECT routes.id, routes.name as "name!", routes.discipline, routes.difficulty as "difficulty!", routes.latitude as "latitude!", routes.longitude as "longitude!", routes.number_pitches, routes.length_meters,
+        routes.bolts, routes.first_ascent_party, routes.first_ascent_date, routes.setter_id, routes.hold_color, routes.set_date, routes.strip_date,
+        routes.wall_id, routes.route_set_id, routes.area_id, routes.archived_at IS NOT NULL as "archived!",
+        AVG(climbs.rating)::float8 as average_rating, COUNT(climbs.id) as "number_ratings!",
+        (WITH RECURSIVE ancestors AS (
+            SELECT id, parent_id FROM areas WHERE id = routes.area_id
+            UNION SELECT areas.id, areas.parent_id FROM areas JOIN ancestors ON areas.id = ancestors.parent_id
+        ), reports AS (
+            SELECT id, status, created_at FROM condition_reports
+            WHERE expires_at > current_timestamp
+            AND (route_id = routes.id OR area_id IN (SELECT id FROM ancestors))
+        ) SELECT status FROM reports
+        ORDER BY status = ANY($12) AND NOT EXISTS (
+            SELECT 1 FROM reports reopened WHERE reopened.status = ($13) AND (reopened.created_at, reopened.id) > (reports.created_at, reports.id)
+        ) DESC, created_at DESC, id DESC LIMIT 1) as current_condition
+        FROM routes LEFT JOIN active_climbs climbs ON climbs.route_id = routes.id AND climbs.hidden_at IS NULL
+        WHERE routes.deleted_at IS NULL
+        AND ($2::varchar IS NULL OR routes.difficulty = $2)
+        AND ($3::float8 IS NULL OR routes.latitude >= $3)
+        AND ($4::float8 IS NULL OR routes.latitude <= $4)
+        AND ($5::float8 IS NULL OR routes.longitude >= $5)
+        AND ($6::float8 IS NULL OR routes.longitude <= $6)
+        AND ($7::varchar IS NULL OR routes.discipline = $7)
+        AND ($8::bool IS NULL OR (routes.set_date IS NOT NULL AND routes.set_date <= current_date AND (routes.strip_date IS NULL OR routes.strip_date > current_date)) = $8)
+        AND ($9::int4 IS NULL OR routes.wall_id = $9)
+        AND ($10::bool IS TRUE OR routes.archived_at IS NULL)
+        AND ($11::int4 IS NULL OR routes.area_id IN (
+            WITH RECURSIVE subareas AS (
+                SELECT id FROM areas WHERE id = $11
+                UNION SELECT areas.id FROM areas JOIN subareas ON areas.parent_id = subareas.id
+            ) SELECT id FROM subareas
+        ))
+        AND ($14::varchar[] IS NULL OR routes.difficulty = ANY($14))
+        AND ($15::int4 IS NULL OR routes.id NOT IN (SELECT route_id FROM climbs WHERE climber_id = $15 AND deleted_at IS NULL))
+        GROUP BY routes.id ORDER BY routes.created_at DESC LIMIT ($1)"#,
+        limit,
+        filter.difficulty.as_ref().map(|difficulty| difficulty.to_string()),
+        filter.min_latitude,
+        filter.max_latitude,
+        filter.min_longitude,
+        filter.max_longitude,
+        filter.discipline.map(|discipline| discipline.to_string()),
+        filter.currently_up,
+        filter.wall_id,
+        filter.include_archived,
+        filter.area_id,
+        &ConditionStatus::closures(),
+        ConditionStatus::Open.to_string(),
+        difficulties.as_deref(),
+        filter.not_climbed_by,
+    )
+    .fetch_all(conn)
+    .await?
+    .iter()
+    .map(|record| {
+        let mut route = Route::new(
+            Some(record.id),
+            record.name.to_owned(),
+            Discipline::from_str(&record.discipline)?,
+            DifficultyRating::from_str(&record.difficulty)?,
+            record.latitude,
+            record.longitude,
+        );
+        route.number_pitches = record.number_pitches;
+        route.length_meters = record.length_meters;
+        route.metadata = RouteMetadata {
+            area_id: record.area_id,
+            bolts: record.bolts,
+            first_ascent_party: record.first_ascent_party.to_owned(),
+            first_ascent_date: record.first_ascent_date,
+            setter_id: record.setter_id,
+            hold_color: record.hold_color.to_owned(),
+            set_date: record.set_date,
+            strip_date: record.strip_date,
+            wall_id: record.wall_id,
+            route_set_id: record.route_set_id,
+        };
+        route.archived = record.archived;
+        route.current_condition = record
+            .current_condition
+            .as_deref()
+            .map(ConditionStatus::from_str)
+            .transpose()?;
+        Ok(RatedRoute {
+            route,
+            average_rating: record.average_rating,
+            number_ratings: record.number_ratings,
+        })
+    })
+    .collect()
 }
 
 #[get("")]
-async fn get_recent_routes(number_routes: Option<web::Json<NumberRoutes>>) -> impl Responder {
+async fn get_recent_routes(
+    number_routes: Option<web::Json<NumberRoutes>>,
+    filter: web::Query<RouteFilter>,
+) -> impl Responder {
     let number_of_routes_to_request = match number_routes {
         Some(n) => n.0.number_routes,
         None => 5,
     };
     if let Ok(mut conn) = conn().await {
-        if let Ok(query_result) = query!(
-            r#"SELECT id, name as "name!", difficulty as "difficulty!", latitude as "latitude!", longitude as "longitude!" FROM routes ORDER BY created_at DESC LIMIT ($1)"#,
-            number_of_routes_to_request,
-        )
-            .fetch_all(&mut conn)
-            .await
-        {
-            if let Ok(routes) = query_result
-                .iter()
-                .map(|record| {
-                    Ok(Route::new(
-                        Some(record.id),
-                        record.name.to_owned(),
-                        DifficultyRating::from_str(&record.difficulty)?,
-                        record.latitude,
-                        record.longitude,
-                    ))
-                })
-                .collect::<Result<Vec<Route>, DatabaseError>>() {
-                    HttpResponse::Ok().json(routes)
-            } else {
-                error!("Failed to parse difficulty column to a DifficultyRating in get_recent_routes()");
+        match select_routes(&mut conn, Some(number_of_routes_to_request), &filter).await {
+            Ok(rated_routes) => {
+                let routes = rated_routes
+                    .into_iter()
+                    .map(|rated_route| rated_route.route)
+                    .collect::<Vec<Route>>();
+                HttpResponse::Ok().json(routes)
+            }
+            Err(
+                DatabaseError::FailedParseDifficultyRating | DatabaseError::FailedParseDiscipline,
+            ) => {
+                error!("Failed to parse discipline or difficulty column in get_recent_routes()");
                 HttpResponse::BadGateway().finish()
             }
+            Err(_) => {
+                error!("SELECT query failed in get_recent_routes()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in get_recent_routes()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+async fn export_routes(filter: &RouteFilter, format: GpsFormat) -> HttpResponse {
+    if let Ok(mut conn) = conn().await {
+        if let Ok(rated_routes) = select_routes(&mut conn, None, filter).await {
+            let waypoints = rated_routes
+                .iter()
+                .map(RatedRoute::to_waypoint)
+                .collect::<Vec<Waypoint>>();
+            HttpResponse::Ok()
+                .content_type(format.content_type())
+                .insert_header((
+                    header::CONTENT_DISPOSITION,
+                    format!(
+                        "attachment; filename=\"routes.{}\"",
+                        format.file_extension()
+                    ),
+                ))
+                .body(format.write(&waypoints))
         } else {
-            error!("SELECT query failed in get_recent_routes()");
+            error!("SELECT query failed in export_routes()");
             HttpResponse::BadGateway().finish()
         }
     } else {
-        error!("Failed to connect to the database in get_recent_routes()");
+        error!("Failed to connect to the database in export_routes()");
         HttpResponse::BadGateway().finish()
     }
 }
 
+#[get("/export.gpx")]
+async fn export_routes_gpx(filter: web::Query<RouteFilter>) -> impl Responder {
+    export_routes(&filter, GpsFormat::Gpx).await
+}
+
+#[get("/export.kml")]
+async fn export_routes_kml(filter: web::Query<RouteFilter>) -> impl Responder {
+    export_routes(&filter, GpsFormat::Kml).await
+}
+
+/// Find a route by its ID. The ID of a route merged into another redirects to the other route.
 #[get("/{id}")]
-async fn get_route_by_id(path: web::Path<i32>) -> impl Responder {
+async fn get_route_by_id(path: web::Path<i32>, request: HttpRequest) -> impl Responder {
     let id = path.into_inner();
     if let Ok(mut conn) = conn().await {
+        match query!(
+            r#"SELECT merged_into as "merged_into!" FROM routes WHERE id = ($1) AND merged_into IS NOT NULL"#,
+            id
+        )
+        .fetch_optional(&mut conn)
+        .await
+        {
+            Ok(Some(query_result)) => {
+                return HttpResponse::MovedPermanently()
+                    .insert_header((
+                        header::LOCATION,
+                        format!("/routes/{}", query_result.merged_into),
+                    ))
+                    .finish()
+            }
+            Ok(None) => (),
+            Err(_) => {
+                error!("SELECT query for a merged route failed in get_route_by_id()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
         if let Ok(query_result) = query!(
-            r#"SELECT id, name as "name!", difficulty as "difficulty!", latitude as "latitude!", longitude as "longitude!" FROM routes WHERE id = ($1)"#,
+            r#"SELECT id, name as "name!", discipline, difficulty as "difficulty!", latitude as "latitude!", longitude as "longitude!", number_pitches, length_meters,
+            bolts, first_ascent_party, first_ascent_date, setter_id, hold_color, set_date, strip_date,
+            wall_id, route_set_id, area_id, archived_at IS NOT NULL as "archived!", version,
+            (WITH RECURSIVE ancestors AS (
+                SELECT id, parent_id FROM areas WHERE id = routes.area_id
+                UNION SELECT areas.id, areas.parent_id FROM areas JOIN ancestors ON areas.id = ancestors.parent_id
+            ), reports AS (
+                SELECT id, status, created_at FROM condition_reports
+                WHERE expires_at > current_timestamp
+                AND (route_id = routes.id OR area_id IN (SELECT id FROM ancestors))
+            ) SELECT status FROM reports
+            ORDER BY status = ANY($2) AND NOT EXISTS (
+                SELECT 1 FROM reports reopened WHERE reopened.status = ($3) AND (reopened.created_at, reopened.id) > (reports.created_at, reports.id)
+            ) DESC, created_at DESC, id DESC LIMIT 1) as current_condition,
+            (SELECT count(*) FROM ticklist_entries
+            JOIN climbers ON climbers.id = ticklist_entries.climber_id
+            WHERE ticklist_entries.route_id = routes.id AND climbers.deleted_at IS NULL
+            AND NOT EXISTS (
+                SELECT 1 FROM active_climbs WHERE climber_id = ticklist_entries.climber_id AND route_id = routes.id
+            )) as "ticklist_count!"
+            FROM routes WHERE id = ($1) AND deleted_at IS NULL"#,
             id,
+            &ConditionStatus::closures(),
+            ConditionStatus::Open.to_string(),
         )
             .fetch_one(&mut conn)
             .await
         {
-            if let Ok(difficulty_rating) = DifficultyRating::from_str(&query_result.difficulty) {
-                let route = Route::new(
+            // The current condition and ticklist count come from other tables, whose writes don't
+            // touch the route
+            let tag = derived_etag(
+                query_result.version,
+                &[
+                    query_result.current_condition.as_deref().unwrap_or("none"),
+                    &query_result.ticklist_count.to_string(),
+                ],
+            );
+            if not_modified(&request, &tag) {
+                return HttpResponse::NotModified()
+                    .insert_header(header::ETag(tag))
+                    .finish();
+            }
+            if let (Ok(discipline), Ok(difficulty_rating)) = (
+                Discipline::from_str(&query_result.discipline),
+                DifficultyRating::from_str(&query_result.difficulty),
+            ) {
+                let mut route = Route::new(
                     Some(query_result.id),
                     query_result.name.to_owned(),
+                    discipline,
                     difficulty_rating,
                     query_result.latitude,
                     query_result.longitude,
                 );
-                HttpResponse::Ok().json(route)
+                route.number_pitches = query_result.number_pitches;
+                route.length_meters = query_result.length_meters;
+                route.metadata = RouteMetadata {
+                    area_id: query_result.area_id,
+                    bolts: query_result.bolts,
+                    first_ascent_party: query_result.first_ascent_party,
+                    first_ascent_date: query_result.first_ascent_date,
+                    setter_id: query_result.setter_id,
+                    hold_color: query_result.hold_color,
+                    set_date: query_result.set_date,
+                    strip_date: query_result.strip_date,
+                    wall_id: query_result.wall_id,
+                    route_set_id: query_result.route_set_id,
+                };
+                route.archived = query_result.archived;
+                match query_result
+                    .current_condition
+                    .as_deref()
+                    .map(ConditionStatus::from_str)
+                    .transpose()
+                {
+                    Ok(current_condition) => route.current_condition = current_condition,
+                    Err(_) => {
+                        error!("Failed to parse current_condition column in get_route_by_id()");
+                        return HttpResponse::BadGateway().finish();
+                    }
+                }
+                match select_pitches(&mut conn, id).await {
+                    Ok(pitches) => route.pitches = pitches,
+                    Err(_) => {
+                        error!("SELECT query for pitches failed in get_route_by_id()");
+                        return HttpResponse::BadGateway().finish();
+                    }
+                }
+                route.ticklist_count = Some(query_result.ticklist_count);
+                HttpResponse::Ok()
+                    .insert_header(header::ETag(tag))
+                    .json(route)
             } else {
-                error!("Failed to parse difficulty column to a DifficultyRating in get_route_by_id()");
+                error!("Failed to parse discipline or difficulty column in get_route_by_id()");
                 HttpResponse::BadGateway().finish()
             }
         } else {
@@ -106,49 +671,526 @@ async fn get_route_by_id(path: web::Path<i32>) -> impl Responder {
     }
 }
 
+/// Response for a conditional write to a route that matched no rows, which is either because the
+/// route doesn't exist or because If-Match didn't have its current version
+async fn route_not_written(conn: &mut PgConnection, id: i32) -> HttpResponse {
+    match query!(
+        "SELECT id FROM routes WHERE id = ($1) AND deleted_at IS NULL",
+        id
+    )
+    .fetch_optional(conn)
+    .await
+    {
+        Ok(Some(_)) => HttpResponse::PreconditionFailed().finish(),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(_) => {
+            error!("SELECT query failed in route_not_written()");
+            HttpResponse::BadGateway().finish()
+        }
+    }
+}
+
+/// Soft delete a route and record it in the route's history. False if the route doesn't exist or
+/// If-Match didn't have its current version.
+async fn delete_route(
+    conn: &mut PgConnection,
+    id: i32,
+    if_match: Option<&[i32]>,
+    editor_id: Option<i32>,
+) -> Result<bool, DatabaseError> {
+    let mut transaction = conn.begin().await?;
+    let Some(route) = select_route(&mut transaction, id).await? else {
+        return Ok(false);
+    };
+    let rows_affected = query!(
+        r#"UPDATE routes SET deleted_at = current_timestamp, version = version + 1 WHERE id = ($1) AND deleted_at IS NULL AND ($2::int4[] IS NULL OR version = ANY($2))"#,
+        id,
+        if_match,
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    if rows_affected == 0 {
+        return Ok(false);
+    }
+    record_revision(
+        &mut transaction,
+        id,
+        editor_id,
+        RevisionAction::Delete,
+        Some(&route),
+        &route,
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(true)
+}
+
 #[delete("/{id}")]
-async fn delete_route_by_id(path: web::Path<i32>) -> impl Responder {
+async fn delete_route_by_id(
+    path: web::Path<i32>,
+    request: HttpRequest,
+    editor: web::Query<RouteEditor>,
+) -> impl Responder {
     let id = path.into_inner();
+    let if_match = if_match_versions(&request);
     if let Ok(mut conn) = conn().await {
-        if query!(r#"DELETE FROM routes WHERE id = ($1)"#, id,)
-            .execute(&mut conn)
-            .await
-            .is_ok()
-        {
-            HttpResponse::Ok()
-        } else {
-            error!("DELETE query failed in delete_route_by_id()");
-            HttpResponse::BadGateway()
+        if let Some(response) = check_editor(&mut conn, editor.editor_id).await {
+            return response;
+        }
+        match delete_route(&mut conn, id, if_match.as_deref(), editor.editor_id).await {
+            Ok(true) => HttpResponse::NoContent().finish(),
+            Ok(false) => route_not_written(&mut conn, id).await,
+            Err(_) => {
+                error!("UPDATE query failed in delete_route_by_id()");
+                HttpResponse::BadGateway().finish()
+            }
         }
     } else {
         error!("Failed to connect to the database in delete_route_by_id()");
-        HttpResponse::BadGateway()
+        HttpResponse::BadGateway().finish()
     }
 }
 
 #[put("/{id}")]
-async fn update_route_by_id(path: web::Path<i32>, json: web::Json<Route>) -> impl Responder {
+async fn update_route_by_id(
+    path: web::Path<i32>,
+    request: HttpRequest,
+    json: web::Json<Route>,
+    editor: web::Query<RouteEditor>,
+) -> impl Responder {
     let id = path.into_inner();
+    let if_match = if_match_versions(&request);
+    let mut route = json.into_inner();
+    route.derive_from_pitches();
+    if let Err(e) = route.check_grade() {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
     if let Ok(mut conn) = conn().await {
-        if query!(
-            "UPDATE routes SET name = $1, difficulty = $2, latitude = $3, longitude = $4 WHERE id = ($5)",
-            json.0.name,
-            format!("{}", json.0.difficulty),
-            json.0.latitude,
-            json.0.longitude,
+        if let Some(response) = check_editor(&mut conn, editor.editor_id).await {
+            return response;
+        }
+        match update_route(
+            &mut conn,
             id,
+            if_match.as_deref(),
+            &mut route,
+            editor.editor_id,
+            RevisionAction::Update,
         )
-            .execute(&mut conn)
-            .await
-            .is_ok()
+        .await
         {
-            HttpResponse::Ok()
-        } else {
-            error!("UPDATE query failed in update_route_by_id()");
-            HttpResponse::BadGateway()
+            Ok(Some(version)) => HttpResponse::NoContent()
+                .insert_header(header::ETag(etag(version)))
+                .finish(),
+            Ok(None) => route_not_written(&mut conn, id).await,
+            Err(e) if is_invalid_route_details(&e) => {
+                HttpResponse::BadRequest().body(e.to_string())
+            }
+            Err(_) => {
+                error!("UPDATE query failed in update_route_by_id()");
+                HttpResponse::BadGateway().finish()
+            }
         }
     } else {
         error!("Failed to connect to the database in update_route_by_id()");
-        HttpResponse::BadGateway()
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Overwrite a route along with its pitches, recording the change in its history, and return its
+/// new version. Ok(None) if the route
+/// doesn't exist or If-Match didn't have its current version.
+pub async fn update_route(
+    conn: &mut PgConnection,
+    id: i32,
+    if_match: Option<&[i32]>,
+    route: &mut Route,
+    editor_id: Option<i32>,
+    action: RevisionAction,
+) -> Result<Option<i32>, DatabaseError> {
+    let mut transaction = conn.begin().await?;
+    check_route_details(&mut transaction, &mut route.metadata).await?;
+    let before = select_route(&mut transaction, id).await?;
+    let version = query!(
+        "UPDATE routes SET name = $1, discipline = $7, difficulty = $2, latitude = $3, longitude = $4, number_pitches = $8, length_meters = $9,
+        bolts = $10, first_ascent_party = $11, first_ascent_date = $12, setter_id = $13, hold_color = $14, set_date = $15, strip_date = $16,
+        wall_id = $17, route_set_id = $18, area_id = $19, version = version + 1 WHERE id = ($5) AND deleted_at IS NULL AND ($6::int4[] IS NULL OR version = ANY($6)) RETURNING version",
+        route.name,
+        format!("{}", route.difficulty),
+        route.latitude,
+        route.longitude,
+        id,
+        if_match,
+        route.discipline.to_string(),
+        route.number_pitches,
+        route.length_meters,
+        route.metadata.bolts,
+        route.metadata.first_ascent_party,
+        route.metadata.first_ascent_date,
+        route.metadata.setter_id,
+        route.metadata.hold_color,
+        route.metadata.set_date,
+        route.metadata.strip_date,
+        route.metadata.wall_id,
+        route.metadata.route_set_id,
+        route.metadata.area_id,
+    )
+    .fetch_optional(&mut transaction)
+    .await?
+    .map(|record| record.version);
+    if version.is_some() {
+        replace_pitches(&mut transaction, id, &route.pitches).await?;
+        if let (Some(before), Some(after)) = (before, select_route(&mut transaction, id).await?) {
+            record_revision(
+                &mut transaction,
+                id,
+                editor_id,
+                action,
+                Some(&before),
+                &after,
+            )
+            .await?;
+        }
+    }
+    transaction.commit().await?;
+    Ok(version)
+}
+
+/// A patch can change a route's discipline or difficulty without the other, so check the grade
+/// (and the grade of every pitch) against the route as it would be after patching. Likewise for
+/// the set and strip dates. The patch's details are checked (and filled in from a route set) by
+/// check_route_details. A route with pitches can't have the fields derived from them patched.
+/// Runs inside patch_route's transaction, with the route locked. Ok(None) if the route doesn't
+/// exist.
+async fn check_patched_route(
+    conn: &mut PgConnection,
+    id: i32,
+    patch: &mut RoutePatch,
+) -> Result<Option<Result<(), DatabaseError>>, DatabaseError> {
+    let current = query!(
+        r#"SELECT discipline, difficulty as "difficulty!", set_date, strip_date FROM routes WHERE id = ($1) AND deleted_at IS NULL"#,
+        id
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+    match current {
+        Some(record) => {
+            let mut metadata = patch.metadata.values();
+            let details = match check_route_details(&mut *conn, &mut metadata).await {
+                Err(e @ DatabaseError::FailedConnection(_)) => return Err(e),
+                details => details,
+            };
+            if metadata.route_set_id.is_some() {
+                patch.metadata.wall_id = Some(metadata.wall_id);
+                patch.metadata.set_date.get_or_insert(metadata.set_date);
+                patch.metadata.strip_date.get_or_insert(metadata.strip_date);
+            }
+            let discipline = match patch.discipline {
+                Some(discipline) => discipline,
+                None => Discipline::from_str(&record.discipline)?,
+            };
+            let difficulty = match &patch.difficulty {
+                Some(difficulty) => difficulty.clone(),
+                None => DifficultyRating::from_str(&record.difficulty)?,
+            };
+            let dates = RouteMetadata {
+                set_date: patch.metadata.set_date.unwrap_or(record.set_date),
+                strip_date: patch.metadata.strip_date.unwrap_or(record.strip_date),
+                ..Default::default()
+            };
+            let pitches = select_pitches(conn, id).await?;
+            Ok(Some(
+                details
+                    .and_then(|()| discipline.check_grade(&difficulty))
+                    .and_then(|()| {
+                        pitches
+                            .iter()
+                            .try_for_each(|pitch| discipline.check_grade(&pitch.difficulty))
+                    })
+                    .and_then(|()| dates.check_dates())
+                    .and_then(|()| {
+                        if !pitches.is_empty()
+                            && (patch.difficulty.is_some()
+                                || patch.length_meters.is_some()
+                                || patch.metadata.bolts.is_some())
+                        {
+                            Err(DatabaseError::PatchDerivedFromPitches)
+                        } else {
+                            Ok(())
+                        }
+                    }),
+            ))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Apply a patch to a route, recording the change in its history, and return its new version. The
+/// route is locked while the patch is checked against it, so that nothing changes it in between.
+/// Ok(Err) with the response to give if the route doesn't exist, If-Match didn't have its current
+/// version or the patch isn't valid for the route.
+async fn patch_route(
+    conn: &mut PgConnection,
+    id: i32,
+    if_match: Option<&[i32]>,
+    patch: &mut RoutePatch,
+    editor_id: Option<i32>,
+) -> Result<Result<i32, HttpResponse>, DatabaseError> {
+    let mut transaction = conn.begin().await?;
+    let Some(current) = query!(
+        "SELECT version FROM routes WHERE id = ($1) AND deleted_at IS NULL FOR UPDATE",
+        id
+    )
+    .fetch_optional(&mut transaction)
+    .await?
+    else {
+        return Ok(Err(HttpResponse::NotFound().finish()));
+    };
+    if if_match.is_some_and(|versions| !versions.contains(&current.version)) {
+        return Ok(Err(HttpResponse::PreconditionFailed().finish()));
+    }
+    if patch.discipline.is_some()
+        || patch.difficulty.is_some()
+        || patch.length_meters.is_some()
+        || patch.metadata.bolts.is_some()
+        || patch.metadata.set_date.is_some()
+        || patch.metadata.strip_date.is_some()
+        || patch.metadata.setter_id.is_some()
+        || patch.metadata.wall_id.is_some()
+        || patch.metadata.route_set_id.is_some()
+        || patch.metadata.area_id.is_some()
+    {
+        match check_patched_route(&mut transaction, id, patch).await? {
+            Some(Ok(())) => (),
+            Some(Err(e)) => return Ok(Err(HttpResponse::BadRequest().body(e.to_string()))),
+            None => return Ok(Err(HttpResponse::NotFound().finish())),
+        }
+    }
+    let before = select_route(&mut transaction, id).await?;
+    let metadata = patch.metadata.values();
+    let cleared = patch.cleared();
+    // A column the patch clears is set to null, and one it leaves out (also null here) is kept
+    let version = query!(
+        "UPDATE routes SET name = COALESCE($1, name), discipline = COALESCE($6, discipline), difficulty = COALESCE($2, difficulty), latitude = COALESCE($3, latitude), longitude = COALESCE($4, longitude),
+        length_meters = CASE WHEN 'length_meters' = ANY($18) THEN NULL ELSE COALESCE($7, length_meters) END,
+        bolts = CASE WHEN 'bolts' = ANY($18) THEN NULL ELSE COALESCE($8, bolts) END,
+        first_ascent_party = CASE WHEN 'first_ascent_party' = ANY($18) THEN NULL ELSE COALESCE($9, first_ascent_party) END,
+        first_ascent_date = CASE WHEN 'first_ascent_date' = ANY($18) THEN NULL ELSE COALESCE($10, first_ascent_date) END,
+        setter_id = CASE WHEN 'setter_id' = ANY($18) THEN NULL ELSE COALESCE($11, setter_id) END,
+        hold_color = CASE WHEN 'hold_color' = ANY($18) THEN NULL ELSE COALESCE($12, hold_color) END,
+        set_date = CASE WHEN 'set_date' = ANY($18) THEN NULL ELSE COALESCE($13, set_date) END,
+        strip_date = CASE WHEN 'strip_date' = ANY($18) THEN NULL ELSE COALESCE($14, strip_date) END,
+        wall_id = CASE WHEN 'wall_id' = ANY($18) THEN NULL ELSE COALESCE($15, wall_id) END,
+        route_set_id = CASE WHEN 'route_set_id' = ANY($18) THEN NULL ELSE COALESCE($16, route_set_id) END,
+        area_id = CASE WHEN 'area_id' = ANY($18) THEN NULL ELSE COALESCE($17, area_id) END,
+        version = version + 1 WHERE id = ($5) AND deleted_at IS NULL RETURNING version",
+        patch.name,
+        patch.difficulty.as_ref().map(|difficulty| difficulty.to_string()),
+        patch.latitude,
+        patch.longitude,
+        id,
+        patch.discipline.map(|discipline| discipline.to_string()),
+        patch.length_meters.flatten(),
+        metadata.bolts,
+        metadata.first_ascent_party,
+        metadata.first_ascent_date,
+        metadata.setter_id,
+        metadata.hold_color,
+        metadata.set_date,
+        metadata.strip_date,
+        metadata.wall_id,
+        metadata.route_set_id,
+        metadata.area_id,
+        &cleared[..],
+    )
+    .fetch_one(&mut transaction)
+    .await?
+    .version;
+    if let (Some(before), Some(after)) = (before, select_route(&mut transaction, id).await?) {
+        record_revision(
+            &mut transaction,
+            id,
+            editor_id,
+            RevisionAction::Patch,
+            Some(&before),
+            &after,
+        )
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(Ok(version))
+}
+
+#[patch("/{id}")]
+async fn patch_route_by_id(
+    path: web::Path<i32>,
+    request: HttpRequest,
+    json: web::Json<RoutePatch>,
+    editor: web::Query<RouteEditor>,
+) -> impl Responder {
+    let id = path.into_inner();
+    let if_match = if_match_versions(&request);
+    let mut patch = json.into_inner();
+    if let Err(e) = patch.check_not_empty() {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+    if let Ok(mut conn) = conn().await {
+        if let Some(response) = check_editor(&mut conn, editor.editor_id).await {
+            return response;
+        }
+        match patch_route(
+            &mut conn,
+            id,
+            if_match.as_deref(),
+            &mut patch,
+            editor.editor_id,
+        )
+        .await
+        {
+            Ok(Ok(version)) => HttpResponse::NoContent()
+                .insert_header(header::ETag(etag(version)))
+                .finish(),
+            Ok(Err(response)) => response,
+            Err(_) => {
+                error!("UPDATE query failed in patch_route_by_id()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in patch_route_by_id()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Replace the approach waypoints for a route with the ones in an uploaded GPX or KML file
+#[post("/{id}/approach")]
+async fn import_approach_waypoints(
+    path: web::Path<i32>,
+    request: HttpRequest,
+    body: String,
+    editor: web::Query<RouteEditor>,
+) -> impl Responder {
+    let id = path.into_inner();
+    let format = match request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .and_then(GpsFormat::from_content_type)
+    {
+        Some(format) => format,
+        None => return HttpResponse::UnsupportedMediaType().finish(),
+    };
+    let waypoints = match format.read(&body) {
+        Ok(waypoints) => waypoints,
+        Err(e) => {
+            error!("Failed to read approach waypoints in import_approach_waypoints(): {e}");
+            return HttpResponse::BadRequest().body(e.to_string());
+        }
+    };
+    if let Ok(mut conn) = conn().await {
+        if let Some(response) = check_editor(&mut conn, editor.editor_id).await {
+            return response;
+        }
+        match replace_approach_waypoints(&mut conn, id, &waypoints, editor.editor_id).await {
+            Ok(Some(version)) => HttpResponse::Ok()
+                .insert_header(header::ETag(etag(version)))
+                .json(waypoints),
+            Ok(None) => HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("INSERT query failed in import_approach_waypoints()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in import_approach_waypoints()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Replace a route's approach waypoints, recording it in the route's history, and return the
+/// route's new version. Ok(None) if the route doesn't exist.
+async fn replace_approach_waypoints(
+    conn: &mut PgConnection,
+    route_id: i32,
+    waypoints: &[Waypoint],
+    editor_id: Option<i32>,
+) -> Result<Option<i32>, DatabaseError> {
+    let mut transaction = conn.begin().await?;
+    let Some(version) = query!(
+        "UPDATE routes SET version = version + 1 WHERE id = ($1) AND deleted_at IS NULL RETURNING version",
+        route_id
+    )
+    .fetch_optional(&mut transaction)
+    .await?
+    .map(|record| record.version) else {
+        return Ok(None);
+    };
+    query!(
+        "DELETE FROM approach_waypoints WHERE route_id = ($1)",
+        route_id
+    )
+    .execute(&mut transaction)
+    .await?;
+    for (position, waypoint) in waypoints.iter().enumerate() {
+        query!(
+            "INSERT INTO approach_waypoints (route_id, position, name, description, latitude, longitude) VALUES ($1, $2, $3, $4, $5, $6)",
+            route_id,
+            position as i32,
+            waypoint.name,
+            waypoint.description,
+            waypoint.latitude,
+            waypoint.longitude,
+        )
+        .execute(&mut transaction)
+        .await?;
+    }
+    if let Some(route) = select_route(&mut transaction, route_id).await? {
+        record_revision(
+            &mut transaction,
+            route_id,
+            editor_id,
+            RevisionAction::Approach,
+            Some(&route),
+            &route,
+        )
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(Some(version))
+}
+
+#[get("/{id}/approach")]
+async fn get_approach_waypoints(path: web::Path<i32>) -> impl Responder {
+    let id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        if let Ok(query_result) = query!(
+            r#"SELECT approach_waypoints.name as "name!", approach_waypoints.description, approach_waypoints.latitude as "latitude!", approach_waypoints.longitude as "longitude!"
+            FROM approach_waypoints JOIN routes ON routes.id = approach_waypoints.route_id
+            WHERE approach_waypoints.route_id = ($1) AND routes.deleted_at IS NULL ORDER BY approach_waypoints.position"#,
+            id,
+        )
+        .fetch_all(&mut conn)
+        .await
+        {
+            let waypoints = query_result
+                .into_iter()
+                .map(|record| {
+                    Waypoint::new(
+                        record.name,
+                        record.description,
+                        record.latitude,
+                        record.longitude,
+                    )
+                })
+                .collect::<Vec<Waypoint>>();
+            HttpResponse::Ok().json(waypoints)
+        } else {
+            error!("SELECT query failed in get_approach_waypoints()");
+            HttpResponse::BadGateway().finish()
+        }
+    } else {
+        error!("Failed to connect to the database in get_approach_waypoints()");
+        HttpResponse::BadGateway().finish()
     }
 }
@@ -0,0 +1,153 @@
+/// module for searching routes, areas and climbers at once, handled by the API endpoints /search
+/// and /search/autocomplete
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::error::DatabaseError;
+use crate::page::Page;
+use crate::route::DifficultyRating;
+
+/// Number of suggestions when the query doesn't say
+pub const DEFAULT_SUGGESTIONS: i64 = 10;
+/// Most suggestions one query can ask for
+pub const MAX_SUGGESTIONS: i64 = 25;
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum SearchResultType {
+    Route,
+    Area,
+    Climber,
+}
+
+impl Display for SearchResultType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let display_str = match self {
+            Self::Route => "route",
+            Self::Area => "area",
+            Self::Climber => "climber",
+        };
+        write!(f, "{}", display_str)
+    }
+}
+
+impl FromStr for SearchResultType {
+    type Err = DatabaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "route" => Ok(Self::Route),
+            "area" => Ok(Self::Area),
+            "climber" => Ok(Self::Climber),
+            _ => Err(DatabaseError::FailedParseSearchResultType),
+        }
+    }
+}
+
+/// Query parameters for GET /search
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+pub struct SearchQuery {
+    /// Words to search for, in the syntax of a web search: "quoted phrases", `or` and `-excluded`
+    pub q: String,
+    /// Only results of this type
+    #[serde(rename = "type")]
+    pub result_type: Option<SearchResultType>,
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+impl SearchQuery {
+    pub fn page(&self) -> Page {
+        Page {
+            page: self.page,
+            per_page: self.per_page,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct SearchResult {
+    #[serde(rename = "type")]
+    pub result_type: SearchResultType,
+    pub id: i32,
+    /// The route's or area's name, or the climber's username
+    pub name: String,
+    /// The text that matched, HTML-escaped, with the matching words in <b></b>
+    pub headline: String,
+    /// Higher is a better match
+    pub rank: f32,
+}
+
+/// Body of GET /search, best matches first
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SearchResults {
+    pub results: Vec<SearchResult>,
+    pub page: i64,
+    pub per_page: i64,
+    /// The next page number, if there are more results
+    pub next_page: Option<i64>,
+}
+
+/// Query parameters for GET /search/autocomplete
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+pub struct AutocompleteQuery {
+    /// The start of a route or area name, ignoring case and accents
+    pub q: String,
+    /// Only suggestions of this type
+    #[serde(rename = "type")]
+    pub result_type: Option<SearchResultType>,
+    pub limit: Option<i64>,
+}
+
+impl AutocompleteQuery {
+    /// The number of suggestions to return, clamped to sensible values
+    pub fn limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_SUGGESTIONS)
+            .clamp(1, MAX_SUGGESTIONS)
+    }
+}
+
+/// A route or area whose name starts with what was typed
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct Suggestion {
+    #[serde(rename = "type")]
+    pub result_type: SearchResultType,
+    pub id: i32,
+    pub name: String,
+    /// The route's grade. Areas don't have one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<DifficultyRating>,
+    /// The names of the areas the route or area is in, outermost first
+    pub breadcrumb: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_result_type_round_trip() {
+        for result_type in [
+            SearchResultType::Route,
+            SearchResultType::Area,
+            SearchResultType::Climber,
+        ] {
+            assert_eq!(
+                SearchResultType::from_str(&result_type.to_string()).unwrap(),
+                result_type
+            );
+        }
+        assert!(SearchResultType::from_str("gym").is_err());
+    }
+
+    #[test]
+    fn test_autocomplete_limit() {
+        let mut query = AutocompleteQuery::default();
+        assert_eq!(query.limit(), DEFAULT_SUGGESTIONS);
+        query.limit = Some(0);
+        assert_eq!(query.limit(), 1);
+        query.limit = Some(1000);
+        assert_eq!(query.limit(), MAX_SUGGESTIONS);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::error::DatabaseError;
+use crate::pg::conn;
+use crate::route::DifficultyRating;
+use crate::search::{
+    AutocompleteQuery, SearchQuery, SearchResult, SearchResultType, SearchResults, Suggestion,
+};
+use actix_web::{get, web, HttpResponse, Responder};
+use log::error;
+/// Functions for the API endpoint /search
+use sqlx::{self, postgres::PgConnection, query};
+use std::str::FromStr;
+
+/// Routes, areas and climbers matching a search, best first, along with whether there is another
+/// page. Names match on their words or, to forgive typos, on how closely the search's trigrams
+/// match some of their words. Routes also match on their first ascent party and the gear and anchor
+/// notes of their pitches, through the `search_vector` that triggers keep up to date, so that each
+/// way of matching is a scan of its own index.
+async fn select_search_results(
+    conn: &mut PgConnection,
+    query: &SearchQuery,
+) -> Result<(Vec<SearchResult>, bool), DatabaseError> {
+    let (_, per_page) = query.page().page_and_size();
+    let records = query!(
+        r#"WITH search AS (SELECT websearch_to_tsquery('english', $1) as tsquery)
+        SELECT result_type as "result_type!", id as "id!", name as "name!", headline as "headline!", rank as "rank!" FROM (
+            SELECT 'route' as result_type, routes.id, routes.name,
+            ts_headline('english', escape_html(concat_ws(' — ', routes.name, NULLIF(concat_ws(' ', routes.first_ascent_party,
+                (SELECT string_agg(concat_ws(' ', gear_notes, anchor), ' ' ORDER BY position, id) FROM pitches WHERE route_id = routes.id)), ''))), search.tsquery) as headline,
+            ts_rank(routes.search_vector, search.tsquery) + word_similarity($1, routes.name) as rank
+            FROM routes CROSS JOIN search
+            WHERE routes.deleted_at IS NULL AND routes.archived_at IS NULL AND ($2::text IS NULL OR $2 = 'route')
+            AND (routes.search_vector @@ search.tsquery OR $1 <% routes.name)
+            UNION ALL
+            SELECT 'area', areas.id, areas.name, ts_headline('english', escape_html(areas.name), search.tsquery),
+            ts_rank(to_tsvector('english', areas.name), search.tsquery) + word_similarity($1, areas.name)
+            FROM areas CROSS JOIN search
+            WHERE ($2::text IS NULL OR $2 = 'area')
+            AND (to_tsvector('english', areas.name) @@ search.tsquery OR $1 <% areas.name)
+            UNION ALL
+            SELECT 'climber', climbers.id, climbers.username, ts_headline('english', escape_html(climbers.username), search.tsquery),
+            ts_rank(to_tsvector('english', climbers.username), search.tsquery) + word_similarity($1, climbers.username)
+            FROM climbers CROSS JOIN search
+            WHERE climbers.deleted_at IS NULL AND ($2::text IS NULL OR $2 = 'climber')
+            AND (to_tsvector('english', climbers.username) @@ search.tsquery OR $1 <% climbers.username)
+        ) results
+        ORDER BY rank DESC, result_type, id
+        LIMIT ($3) OFFSET ($4)"#,
+        query.q,
+        query.result_type.map(|result_type| result_type.to_string()),
+        per_page + 1,
+        query.page().offset(),
+    )
+    .fetch_all(conn)
+    .await?;
+    let more = records.len() as i64 > per_page;
+    let results = records
+        .into_iter()
+        .take(per_page as usize)
+        .map(|record| {
+            Ok(SearchResult {
+                result_type: SearchResultType::from_str(&record.result_type)?,
+                id: record.id,
+                name: record.name,
+                headline: record.headline,
+                rank: record.rank,
+            })
+        })
+        .collect::<Result<Vec<SearchResult>, DatabaseError>>()?;
+    Ok((results, more))
+}
+
+/// Routes and areas whose names start with the query, ignoring case and accents, in the order of
+/// their names. Names are compared lowercased and unaccented, with the "C" collation so that they
+/// are ordered by code point and every name starting with the query sorts between the query and the
+/// query followed by the highest code point. That range is a scan of the name's prefix index.
+async fn select_suggestions(
+    conn: &mut PgConnection,
+    query: &AutocompleteQuery,
+) -> Result<Vec<Suggestion>, DatabaseError> {
+    query!(
+        r#"SELECT result_type as "result_type!", id as "id!", name as "name!", difficulty, breadcrumb as "breadcrumb!" FROM (
+            (SELECT 'route' as result_type, routes.id, routes.name, routes.difficulty,
+            lower(immutable_unaccent(routes.name)) COLLATE "C" as sort_key,
+            COALESCE((
+                WITH RECURSIVE ancestors AS (
+                    SELECT id, parent_id, name, 0 as depth FROM areas WHERE id = routes.area_id
+                    UNION SELECT areas.id, areas.parent_id, areas.name, ancestors.depth + 1 FROM areas JOIN ancestors ON areas.id = ancestors.parent_id
+                ) SELECT array_agg(name ORDER BY depth DESC) FROM ancestors
+            ), '{}') as breadcrumb
+            FROM routes
+            WHERE routes.deleted_at IS NULL AND routes.archived_at IS NULL AND ($2::text IS NULL OR $2 = 'route')
+            AND lower(immutable_unaccent(routes.name)) COLLATE "C" >= lower(immutable_unaccent($1))
+            AND lower(immutable_unaccent(routes.name)) COLLATE "C" < lower(immutable_unaccent($1)) || chr(1114111)
+            ORDER BY sort_key, id LIMIT ($3))
+            UNION ALL
+            (SELECT 'area', areas.id, areas.name, NULL,
+            lower(immutable_unaccent(areas.name)) COLLATE "C" as sort_key,
+            COALESCE((
+                WITH RECURSIVE ancestors AS (
+                    SELECT id, parent_id, name, 0 as depth FROM areas ancestor WHERE ancestor.id = areas.parent_id
+                    UNION SELECT ancestor.id, ancestor.parent_id, ancestor.name, ancestors.depth + 1 FROM areas ancestor JOIN ancestors ON ancestor.id = ancestors.parent_id
+                ) SELECT array_agg(name ORDER BY depth DESC) FROM ancestors
+            ), '{}')
+            FROM areas
+            WHERE ($2::text IS NULL OR $2 = 'area')
+            AND lower(immutable_unaccent(areas.name)) COLLATE "C" >= lower(immutable_unaccent($1))
+            AND lower(immutable_unaccent(areas.name)) COLLATE "C" < lower(immutable_unaccent($1)) || chr(1114111)
+            ORDER BY sort_key, id LIMIT ($3))
+        ) suggestions
+        ORDER BY sort_key, result_type, id
+        LIMIT ($3)"#,
+        query.q,
+        query.result_type.map(|result_type| result_type.to_string()),
+        query.limit(),
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|record| {
+        Ok(Suggestion {
+            result_type: SearchResultType::from_str(&record.result_type)?,
+            id: record.id,
+            name: record.name,
+            difficulty: record
+                .difficulty
+                .map(|difficulty| DifficultyRating::from_str(&difficulty))
+                .transpose()?,
+            breadcrumb: record.breadcrumb,
+        })
+    })
+    .collect()
+}
+
+/// Search route, area and climber names at once, best matches first
+#[get("")]
+async fn search(query: web::Query<SearchQuery>) -> impl Responder {
+    let (page, per_page) = query.page().page_and_size();
+    if query.q.trim().is_empty() {
+        return HttpResponse::BadRequest().body(DatabaseError::SearchQueryEmpty.to_string());
+    }
+    if let Ok(mut conn) = conn().await {
+        if let Ok((results, more)) = select_search_results(&mut conn, &query).await {
+            HttpResponse::Ok().json(SearchResults {
+                results,
+                page,
+                per_page,
+                next_page: more.then_some(page + 1),
+            })
+        } else {
+            error!("SELECT query failed in search()");
+            HttpResponse::BadGateway().finish()
+        }
+    } else {
+        error!("Failed to connect to the database in search()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Suggest routes and areas as their names are typed
+#[get("/autocomplete")]
+async fn autocomplete(query: web::Query<AutocompleteQuery>) -> impl Responder {
+    if query.q.trim().is_empty() {
+        return HttpResponse::BadRequest().body(DatabaseError::SearchQueryEmpty.to_string());
+    }
+    if let Ok(mut conn) = conn().await {
+        if let Ok(suggestions) = select_suggestions(&mut conn, &query).await {
+            HttpResponse::Ok().json(suggestions)
+        } else {
+            error!("SELECT query failed in autocomplete()");
+            HttpResponse::BadGateway().finish()
+        }
+    } else {
+        error!("Failed to connect to the database in autocomplete()");
+        HttpResponse::BadGateway().finish()
+    }
+}
@@ -0,0 +1,72 @@
+/// module for storing uploaded files, such as photos, outside of the database
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+pub const DEFAULT_STORAGE_DIR: &str = "uploads";
+
+/// Somewhere to keep uploaded files by key. Keys are relative paths like "photos/12.jpg".
+pub trait FileStorage: Send + Sync {
+    fn save(&self, key: &str, data: &[u8]) -> io::Result<()>;
+    fn load(&self, key: &str) -> io::Result<Vec<u8>>;
+    fn remove(&self, key: &str) -> io::Result<()>;
+}
+
+/// Keeps files in a directory on the local filesystem
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl FileStorage for LocalStorage {
+    fn save(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        let path = self.root.join(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, data)
+    }
+
+    fn load(&self, key: &str) -> io::Result<Vec<u8>> {
+        fs::read(self.root.join(key))
+    }
+
+    fn remove(&self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.root.join(key)) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
+
+/// Storage backend for the app, which for now is always a LocalStorage in the STORAGE_DIR
+/// environment variable, or DEFAULT_STORAGE_DIR if it is missing
+pub fn storage() -> Arc<dyn FileStorage> {
+    let root = env::var("STORAGE_DIR").unwrap_or_else(|_| DEFAULT_STORAGE_DIR.to_string());
+    Arc::new(LocalStorage::new(root))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_storage_round_trip() {
+        let root = env::temp_dir().join(format!("restful-climbing-{}", std::process::id()));
+        let storage = LocalStorage::new(&root);
+        storage.save("photos/1.png", b"not really a png").unwrap();
+        assert_eq!(storage.load("photos/1.png").unwrap(), b"not really a png");
+        storage.remove("photos/1.png").unwrap();
+        assert!(storage.load("photos/1.png").is_err());
+        // removing something that is already gone is fine
+        assert!(storage.remove("photos/1.png").is_ok());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -1,7 +1,33 @@
 use crate::{
-    climb::{Climb, Rating, Review},
-    climber::{Climber, NumberClimbers},
-    route::{DifficultyRating, NumberRoutes, Route},
+    area::Area,
+    climb::{Climb, Review, Reviews},
+    climber::{
+        Climber, ClimberStats, Feed, FeedEventKind, Logbook, NumberClimbers, Privacy, Profile,
+        Visibility,
+    },
+    comment::{Comment, CommentThreads, MAX_COMMENT_DEPTH},
+    competition::{
+        Attempt, Category, Competition, CompetitionResults, Problem, Registration, Scorecard,
+        ScoringFormat,
+    },
+    condition::{ConditionReport, ConditionStatus},
+    error::DatabaseError,
+    etag::etag,
+    gym::{CurrentSet, Gym, RouteSet, Wall},
+    leaderboard::{Leaderboard, LeaderboardMetric},
+    moderation::{
+        CommentModerationQueue, FlagReason, ModeratedComment, ModeratedReview, Moderation,
+        ModerationAction, ModerationQueue, ReviewFlag,
+    },
+    photo::{Photo, PhotoFormat, Topo, TopoLine, TopoPoint},
+    recommendation::Recommendations,
+    revision::{RevisionAction, RouteHistory},
+    route::{
+        DifficultyRating, Discipline, GradeScale, NumberRoutes, Pitch, Route, RouteMerge,
+        RouteMetadata, RouteMetadataPatch, RoutePatch,
+    },
+    search::{SearchResultType, SearchResults, Suggestion},
+    ticklist::{Priority, TicklistEntry},
 };
 
 use super::*;
@@ -9,30 +35,67 @@ use actix_web::{
     http::{self},
     test,
 };
-use chrono::NaiveDate;
-use serde::Serialize;
+use chrono::{Local, NaiveDate};
 
 fn test_route() -> Route {
     Route::new(
         None,
         "funky monkey".to_string(),
+        Discipline::Sport,
         DifficultyRating::Rating59,
         123.45,
         52.310,
     )
 }
 
+/// Make a climber a moderator directly in the database, as only moderators can make moderators
+async fn make_moderator(id: i32) {
+    let mut conn = crate::pg::conn().await.unwrap();
+    sqlx::query!(
+        "UPDATE climbers SET is_moderator = true WHERE id = ($1)",
+        id
+    )
+    .execute(&mut conn)
+    .await
+    .unwrap();
+}
+
+/// The climber the tests make their route edits as, since every route edit needs an editor
+async fn route_editor() -> i32 {
+    let mut conn = crate::pg::conn().await.unwrap();
+    let editor = sqlx::query!(
+        "SELECT id FROM climbers WHERE username = 'testeditor' AND deleted_at IS NULL ORDER BY id LIMIT 1"
+    )
+    .fetch_optional(&mut conn)
+    .await
+    .unwrap();
+    match editor {
+        Some(editor) => editor.id,
+        None => {
+            sqlx::query!("INSERT INTO climbers (username) VALUES ('testeditor') RETURNING id")
+                .fetch_one(&mut conn)
+                .await
+                .unwrap()
+                .id
+        }
+    }
+}
+
 #[actix_web::test]
 async fn test_add_get_delete_route() {
     let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
 
     // Add route
     let req = test::TestRequest::post()
-        .uri("/routes")
+        .uri(&format!("/routes?editor_id={editor_id}"))
         .set_json(test_route())
         .to_request();
     let resp = test::call_service(&app, req).await;
-    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let id = body.id.unwrap();
+    println!("returned id for funky monkey: {id}");
 
     // Get route
     let one_route = NumberRoutes { number_routes: 1 };
@@ -44,8 +107,6 @@ async fn test_add_get_delete_route() {
     assert_eq!(resp.status(), http::StatusCode::OK);
     let body: Vec<Route> = test::read_body_json(resp).await;
     assert_eq!(body.len(), 1);
-    let id = body[0].id.unwrap();
-    println!("returned id for funky monkey: {id}");
 
     // Get route by id
     let req = test::TestRequest::get()
@@ -60,11 +121,11 @@ async fn test_add_get_delete_route() {
     let mut updated_route = test_route();
     updated_route.difficulty = DifficultyRating::Rating512;
     let req = test::TestRequest::put()
-        .uri(&format!("/routes/{id}"))
+        .uri(&format!("/routes/{id}?editor_id={editor_id}"))
         .set_json(updated_route)
         .to_request();
     let resp = test::call_service(&app, req).await;
-    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
 
     // Get route by id again to verify that the change worked
     let req = test::TestRequest::get()
@@ -79,12 +140,40 @@ async fn test_add_get_delete_route() {
     );
     assert_eq!(body.difficulty, DifficultyRating::Rating512);
 
-    // Delete route
-    let req = test::TestRequest::delete()
+    // Patch just the name of the route
+    let req = test::TestRequest::patch()
+        .uri(&format!("/routes/{id}?editor_id={editor_id}"))
+        .insert_header((http::header::CONTENT_TYPE, "application/merge-patch+json"))
+        .set_payload(r#"{"name": "funkier monkey"}"#)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+
+    // Get route by id again to verify that only the name changed
+    let req = test::TestRequest::get()
         .uri(&format!("/routes/{id}"))
         .to_request();
     let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Route = test::read_body_json(resp).await;
+    assert_eq!(body.name, "funkier monkey");
+    assert_eq!(body.difficulty, DifficultyRating::Rating512);
+
+    // An empty patch is rejected
+    let req = test::TestRequest::patch()
+        .uri(&format!("/routes/{id}?editor_id={editor_id}"))
+        .insert_header((http::header::CONTENT_TYPE, "application/merge-patch+json"))
+        .set_payload("{}")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+
+    // Delete route
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/{id}?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
 
     // Get route by id again to verify that the deletion worked
     let req = test::TestRequest::get()
@@ -94,6 +183,314 @@ async fn test_add_get_delete_route() {
     assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
 }
 
+#[actix_web::test]
+async fn test_delete_restore_route() {
+    let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
+
+    // Add route
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(test_route())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let id = body.id.unwrap();
+
+    // Restoring a route that isn't deleted does nothing
+    let req = test::TestRequest::post()
+        .uri(&format!("/admin/routes/{id}/restore?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+
+    // Delete route
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/{id}?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+
+    // Restore route
+    let req = test::TestRequest::post()
+        .uri(&format!("/admin/routes/{id}/restore?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+
+    // Get route by id to verify that the restore worked
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+
+    // Give it a photo, whose files are purged along with it
+    let png = PhotoFormat::Png
+        .encode(&image::DynamicImage::ImageRgb8(image::RgbImage::new(
+            16, 16,
+        )))
+        .unwrap();
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes/{id}/photos"))
+        .insert_header((http::header::CONTENT_TYPE, "image/png"))
+        .set_payload(png)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Photo = test::read_body_json(resp).await;
+    let photo_key = Photo::file_key(body.id, PhotoFormat::Png);
+    let storage = crate::storage::storage();
+    assert!(storage.load(&photo_key).is_ok());
+
+    // Delete route again, and backdate the deletion past the retention period
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/{id}?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let mut conn = crate::pg::conn().await.unwrap();
+    sqlx::query!(
+        "UPDATE routes SET deleted_at = current_timestamp - interval '1 year' WHERE id = ($1)",
+        id
+    )
+    .execute(&mut conn)
+    .await
+    .unwrap();
+
+    // Purge, after which the route can no longer be restored
+    let rows_purged = crate::purge::purge_deleted(
+        &mut conn,
+        crate::purge::DEFAULT_RETENTION_DAYS,
+        crate::storage::storage(),
+    )
+    .await
+    .unwrap();
+    assert!(rows_purged >= 2);
+    assert!(storage.load(&photo_key).is_err());
+    let req = test::TestRequest::post()
+        .uri(&format!("/admin/routes/{id}/restore?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_purge_keeps_routes_with_live_climbs() {
+    let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
+
+    // Add route
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(test_route())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let route_id = body.id.unwrap();
+
+    // Add a climber who climbs it
+    let req = test::TestRequest::post()
+        .uri("/climbers")
+        .set_json(Climber::new(None, "purgesurvivor".to_string()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Climber = test::read_body_json(resp).await;
+    let climber_id = body.id.unwrap();
+    let req = test::TestRequest::post()
+        .uri(&format!("/climbers/{climber_id}/{route_id}"))
+        .set_json(Review::new(
+            10,
+            "Still counts".to_string(),
+            NaiveDate::from_ymd_opt(2023, 4, 2).unwrap(),
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+
+    // Delete route, and backdate the deletion past the retention period
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/{route_id}?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let mut conn = crate::pg::conn().await.unwrap();
+    sqlx::query!(
+        "UPDATE routes SET deleted_at = current_timestamp - interval '1 year' WHERE id = ($1)",
+        route_id
+    )
+    .execute(&mut conn)
+    .await
+    .unwrap();
+
+    // Purging keeps the route and the climber's climb of it, so it can still be restored
+    crate::purge::purge_deleted(
+        &mut conn,
+        crate::purge::DEFAULT_RETENTION_DAYS,
+        crate::storage::storage(),
+    )
+    .await
+    .unwrap();
+    let climbs = sqlx::query!(
+        r#"SELECT count(*) as "count!" FROM climbs WHERE climber_id = ($1) AND route_id = ($2)"#,
+        climber_id,
+        route_id
+    )
+    .fetch_one(&mut conn)
+    .await
+    .unwrap();
+    assert_eq!(climbs.count, 1);
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/admin/routes/{route_id}/restore?editor_id={editor_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let req = test::TestRequest::get()
+        .uri(&format!("/climbers/{climber_id}/{route_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+
+    // Delete route
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/{route_id}?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+
+    // Delete climber by id
+    let req = test::TestRequest::delete()
+        .uri(&format!("/climbers/{climber_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+}
+
+#[actix_web::test]
+async fn test_route_etags() {
+    let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
+
+    // Add route
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(test_route())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let id = body.id.unwrap();
+
+    // Get route by id, and keep its ETag
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let etag = resp.headers().get(http::header::ETAG).unwrap().clone();
+
+    // A conditional get with the same ETag is not modified
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{id}"))
+        .insert_header((http::header::IF_NONE_MATCH, etag.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_MODIFIED);
+
+    // Update route with the ETag, which changes it
+    let req = test::TestRequest::put()
+        .uri(&format!("/routes/{id}?editor_id={editor_id}"))
+        .insert_header((http::header::IF_MATCH, etag.clone()))
+        .set_json(test_route())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let new_etag = resp.headers().get(http::header::ETAG).unwrap().clone();
+    assert_ne!(etag, new_etag);
+
+    // Updating or deleting with the old ETag fails
+    let req = test::TestRequest::patch()
+        .uri(&format!("/routes/{id}?editor_id={editor_id}"))
+        .insert_header((http::header::IF_MATCH, etag.clone()))
+        .set_json(RoutePatch {
+            name: Some("clobbered".to_string()),
+            ..Default::default()
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::PRECONDITION_FAILED);
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/{id}?editor_id={editor_id}"))
+        .insert_header((http::header::IF_MATCH, etag))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::PRECONDITION_FAILED);
+
+    // A conditional get with the old ETag gets the route
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{id}"))
+        .insert_header((http::header::IF_NONE_MATCH, "\"0\""))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Route = test::read_body_json(resp).await;
+    assert_eq!(body.name, "funky monkey");
+
+    // Delete route with the new ETag
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/{id}?editor_id={editor_id}"))
+        .insert_header((http::header::IF_MATCH, new_etag))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+}
+
+#[actix_web::test]
+async fn test_mutating_missing_rows_not_found() {
+    let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
+
+    let req = test::TestRequest::put()
+        .uri(&format!("/routes/-1?editor_id={editor_id}"))
+        .set_json(test_route())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/-1?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+
+    let req = test::TestRequest::delete().uri("/climbers/-1").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+
+    let review = Review::new(
+        5,
+        "Who climbed this?".to_string(),
+        NaiveDate::from_ymd_opt(2023, 4, 2).unwrap(),
+    );
+    let req = test::TestRequest::put()
+        .uri("/climbers/-1/-1")
+        .set_json(review)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+
+    let req = test::TestRequest::delete()
+        .uri("/climbers/-1/-1")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+}
+
 #[actix_web::test]
 async fn test_adding_route_fails() {
     let app = test::init_service(app!()).await;
@@ -114,7 +511,7 @@ async fn test_adding_route_fails() {
 //             .set_json(route)
 //             .to_request();
 //         let resp = test::call_service(&app, req).await;
-//         assert_eq!(resp.status(), http::StatusCode::OK);
+//         assert_eq!(resp.status(), http::StatusCode::CREATED);
 //     }
 // }
 
@@ -143,166 +540,3527 @@ async fn test_get_routes_with_specific_number() {
 }
 
 #[actix_web::test]
-async fn test_add_get_delete_climber() {
+async fn test_get_routes_with_filter() {
     let app = test::init_service(app!()).await;
+    let req = test::TestRequest::get()
+        .uri("/routes?difficulty=Rating512&min_latitude=-90.0&max_longitude=180.0")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Vec<Route> = test::read_body_json(resp).await;
+    assert!(body
+        .iter()
+        .all(|route| route.difficulty == DifficultyRating::Rating512));
+}
 
-    let test_climber = Climber {
-        id: None,
-        username: "testclimber123".to_string(),
-    };
+#[actix_web::test]
+async fn test_route_discipline_grades() {
+    let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
 
-    // Add climber
+    // A boulder problem can't have a YDS grade
+    let mut route = test_route();
+    route.discipline = Discipline::Boulder;
     let req = test::TestRequest::post()
-        .uri("/climbers")
-        .set_json(test_climber)
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(&route)
         .to_request();
     let resp = test::call_service(&app, req).await;
-    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
 
-    // Get climber
-    let one_climber = NumberClimbers { number_climbers: 1 };
-    let req = test::TestRequest::get()
-        .uri("/climbers")
-        .set_json(one_climber)
+    // but it can have a V grade
+    route.difficulty = DifficultyRating::RatingV4;
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(&route)
         .to_request();
     let resp = test::call_service(&app, req).await;
-    assert_eq!(resp.status(), http::StatusCode::OK);
-    let body: Vec<Climber> = test::read_body_json(resp).await;
-    assert_eq!(body.len(), 1);
-    let id = body[0].id.unwrap();
-    println!("returned id for test climber: {id}");
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let id = body.id.unwrap();
 
-    // Get climber by id
-    let req = test::TestRequest::get()
-        .uri(&format!("/climbers/{id}"))
+    // Patching just the discipline is checked against the existing grade
+    let req = test::TestRequest::patch()
+        .uri(&format!("/routes/{id}?editor_id={editor_id}"))
+        .set_json(RoutePatch {
+            discipline: Some(Discipline::Ice),
+            ..Default::default()
+        })
         .to_request();
     let resp = test::call_service(&app, req).await;
-    assert_eq!(resp.status(), http::StatusCode::OK);
-    let body: Climber = test::read_body_json(resp).await;
-    println!("returned climbs for test climber:\n{:?}", body);
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
 
-    // Delete climber by id
-    let req = test::TestRequest::delete()
-        .uri(&format!("/climbers/{id}"))
+    // Filter the listing by discipline
+    let req = test::TestRequest::get()
+        .uri("/routes?discipline=Boulder")
         .to_request();
     let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Vec<Route> = test::read_body_json(resp).await;
+    assert!(body
+        .iter()
+        .all(|route| route.discipline == Discipline::Boulder));
 
-    // Get climber by id again to verify that the deletion worked
-    let req = test::TestRequest::get()
-        .uri(&format!("/climbers/{id}"))
+    // Delete route
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/{id}?editor_id={editor_id}"))
         .to_request();
     let resp = test::call_service(&app, req).await;
-    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
 }
 
 #[actix_web::test]
-async fn test_post_get_put_delete_climb() {
+async fn test_multi_pitch_route() {
     let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
 
-    let test_climber = Climber {
-        id: None,
-        username: "testclimber456".to_string(),
-    };
-
-    // Add climber
+    // The route's grade and length come from its pitches
+    let mut route = test_route();
+    route.discipline = Discipline::Trad;
+    route.pitches = vec![
+        Pitch::new(DifficultyRating::Rating510, Some(30)),
+        Pitch::new(DifficultyRating::Rating511, Some(40)),
+    ];
     let req = test::TestRequest::post()
-        .uri("/climbers")
-        .set_json(test_climber)
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(&route)
         .to_request();
     let resp = test::call_service(&app, req).await;
-    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let route_id = body.id.unwrap();
+    assert_eq!(body.difficulty, DifficultyRating::Rating511);
+    assert_eq!(body.number_pitches, 2);
+    assert_eq!(body.length_meters, Some(70));
 
-    // Get climber id
-    let one_climber = NumberClimbers { number_climbers: 1 };
+    // Route detail has the pitches in order
     let req = test::TestRequest::get()
-        .uri("/climbers")
-        .set_json(one_climber)
+        .uri(&format!("/routes/{route_id}"))
         .to_request();
     let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), http::StatusCode::OK);
-    let body: Vec<Climber> = test::read_body_json(resp).await;
-    assert_eq!(body.len(), 1);
-    let climber_id = body[0].id.unwrap();
-    println!("returned climber_id for test climber: {climber_id}");
+    let body: Route = test::read_body_json(resp).await;
+    assert_eq!(body.pitches, route.pitches);
 
-    // Add route
-    let req = test::TestRequest::post()
-        .uri("/routes")
+    // Listings have the pitch count and total length, but not the pitches themselves
+    let req = test::TestRequest::get()
+        .uri("/routes?discipline=Trad")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Vec<Route> = test::read_body_json(resp).await;
+    let listed = body
+        .iter()
+        .find(|route| route.id == Some(route_id))
+        .unwrap();
+    assert_eq!(listed.number_pitches, 2);
+    assert_eq!(listed.length_meters, Some(70));
+    assert!(listed.pitches.is_empty());
+
+    // What comes from the pitches can't be patched, but the rest can
+    for patch in [
+        RoutePatch {
+            difficulty: Some(DifficultyRating::Rating59),
+            ..Default::default()
+        },
+        RoutePatch {
+            length_meters: Some(Some(10)),
+            ..Default::default()
+        },
+    ] {
+        let req = test::TestRequest::patch()
+            .uri(&format!("/routes/{route_id}?editor_id={editor_id}"))
+            .set_json(patch)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    }
+    let req = test::TestRequest::patch()
+        .uri(&format!("/routes/{route_id}?editor_id={editor_id}"))
+        .set_json(RoutePatch {
+            name: Some("Funky Monkey Direct".to_string()),
+            ..Default::default()
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+
+    // Add climber
+    let req = test::TestRequest::post()
+        .uri("/climbers")
+        .set_json(Climber::new(None, "multipitchclimber".to_string()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Climber = test::read_body_json(resp).await;
+    let climber_id = body.id.unwrap();
+
+    // A climber can't lead a pitch the route doesn't have
+    let mut review = Review::new(
+        8,
+        "Swapped leads".to_string(),
+        NaiveDate::from_ymd_opt(2023, 6, 10).unwrap(),
+    );
+    review.led_pitches = vec![3];
+    let req = test::TestRequest::post()
+        .uri(&format!("/climbers/{climber_id}/{route_id}"))
+        .set_json(&review)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+
+    review.led_pitches = vec![2];
+    let req = test::TestRequest::post()
+        .uri(&format!("/climbers/{climber_id}/{route_id}"))
+        .set_json(&review)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let req = test::TestRequest::get()
+        .uri(&format!("/climbers/{climber_id}/{route_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Climb = test::read_body_json(resp).await;
+    assert_eq!(body.review.led_pitches, vec![2]);
+
+    // Delete route
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/{route_id}?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+
+    // Delete climber by id
+    let req = test::TestRequest::delete()
+        .uri(&format!("/climbers/{climber_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+}
+
+#[actix_web::test]
+async fn test_gym_route_metadata() {
+    let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
+
+    // Add setter
+    let req = test::TestRequest::post()
+        .uri("/climbers")
+        .set_json(Climber::new(None, "routesetter".to_string()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Climber = test::read_body_json(resp).await;
+    let setter_id = body.id.unwrap();
+
+    let today = chrono::Local::now().date_naive();
+    let mut route = test_route();
+    route.metadata = RouteMetadata {
+        bolts: Some(8),
+        setter_id: Some(setter_id),
+        hold_color: Some("orange".to_string()),
+        set_date: Some(today - chrono::Duration::days(7)),
+        strip_date: Some(today + chrono::Duration::days(21)),
+        ..Default::default()
+    };
+
+    // A route can't be stripped before it is set
+    let mut stripped_early = test_route();
+    stripped_early.metadata.set_date = route.metadata.strip_date;
+    stripped_early.metadata.strip_date = route.metadata.set_date;
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(&stripped_early)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(&route)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let id = body.id.unwrap();
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Route = test::read_body_json(resp).await;
+    assert_eq!(body.metadata, route.metadata);
+
+    // It shows up as currently up until its strip date has passed
+    let req = test::TestRequest::get()
+        .uri("/routes?currently_up=true")
+        .set_json(NumberRoutes { number_routes: 100 })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Vec<Route> = test::read_body_json(resp).await;
+    assert!(body.iter().any(|route| route.id == Some(id)));
+
+    let req = test::TestRequest::patch()
+        .uri(&format!("/routes/{id}?editor_id={editor_id}"))
+        .set_json(RoutePatch {
+            metadata: RouteMetadataPatch {
+                strip_date: Some(Some(today - chrono::Duration::days(1))),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let req = test::TestRequest::get()
+        .uri("/routes?currently_up=true")
+        .set_json(NumberRoutes { number_routes: 100 })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Vec<Route> = test::read_body_json(resp).await;
+    assert!(body.iter().all(|route| route.id != Some(id)));
+
+    // Clearing the strip date with a null puts it back up, and leaves the other details alone
+    let req = test::TestRequest::patch()
+        .uri(&format!("/routes/{id}?editor_id={editor_id}"))
+        .set_json(serde_json::json!({ "strip_date": null }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Route = test::read_body_json(resp).await;
+    assert_eq!(body.metadata.strip_date, None);
+    assert_eq!(body.metadata.setter_id, route.metadata.setter_id);
+    assert_eq!(body.metadata.hold_color, route.metadata.hold_color);
+    let req = test::TestRequest::get()
+        .uri("/routes?currently_up=true")
+        .set_json(NumberRoutes { number_routes: 100 })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Vec<Route> = test::read_body_json(resp).await;
+    assert!(body.iter().any(|route| route.id == Some(id)));
+
+    // Two patches that are each fine alone but would strip it before it is set can't both apply
+    let patch = |body: serde_json::Value| {
+        test::TestRequest::patch()
+            .uri(&format!("/routes/{id}?editor_id={editor_id}"))
+            .set_json(body)
+            .to_request()
+    };
+    let (first, second) = tokio::join!(
+        test::call_service(
+            &app,
+            patch(serde_json::json!({ "strip_date": today + chrono::Duration::days(1) }))
+        ),
+        test::call_service(
+            &app,
+            patch(serde_json::json!({ "set_date": today + chrono::Duration::days(2) }))
+        )
+    );
+    let mut statuses = vec![first.status(), second.status()];
+    statuses.sort();
+    assert_eq!(
+        statuses,
+        vec![http::StatusCode::NO_CONTENT, http::StatusCode::BAD_REQUEST]
+    );
+
+    // The setter has to be a climber
+    let req = test::TestRequest::patch()
+        .uri(&format!("/routes/{id}?editor_id={editor_id}"))
+        .set_json(RoutePatch {
+            metadata: RouteMetadataPatch {
+                setter_id: Some(Some(-1)),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+
+    // Delete route
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/{id}?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+
+    // Delete setter
+    let req = test::TestRequest::delete()
+        .uri(&format!("/climbers/{setter_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+}
+
+#[actix_web::test]
+async fn test_gym_walls_and_route_sets() {
+    let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
+    let today = chrono::Local::now().date_naive();
+
+    // Add gym with two walls
+    let req = test::TestRequest::post()
+        .uri("/gyms")
+        .set_json(Gym::new(None, "The Boulder Barn".to_string()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Gym = test::read_body_json(resp).await;
+    let gym_id = body.id.unwrap();
+    let mut wall_ids = Vec::new();
+    for name in ["cave", "slab"] {
+        let req = test::TestRequest::post()
+            .uri(&format!("/gyms/{gym_id}/walls"))
+            .set_json(Wall::new(None, None, name.to_string()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+        let body: Wall = test::read_body_json(resp).await;
+        assert_eq!(body.gym_id, Some(gym_id));
+        wall_ids.push(body.id.unwrap());
+    }
+    let req = test::TestRequest::get()
+        .uri(&format!("/gyms/{gym_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Gym = test::read_body_json(resp).await;
+    assert_eq!(body.walls.len(), 2);
+
+    // Add a route set to the cave
+    let req = test::TestRequest::post()
+        .uri(&format!("/walls/{}/sets", wall_ids[0]))
+        .set_json(RouteSet {
+            id: None,
+            wall_id: None,
+            name: Some("comp set".to_string()),
+            set_date: today - chrono::Duration::days(7),
+            strip_date: Some(today + chrono::Duration::days(21)),
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: RouteSet = test::read_body_json(resp).await;
+    let route_set_id = body.id.unwrap();
+
+    // A route in the set goes on its wall, with its dates
+    let mut route = test_route();
+    route.discipline = Discipline::Boulder;
+    route.difficulty = DifficultyRating::RatingV3;
+    route.metadata.hold_color = Some("purple".to_string());
+    route.metadata.route_set_id = Some(route_set_id);
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(&route)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let current_route_id = body.id.unwrap();
+    assert_eq!(body.metadata.wall_id, Some(wall_ids[0]));
+    assert_eq!(
+        body.metadata.set_date,
+        Some(today - chrono::Duration::days(7))
+    );
+
+    // but not on a different wall
+    route.metadata.wall_id = Some(wall_ids[1]);
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(&route)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+
+    // A route on the cave that was stripped yesterday
+    route.metadata = RouteMetadata {
+        wall_id: Some(wall_ids[0]),
+        set_date: Some(today - chrono::Duration::days(60)),
+        strip_date: Some(today - chrono::Duration::days(1)),
+        ..Default::default()
+    };
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(&route)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let stripped_route_id = body.id.unwrap();
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/walls/{}/current", wall_ids[0]))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: CurrentSet = test::read_body_json(resp).await;
+    assert_eq!(body.route_set.unwrap().id, Some(route_set_id));
+    assert_eq!(
+        body.routes
+            .iter()
+            .map(|route| route.id.unwrap())
+            .collect::<Vec<i32>>(),
+        vec![current_route_id]
+    );
+
+    // Log a climb on the stripped route, then archive it
+    let req = test::TestRequest::post()
+        .uri("/climbers")
+        .set_json(Climber::new(None, "gymrat".to_string()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Climber = test::read_body_json(resp).await;
+    let climber_id = body.id.unwrap();
+    let req = test::TestRequest::post()
+        .uri(&format!("/climbers/{climber_id}/{stripped_route_id}"))
+        .set_json(Review::new(
+            6,
+            "Fun compression".to_string(),
+            today - chrono::Duration::days(3),
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+
+    let mut conn = crate::pg::conn().await.unwrap();
+    let routes_archived = crate::archive::archive_stripped_routes(&mut conn)
+        .await
+        .unwrap();
+    assert!(routes_archived >= 1);
+
+    // Archived routes are left out of listings unless asked for, but keep their climbs
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes?wall_id={}", wall_ids[0]))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Vec<Route> = test::read_body_json(resp).await;
+    assert!(body.iter().all(|route| route.id != Some(stripped_route_id)));
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/routes?wall_id={}&include_archived=true",
+            wall_ids[0]
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Vec<Route> = test::read_body_json(resp).await;
+    assert!(body
+        .iter()
+        .any(|route| route.id == Some(stripped_route_id) && route.archived));
+    let req = test::TestRequest::get()
+        .uri(&format!("/climbers/{climber_id}/logbook"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Logbook = test::read_body_json(resp).await;
+    assert_eq!(body.entries.len(), 1);
+    assert!(body.entries[0].route.archived);
+
+    // Delete routes and climber
+    for uri in [
+        format!("/routes/{current_route_id}?editor_id={editor_id}"),
+        format!("/routes/{stripped_route_id}?editor_id={editor_id}"),
+        format!("/climbers/{climber_id}"),
+    ] {
+        let req = test::TestRequest::delete().uri(&uri).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    }
+}
+
+#[actix_web::test]
+async fn test_export_routes() {
+    let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
+
+    // Add route
+    let mut route = test_route();
+    route.name = "gpx gecko".to_string();
+    route.latitude = -33.8;
+    route.longitude = 151.2;
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(route)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let id = body.id.unwrap();
+
+    // Export routes in a bounding box around the new route as GPX
+    let req = test::TestRequest::get()
+        .uri("/routes/export.gpx?min_latitude=-33.9&max_latitude=-33.7&min_longitude=151.1&max_longitude=151.3")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert_eq!(
+        resp.headers().get(http::header::CONTENT_TYPE).unwrap(),
+        "application/gpx+xml"
+    );
+    let body = test::read_body(resp).await;
+    let waypoints = crate::gps::read_gpx(std::str::from_utf8(&body).unwrap()).unwrap();
+    println!("exported GPX waypoints:\n{:?}", waypoints);
+    assert!(waypoints
+        .iter()
+        .any(|waypoint| waypoint.name == "gpx gecko (5.9)"));
+
+    // Export the same routes as KML
+    let req = test::TestRequest::get()
+        .uri("/routes/export.kml?min_latitude=-33.9&max_latitude=-33.7&min_longitude=151.1&max_longitude=151.3")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body = test::read_body(resp).await;
+    let waypoints = crate::gps::read_kml(std::str::from_utf8(&body).unwrap()).unwrap();
+    let latitude = waypoints
+        .iter()
+        .find(|waypoint| waypoint.name == "gpx gecko (5.9)")
+        .map(|waypoint| waypoint.latitude);
+    assert_eq!(latitude, Some(-33.8));
+
+    // Delete route
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/{id}?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+}
+
+#[actix_web::test]
+async fn test_import_get_approach_waypoints() {
+    let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
+
+    // Add route
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(test_route())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let route_id = body.id.unwrap();
+
+    // Import approach waypoints from a KML file
+    let kml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<kml xmlns="http://www.opengis.net/kml/2.2"><Document>
+  <Placemark><name>parking</name><Point><coordinates>52.300,123.40</coordinates></Point></Placemark>
+  <Placemark><name>trail junction</name><description>Go left</description><Point><coordinates>52.305,123.42,10</coordinates></Point></Placemark>
+</Document></kml>"#;
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/routes/{route_id}/approach?editor_id={editor_id}"
+        ))
+        .insert_header((
+            http::header::CONTENT_TYPE,
+            "application/vnd.google-earth.kml+xml",
+        ))
+        .set_payload(kml)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert_eq!(
+        resp.headers().get(http::header::ETAG).unwrap(),
+        &etag(2).to_string()
+    );
+
+    // The import is in the route's history
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{route_id}/history"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: RouteHistory = test::read_body_json(resp).await;
+    assert_eq!(body.revisions[0].action, RevisionAction::Approach);
+    assert_eq!(body.revisions[0].editor_id, Some(editor_id));
+    assert!(body.revisions[0].changes.is_empty());
+
+    // Get the waypoints back
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{route_id}/approach"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Vec<crate::gps::Waypoint> = test::read_body_json(resp).await;
+    println!("got back approach waypoints:\n{:?}", body);
+    assert_eq!(body.len(), 2);
+    assert_eq!(body[1].name, "trail junction");
+    assert_eq!(body[1].latitude, 123.42);
+
+    // Importing without a GPS content type is rejected
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes/{route_id}/approach"))
+        .set_payload(kml)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    // Delete route, after which its approach can't be replaced
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/{route_id}?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/routes/{route_id}/approach?editor_id={editor_id}"
+        ))
+        .insert_header((
+            http::header::CONTENT_TYPE,
+            "application/vnd.google-earth.kml+xml",
+        ))
+        .set_payload(kml)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_add_get_delete_climber() {
+    let app = test::init_service(app!()).await;
+
+    let test_climber = Climber::new(None, "testclimber123".to_string());
+
+    // Add climber
+    let req = test::TestRequest::post()
+        .uri("/climbers")
+        .set_json(test_climber)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    assert!(resp.headers().contains_key(http::header::LOCATION));
+    let body: Climber = test::read_body_json(resp).await;
+    let id = body.id.unwrap();
+    println!("returned id for test climber: {id}");
+
+    // Get climber
+    let one_climber = NumberClimbers { number_climbers: 1 };
+    let req = test::TestRequest::get()
+        .uri("/climbers")
+        .set_json(one_climber)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Vec<Climber> = test::read_body_json(resp).await;
+    assert_eq!(body.len(), 1);
+
+    // Get climber by id
+    let req = test::TestRequest::get()
+        .uri(&format!("/climbers/{id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Climber = test::read_body_json(resp).await;
+    println!("returned climbs for test climber:\n{:?}", body);
+
+    // Delete climber by id
+    let req = test::TestRequest::delete()
+        .uri(&format!("/climbers/{id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+
+    // Get climber by id again to verify that the deletion worked
+    let req = test::TestRequest::get()
+        .uri(&format!("/climbers/{id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn test_post_get_put_delete_climb() {
+    let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
+
+    let test_climber = Climber::new(None, "testclimber456".to_string());
+
+    // Add climber
+    let req = test::TestRequest::post()
+        .uri("/climbers")
+        .set_json(test_climber)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Climber = test::read_body_json(resp).await;
+    let climber_id = body.id.unwrap();
+    println!("returned climber_id for test climber: {climber_id}");
+
+    // Add route
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(test_route())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let route_id = body.id.unwrap();
+    println!("returned route_id for funky monkey: {route_id}");
+
+    // add test review
+    let test_review = Review::new(
+        10,
+        "I loved this route!".to_string(),
+        NaiveDate::from_ymd_opt(2023, 4, 2).unwrap(),
+    );
+    let req = test::TestRequest::post()
+        .uri(&format!("/climbers/{climber_id}/{route_id}"))
+        .set_json(test_review)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    assert_eq!(
+        resp.headers().get(http::header::LOCATION).unwrap(),
+        &format!("/climbers/{climber_id}/{route_id}")
+    );
+    let body: Climb = test::read_body_json(resp).await;
+    let climb_id = body.id.unwrap();
+
+    // get that test review
+    let req = test::TestRequest::get()
+        .uri(&format!("/climbers/{climber_id}/{route_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Climb = test::read_body_json(resp).await;
+    println!("got back climb:\n{:?}", body);
+    assert_eq!(body.climber_id, climber_id);
+    assert_eq!(body.route_id, route_id);
+    assert_eq!(body.id, Some(climb_id));
+
+    // get the climber's logbook, which has the review and its route
+    let req = test::TestRequest::get()
+        .uri(&format!("/climbers/{climber_id}/logbook"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Logbook = test::read_body_json(resp).await;
+    println!("got back logbook:\n{:?}", body);
+    assert_eq!(body.entries.len(), 1);
+    assert_eq!(body.entries[0].route.id, Some(route_id));
+    assert_eq!(body.disciplines[0].discipline, Discipline::Sport);
+    assert_eq!(body.disciplines[0].number_climbs, 1);
+
+    // and their stats
+    let req = test::TestRequest::get()
+        .uri(&format!("/climbers/{climber_id}/stats"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: ClimberStats = test::read_body_json(resp).await;
+    assert_eq!(body.number_climbs, 1);
+    assert_eq!(body.hardest_sends[0].route_id, route_id);
+    assert_eq!(body.first_ascents_of_grade.len(), 1);
+
+    // update that test review
+    let updated_test_review = Review::new(
+        2,
+        "Actually, I hated it!".to_string(),
+        NaiveDate::from_ymd_opt(2023, 4, 2).unwrap(),
+    );
+    let req = test::TestRequest::put()
+        .uri(&format!("/climbers/{climber_id}/{route_id}"))
+        .set_json(updated_test_review)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+
+    // patch just the text of that test review, and check that the rating is unchanged
+    let req = test::TestRequest::patch()
+        .uri(&format!("/climbers/{climber_id}/{route_id}"))
+        .insert_header((http::header::CONTENT_TYPE, "application/merge-patch+json"))
+        .set_payload(r#"{"review": "Okay, it was fine."}"#)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let req = test::TestRequest::get()
+        .uri(&format!("/climbers/{climber_id}/{route_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Climb = test::read_body_json(resp).await;
+    assert_eq!(body.review.review, "Okay, it was fine.");
+    assert_eq!(body.review.rating.i32(), 2);
+
+    // delete that test review
+    let req = test::TestRequest::delete()
+        .uri(&format!("/climbers/{climber_id}/{route_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+
+    // restore that test review, and check that it is visible again
+    let req = test::TestRequest::post()
+        .uri(&format!("/admin/climbers/{climber_id}/{route_id}/restore"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let req = test::TestRequest::get()
+        .uri(&format!("/climbers/{climber_id}/{route_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+
+    // Delete route
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/{route_id}?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+
+    // Delete climber by id
+    let req = test::TestRequest::delete()
+        .uri(&format!("/climbers/{climber_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+}
+
+#[actix_web::test]
+async fn test_route_and_area_photos() {
+    let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
+
+    // Add area and a route in it
+    let req = test::TestRequest::post()
+        .uri("/areas")
+        .set_json(Area::new(None, "photo crag".to_string(), None))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Area = test::read_body_json(resp).await;
+    let area_id = body.id.unwrap();
+
+    let mut route = test_route();
+    route.metadata.area_id = Some(area_id);
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(&route)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let route_id = body.id.unwrap();
+
+    let png = PhotoFormat::Png
+        .encode(&image::DynamicImage::ImageRgb8(image::RgbImage::new(
+            640, 480,
+        )))
+        .unwrap();
+
+    // Only JPEGs and PNGs can be uploaded, and they have to be what they say they are
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes/{route_id}/photos"))
+        .insert_header((http::header::CONTENT_TYPE, "image/gif"))
+        .set_payload(png.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes/{route_id}/photos"))
+        .insert_header((http::header::CONTENT_TYPE, "image/png"))
+        .set_payload("not a png")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes/{route_id}/photos?caption=the%20crux"))
+        .insert_header((http::header::CONTENT_TYPE, "image/png"))
+        .set_payload(png.clone())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Photo = test::read_body_json(resp).await;
+    let route_photo_id = body.id;
+    assert_eq!(body.route_id, Some(route_id));
+    assert_eq!((body.width, body.height), (640, 480));
+    assert_eq!(body.caption.as_deref(), Some("the crux"));
+
+    let req = test::TestRequest::get().uri(&body.url).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert_eq!(test::read_body(resp).await, png);
+    let req = test::TestRequest::get()
+        .uri(&body.thumbnail_url)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let thumbnail = image::load_from_memory(&test::read_body(resp).await).unwrap();
+    assert_eq!((thumbnail.width(), thumbnail.height()), (256, 192));
+
+    // Draw the route on the photo
+    let topo = Topo {
+        lines: vec![TopoLine {
+            route_id: Some(route_id),
+            label: Some("1".to_string()),
+            points: vec![TopoPoint { x: 0.5, y: 1.0 }, TopoPoint { x: 0.45, y: 0.0 }],
+        }],
+    };
+    let req = test::TestRequest::put()
+        .uri(&format!("/photos/{route_photo_id}/topo"))
+        .set_json(&topo)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{route_id}/photos"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Vec<Photo> = test::read_body_json(resp).await;
+    assert_eq!(body.len(), 1);
+    assert_eq!(body[0].topo, Some(topo.clone()));
+
+    // Area photos can't have topos
+    let req = test::TestRequest::post()
+        .uri(&format!("/areas/{area_id}/photos"))
+        .insert_header((http::header::CONTENT_TYPE, "image/png"))
+        .set_payload(png)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Photo = test::read_body_json(resp).await;
+    let area_photo_id = body.id;
+    let req = test::TestRequest::put()
+        .uri(&format!("/photos/{area_photo_id}/topo"))
+        .set_json(&topo)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+
+    // Delete photos and route
+    for id in [route_photo_id, area_photo_id] {
+        let req = test::TestRequest::delete()
+            .uri(&format!("/photos/{id}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+        let req = test::TestRequest::get().uri(&Photo::url(id)).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    }
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/{route_id}?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+}
+
+#[actix_web::test]
+async fn test_follow_and_feed() {
+    let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
+
+    // Add a reader, a climber they follow, and a climber they follow whose activity is private
+    let mut climber_ids = Vec::new();
+    for (username, activity_visibility) in [
+        ("feedreader", Visibility::Public),
+        ("feedsetter", Visibility::Followers),
+        ("feedhermit", Visibility::Private),
+    ] {
+        let mut climber = Climber::new(None, username.to_string());
+        climber.privacy.activity = activity_visibility;
+        let req = test::TestRequest::post()
+            .uri("/climbers")
+            .set_json(climber)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+        let body: Climber = test::read_body_json(resp).await;
+        assert_eq!(body.privacy.activity, activity_visibility);
+        climber_ids.push(body.id.unwrap());
+    }
+    let (reader_id, setter_id, hermit_id) = (climber_ids[0], climber_ids[1], climber_ids[2]);
+
+    let req = test::TestRequest::put()
+        .uri(&format!("/climbers/{reader_id}/following/{reader_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    let req = test::TestRequest::put()
+        .uri(&format!("/climbers/{reader_id}/following/-1"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    for followed_id in [setter_id, hermit_id, setter_id] {
+        let req = test::TestRequest::put()
+            .uri(&format!("/climbers/{reader_id}/following/{followed_id}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    }
+    let req = test::TestRequest::get()
+        .uri(&format!("/climbers/{setter_id}/followers"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Vec<Climber> = test::read_body_json(resp).await;
+    assert_eq!(body.len(), 1);
+    assert_eq!(body[0].id, Some(reader_id));
+
+    // The setter sets a route and climbs it, and the hermit climbs it too
+    let mut route = test_route();
+    route.metadata.setter_id = Some(setter_id);
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(&route)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let route_id = body.id.unwrap();
+    for (climber_id, review) in [(setter_id, ""), (hermit_id, "Nobody saw me")] {
+        let req = test::TestRequest::post()
+            .uri(&format!("/climbers/{climber_id}/{route_id}"))
+            .set_json(Review::new(
+                7,
+                review.to_string(),
+                NaiveDate::from_ymd_opt(2023, 7, 1).unwrap(),
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+    }
+
+    // A feed is only for its reader
+    for uri in [
+        format!("/climbers/{reader_id}/feed"),
+        format!("/climbers/{reader_id}/feed?viewer_id={setter_id}"),
+    ] {
+        let req = test::TestRequest::get().uri(&uri).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+    }
+
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/climbers/{reader_id}/feed?per_page=1&viewer_id={reader_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Feed = test::read_body_json(resp).await;
+    assert_eq!(body.events.len(), 1);
+    assert_eq!(body.events[0].kind, FeedEventKind::Ascent);
+    assert_eq!(body.events[0].climber_id, setter_id);
+    assert!(body.events[0].climb.is_some());
+    assert_eq!(body.next_page, Some(2));
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/climbers/{reader_id}/feed?page=2&per_page=1&viewer_id={reader_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Feed = test::read_body_json(resp).await;
+    assert_eq!(body.events.len(), 1);
+    assert_eq!(body.events[0].kind, FeedEventKind::RouteSet);
+    assert_eq!(body.events[0].route_id, route_id);
+    assert_eq!(body.next_page, None);
+
+    // Unfollowing empties the feed
+    let req = test::TestRequest::delete()
+        .uri(&format!("/climbers/{reader_id}/following/{setter_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let req = test::TestRequest::get()
+        .uri(&format!("/climbers/{reader_id}/feed?viewer_id={reader_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Feed = test::read_body_json(resp).await;
+    assert!(body.events.is_empty());
+    let req = test::TestRequest::delete()
+        .uri(&format!("/climbers/{reader_id}/following/{setter_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+
+    // Delete route and climbers
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/{route_id}?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    for id in climber_ids {
+        let req = test::TestRequest::delete()
+            .uri(&format!("/climbers/{id}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    }
+}
+
+#[actix_web::test]
+async fn test_climber_profile_privacy() {
+    let app = test::init_service(app!()).await;
+
+    let mut climber = Climber::new(None, "privateclimber".to_string());
+    climber.profile = Profile {
+        display_name: Some("Private Climber".to_string()),
+        bio: Some("Only my followers know".to_string()),
+        climbing_since: NaiveDate::from_ymd_opt(2012, 3, 4),
+        preferred_grade_scale: Some(GradeScale::VScale),
+        ..Default::default()
+    };
+    climber.privacy = Privacy {
+        bio: Visibility::Followers,
+        climbing_since: Visibility::Private,
+        logbook: Visibility::Followers,
+        ..Default::default()
+    };
+
+    // The home area has to exist
+    climber.profile.home_area_id = Some(-1);
+    let req = test::TestRequest::post()
+        .uri("/climbers")
+        .set_json(&climber)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    climber.profile.home_area_id = None;
+
+    let req = test::TestRequest::post()
+        .uri("/climbers")
+        .set_json(&climber)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Climber = test::read_body_json(resp).await;
+    let id = body.id.unwrap();
+    let req = test::TestRequest::post()
+        .uri("/climbers")
+        .set_json(Climber::new(None, "privatefollower".to_string()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Climber = test::read_body_json(resp).await;
+    let follower_id = body.id.unwrap();
+    let req = test::TestRequest::put()
+        .uri(&format!("/climbers/{follower_id}/following/{id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+
+    // Each viewer only sees the fields they are allowed to
+    for (viewer, bio, climbing_since) in [
+        (String::new(), None, None),
+        (
+            format!("?viewer_id={follower_id}"),
+            climber.profile.bio.clone(),
+            None,
+        ),
+        (
+            format!("?viewer_id={id}"),
+            climber.profile.bio.clone(),
+            climber.profile.climbing_since,
+        ),
+    ] {
+        let req = test::TestRequest::get()
+            .uri(&format!("/climbers/{id}{viewer}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+        let body: Climber = test::read_body_json(resp).await;
+        assert_eq!(body.profile.display_name, climber.profile.display_name);
+        assert_eq!(body.profile.preferred_grade_scale, Some(GradeScale::VScale));
+        assert_eq!(body.profile.bio, bio);
+        assert_eq!(body.profile.climbing_since, climbing_since);
+    }
+    let req = test::TestRequest::get()
+        .uri(&format!("/climbers/{follower_id}/following"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Vec<Climber> = test::read_body_json(resp).await;
+    assert_eq!(body.len(), 1);
+    assert_eq!(body[0].profile.bio, None);
+
+    // The logbook and reviews are only for followers
+    let req = test::TestRequest::get()
+        .uri(&format!("/climbers/{id}/logbook"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+    let req = test::TestRequest::get()
+        .uri(&format!("/climbers/{id}/1"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+    let req = test::TestRequest::get()
+        .uri(&format!("/climbers/{id}/logbook?viewer_id={follower_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+
+    // Until the climber makes them public
+    climber.privacy.logbook = Visibility::Public;
+    let req = test::TestRequest::put()
+        .uri(&format!("/climbers/{id}"))
+        .set_json(&climber)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let req = test::TestRequest::get()
+        .uri(&format!("/climbers/{id}/logbook"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+
+    // Delete climbers
+    for id in [id, follower_id] {
+        let req = test::TestRequest::delete()
+            .uri(&format!("/climbers/{id}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    }
+}
+
+#[actix_web::test]
+async fn test_gym_leaderboards() {
+    let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
+
+    // Add gym, wall and a route on it
+    let req = test::TestRequest::post()
+        .uri("/gyms")
+        .set_json(Gym::new(None, "leaderboard gym".to_string()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Gym = test::read_body_json(resp).await;
+    let gym_id = body.id.unwrap();
+    let req = test::TestRequest::post()
+        .uri(&format!("/gyms/{gym_id}/walls"))
+        .set_json(Wall::new(None, None, "comp wall".to_string()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Wall = test::read_body_json(resp).await;
+    let mut route = test_route();
+    route.metadata.wall_id = body.id;
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(&route)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let route_id = body.id.unwrap();
+
+    // Two climbers climb it in May, one of them first
+    let mut climber_ids = Vec::new();
+    for (username, day) in [("slowcomper", 20), ("fastcomper", 3)] {
+        let req = test::TestRequest::post()
+            .uri("/climbers")
+            .set_json(Climber::new(None, username.to_string()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+        let body: Climber = test::read_body_json(resp).await;
+        let climber_id = body.id.unwrap();
+        climber_ids.push(climber_id);
+        let req = test::TestRequest::post()
+            .uri(&format!("/climbers/{climber_id}/{route_id}"))
+            .set_json(Review::new(
+                5,
+                String::new(),
+                NaiveDate::from_ymd_opt(2023, 5, day).unwrap(),
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+    }
+
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/leaderboards?gym_id={gym_id}&metric=Hardest&discipline=Sport&from=2023-05-01&to=2023-05-31&per_page=1"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Leaderboard = test::read_body_json(resp).await;
+    assert_eq!(body.scope.metric, LeaderboardMetric::Hardest);
+    assert_eq!(body.scope.scale, Some(GradeScale::Yds));
+    assert_eq!(body.entries.len(), 1);
+    assert_eq!(body.entries[0].rank, 1);
+    assert_eq!(body.entries[0].climber_id, climber_ids[1]);
+    assert_eq!(body.entries[0].points, DifficultyRating::Rating59.points());
+    assert_eq!(body.next_page, Some(2));
+
+    // Climbs outside the window don't count
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/leaderboards?gym_id={gym_id}&scale=Yds&from=2023-05-10&to=2023-05-31"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Leaderboard = test::read_body_json(resp).await;
+    assert_eq!(body.entries.len(), 1);
+    assert_eq!(body.entries[0].climber_id, climber_ids[0]);
+
+    let req = test::TestRequest::get()
+        .uri("/leaderboards?from=2023-06-01&to=2023-05-01")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    // Points can't be compared across grade scales
+    let req = test::TestRequest::get()
+        .uri(&format!("/leaderboards?gym_id={gym_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    let req = test::TestRequest::get()
+        .uri("/leaderboards?metric=Hardest&discipline=Boulder&scale=Yds")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    let req = test::TestRequest::get()
+        .uri("/leaderboards?gym_id=-1&metric=Ascents")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+
+    let mut conn = crate::pg::conn().await.unwrap();
+    let leaderboards_refreshed = crate::leaderboards::refresh_leaderboards(&mut conn)
+        .await
+        .unwrap();
+    assert!(leaderboards_refreshed >= 2);
+
+    // Delete route and climbers
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/{route_id}?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    for id in climber_ids {
+        let req = test::TestRequest::delete()
+            .uri(&format!("/climbers/{id}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    }
+}
+
+#[actix_web::test]
+async fn test_competition_scoring() {
+    let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
+
+    // Running from yesterday to tomorrow, so that attempts can be recorded today
+    let today = Local::now().date_naive();
+    let mut competition = Competition {
+        id: None,
+        name: "bouldering league".to_string(),
+        gym_id: None,
+        format: ScoringFormat::Ifsc,
+        starts_on: today.pred_opt().unwrap(),
+        ends_on: today.pred_opt().unwrap().pred_opt().unwrap(),
+        categories: Vec::new(),
+        problems: Vec::new(),
+    };
+    let req = test::TestRequest::post()
+        .uri("/competitions")
+        .set_json(&competition)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    competition.ends_on = today.succ_opt().unwrap();
+    let req = test::TestRequest::post()
+        .uri("/competitions")
+        .set_json(&competition)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Competition = test::read_body_json(resp).await;
+    let competition_id = body.id.unwrap();
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/competitions/{competition_id}/categories"))
+        .set_json(Category {
+            id: None,
+            name: "open".to_string(),
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Category = test::read_body_json(resp).await;
+    let category_id = body.id.unwrap();
+
+    // Two problems, the second worth its own points
+    let mut route_ids = Vec::new();
+    for points in [None, Some(1000)] {
+        let req = test::TestRequest::post()
+            .uri(&format!("/routes?editor_id={editor_id}"))
+            .set_json(test_route())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+        let body: Route = test::read_body_json(resp).await;
+        let route_id = body.id.unwrap();
+        route_ids.push(route_id);
+        let req = test::TestRequest::post()
+            .uri(&format!("/competitions/{competition_id}/problems"))
+            .set_json(Problem {
+                route_id,
+                number: None,
+                points,
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+    }
+    let req = test::TestRequest::post()
+        .uri(&format!("/competitions/{competition_id}/problems"))
+        .set_json(Problem {
+            route_id: route_ids[0],
+            number: None,
+            points: None,
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CONFLICT);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/competitions/{competition_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Competition = test::read_body_json(resp).await;
+    assert_eq!(body.categories.len(), 1);
+    assert_eq!(
+        body.problems,
+        vec![
+            Problem {
+                route_id: route_ids[0],
+                number: Some(1),
+                points: Some(DifficultyRating::Rating59.points() as i32),
+            },
+            Problem {
+                route_id: route_ids[1],
+                number: Some(2),
+                points: Some(1000),
+            },
+        ]
+    );
+
+    let mut climber_ids = Vec::new();
+    for username in ["flasher", "projector"] {
+        let req = test::TestRequest::post()
+            .uri("/climbers")
+            .set_json(Climber::new(None, username.to_string()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+        let body: Climber = test::read_body_json(resp).await;
+        climber_ids.push(body.id.unwrap());
+    }
+    let attempt = |route_id, zone, top| Attempt {
+        route_id,
+        attempt_number: None,
+        zone,
+        top,
+    };
+
+    // Attempts only count for registered climbers, in a category of the competition
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/competitions/{competition_id}/scorecards/{}/attempts",
+            climber_ids[0]
+        ))
+        .set_json(attempt(route_ids[0], false, true))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    let req = test::TestRequest::post()
+        .uri(&format!("/competitions/{competition_id}/registrations"))
+        .set_json(Registration {
+            climber_id: climber_ids[0],
+            category_id: -1,
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    for &climber_id in climber_ids.iter() {
+        let req = test::TestRequest::post()
+            .uri(&format!("/competitions/{competition_id}/registrations"))
+            .set_json(Registration {
+                climber_id,
+                category_id,
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+    }
+
+    // The flasher flashes the first problem, the projector tops the second on their third go
+    let attempts = [
+        (climber_ids[0], attempt(route_ids[0], false, true)),
+        (climber_ids[1], attempt(route_ids[1], false, false)),
+        (climber_ids[1], attempt(route_ids[1], true, false)),
+        (climber_ids[1], attempt(route_ids[1], true, true)),
+    ];
+    for (climber_id, attempt) in attempts {
+        let req = test::TestRequest::post()
+            .uri(&format!(
+                "/competitions/{competition_id}/scorecards/{climber_id}/attempts"
+            ))
+            .set_json(attempt)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+    }
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/competitions/{competition_id}/scorecards/{}/attempts",
+            climber_ids[0]
+        ))
+        .set_json(attempt(route_ids[0], true, true))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/competitions/{competition_id}/scorecards/{}",
+            climber_ids[1]
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Scorecard = test::read_body_json(resp).await;
+    assert_eq!(body.problems[0].attempts, 0);
+    assert_eq!(body.problems[1].attempts, 3);
+    assert_eq!(body.problems[1].zone_attempt, Some(2));
+    assert_eq!(body.problems[1].top_attempt, Some(3));
+
+    // Both have one top, but the flasher needed fewer attempts
+    let req = test::TestRequest::get()
+        .uri(&format!("/competitions/{competition_id}/results"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: CompetitionResults = test::read_body_json(resp).await;
+    assert_eq!(body.format, ScoringFormat::Ifsc);
+    let results = &body.categories[0].results;
+    assert_eq!(results[0].climber_id, climber_ids[0]);
+    assert_eq!((results[0].rank, results[1].rank), (1, 2));
+    assert_eq!(results[1].points, 1000);
+
+    // Two attempts sent at once are numbered one after the other
+    let requests = [1, 2].map(|_| {
+        test::TestRequest::post()
+            .uri(&format!(
+                "/competitions/{competition_id}/scorecards/{}/attempts",
+                climber_ids[1]
+            ))
+            .set_json(attempt(route_ids[0], false, false))
+            .to_request()
+    });
+    let [first, second] = requests;
+    let (first, second) = tokio::join!(
+        test::call_service(&app, first),
+        test::call_service(&app, second)
+    );
+    let mut attempt_numbers = Vec::new();
+    for resp in [first, second] {
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+        let body: Attempt = test::read_body_json(resp).await;
+        attempt_numbers.push(body.attempt_number.unwrap());
+    }
+    attempt_numbers.sort();
+    assert_eq!(attempt_numbers, vec![1, 2]);
+
+    // Attempts aren't recorded once the competition is over
+    competition.starts_on = NaiveDate::from_ymd_opt(2023, 6, 2).unwrap();
+    competition.ends_on = NaiveDate::from_ymd_opt(2023, 6, 3).unwrap();
+    let req = test::TestRequest::post()
+        .uri("/competitions")
+        .set_json(&competition)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Competition = test::read_body_json(resp).await;
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/competitions/{}/scorecards/{}/attempts",
+            body.id.unwrap(),
+            climber_ids[1]
+        ))
+        .set_json(attempt(route_ids[0], false, false))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    assert_eq!(
+        test::read_body(resp).await,
+        DatabaseError::CompetitionNotRunning.to_string()
+    );
+
+    // Delete routes and climbers
+    for id in route_ids {
+        let req = test::TestRequest::delete()
+            .uri(&format!("/routes/{id}?editor_id={editor_id}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    }
+    for id in climber_ids {
+        let req = test::TestRequest::delete()
+            .uri(&format!("/climbers/{id}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    }
+}
+
+#[actix_web::test]
+async fn test_climber_recommendations() {
+    let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
+
+    let mut route_ids = Vec::new();
+    for _ in 0..2 {
+        let req = test::TestRequest::post()
+            .uri(&format!("/routes?editor_id={editor_id}"))
+            .set_json(test_route())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+        let body: Route = test::read_body_json(resp).await;
+        route_ids.push(body.id.unwrap());
+    }
+    let mut climber_ids = Vec::new();
+    for username in ["recommendee", "likeminded"] {
+        let req = test::TestRequest::post()
+            .uri("/climbers")
+            .set_json(Climber::new(None, username.to_string()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+        let body: Climber = test::read_body_json(resp).await;
+        climber_ids.push(body.id.unwrap());
+    }
+
+    // Both rate the first route the same, and only the second climber has climbed the other
+    let climbs = [
+        (climber_ids[0], route_ids[0], 8),
+        (climber_ids[1], route_ids[0], 8),
+        (climber_ids[1], route_ids[1], 10),
+    ];
+    for (climber_id, route_id, rating) in climbs {
+        let req = test::TestRequest::post()
+            .uri(&format!("/climbers/{climber_id}/{route_id}"))
+            .set_json(Review::new(
+                rating,
+                String::new(),
+                NaiveDate::from_ymd_opt(2023, 7, 1).unwrap(),
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+    }
+
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/climbers/{}/recommendations?max_distance_km=1&limit=50",
+            climber_ids[0]
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Recommendations = test::read_body_json(resp).await;
+    assert_eq!(body.envelopes.len(), 1);
+    assert_eq!(body.envelopes[0].hardest, DifficultyRating::Rating59);
+    assert!(body
+        .recommendations
+        .iter()
+        .all(|recommendation| recommendation.route.route.id != Some(route_ids[0])));
+    let recommendation = body
+        .recommendations
+        .iter()
+        .find(|recommendation| recommendation.route.route.id == Some(route_ids[1]))
+        .unwrap();
+    assert_eq!(recommendation.similar_climbers_rating, Some(10.0));
+    assert!(recommendation
+        .explanation
+        .contains("1 climber who rate routes like you gave it 10.0/10"));
+
+    let req = test::TestRequest::get()
+        .uri("/climbers/-1/recommendations")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+
+    // Delete routes and climbers
+    for id in route_ids {
+        let req = test::TestRequest::delete()
+            .uri(&format!("/routes/{id}?editor_id={editor_id}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    }
+    for id in climber_ids {
+        let req = test::TestRequest::delete()
+            .uri(&format!("/climbers/{id}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    }
+}
+
+#[actix_web::test]
+async fn test_ticklist() {
+    let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(test_route())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let route_id = body.id.unwrap();
+    let mut climber_ids = Vec::new();
+    for username in ["projecter", "alsoprojecting"] {
+        let req = test::TestRequest::post()
+            .uri("/climbers")
+            .set_json(Climber::new(None, username.to_string()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+        let body: Climber = test::read_body_json(resp).await;
+        climber_ids.push(body.id.unwrap());
+    }
+
+    // Both climbers put the route on their ticklists
+    let entry = TicklistEntry {
+        route_id,
+        priority: Priority::High,
+        notes: "need to work the crux".to_string(),
+        done_on: None,
+    };
+    for id in climber_ids.iter() {
+        let req = test::TestRequest::post()
+            .uri(&format!("/climbers/{id}/ticklist"))
+            .set_json(&entry)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+    }
+    let req = test::TestRequest::post()
+        .uri(&format!("/climbers/{}/ticklist", climber_ids[0]))
+        .set_json(&entry)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CONFLICT);
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{route_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Route = test::read_body_json(resp).await;
+    assert_eq!(body.ticklist_count, Some(2));
+
+    let updated_entry = TicklistEntry {
+        route_id: 0,
+        priority: Priority::Low,
+        notes: String::new(),
+        done_on: None,
+    };
+    let req = test::TestRequest::put()
+        .uri(&format!("/climbers/{}/ticklist/{route_id}", climber_ids[1]))
+        .set_json(&updated_entry)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: TicklistEntry = test::read_body_json(resp).await;
+    assert_eq!(body.route_id, route_id);
+    assert_eq!(body.priority, Priority::Low);
+
+    // Logging an ascent ticks the route off
+    let req = test::TestRequest::post()
+        .uri(&format!("/climbers/{}/{route_id}", climber_ids[0]))
+        .set_json(Review::new(
+            9,
+            "sent!".to_string(),
+            NaiveDate::from_ymd_opt(2023, 8, 1).unwrap(),
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let req = test::TestRequest::get()
+        .uri(&format!("/climbers/{}/ticklist?done=true", climber_ids[0]))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Vec<TicklistEntry> = test::read_body_json(resp).await;
+    assert_eq!(body.len(), 1);
+    assert_eq!(body[0].done_on, NaiveDate::from_ymd_opt(2023, 8, 1));
+    let req = test::TestRequest::get()
+        .uri(&format!("/climbers/{}/ticklist?done=false", climber_ids[0]))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: Vec<TicklistEntry> = test::read_body_json(resp).await;
+    assert!(body.is_empty());
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{route_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let etag = resp.headers().get(http::header::ETAG).unwrap().clone();
+    let body: Route = test::read_body_json(resp).await;
+    assert_eq!(body.ticklist_count, Some(1));
+
+    // Deleting the ascent puts the route back on the ticklist, and changes the route's ETag
+    let req = test::TestRequest::delete()
+        .uri(&format!("/climbers/{}/{route_id}", climber_ids[0]))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let req = test::TestRequest::get()
+        .uri(&format!("/climbers/{}/ticklist?done=false", climber_ids[0]))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: Vec<TicklistEntry> = test::read_body_json(resp).await;
+    assert_eq!(body.len(), 1);
+    assert_eq!(body[0].done_on, None);
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{route_id}"))
+        .insert_header((http::header::IF_NONE_MATCH, etag))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Route = test::read_body_json(resp).await;
+    assert_eq!(body.ticklist_count, Some(2));
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/climbers/{}/ticklist/{route_id}", climber_ids[1]))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let req = test::TestRequest::get()
+        .uri(&format!("/climbers/{}/ticklist/{route_id}", climber_ids[1]))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+
+    // Delete route and climbers
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/{route_id}?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    for id in climber_ids {
+        let req = test::TestRequest::delete()
+            .uri(&format!("/climbers/{id}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    }
+}
+
+#[actix_web::test]
+async fn test_condition_reports() {
+    let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
+
+    // A crag with a sector, and a route in the sector
+    let mut area_ids = Vec::new();
+    for name in ["conditions crag", "conditions sector"] {
+        let req = test::TestRequest::post()
+            .uri("/areas")
+            .set_json(Area::new(None, name.to_string(), area_ids.last().copied()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+        let body: Area = test::read_body_json(resp).await;
+        area_ids.push(body.id.unwrap());
+    }
+    let crag_id = area_ids[0];
+    let mut route = test_route();
+    route.metadata.area_id = Some(area_ids[1]);
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(&route)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let route_id = body.id.unwrap();
+    let req = test::TestRequest::post()
+        .uri("/climbers")
+        .set_json(Climber::new(None, "conditionsreporter".to_string()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Climber = test::read_body_json(resp).await;
+    let climber_id = body.id.unwrap();
+
+    let report = |status, expires_in_hours| ConditionReport {
+        id: None,
+        climber_id,
+        route_id: None,
+        area_id: None,
+        status,
+        note: String::new(),
+        expires_in_hours,
+        created_at: None,
+        expires_at: None,
+    };
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes/{route_id}/conditions"))
+        .set_json(report(ConditionStatus::Dry, Some(0)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+
+    // A raptor closure on the crag outranks a newer report on the route
+    let req = test::TestRequest::post()
+        .uri(&format!("/areas/{crag_id}/conditions"))
+        .set_json(report(ConditionStatus::RaptorClosure, None))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: ConditionReport = test::read_body_json(resp).await;
+    assert_eq!(body.area_id, Some(crag_id));
+    let closure_id = body.id.unwrap();
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes/{route_id}/conditions"))
+        .set_json(report(ConditionStatus::Wet, Some(24)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{route_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let etag = resp.headers().get(http::header::ETAG).unwrap().clone();
+    let body: Route = test::read_body_json(resp).await;
+    assert_eq!(body.current_condition, Some(ConditionStatus::RaptorClosure));
+
+    // Until a newer report says it's open again, which also changes the route's ETag
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes/{route_id}/conditions"))
+        .set_json(report(ConditionStatus::Open, Some(24)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: ConditionReport = test::read_body_json(resp).await;
+    let open_id = body.id.unwrap();
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{route_id}"))
+        .insert_header((http::header::IF_NONE_MATCH, etag))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Route = test::read_body_json(resp).await;
+    assert_eq!(body.current_condition, Some(ConditionStatus::Open));
+
+    // Once the closure and the reopening expire the route's own report shows, in listings too
+    let mut conn = crate::pg::conn().await.unwrap();
+    sqlx::query("UPDATE condition_reports SET expires_at = current_timestamp - interval '1 hour' WHERE id = $1 OR id = $2")
+        .bind(closure_id)
+        .bind(open_id)
+        .execute(&mut conn)
+        .await
+        .unwrap();
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes?area_id={crag_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Vec<Route> = test::read_body_json(resp).await;
+    assert_eq!(body.len(), 1);
+    assert_eq!(body[0].current_condition, Some(ConditionStatus::Wet));
+    let req = test::TestRequest::get()
+        .uri(&format!("/areas/{crag_id}/conditions"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: Vec<ConditionReport> = test::read_body_json(resp).await;
+    assert!(body.is_empty());
+    let req = test::TestRequest::get()
+        .uri(&format!("/areas/{crag_id}/conditions?include_expired=true"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: Vec<ConditionReport> = test::read_body_json(resp).await;
+    assert_eq!(body.len(), 1);
+
+    // Delete route and climber
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/{route_id}?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let req = test::TestRequest::delete()
+        .uri(&format!("/climbers/{climber_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+}
+
+#[actix_web::test]
+async fn test_review_moderation() {
+    let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(test_route())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let route_id = body.id.unwrap();
+    let mut climber_ids = Vec::new();
+    for username in ["ranter", "flagger", "moderator"] {
+        let req = test::TestRequest::post()
+            .uri("/climbers")
+            .set_json(Climber::new(None, username.to_string()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+        let body: Climber = test::read_body_json(resp).await;
+        climber_ids.push(body.id.unwrap());
+    }
+    let (author_id, flagger_id, moderator_id) = (climber_ids[0], climber_ids[1], climber_ids[2]);
+    let req = test::TestRequest::post()
+        .uri(&format!("/climbers/{author_id}/{route_id}"))
+        .set_json(Review::new(
+            1,
+            "the first ascensionist is a clown".to_string(),
+            NaiveDate::from_ymd_opt(2023, 9, 1).unwrap(),
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Climb = test::read_body_json(resp).await;
+    let climb_id = body.id.unwrap();
+
+    // Flag the review, once per climber and never your own
+    let flag = |climber_id| ReviewFlag {
+        id: None,
+        climber_id,
+        reason: FlagReason::Offensive,
+        note: "name calling".to_string(),
+        created_at: None,
+        resolved_at: None,
+    };
+    let req = test::TestRequest::post()
+        .uri(&format!("/climbers/{author_id}/{route_id}/flags"))
+        .set_json(flag(flagger_id))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let req = test::TestRequest::post()
+        .uri(&format!("/climbers/{author_id}/{route_id}/flags"))
+        .set_json(flag(flagger_id))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CONFLICT);
+    let req = test::TestRequest::post()
+        .uri(&format!("/climbers/{author_id}/{route_id}/flags"))
+        .set_json(flag(author_id))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    // Only moderators see the queue, and only a moderator can make a climber one
+    for uri in [
+        "/admin/moderation?per_page=100".to_string(),
+        format!("/admin/moderation?per_page=100&moderator_id={moderator_id}"),
+        format!("/admin/moderation/{climb_id}?moderator_id={flagger_id}"),
+    ] {
+        let req = test::TestRequest::get().uri(&uri).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+    }
+    let req = test::TestRequest::put()
+        .uri(&format!(
+            "/admin/moderators/{moderator_id}?moderator_id={moderator_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+    make_moderator(moderator_id).await;
+    let req = test::TestRequest::put()
+        .uri(&format!(
+            "/admin/moderators/{flagger_id}?moderator_id={moderator_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let req = test::TestRequest::delete()
+        .uri(&format!(
+            "/admin/moderators/{flagger_id}?moderator_id={moderator_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/admin/moderation?per_page=100&moderator_id={moderator_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: ModerationQueue = test::read_body_json(resp).await;
+    let queued = body
+        .reviews
+        .iter()
+        .find(|review| review.climb.id == Some(climb_id))
+        .unwrap();
+    assert_eq!(queued.flags.len(), 1);
+
+    // A moderator hides it, which needs a reason
+    let moderation = |action, reason: &str, review: Option<&str>| Moderation {
+        id: None,
+        climb_id: None,
+        comment_id: None,
+        moderator_id: Some(moderator_id),
+        action,
+        reason: reason.to_string(),
+        review: review.map(str::to_string),
+        previous_review: None,
+        created_at: None,
+    };
+    let req = test::TestRequest::post()
+        .uri(&format!("/admin/moderation/{climb_id}"))
+        .set_json(moderation(ModerationAction::Hide, "", None))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    // Only by a moderator
+    let req = test::TestRequest::post()
+        .uri(&format!("/admin/moderation/{climb_id}"))
+        .set_json(Moderation {
+            moderator_id: Some(flagger_id),
+            ..moderation(ModerationAction::Hide, "personal attack", None)
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+    // Two moderators hiding it at once only hide it once
+    let hide = || {
+        test::TestRequest::post()
+            .uri(&format!("/admin/moderation/{climb_id}"))
+            .set_json(moderation(ModerationAction::Hide, "personal attack", None))
+            .to_request()
+    };
+    let (first, second) = tokio::join!(
+        test::call_service(&app, hide()),
+        test::call_service(&app, hide())
+    );
+    let mut statuses = vec![first.status(), second.status()];
+    statuses.sort();
+    assert_eq!(
+        statuses,
+        vec![http::StatusCode::CREATED, http::StatusCode::CONFLICT]
+    );
+
+    // Hidden from everyone but its author
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/climbers/{author_id}/{route_id}?viewer_id={flagger_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(!resp.status().is_success());
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/climbers/{author_id}/{route_id}?viewer_id={author_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Climb = test::read_body_json(resp).await;
+    assert!(body.hidden);
+    let req = test::TestRequest::get()
+        .uri(&format!("/climbers/{author_id}/logbook"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Logbook = test::read_body_json(resp).await;
+    assert!(body.entries.is_empty());
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/climbers/{author_id}/logbook?viewer_id={author_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: Logbook = test::read_body_json(resp).await;
+    assert_eq!(body.entries.len(), 1);
+
+    // Its author can't edit it while it is hidden
+    let req = test::TestRequest::put()
+        .uri(&format!("/climbers/{author_id}/{route_id}"))
+        .set_json(Review::new(
+            3,
+            "Still a sandbag".to_string(),
+            NaiveDate::from_ymd_opt(2023, 4, 2).unwrap(),
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CONFLICT);
+    let req = test::TestRequest::patch()
+        .uri(&format!("/climbers/{author_id}/{route_id}"))
+        .set_json(serde_json::json!({ "review": "Still a sandbag" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CONFLICT);
+    let body = test::read_body(resp).await;
+    assert_eq!(body, DatabaseError::CannotEditHiddenReview.to_string());
+
+    // Acting on the review resolves its flags
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/admin/moderation?per_page=100&moderator_id={moderator_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: ModerationQueue = test::read_body_json(resp).await;
+    assert!(body
+        .reviews
+        .iter()
+        .all(|review| review.climb.id != Some(climb_id)));
+
+    // Restore it with the insult edited out
+    let req = test::TestRequest::post()
+        .uri(&format!("/admin/moderation/{climb_id}"))
+        .set_json(moderation(
+            ModerationAction::Restore,
+            "author apologised",
+            None,
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let req = test::TestRequest::post()
+        .uri(&format!("/admin/moderation/{climb_id}"))
+        .set_json(moderation(
+            ModerationAction::Edit,
+            "removed the name calling",
+            Some(&"a".repeat(1001)),
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    let req = test::TestRequest::post()
+        .uri(&format!("/admin/moderation/{climb_id}"))
+        .set_json(moderation(
+            ModerationAction::Edit,
+            "removed the name calling",
+            Some("[removed by a moderator]"),
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Moderation = test::read_body_json(resp).await;
+    assert_eq!(
+        body.previous_review.as_deref(),
+        Some("the first ascensionist is a clown")
+    );
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/climbers/{author_id}/{route_id}?viewer_id={flagger_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Climb = test::read_body_json(resp).await;
+    assert!(!body.hidden);
+    assert_eq!(body.review.review, "[removed by a moderator]");
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/admin/moderation/{climb_id}?moderator_id={moderator_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: ModeratedReview = test::read_body_json(resp).await;
+    assert!(body.flags[0].resolved_at.is_some());
+    assert_eq!(
+        body.log
+            .iter()
+            .map(|moderation| moderation.action)
+            .collect::<Vec<ModerationAction>>(),
+        vec![
+            ModerationAction::Hide,
+            ModerationAction::Restore,
+            ModerationAction::Edit
+        ]
+    );
+
+    // Delete route and climbers
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/{route_id}?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    for id in climber_ids {
+        let req = test::TestRequest::delete()
+            .uri(&format!("/climbers/{id}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    }
+}
+
+#[actix_web::test]
+async fn test_route_reviews() {
+    let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(test_route())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let route_id = body.id.unwrap();
+    let mut climber_ids = Vec::new();
+    for username in ["reviewwriter", "reviewreader", "quietreviewer"] {
+        let req = test::TestRequest::post()
+            .uri("/climbers")
+            .set_json(Climber::new(None, username.to_string()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+        let body: Climber = test::read_body_json(resp).await;
+        climber_ids.push(body.id.unwrap());
+    }
+    let (writer_id, reader_id, quiet_id) = (climber_ids[0], climber_ids[1], climber_ids[2]);
+    for (id, rating, text) in [
+        (writer_id, 6, "the slopertastic crux rewards patience"),
+        (reader_id, 9, "classic line <script>alert(1)</script>"),
+        (quiet_id, 8, "slopertastic but polished"),
+    ] {
+        let req = test::TestRequest::post()
+            .uri(&format!("/climbers/{id}/{route_id}"))
+            .set_json(Review::new(
+                rating,
+                text.to_string(),
+                NaiveDate::from_ymd_opt(2023, 10, 1).unwrap(),
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+    }
+    let mut conn = crate::pg::conn().await.unwrap();
+    sqlx::query("UPDATE climbers SET logbook_visibility = 'private' WHERE id = $1")
+        .bind(quiet_id)
+        .execute(&mut conn)
+        .await
+        .unwrap();
+
+    // Mark a review helpful, but never your own
+    let req = test::TestRequest::put()
+        .uri(&format!(
+            "/climbers/{writer_id}/{route_id}/helpful/{writer_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    let req = test::TestRequest::put()
+        .uri(&format!(
+            "/climbers/{writer_id}/{route_id}/helpful/{reader_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+
+    // The private logbook's review is only listed for its climber
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/routes/{route_id}/reviews?sort=Helpful&viewer_id={reader_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Reviews = test::read_body_json(resp).await;
+    assert_eq!(body.reviews.len(), 2);
+    assert_eq!(body.reviews[0].climb.climber_id, writer_id);
+    assert_eq!(body.reviews[0].helpful_count, 1);
+    assert!(body.reviews[0].viewer_found_helpful);
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/routes/{route_id}/reviews?sort=Rating&per_page=1&viewer_id={quiet_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: Reviews = test::read_body_json(resp).await;
+    assert_eq!(body.reviews[0].climb.climber_id, reader_id);
+    assert_eq!(body.next_page, Some(2));
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/routes/{route_id}/reviews?page=3&per_page=1&viewer_id={quiet_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: Reviews = test::read_body_json(resp).await;
+    assert_eq!(body.reviews[0].climb.climber_id, writer_id);
+    assert_eq!(body.next_page, None);
+
+    // Search every review
+    let req = test::TestRequest::get()
+        .uri("/reviews?q=slopertastic")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Reviews = test::read_body_json(resp).await;
+    assert_eq!(body.reviews.len(), 1);
+    assert_eq!(body.reviews[0].route_name, "funky monkey");
+    assert!(body.reviews[0]
+        .headline
+        .as_ref()
+        .unwrap()
+        .contains("<b>slopertastic</b>"));
+    // The review's own markup is escaped, leaving only the highlights as HTML
+    let req = test::TestRequest::get()
+        .uri("/reviews?q=classic&per_page=100")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: Reviews = test::read_body_json(resp).await;
+    let headline = body
+        .reviews
+        .iter()
+        .find(|review| review.climb.climber_id == reader_id)
+        .unwrap()
+        .headline
+        .clone()
+        .unwrap();
+    assert_eq!(
+        headline,
+        "<b>classic</b> line &lt;script&gt;alert(1)&lt;/script&gt;"
+    );
+    let req = test::TestRequest::get().uri("/reviews?q=%20").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::delete()
+        .uri(&format!(
+            "/climbers/{writer_id}/{route_id}/helpful/{reader_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let req = test::TestRequest::delete()
+        .uri(&format!(
+            "/climbers/{writer_id}/{route_id}/helpful/{reader_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+
+    // Delete route and climbers
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/{route_id}?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    for id in climber_ids {
+        let req = test::TestRequest::delete()
+            .uri(&format!("/climbers/{id}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    }
+}
+
+#[actix_web::test]
+async fn test_comment_threads() {
+    let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
         .set_json(test_route())
         .to_request();
     let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let route_id = body.id.unwrap();
+    let mut climber_ids = Vec::new();
+    for username in ["commenter", "replier", "commentmod"] {
+        let req = test::TestRequest::post()
+            .uri("/climbers")
+            .set_json(Climber::new(None, username.to_string()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+        let body: Climber = test::read_body_json(resp).await;
+        climber_ids.push(body.id.unwrap());
+    }
+    let (commenter_id, replier_id, moderator_id) = (climber_ids[0], climber_ids[1], climber_ids[2]);
+    let comment = |climber_id: i32, parent_id: Option<i32>, body: &str| Comment {
+        id: None,
+        climber_id,
+        username: None,
+        route_id: None,
+        area_id: None,
+        parent_id,
+        depth: 0,
+        body: body.to_string(),
+        hidden: false,
+        deleted: false,
+        created_at: None,
+        edited_at: None,
+        replies: Vec::new(),
+    };
+
+    // Two threads, the first with a chain of replies as deep as they go
+    let mut first_ids = Vec::new();
+    let mut parent_id = None;
+    for depth in 0..=MAX_COMMENT_DEPTH {
+        let climber_id = [commenter_id, replier_id][depth as usize % 2];
+        let req = test::TestRequest::post()
+            .uri(&format!("/routes/{route_id}/comments"))
+            .set_json(comment(
+                climber_id,
+                parent_id,
+                "is the second bolt still spinning?",
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+        let body: Comment = test::read_body_json(resp).await;
+        assert_eq!(body.depth, depth);
+        assert_eq!(body.route_id, Some(route_id));
+        first_ids.push(body.id.unwrap());
+        parent_id = body.id;
+    }
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes/{route_id}/comments"))
+        .set_json(comment(commenter_id, parent_id, "one too many"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes/{route_id}/comments"))
+        .set_json(comment(commenter_id, None, " "))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes/{route_id}/comments"))
+        .set_json(comment(replier_id, None, "Sticky rubber recommended"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Comment = test::read_body_json(resp).await;
+    let second_id = body.id.unwrap();
+
+    // Threads are paginated newest first, with all of their replies
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{route_id}/comments?per_page=1"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: CommentThreads = test::read_body_json(resp).await;
+    assert_eq!(body.comments.len(), 1);
+    assert_eq!(body.comments[0].id, Some(second_id));
+    assert_eq!(body.next_page, Some(2));
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{route_id}/comments?per_page=1&page=2"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: CommentThreads = test::read_body_json(resp).await;
+    assert_eq!(body.next_page, None);
+    let mut depth = 0;
+    let mut thread = &body.comments[0];
+    while let Some(reply) = thread.replies.first() {
+        thread = reply;
+        depth += 1;
+    }
+    assert_eq!(depth, MAX_COMMENT_DEPTH);
+
+    // Only the author edits or deletes a comment, and a deleted comment with replies stays
+    let req = test::TestRequest::put()
+        .uri(&format!("/comments/{}", first_ids[0]))
+        .set_json(comment(replier_id, None, "not mine to edit"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+    let req = test::TestRequest::put()
+        .uri(&format!("/comments/{}", first_ids[0]))
+        .set_json(comment(
+            commenter_id,
+            None,
+            "is the third bolt still spinning?",
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Comment = test::read_body_json(resp).await;
+    assert_eq!(body.body, "is the third bolt still spinning?");
+    assert!(body.edited_at.is_some());
+    let req = test::TestRequest::delete()
+        .uri(&format!(
+            "/comments/{}?viewer_id={replier_id}",
+            first_ids[0]
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+    let req = test::TestRequest::delete()
+        .uri(&format!(
+            "/comments/{}?viewer_id={commenter_id}",
+            first_ids[0]
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let req = test::TestRequest::get()
+        .uri(&format!("/comments/{}", first_ids[0]))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Comment = test::read_body_json(resp).await;
+    assert!(body.deleted);
+    assert_eq!(body.body, "");
+    assert_eq!(body.replies[0].id, Some(first_ids[1]));
+
+    // Flag a reply, never your own, and hide it
+    let flag = |climber_id: i32| ReviewFlag {
+        id: None,
+        climber_id,
+        reason: FlagReason::Offensive,
+        note: String::new(),
+        created_at: None,
+        resolved_at: None,
+    };
+    let req = test::TestRequest::post()
+        .uri(&format!("/comments/{second_id}/flags"))
+        .set_json(flag(replier_id))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    let req = test::TestRequest::post()
+        .uri(&format!("/comments/{second_id}/flags"))
+        .set_json(flag(commenter_id))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let req = test::TestRequest::post()
+        .uri(&format!("/comments/{second_id}/flags"))
+        .set_json(flag(commenter_id))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CONFLICT);
+    for uri in [
+        "/admin/moderation/comments?per_page=100".to_string(),
+        format!("/admin/moderation/comments/{second_id}?moderator_id={commenter_id}"),
+    ] {
+        let req = test::TestRequest::get().uri(&uri).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+    }
+    make_moderator(moderator_id).await;
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/admin/moderation/comments?per_page=100&moderator_id={moderator_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: CommentModerationQueue = test::read_body_json(resp).await;
+    assert!(body
+        .comments
+        .iter()
+        .any(|moderated| moderated.comment.id == Some(second_id)));
+    let req = test::TestRequest::post()
+        .uri(&format!("/admin/moderation/comments/{second_id}"))
+        .set_json(Moderation {
+            id: None,
+            climb_id: None,
+            comment_id: None,
+            moderator_id: Some(moderator_id),
+            action: ModerationAction::Hide,
+            reason: "rude".to_string(),
+            review: None,
+            previous_review: None,
+            created_at: None,
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Moderation = test::read_body_json(resp).await;
+    assert_eq!(body.comment_id, Some(second_id));
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/admin/moderation/comments/{second_id}?moderator_id={moderator_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: ModeratedComment = test::read_body_json(resp).await;
+    assert!(body.comment.hidden);
+    assert!(body.flags[0].resolved_at.is_some());
+    assert_eq!(body.log.len(), 1);
+
+    // The hidden thread is gone for everyone but its author
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{route_id}/comments"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: CommentThreads = test::read_body_json(resp).await;
+    assert_eq!(body.comments.len(), 1);
+    let req = test::TestRequest::get()
+        .uri(&format!("/comments/{second_id}?viewer_id={replier_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), http::StatusCode::OK);
 
-    // Get route id
-    let one_route = NumberRoutes { number_routes: 1 };
+    // Its author can't edit it back in while it is hidden
+    let req = test::TestRequest::put()
+        .uri(&format!("/comments/{second_id}"))
+        .set_json(comment(
+            replier_id,
+            None,
+            "Sticky rubber recommended, please",
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CONFLICT);
+    let body = test::read_body(resp).await;
+    assert_eq!(body, DatabaseError::CannotEditHiddenComment.to_string());
+
+    // A deleted thread whose replies are all deleted doesn't take up a place on a page
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes/{route_id}/comments"))
+        .set_json(comment(replier_id, None, "Anyone got beta?"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Comment = test::read_body_json(resp).await;
+    let third_id = body.id.unwrap();
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes/{route_id}/comments"))
+        .set_json(comment(
+            commenter_id,
+            Some(third_id),
+            "Undercling the flake",
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Comment = test::read_body_json(resp).await;
+    for (id, viewer_id) in [(body.id.unwrap(), commenter_id), (third_id, replier_id)] {
+        let req = test::TestRequest::delete()
+            .uri(&format!("/comments/{id}?viewer_id={viewer_id}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    }
     let req = test::TestRequest::get()
-        .uri("/routes")
-        .set_json(one_route)
+        .uri(&format!("/routes/{route_id}/comments?per_page=1"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: CommentThreads = test::read_body_json(resp).await;
+    assert_eq!(body.comments.len(), 1);
+    assert_eq!(body.comments[0].id, Some(first_ids[0]));
+    assert_eq!(body.next_page, None);
+
+    // Delete route and climbers
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/{route_id}?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    for id in climber_ids {
+        let req = test::TestRequest::delete()
+            .uri(&format!("/climbers/{id}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    }
+}
+
+#[actix_web::test]
+async fn test_route_merge() {
+    let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
+
+    // The same route added twice ten meters apart, the second time with its pitches, and once
+    // more a kilometer away
+    let mut route_ids = Vec::new();
+    let mut possible_duplicates = Vec::new();
+    for (name, latitude) in [
+        ("Crimpy McCrimpface", 47.5),
+        ("crimpy mccrimpface!", 47.50009),
+        ("Crimpy McCrimpface", 47.509),
+    ] {
+        let mut route = Route::new(
+            None,
+            name.to_string(),
+            Discipline::Sport,
+            DifficultyRating::Rating511,
+            latitude,
+            -120.7,
+        );
+        if latitude == 47.50009 {
+            route.pitches = vec![
+                Pitch::new(DifficultyRating::Rating510, Some(25)),
+                Pitch::new(DifficultyRating::Rating511, Some(20)),
+            ];
+        }
+        let req = test::TestRequest::post()
+            .uri(&format!("/routes?editor_id={editor_id}"))
+            .set_json(route)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+        let body: Route = test::read_body_json(resp).await;
+        route_ids.push(body.id.unwrap());
+        possible_duplicates.push(body.possible_duplicates);
+    }
+    let (route_id, duplicate_id, far_id) = (route_ids[0], route_ids[1], route_ids[2]);
+    assert!(!possible_duplicates[1].is_empty());
+    assert_eq!(possible_duplicates[1][0].id, route_id);
+    assert!((possible_duplicates[1][0].distance_meters - 10.0).abs() < 1.0);
+    assert!(possible_duplicates[2].is_empty());
+
+    // A climber logs the duplicate and has both routes on their ticklist
+    let req = test::TestRequest::post()
+        .uri("/climbers")
+        .set_json(Climber::new(None, "routemerger".to_string()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Climber = test::read_body_json(resp).await;
+    let climber_id = body.id.unwrap();
+    for id in [route_id, duplicate_id] {
+        let req = test::TestRequest::post()
+            .uri(&format!("/climbers/{climber_id}/ticklist"))
+            .set_json(TicklistEntry {
+                route_id: id,
+                priority: Priority::Medium,
+                notes: String::new(),
+                done_on: None,
+            })
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+    }
+    let req = test::TestRequest::post()
+        .uri(&format!("/climbers/{climber_id}/{duplicate_id}"))
+        .set_json(Review::new(
+            7,
+            "thin".to_string(),
+            NaiveDate::from_ymd_opt(2023, 6, 1).unwrap(),
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+
+    // Another climber logged both routes, leading only the second pitch of the duplicate
+    let req = test::TestRequest::post()
+        .uri("/climbers")
+        .set_json(Climber::new(None, "routemerger2".to_string()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Climber = test::read_body_json(resp).await;
+    let other_climber_id = body.id.unwrap();
+    for id in [route_id, duplicate_id] {
+        let mut review = Review::new(
+            8,
+            "sustained".to_string(),
+            NaiveDate::from_ymd_opt(2023, 6, 2).unwrap(),
+        );
+        if id == duplicate_id {
+            review.led_pitches = vec![2];
+        }
+        let req = test::TestRequest::post()
+            .uri(&format!("/climbers/{other_climber_id}/{id}"))
+            .set_json(review)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+    }
+
+    // Only a moderator can merge routes, and not into a route of another discipline's grades
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/admin/routes/{duplicate_id}/merge?editor_id={editor_id}"
+        ))
+        .set_json(RouteMerge {
+            target_id: route_id,
+            ..Default::default()
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+    make_moderator(climber_id).await;
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(Route::new(
+            None,
+            "Crimpy McCrimpface".to_string(),
+            Discipline::Boulder,
+            DifficultyRating::RatingV2,
+            47.5,
+            -120.7,
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let boulder_id = body.id.unwrap();
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/admin/routes/{boulder_id}/merge?editor_id={climber_id}"
+        ))
+        .set_json(RouteMerge {
+            target_id: route_id,
+            ..Default::default()
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+
+    // Merge the duplicate
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/admin/routes/{route_id}/merge?editor_id={climber_id}"
+        ))
+        .set_json(RouteMerge {
+            target_id: route_id,
+            ..Default::default()
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/admin/routes/{duplicate_id}/merge?editor_id={climber_id}"
+        ))
+        .set_json(RouteMerge {
+            target_id: route_id,
+            ..Default::default()
+        })
         .to_request();
     let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), http::StatusCode::OK);
-    let body: Vec<Route> = test::read_body_json(resp).await;
-    assert_eq!(body.len(), 1);
-    let route_id = body[0].id.unwrap();
-    println!("returned route_id for funky monkey: {route_id}");
+    let body: RouteMerge = test::read_body_json(resp).await;
+    assert_eq!(body.climbs, 1);
+    assert_eq!(body.duplicate_climbs, 1);
+    assert_eq!(body.pitches, 2);
+    assert_eq!(body.ticklist_entries, 0);
 
-    // add test review
-    let test_review = Review::new(
-        10,
-        "I loved this route!".to_string(),
-        NaiveDate::from_ymd_opt(2023, 4, 2).unwrap(),
-    );
+    // The merged route can't be merged again, or have anything merged into it
     let req = test::TestRequest::post()
-        .uri(&format!("/climbers/{climber_id}/{route_id}"))
-        .set_json(test_review)
+        .uri(&format!(
+            "/admin/routes/{duplicate_id}/merge?editor_id={climber_id}"
+        ))
+        .set_json(RouteMerge {
+            target_id: far_id,
+            ..Default::default()
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/admin/routes/{far_id}/merge?editor_id={climber_id}"
+        ))
+        .set_json(RouteMerge {
+            target_id: duplicate_id,
+            ..Default::default()
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+
+    // The target took the pitches, and so the grade and length derived from them
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{route_id}"))
         .to_request();
     let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Route = test::read_body_json(resp).await;
+    assert_eq!(body.pitches.len(), 2);
+    assert_eq!(body.number_pitches, 2);
+    assert_eq!(body.length_meters, Some(45));
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{route_id}/history?per_page=1"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: RouteHistory = test::read_body_json(resp).await;
+    assert_eq!(body.revisions[0].action, RevisionAction::Merge);
+    assert!(body.revisions[0]
+        .changes
+        .iter()
+        .any(|change| change.field == "pitches"));
 
-    // get that test review
+    // The old ID redirects, and the climb is on the route it was merged into
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{duplicate_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(
+        resp.headers().get(http::header::LOCATION).unwrap(),
+        &format!("/routes/{route_id}")
+    );
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{duplicate_id}/reviews?per_page=5"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(
+        resp.headers().get(http::header::LOCATION).unwrap(),
+        &format!("/routes/{route_id}/reviews?per_page=5")
+    );
+    let req = test::TestRequest::patch()
+        .uri(&format!("/routes/{duplicate_id}?editor_id={editor_id}"))
+        .insert_header((http::header::CONTENT_TYPE, "application/merge-patch+json"))
+        .set_payload(r#"{"name": "Crimpy"}"#)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(
+        resp.headers().get(http::header::LOCATION).unwrap(),
+        &format!("/routes/{route_id}?editor_id={editor_id}")
+    );
     let req = test::TestRequest::get()
         .uri(&format!("/climbers/{climber_id}/{route_id}"))
         .to_request();
     let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), http::StatusCode::OK);
+    let req = test::TestRequest::get()
+        .uri(&format!("/climbers/{other_climber_id}/{route_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
     let body: Climb = test::read_body_json(resp).await;
-    println!("got back climb:\n{:?}", body);
-    assert_eq!(body.climber_id, climber_id);
-    assert_eq!(body.route_id, route_id);
-    let climb_id = body.id.unwrap();
+    assert!(body.review.led_pitches.is_empty());
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/admin/routes/{duplicate_id}/restore?editor_id={editor_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
 
-    // update that test review
-    let updated_test_review = Review::new(
-        2,
-        "Actually, I hated it!".to_string(),
-        NaiveDate::from_ymd_opt(2023, 4, 2).unwrap(),
-    );
+    // Delete routes and climber
+    for id in [route_id, far_id, boulder_id] {
+        let req = test::TestRequest::delete()
+            .uri(&format!("/routes/{id}?editor_id={editor_id}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    }
+    for id in [climber_id, other_climber_id] {
+        let req = test::TestRequest::delete()
+            .uri(&format!("/climbers/{id}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    }
+}
+
+#[actix_web::test]
+async fn test_route_history() {
+    let app = test::init_service(app!()).await;
+
+    let req = test::TestRequest::post()
+        .uri("/climbers")
+        .set_json(Climber::new(None, "routeeditor".to_string()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Climber = test::read_body_json(resp).await;
+    let editor_id = body.id.unwrap();
+    let req = test::TestRequest::post()
+        .uri("/routes?editor_id=-1")
+        .set_json(test_route())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(test_route())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let route_id = body.id.unwrap();
+
+    // A patch by the editor, then an update by another, as an update without an editor is refused
+    let req = test::TestRequest::patch()
+        .uri(&format!("/routes/{route_id}?editor_id={editor_id}"))
+        .set_json(RoutePatch {
+            name: Some("Funky Monkey".to_string()),
+            ..Default::default()
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let mut route = test_route();
+    route.name = "Funky Monkey".to_string();
+    route.metadata.bolts = Some(9);
     let req = test::TestRequest::put()
-        .uri(&format!("/climbers/{climber_id}/{route_id}"))
-        .set_json(updated_test_review)
+        .uri(&format!("/routes/{route_id}"))
+        .set_json(&route)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    let other_editor_id = route_editor().await;
+    let req = test::TestRequest::put()
+        .uri(&format!("/routes/{route_id}?editor_id={other_editor_id}"))
+        .set_json(route)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{route_id}/history"))
         .to_request();
     let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: RouteHistory = test::read_body_json(resp).await;
+    assert_eq!(
+        body.revisions
+            .iter()
+            .map(|revision| revision.action)
+            .collect::<Vec<RevisionAction>>(),
+        vec![
+            RevisionAction::Update,
+            RevisionAction::Patch,
+            RevisionAction::Create
+        ]
+    );
+    assert_eq!(body.revisions[0].editor_id, Some(other_editor_id));
+    assert_eq!(body.revisions[0].changes.len(), 1);
+    assert_eq!(body.revisions[0].changes[0].field, "bolts");
+    assert_eq!(body.revisions[1].editor_id, Some(editor_id));
+    assert_eq!(body.revisions[1].changes[0].field, "name");
+    assert_eq!(body.revisions[1].changes[0].from, "funky monkey");
+    let created_id = body.revisions[2].id;
 
-    // delete that test review
+    // Revert to the route as it was created, which only a moderator can do
+    let req = test::TestRequest::post()
+        .uri(&format!("/admin/routes/{route_id}/revert/{created_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/admin/routes/{route_id}/revert/{created_id}?editor_id={editor_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::FORBIDDEN);
+    make_moderator(editor_id).await;
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/admin/routes/{route_id}/revert/0?editor_id={editor_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/admin/routes/{route_id}/revert/{created_id}?editor_id={editor_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{route_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: Route = test::read_body_json(resp).await;
+    assert_eq!(body.name, "funky monkey");
+    assert_eq!(body.metadata.bolts, None);
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{route_id}/history?per_page=1"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: RouteHistory = test::read_body_json(resp).await;
+    assert_eq!(body.revisions[0].action, RevisionAction::Revert);
+    assert_eq!(body.revisions[0].changes.len(), 2);
+    assert_eq!(body.next_page, Some(2));
+
+    // Deleting and restoring the route are in its history too, with no changes
     let req = test::TestRequest::delete()
-        .uri(&format!("/climbers/{climber_id}/{route_id}"))
+        .uri(&format!("/routes/{route_id}?editor_id={editor_id}"))
         .to_request();
     let resp = test::call_service(&app, req).await;
-    assert_eq!(resp.status(), http::StatusCode::OK);
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/admin/routes/{route_id}/restore?editor_id={editor_id}"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let req = test::TestRequest::get()
+        .uri(&format!("/routes/{route_id}/history?per_page=2"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: RouteHistory = test::read_body_json(resp).await;
+    assert_eq!(body.revisions[0].action, RevisionAction::Restore);
+    assert_eq!(body.revisions[0].editor_id, Some(editor_id));
+    assert!(body.revisions[0].changes.is_empty());
+    assert_eq!(body.revisions[1].action, RevisionAction::Delete);
+    assert_eq!(body.revisions[1].editor_id, Some(editor_id));
 
-    // Delete route
+    // Delete route and climber
     let req = test::TestRequest::delete()
-        .uri(&format!("/routes/{route_id}"))
+        .uri(&format!("/routes/{route_id}?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    let req = test::TestRequest::delete()
+        .uri(&format!("/climbers/{editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+}
+
+#[actix_web::test]
+async fn test_search() {
+    let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
+
+    let req = test::TestRequest::post()
+        .uri("/climbers")
+        .set_json(Climber::new(None, "quokka".to_string()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Climber = test::read_body_json(resp).await;
+    let climber_id = body.id.unwrap();
+    let req = test::TestRequest::post()
+        .uri("/areas")
+        .set_json(Area::new(None, "Quokka Buttress".to_string(), None))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Area = test::read_body_json(resp).await;
+    let area_id = body.id.unwrap();
+    let mut route = test_route();
+    route.name = "Zanzibar Quokka Arete".to_string();
+    route.metadata.first_ascent_party =
+        Some("Ottoline Wexford <img src=x onerror=alert(1)>".to_string());
+    let mut pitch = Pitch::new(DifficultyRating::Rating510, Some(30));
+    pitch.anchor = Some("chained marmalade tree".to_string());
+    route.pitches = vec![pitch];
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(&route)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let route_id = body.id.unwrap();
+
+    // One search finds all three, each with its type
+    let req = test::TestRequest::get()
+        .uri("/search?q=quokka")
         .to_request();
     let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: SearchResults = test::read_body_json(resp).await;
+    for (result_type, id) in [
+        (SearchResultType::Route, route_id),
+        (SearchResultType::Area, area_id),
+        (SearchResultType::Climber, climber_id),
+    ] {
+        assert!(body
+            .results
+            .iter()
+            .any(|result| result.result_type == result_type && result.id == id));
+    }
+    let result = body
+        .results
+        .iter()
+        .find(|result| result.result_type == SearchResultType::Area && result.id == area_id)
+        .unwrap();
+    assert_eq!(result.headline, "<b>Quokka</b> Buttress");
 
-    // Delete climber by id
+    // Only routes, and a route matches on its description
+    let req = test::TestRequest::get()
+        .uri("/search?q=marmalade%20wexford&type=Route")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: SearchResults = test::read_body_json(resp).await;
+    assert_eq!(body.results.len(), 1);
+    assert_eq!(body.results[0].id, route_id);
+    assert!(body.results[0].headline.contains("<b>Wexford</b>"));
+    assert!(body.results[0]
+        .headline
+        .contains("&lt;img src=x onerror=alert(1)&gt;"));
+
+    // A typo still finds the route
+    let req = test::TestRequest::get()
+        .uri("/search?q=zanzibr&type=Route")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: SearchResults = test::read_body_json(resp).await;
+    assert!(body.results.iter().any(|result| result.id == route_id));
+
+    // Editing a route's pitches changes what it matches on
+    route.pitches[0].anchor = Some("chained kumquat tree".to_string());
+    let req = test::TestRequest::put()
+        .uri(&format!("/routes/{route_id}?editor_id={editor_id}"))
+        .set_json(&route)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+    for (q, found) in [("marmalade", false), ("kumquat", true)] {
+        let req = test::TestRequest::get()
+            .uri(&format!("/search?q={q}&type=Route"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let body: SearchResults = test::read_body_json(resp).await;
+        assert_eq!(
+            body.results.iter().any(|result| result.id == route_id),
+            found
+        );
+    }
+
+    // Paginated
+    let req = test::TestRequest::get()
+        .uri("/search?q=quokka&per_page=1")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: SearchResults = test::read_body_json(resp).await;
+    assert_eq!(body.results.len(), 1);
+    assert_eq!(body.next_page, Some(2));
+
+    // Nothing to search for
+    let req = test::TestRequest::get().uri("/search?q=%20").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+
+    // Delete route and climber
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/{route_id}?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
     let req = test::TestRequest::delete()
         .uri(&format!("/climbers/{climber_id}"))
         .to_request();
     let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
+
+    // Areas can't be deleted through the API, so remove this one directly to keep it from
+    // crowding out the next run's area
+    let mut conn = crate::pg::conn().await.unwrap();
+    sqlx::query!("DELETE FROM areas WHERE id = ($1)", area_id)
+        .execute(&mut conn)
+        .await
+        .unwrap();
+}
+
+#[actix_web::test]
+async fn test_autocomplete() {
+    let app = test::init_service(app!()).await;
+    let editor_id = route_editor().await;
+
+    // Areas can't be deleted, so each run names its own to keep earlier runs' out of the way
+    let run = Local::now().timestamp_millis();
+    let mut area_ids = Vec::new();
+    for name in ["Valley", "Wall"] {
+        let req = test::TestRequest::post()
+            .uri("/areas")
+            .set_json(Area::new(
+                None,
+                format!("Zébrine {run} {name}"),
+                area_ids.last().copied(),
+            ))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::CREATED);
+        let body: Area = test::read_body_json(resp).await;
+        area_ids.push(body.id.unwrap());
+    }
+    let mut route = test_route();
+    route.name = format!("Zebrine {run} Crack");
+    route.metadata.area_id = Some(area_ids[1]);
+    let req = test::TestRequest::post()
+        .uri(&format!("/routes?editor_id={editor_id}"))
+        .set_json(route)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let body: Route = test::read_body_json(resp).await;
+    let route_id = body.id.unwrap();
+
+    // Case and accents are ignored, and names are in order
+    let req = test::TestRequest::get()
+        .uri(&format!("/search/autocomplete?q=ZEBRINE%20{run}&limit=25"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), http::StatusCode::OK);
+    let body: Vec<Suggestion> = test::read_body_json(resp).await;
+    let route = body
+        .iter()
+        .position(|suggestion| {
+            suggestion.result_type == SearchResultType::Route && suggestion.id == route_id
+        })
+        .unwrap();
+    let wall = body
+        .iter()
+        .position(|suggestion| {
+            suggestion.result_type == SearchResultType::Area && suggestion.id == area_ids[1]
+        })
+        .unwrap();
+    assert!(route < wall);
+    assert_eq!(body[route].name, format!("Zebrine {run} Crack"));
+    assert_eq!(body[route].difficulty, Some(DifficultyRating::Rating59));
+    assert_eq!(
+        body[route].breadcrumb,
+        vec![
+            format!("Zébrine {run} Valley"),
+            format!("Zébrine {run} Wall")
+        ]
+    );
+    assert_eq!(body[wall].difficulty, None);
+    assert_eq!(body[wall].breadcrumb, vec![format!("Zébrine {run} Valley")]);
+
+    // Only routes, and only as many as asked for
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/search/autocomplete?q=z%C3%89brine%20{run}%20c&type=Route&limit=1"
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: Vec<Suggestion> = test::read_body_json(resp).await;
+    assert_eq!(body.len(), 1);
+    assert_eq!(body[0].result_type, SearchResultType::Route);
+    assert!(body[0].name.starts_with(&format!("Zebrine {run} C")));
+
+    // Nothing typed
+    let req = test::TestRequest::get()
+        .uri("/search/autocomplete?q=")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+
+    // Delete route
+    let req = test::TestRequest::delete()
+        .uri(&format!("/routes/{route_id}?editor_id={editor_id}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NO_CONTENT);
 }
@@ -0,0 +1,78 @@
+/// module for ticklists of routes climbers want to climb, handled by the API endpoint
+/// /climbers/{id}/ticklist
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::NaiveDate;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::error::DatabaseError;
+
+/// How keen a climber is on a route, declared most keen first
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, Default)]
+pub enum Priority {
+    High,
+    #[default]
+    Medium,
+    Low,
+}
+
+impl Display for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let display_str = match self {
+            Self::High => "high",
+            Self::Medium => "medium",
+            Self::Low => "low",
+        };
+        write!(f, "{}", display_str)
+    }
+}
+
+impl FromStr for Priority {
+    type Err = DatabaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "high" => Ok(Self::High),
+            "medium" => Ok(Self::Medium),
+            "low" => Ok(Self::Low),
+            _ => Err(DatabaseError::FailedParsePriority),
+        }
+    }
+}
+
+/// A route on a climber's ticklist
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct TicklistEntry {
+    /// Taken from the path when an entry is updated
+    #[serde(default)]
+    pub route_id: i32,
+    #[serde(default)]
+    pub priority: Priority,
+    #[serde(default)]
+    pub notes: String,
+    /// The date of the first ascent of the route in the climber's logbook. Ignored on writes.
+    #[serde(default)]
+    pub done_on: Option<NaiveDate>,
+}
+
+/// Query parameters for GET /climbers/{id}/ticklist
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+pub struct TicklistQuery {
+    pub viewer_id: Option<i32>,
+    /// Only entries that are done, or only those that aren't
+    pub done: Option<bool>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_priority_round_trip() {
+        for priority in [Priority::High, Priority::Medium, Priority::Low] {
+            assert_eq!(Priority::from_str(&priority.to_string()).unwrap(), priority);
+        }
+        assert!(Priority::from_str("urgent").is_err());
+        assert!(Priority::High < Priority::Low);
+    }
+}
@@ -0,0 +1,240 @@
+use crate::climber::Viewer;
+use crate::climbers::{climber_exists, logbook_visible};
+use crate::error::DatabaseError;
+use crate::pg::conn;
+use crate::ticklist::{Priority, TicklistEntry, TicklistQuery};
+use actix_web::{delete, get, http::header, post, put, web, HttpResponse, Responder};
+use log::error;
+/// Functions for the API endpoint /climbers/{id}/ticklist
+use sqlx::{self, postgres::PgConnection, query};
+use std::str::FromStr;
+
+/// A climber's ticklist, or just one route on it, with routes still to climb first and then by
+/// priority
+async fn select_ticklist(
+    conn: &mut PgConnection,
+    climber_id: i32,
+    route_id: Option<i32>,
+    done: Option<bool>,
+) -> Result<Vec<TicklistEntry>, DatabaseError> {
+    query!(
+        r#"SELECT ticklist_entries.route_id, priority, notes, done.done_on
+        FROM ticklist_entries JOIN routes ON routes.id = ticklist_entries.route_id
+        CROSS JOIN LATERAL (
+            SELECT min(completion_date) as done_on FROM active_climbs
+            WHERE active_climbs.climber_id = ticklist_entries.climber_id AND active_climbs.route_id = ticklist_entries.route_id
+        ) done
+        WHERE ticklist_entries.climber_id = ($1) AND routes.deleted_at IS NULL
+        AND ($2::int4 IS NULL OR ticklist_entries.route_id = $2)
+        AND ($3::bool IS NULL OR (done.done_on IS NOT NULL) = $3)
+        ORDER BY done.done_on IS NOT NULL, CASE priority WHEN 'high' THEN 0 WHEN 'medium' THEN 1 ELSE 2 END, ticklist_entries.created_at"#,
+        climber_id,
+        route_id,
+        done,
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|record| {
+        Ok(TicklistEntry {
+            route_id: record.route_id,
+            priority: Priority::from_str(&record.priority)?,
+            notes: record.notes,
+            done_on: record.done_on,
+        })
+    })
+    .collect()
+}
+
+/// A climber's ticklist. It shows which routes they have climbed, so it follows their logbook's
+/// privacy setting.
+#[get("/{id}/ticklist")]
+async fn get_ticklist(path: web::Path<i32>, query: web::Query<TicklistQuery>) -> impl Responder {
+    let id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match logbook_visible(&mut conn, id, query.viewer_id).await {
+            Ok(Some(true)) => (),
+            Ok(Some(false)) => return HttpResponse::Forbidden().finish(),
+            Ok(None) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in get_ticklist()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        if let Ok(entries) = select_ticklist(&mut conn, id, None, query.done).await {
+            HttpResponse::Ok().json(entries)
+        } else {
+            error!("SELECT query for ticklist entries failed in get_ticklist()");
+            HttpResponse::BadGateway().finish()
+        }
+    } else {
+        error!("Failed to connect to the database in get_ticklist()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Put a route on a climber's ticklist. A route the climber has already climbed is done straight
+/// away.
+#[post("/{id}/ticklist")]
+async fn add_ticklist_entry(
+    path: web::Path<i32>,
+    json: web::Json<TicklistEntry>,
+) -> impl Responder {
+    let id = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match climber_exists(&mut conn, id).await {
+            Ok(true) => (),
+            Ok(false) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in add_ticklist_entry()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        match query!(
+            "SELECT id FROM routes WHERE id = ($1) AND deleted_at IS NULL",
+            json.0.route_id
+        )
+        .fetch_optional(&mut conn)
+        .await
+        {
+            Ok(Some(_)) => (),
+            Ok(None) => {
+                return HttpResponse::BadRequest().body(DatabaseError::RouteNotFound.to_string())
+            }
+            Err(_) => {
+                error!("SELECT query for the route failed in add_ticklist_entry()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        match query!(
+            r#"INSERT INTO ticklist_entries (climber_id, route_id, priority, notes)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT DO NOTHING
+            RETURNING (SELECT min(completion_date) FROM active_climbs WHERE climber_id = ($1) AND route_id = ($2)) as done_on"#,
+            id,
+            json.0.route_id,
+            json.0.priority.to_string(),
+            json.0.notes,
+        )
+        .fetch_optional(&mut conn)
+        .await
+        {
+            Ok(Some(query_result)) => {
+                let mut entry = json.into_inner();
+                entry.done_on = query_result.done_on;
+                HttpResponse::Created()
+                    .insert_header((
+                        header::LOCATION,
+                        format!("/climbers/{id}/ticklist/{}", entry.route_id),
+                    ))
+                    .json(entry)
+            }
+            Ok(None) => HttpResponse::Conflict()
+                .body(DatabaseError::RouteAlreadyOnTicklist.to_string()),
+            Err(_) => {
+                error!("INSERT query failed in add_ticklist_entry()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in add_ticklist_entry()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+#[get("/{id}/ticklist/{route_id}")]
+async fn get_ticklist_entry(
+    path: web::Path<(i32, i32)>,
+    viewer: web::Query<Viewer>,
+) -> impl Responder {
+    let (id, route_id) = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match logbook_visible(&mut conn, id, viewer.viewer_id).await {
+            Ok(Some(true)) => (),
+            Ok(Some(false)) => return HttpResponse::Forbidden().finish(),
+            Ok(None) => return HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("SELECT query failed in get_ticklist_entry()");
+                return HttpResponse::BadGateway().finish();
+            }
+        }
+        match select_ticklist(&mut conn, id, Some(route_id), None).await {
+            Ok(mut entries) => match entries.pop() {
+                Some(entry) => HttpResponse::Ok().json(entry),
+                None => HttpResponse::NotFound().finish(),
+            },
+            Err(_) => {
+                error!("SELECT query for the ticklist entry failed in get_ticklist_entry()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in get_ticklist_entry()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+/// Change the priority and notes of a route on a climber's ticklist
+#[put("/{id}/ticklist/{route_id}")]
+async fn update_ticklist_entry(
+    path: web::Path<(i32, i32)>,
+    json: web::Json<TicklistEntry>,
+) -> impl Responder {
+    let (id, route_id) = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match query!(
+            r#"UPDATE ticklist_entries SET priority = ($3), notes = ($4)
+            WHERE climber_id = ($1) AND route_id = ($2)
+            RETURNING (SELECT min(completion_date) FROM active_climbs WHERE climber_id = ($1) AND route_id = ($2)) as done_on"#,
+            id,
+            route_id,
+            json.0.priority.to_string(),
+            json.0.notes,
+        )
+        .fetch_optional(&mut conn)
+        .await
+        {
+            Ok(Some(query_result)) => {
+                let mut entry = json.into_inner();
+                entry.route_id = route_id;
+                entry.done_on = query_result.done_on;
+                HttpResponse::Ok().json(entry)
+            }
+            Ok(None) => HttpResponse::NotFound().finish(),
+            Err(_) => {
+                error!("UPDATE query failed in update_ticklist_entry()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in update_ticklist_entry()");
+        HttpResponse::BadGateway().finish()
+    }
+}
+
+#[delete("/{id}/ticklist/{route_id}")]
+async fn delete_ticklist_entry(path: web::Path<(i32, i32)>) -> impl Responder {
+    let (id, route_id) = path.into_inner();
+    if let Ok(mut conn) = conn().await {
+        match query!(
+            "DELETE FROM ticklist_entries WHERE climber_id = ($1) AND route_id = ($2)",
+            id,
+            route_id,
+        )
+        .execute(&mut conn)
+        .await
+        {
+            Ok(query_result) if query_result.rows_affected() == 0 => {
+                HttpResponse::NotFound().finish()
+            }
+            Ok(_) => HttpResponse::NoContent().finish(),
+            Err(_) => {
+                error!("DELETE query failed in delete_ticklist_entry()");
+                HttpResponse::BadGateway().finish()
+            }
+        }
+    } else {
+        error!("Failed to connect to the database in delete_ticklist_entry()");
+        HttpResponse::BadGateway().finish()
+    }
+}